@@ -0,0 +1,423 @@
+// 预算限制
+// 在发出请求前按用量台账检查每日、每月、单画布的费用上限和各供应商每日请求数上限，
+// 超限时直接返回 budgetExceeded（包含当前花费），不会发出 HTTP 请求
+// 配置保存在 app_data/usage/budget.json，费用单位与价格表的 currency 一致
+// 通过检查的请求会预留预估费用直到请求结束，并发请求不会同时越过上限
+
+use crate::usage::{get_usage_dir, load_price_table, read_records, Usage, UsageContext, UsageLedger, UsageRecord};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+const BUDGET_FILE_NAME: &str = "budget.json";
+const REQUEST_COUNTS_FILE_NAME: &str = "request_counts.json";
+
+// ==================== 全局状态 ====================
+
+lazy_static::lazy_static! {
+    // 已通过检查、尚未结束的请求（预留的预估费用），检查与预留在同一把锁内完成
+    static ref RESERVATIONS: Mutex<Vec<(u64, UsageRecord)>> = Mutex::new(Vec::new());
+}
+
+static NEXT_RESERVATION_ID: AtomicU64 = AtomicU64::new(1);
+
+// ==================== 数据结构 ====================
+
+// 预算配置（未设置的项不限制）
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetConfig {
+    pub daily_limit: Option<f64>,
+    pub monthly_limit: Option<f64>,
+    pub canvas_limit: Option<f64>, // 每个画布的累计上限
+    #[serde(default)]
+    pub canvas_limits: BTreeMap<String, f64>, // 单独设置的画布上限（优先于 canvas_limit）
+    #[serde(default)]
+    pub provider_daily_requests: BTreeMap<String, u32>, // 各供应商每日请求数上限
+}
+
+impl BudgetConfig {
+    fn is_empty(&self) -> bool {
+        self.daily_limit.is_none()
+            && self.monthly_limit.is_none()
+            && self.canvas_limit.is_none()
+            && self.canvas_limits.is_empty()
+            && self.provider_daily_requests.is_empty()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum BudgetScope {
+    Daily,
+    Monthly,
+    Canvas,
+    ProviderRequests,
+    InvalidConfig, // budget.json 无法解析，阻止请求直到修复配置
+}
+
+// 超出预算（结果中的 budgetExceeded 字段，与供应商错误区分）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetExceeded {
+    pub scope: BudgetScope,
+    pub limit: f64,
+    pub spent: f64,   // 当前花费（ProviderRequests 时为今日请求数）
+    pub planned: f64, // 本次请求的预估费用
+    pub currency: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub canvas_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<Box<str>>, // InvalidConfig 时的解析错误（Box 保持错误类型足够小）
+}
+
+impl std::fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let scope = match self.scope {
+            BudgetScope::Daily => "今日费用",
+            BudgetScope::Monthly => "本月费用",
+            BudgetScope::Canvas => "画布费用",
+            BudgetScope::InvalidConfig => {
+                return write!(f, "预算配置无效，已阻止请求：{}", self.reason.as_deref().unwrap_or_default());
+            }
+            BudgetScope::ProviderRequests => {
+                return write!(
+                    f,
+                    "已超出预算：{} 今日请求数 {} 已达上限 {}",
+                    self.provider.as_deref().unwrap_or_default(),
+                    self.spent,
+                    self.limit
+                );
+            }
+        };
+        write!(
+            f,
+            "已超出预算：{} {:.2} {}（本次预估 {:.2}），上限 {:.2}",
+            scope, self.spent, self.currency, self.planned, self.limit
+        )
+    }
+}
+
+// 当前预算使用情况
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetStatus {
+    pub config: BudgetConfig,
+    pub currency: String,
+    pub daily_spent: f64,
+    pub monthly_spent: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub canvas_spent: Option<f64>,
+    pub provider_requests: BTreeMap<String, u32>, // 各供应商今日请求数
+}
+
+// 各供应商当日的请求次数（每次通过预算检查计一次，与台账记录数无关）
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct RequestCounts {
+    date: String,
+    counts: BTreeMap<String, u32>, // 供应商名（小写）-> 次数
+}
+
+impl RequestCounts {
+    fn get(&self, provider: &str) -> u32 {
+        self.counts.get(&provider.to_ascii_lowercase()).copied().unwrap_or(0)
+    }
+}
+
+// 检查时的已有用量：台账记录（含未结束请求的预留）与今日请求次数
+struct Spending<'a> {
+    records: &'a [UsageRecord],
+    requests: &'a RequestCounts,
+}
+
+/// 预算预留：请求结束（实际用量已写入台账）时释放
+#[derive(Debug)]
+#[must_use = "预留需保持到请求结束"]
+pub struct BudgetReservation(Option<u64>);
+
+impl Drop for BudgetReservation {
+    fn drop(&mut self) {
+        if let Some(id) = self.0 {
+            if let Ok(mut reservations) = RESERVATIONS.lock() {
+                reservations.retain(|(reserved, _)| *reserved != id);
+            }
+        }
+    }
+}
+
+// ==================== 检查 ====================
+
+/// 读取预算配置：文件不存在时不限制，无法解析时返回错误（不能静默放开限制）
+fn load_budget_config(dir: &Path) -> Result<BudgetConfig, String> {
+    match fs::read_to_string(dir.join(BUDGET_FILE_NAME)) {
+        Ok(content) => serde_json::from_str(&content).map_err(|e| format!("预算配置文件已损坏: {}", e)),
+        Err(_) => Ok(BudgetConfig::default()),
+    }
+}
+
+/// 读取今日请求次数（日期变化后从零开始）
+fn load_request_counts(dir: &Path, today: &str) -> RequestCounts {
+    fs::read_to_string(dir.join(REQUEST_COUNTS_FILE_NAME))
+        .ok()
+        .and_then(|content| serde_json::from_str::<RequestCounts>(&content).ok())
+        .filter(|counts| counts.date == today)
+        .unwrap_or_else(|| RequestCounts { date: today.to_string(), ..Default::default() })
+}
+
+fn save_request_counts(dir: &Path, counts: &RequestCounts) -> Result<(), String> {
+    let content = serde_json::to_vec(counts).map_err(|e| format!("序列化请求次数失败: {}", e))?;
+    crate::storage::write_file_atomic(&dir.join(REQUEST_COUNTS_FILE_NAME), &content)
+}
+
+fn total_cost<'a>(records: impl Iterator<Item = &'a UsageRecord>) -> f64 {
+    records.map(|record| record.cost).sum()
+}
+
+/// 按已有用量判断本次请求是否超出预算（today 为 YYYY-MM-DD）
+fn evaluate(
+    config: &BudgetConfig,
+    spending: &Spending,
+    today: &str,
+    provider: &str,
+    context: &UsageContext,
+    planned: f64,
+    currency: &str,
+) -> Result<(), BudgetExceeded> {
+    let exceeded = |scope, limit: f64, spent: f64| BudgetExceeded {
+        scope,
+        limit,
+        spent,
+        planned,
+        currency: currency.to_string(),
+        provider: Some(provider.to_string()),
+        canvas_id: context.canvas_id.clone(),
+        reason: None,
+    };
+    let records = spending.records;
+    // 已达上限，或加上本次预估费用后超出上限
+    let over = |spent: f64, limit: f64| spent >= limit || spent + planned > limit;
+
+    if let Some(limit) = config.daily_limit {
+        let spent = total_cost(records.iter().filter(|r| r.date == today));
+        if over(spent, limit) {
+            return Err(exceeded(BudgetScope::Daily, limit, spent));
+        }
+    }
+    if let Some(limit) = config.monthly_limit {
+        let month = &today[..today.len().min(7)];
+        let spent = total_cost(records.iter().filter(|r| r.date.starts_with(month)));
+        if over(spent, limit) {
+            return Err(exceeded(BudgetScope::Monthly, limit, spent));
+        }
+    }
+    if let Some(canvas_id) = &context.canvas_id {
+        let limit = config.canvas_limits.get(canvas_id).copied().or(config.canvas_limit);
+        if let Some(limit) = limit {
+            let spent = total_cost(records.iter().filter(|r| r.canvas_id.as_ref() == Some(canvas_id)));
+            if over(spent, limit) {
+                return Err(exceeded(BudgetScope::Canvas, limit, spent));
+            }
+        }
+    }
+    let request_limit = config
+        .provider_daily_requests
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(provider))
+        .map(|(_, limit)| *limit);
+    if let Some(limit) = request_limit {
+        let count = spending.requests.get(provider);
+        if count >= limit {
+            return Err(exceeded(BudgetScope::ProviderRequests, limit as f64, count as f64));
+        }
+    }
+    Ok(())
+}
+
+impl UsageLedger {
+    /// 发出请求前检查预算，planned 为本次请求可预估的用量（如图片数、视频秒数）
+    /// 通过时计入今日请求次数并预留预估费用，返回的预留需保持到请求结束
+    pub fn check_budget(&self, provider: &str, model: &str, planned: &Usage) -> Result<BudgetReservation, BudgetExceeded> {
+        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+        let config = load_budget_config(self.dir()).map_err(|e| {
+            rust_log!("[Rust] Request blocked: {}", e);
+            BudgetExceeded {
+                scope: BudgetScope::InvalidConfig,
+                limit: 0.0,
+                spent: 0.0,
+                planned: 0.0,
+                currency: String::new(),
+                provider: Some(provider.to_string()),
+                canvas_id: self.context().canvas_id.clone(),
+                reason: Some(e.into()),
+            }
+        })?;
+
+        let mut reservations = match RESERVATIONS.lock() {
+            Ok(reservations) => reservations,
+            Err(e) => e.into_inner(),
+        };
+        let mut requests = load_request_counts(self.dir(), &today);
+        let prices = load_price_table(self.dir());
+        let planned_cost = prices.estimate(provider, model, planned);
+
+        if !config.is_empty() {
+            // 台账读取失败时不阻断请求（预留和请求次数仍然生效）
+            let mut records = read_records(self.dir()).unwrap_or_else(|e| {
+                rust_log!("[Rust] Budget ledger unavailable: {}", e);
+                Vec::new()
+            });
+            records.extend(reservations.iter().map(|(_, record)| record.clone()));
+            let spending = Spending { records: &records, requests: &requests };
+            if let Err(e) = evaluate(&config, &spending, &today, provider, self.context(), planned_cost, &prices.currency) {
+                rust_log!("[Rust] Request blocked: {}", e);
+                return Err(e);
+            }
+        }
+
+        *requests.counts.entry(provider.to_ascii_lowercase()).or_default() += 1;
+        if let Err(e) = save_request_counts(self.dir(), &requests) {
+            rust_log!("[Rust] Failed to save request counts: {}", e);
+        }
+        if planned_cost <= 0.0 {
+            return Ok(BudgetReservation(None));
+        }
+        let id = NEXT_RESERVATION_ID.fetch_add(1, Ordering::Relaxed);
+        reservations.push((
+            id,
+            UsageRecord {
+                id: String::new(),
+                timestamp: String::new(),
+                date: today,
+                provider: provider.to_string(),
+                model: model.to_string(),
+                canvas_id: self.context().canvas_id.clone(),
+                node_id: self.context().node_id.clone(),
+                usage: *planned,
+                cost: planned_cost,
+            },
+        ));
+        Ok(BudgetReservation(Some(id)))
+    }
+}
+
+/// 检查预算（命令中使用），无法打开台账时不阻断请求
+pub fn check_budget(
+    app: &tauri::AppHandle,
+    context: &UsageContext,
+    provider: &str,
+    model: &str,
+    planned: &Usage,
+) -> Result<BudgetReservation, BudgetExceeded> {
+    match UsageLedger::open(app, context.clone()) {
+        Ok(ledger) => ledger.check_budget(provider, model, planned),
+        Err(e) => {
+            rust_log!("[Rust] Budget check skipped: {}", e);
+            Ok(BudgetReservation(None))
+        }
+    }
+}
+
+// ==================== 命令 ====================
+
+#[tauri::command]
+pub async fn budget_get_config(app: tauri::AppHandle) -> Result<BudgetConfig, String> {
+    load_budget_config(&get_usage_dir(&app)?)
+}
+
+#[tauri::command]
+pub async fn budget_set_config(app: tauri::AppHandle, config: BudgetConfig) -> Result<(), String> {
+    let path = get_usage_dir(&app)?.join(BUDGET_FILE_NAME);
+    let content = serde_json::to_vec_pretty(&config).map_err(|e| format!("序列化预算配置失败: {}", e))?;
+    crate::storage::write_file_atomic(&path, &content)
+}
+
+// 预算使用情况（指定 canvas_id 时包含该画布的累计花费）
+#[tauri::command]
+pub async fn budget_get_status(app: tauri::AppHandle, canvas_id: Option<String>) -> Result<BudgetStatus, String> {
+    let dir = get_usage_dir(&app)?;
+    let records = read_records(&dir)?;
+    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+
+    Ok(BudgetStatus {
+        config: load_budget_config(&dir)?,
+        currency: load_price_table(&dir).currency,
+        daily_spent: total_cost(records.iter().filter(|r| r.date == today)),
+        monthly_spent: total_cost(records.iter().filter(|r| r.date.starts_with(&today[..7]))),
+        canvas_spent: canvas_id
+            .map(|id| total_cost(records.iter().filter(|r| r.canvas_id.as_ref() == Some(&id)))),
+        provider_requests: load_request_counts(&dir, &today).counts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(date: &str, provider: &str, canvas: Option<&str>, cost: f64) -> UsageRecord {
+        UsageRecord {
+            id: String::new(),
+            timestamp: String::new(),
+            date: date.to_string(),
+            provider: provider.to_string(),
+            model: String::new(),
+            canvas_id: canvas.map(str::to_string),
+            node_id: None,
+            usage: Usage::default(),
+            cost,
+        }
+    }
+
+    #[test]
+    fn test_evaluate_budget_scopes() {
+        let records = vec![
+            record("2026-10-01", "veo", Some("deck-a"), 30.0),
+            record("2026-10-18", "veo", Some("deck-a"), 6.0),
+            record("2026-10-18", "openai", Some("deck-b"), 1.0),
+        ];
+        let context = UsageContext {
+            canvas_id: Some("deck-a".to_string()),
+            node_id: None,
+        };
+        let requests = RequestCounts {
+            date: "2026-10-18".to_string(),
+            counts: BTreeMap::from([("veo".to_string(), 1)]),
+        };
+        let spending = Spending { records: &records, requests: &requests };
+        let check = |config: &BudgetConfig, provider: &str, planned: f64| {
+            evaluate(config, &spending, "2026-10-18", provider, &context, planned, "USD")
+        };
+
+        assert!(check(&BudgetConfig::default(), "veo", 100.0).is_ok());
+
+        // 今日已花费 7，本次预估 3.2 会超出 10
+        let daily = BudgetConfig { daily_limit: Some(10.0), ..Default::default() };
+        assert!(check(&daily, "veo", 2.0).is_ok());
+        let err = check(&daily, "veo", 3.2).unwrap_err();
+        assert_eq!(err.scope, BudgetScope::Daily);
+        assert_eq!(err.spent, 7.0);
+
+        let monthly = BudgetConfig { monthly_limit: Some(37.0), ..Default::default() };
+        assert_eq!(check(&monthly, "openai", 0.0).unwrap_err().scope, BudgetScope::Monthly);
+
+        // 单独设置的画布上限优先
+        let canvas = BudgetConfig {
+            canvas_limit: Some(100.0),
+            canvas_limits: BTreeMap::from([("deck-a".to_string(), 36.0)]),
+            ..Default::default()
+        };
+        assert_eq!(check(&canvas, "openai", 0.0).unwrap_err().scope, BudgetScope::Canvas);
+
+        let requests = BudgetConfig {
+            provider_daily_requests: BTreeMap::from([("VEO".to_string(), 1)]),
+            ..Default::default()
+        };
+        assert_eq!(check(&requests, "veo", 0.0).unwrap_err().scope, BudgetScope::ProviderRequests);
+        assert!(check(&requests, "openai", 0.0).is_ok());
+    }
+}
@@ -1,11 +1,25 @@
+use crate::budget::{check_budget, BudgetExceeded};
+use crate::endpoint::{ApiFlavor, OpenAIEndpoint};
+use crate::failover::{run_with_failover, FailoverParams, FailoverResult, FailureKind, FallbackProvider, ServedBy};
+use crate::image_preprocess::{decode_base64_image, prepare_image, UploadTarget};
+use crate::image_processing::{encode_image, OutputFormat};
+use crate::image_source::{input_infos, ImageSource};
+use crate::storage::{save_generated_image, GenerationOutputOptions, ImageInfo, ImageMetadata};
+use crate::usage::{record_usage, Usage, UsageContext};
+use crate::vault::resolve_api_key;
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use image::imageops::FilterType;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
+use tauri::Emitter;
+
+// 流式生成的部分图片事件
+pub const DALLE_PARTIAL_IMAGE_EVENT: &str = "dalle-partial-image";
 
 /// 从 URL 下载图片并转换为 base64
-async fn download_image_as_base64(client: &Client, url: &str) -> Result<String, String> {
-    println!("[Rust] Downloading image from URL: {}", url);
+pub(crate) async fn download_image_as_base64(client: &Client, url: &str) -> Result<String, String> {
+    rust_log!("[Rust] Downloading image from URL: {}", url);
     let start_time = std::time::Instant::now();
 
     let response = client
@@ -35,7 +49,7 @@ async fn download_image_as_base64(client: &Client, url: &str) -> Result<String,
         .await
         .map_err(|e| format!("读取图片数据失败: {}", e))?;
 
-    println!(
+    rust_log!(
         "[Rust] Image downloaded: {} bytes in {:?}",
         bytes.len(),
         start_time.elapsed()
@@ -67,6 +81,20 @@ pub struct DalleRequest {
     // 负面提示词（部分模型支持）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub negative_prompt: Option<String>,
+    // gpt-image 系列参数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_compression: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub moderation: Option<String>,
+    // 流式输出（gpt-image 系列）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partial_images: Option<u8>,
 }
 
 // DALL-E API 响应结构
@@ -76,6 +104,15 @@ pub struct DalleResponse {
     pub created: Option<i64>,
     pub data: Option<Vec<DalleImageData>>,
     pub error: Option<DalleError>,
+    pub usage: Option<DalleUsage>, // 仅 gpt-image 系列返回
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DalleUsage {
+    #[serde(default)]
+    pub input_tokens: u64,
+    #[serde(default)]
+    pub output_tokens: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -94,18 +131,209 @@ pub struct DalleError {
 }
 
 // 前端调用的参数
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct DalleRequestParams {
     pub base_url: String,
-    pub api_key: String,
+    pub api_flavor: Option<ApiFlavor>, // OpenAI 兼容接口类型（未设置时按域名识别）
+    #[serde(default)]
+    pub api_key: String, // 使用 credential_id 时可省略
+    pub credential_id: Option<String>, // 凭据库中的凭据 ID（优先于 api_key）
     pub model: String,
     pub prompt: String,
-    pub input_images: Option<Vec<String>>,
+    pub input_images: Option<Vec<ImageSource>>, // 输入图片（base64、存储路径或图片 ID）
     pub size: Option<String>,
     pub quality: Option<String>,
     pub style: Option<String>,
     pub negative_prompt: Option<String>,
+    pub n: Option<u32>,                // 生成数量，默认 1
+    pub edit: Option<bool>,            // 编辑模式：multipart 发送到 /v1/images/edits
+    pub mask: Option<ImageSource>,     // 编辑遮罩（透明区域为需要编辑的部分），指定后自动使用编辑模式
+    pub background: Option<String>,    // transparent / opaque / auto
+    pub output_format: Option<String>, // png / jpeg / webp
+    pub output_compression: Option<u8>, // 0-100，仅 jpeg/webp
+    pub moderation: Option<String>,    // auto / low
+    pub input_fidelity: Option<String>, // high / low（仅编辑模式）
+    pub partial_images: Option<u8>,    // 流式模式下的预览数量（0-3，默认 2）
+    pub stream_id: Option<String>,     // 流式事件标识（默认使用 output.nodeId）
+    pub output: Option<GenerationOutputOptions>, // 指定时直接保存生成结果并返回图片信息
+    #[serde(default)]
+    pub fallbacks: Vec<FallbackProvider>, // 备用供应商（按顺序）
+    #[serde(flatten)]
+    pub usage_context: UsageContext,
+}
+
+impl FailoverParams for DalleRequestParams {
+    fn fallbacks(&self) -> &[FallbackProvider] {
+        &self.fallbacks
+    }
+
+    fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn use_fallback(&mut self, fallback: &FallbackProvider) {
+        fallback.apply(&mut self.base_url, &mut self.api_key, &mut self.credential_id, &mut self.model);
+        self.api_flavor = fallback.api_flavor;
+    }
+}
+
+impl DalleRequestParams {
+    fn is_edit(&self) -> bool {
+        self.edit.unwrap_or(false) || self.mask.is_some()
+    }
+
+    // dall-e 系列支持 response_format，gpt-image 始终返回 b64_json
+    fn supports_response_format(&self) -> bool {
+        self.model.starts_with("dall-e")
+    }
+
+    fn is_dalle2(&self) -> bool {
+        self.model.starts_with("dall-e-2")
+    }
+
+    fn usage_context(&self) -> UsageContext {
+        self.usage_context.or_output(self.output.as_ref())
+    }
+
+    /// 记录生成用量（生成成功即计费，与下载或保存是否成功无关）
+    fn record_usage(&self, app: &tauri::AppHandle, usage: Usage) {
+        record_usage(app, &self.usage_context(), "openai", &self.model, &usage);
+    }
+
+    fn stream_id(&self) -> Option<String> {
+        self.stream_id
+            .clone()
+            .or_else(|| self.output.as_ref().and_then(|o| o.node_id.clone()))
+    }
+}
+
+// 部分图片事件负载
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DallePartialImageEvent {
+    pub stream_id: Option<String>,
+    pub partial_image_index: u32,
+    pub image_data: String,
+    pub output_format: Option<String>,
+}
+
+// SSE 事件中的图片数据（partial_image / completed）
+#[derive(Debug, Deserialize)]
+struct StreamImageEvent {
+    #[serde(rename = "type")]
+    event_type: Option<String>,
+    b64_json: Option<String>,
+    partial_image_index: Option<u32>,
+    output_format: Option<String>,
+    revised_prompt: Option<String>,
+    error: Option<DalleError>,
+}
+
+/// 增量解析 SSE 数据流，返回完整事件的 (event, data)
+#[derive(Default)]
+struct SseParser {
+    buffer: Vec<u8>,
+}
+
+impl SseParser {
+    fn push(&mut self, chunk: &[u8]) -> Vec<(Option<String>, String)> {
+        // 统一换行符，事件之间以空行分隔
+        self.buffer.extend(chunk.iter().filter(|b| **b != b'\r'));
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let block: Vec<u8> = self.buffer.drain(..pos + 2).collect();
+            let block = String::from_utf8_lossy(&block[..pos]);
+
+            let mut event = None;
+            let mut data_lines = Vec::new();
+            for line in block.lines() {
+                if let Some(value) = line.strip_prefix("event:") {
+                    event = Some(value.trim().to_string());
+                } else if let Some(value) = line.strip_prefix("data:") {
+                    data_lines.push(value.trim_start().to_string());
+                }
+            }
+            if !data_lines.is_empty() {
+                events.push((event, data_lines.join("\n")));
+            }
+        }
+        events
+    }
+}
+
+/// 读取流式响应：部分图片通过事件推送，返回最终图片的 (b64, revised_prompt)
+async fn read_image_stream(
+    app: &tauri::AppHandle,
+    mut response: reqwest::Response,
+    stream_id: Option<String>,
+) -> Result<Vec<(String, Option<String>)>, String> {
+    let mut parser = SseParser::default();
+    let mut completed = Vec::new();
+
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("读取流式响应失败: {}", e))?
+    {
+        for (event, data) in parser.push(&chunk) {
+            if data == "[DONE]" {
+                continue;
+            }
+            let parsed: StreamImageEvent = match serde_json::from_str(&data) {
+                Ok(p) => p,
+                Err(e) => {
+                    rust_log!("[Rust] Skipping unparsable stream event: {}", e);
+                    continue;
+                }
+            };
+            if let Some(err) = parsed.error {
+                return Err(err.message);
+            }
+            let kind = parsed.event_type.clone().or(event).unwrap_or_default();
+            let Some(b64) = parsed.b64_json else { continue };
+
+            // image_generation.* 或 image_edit.*
+            if kind.ends_with(".partial_image") {
+                let index = parsed.partial_image_index.unwrap_or(0);
+                rust_log!("[Rust] Partial image {} received", index);
+                let _ = app.emit(
+                    DALLE_PARTIAL_IMAGE_EVENT,
+                    DallePartialImageEvent {
+                        stream_id: stream_id.clone(),
+                        partial_image_index: index,
+                        image_data: b64,
+                        output_format: parsed.output_format,
+                    },
+                );
+            } else if kind.ends_with(".completed") {
+                rust_log!("[Rust] Final streamed image received");
+                completed.push((b64, parsed.revised_prompt));
+            }
+        }
+    }
+
+    if completed.is_empty() {
+        return Err("流式响应未返回最终图片".to_string());
+    }
+    Ok(completed)
+}
+
+// 单张生成结果（每张图片单独报告成功或失败）
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DalleImage {
+    pub index: usize,
+    pub image_data: Option<String>,
+    pub image_url: Option<String>,
+    pub revised_prompt: Option<String>,
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<ImageInfo>,
 }
 
 // 前端返回的结果
@@ -117,42 +345,331 @@ pub struct DalleResult {
     pub image_url: Option<String>,
     pub revised_prompt: Option<String>,
     pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<ImageInfo>, // 指定 output 时保存到存储后的图片信息（此时不返回 image_data）
+    pub images: Vec<DalleImage>,  // 全部图片；顶层字段对应第一张成功的图片
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_exceeded: Option<BudgetExceeded>, // 超出预算时未发出请求
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub served_by: Option<ServedBy>, // 配置了备用供应商时，实际完成请求的供应商
+    #[serde(skip)]
+    pub(crate) failure_kind: Option<FailureKind>, // 请求失败的类型（用于判断是否切换供应商）
+}
+
+impl FailoverResult for DalleResult {
+    fn failover_error(&self) -> Option<(FailureKind, &str)> {
+        self.failure_kind.zip(self.error.as_deref()).filter(|_| !self.success)
+    }
+
+    fn set_served_by(&mut self, served_by: ServedBy) {
+        self.served_by = Some(served_by);
+    }
+}
+
+impl DalleResult {
+    pub(crate) fn failure(error: String) -> Self {
+        DalleResult {
+            success: false,
+            image_data: None,
+            image_url: None,
+            revised_prompt: None,
+            error: Some(error),
+            image: None,
+            images: Vec::new(),
+            budget_exceeded: None,
+            served_by: None,
+            failure_kind: None,
+        }
+    }
+
+    pub(crate) fn request_failure(kind: FailureKind, error: String) -> Self {
+        DalleResult {
+            failure_kind: Some(kind),
+            ..DalleResult::failure(error)
+        }
+    }
+
+    pub(crate) fn budget_exceeded(exceeded: BudgetExceeded) -> Self {
+        DalleResult {
+            budget_exceeded: Some(exceeded.clone()),
+            ..DalleResult::failure(exceeded.to_string())
+        }
+    }
+
+    /// 至少一张成功即视为成功，部分失败在各图片的 error 中报告
+    pub(crate) fn from_images(images: Vec<DalleImage>) -> Self {
+        let primary = images
+            .iter()
+            .find(|i| i.error.is_none())
+            .or_else(|| images.first())
+            .cloned();
+        let Some(primary) = primary else {
+            return DalleResult::failure("API 未返回有效内容".to_string());
+        };
+        DalleResult {
+            success: primary.error.is_none(),
+            image_data: primary.image_data,
+            image_url: primary.image_url,
+            revised_prompt: primary.revised_prompt,
+            error: primary.error,
+            image: primary.image,
+            images,
+            budget_exceeded: None,
+            served_by: None,
+            failure_kind: None,
+        }
+    }
+}
+
+/// 单张图片的结果；指定 output 时保存到存储并只返回图片信息
+fn finish_image(
+    app: &tauri::AppHandle,
+    params: &DalleRequestParams,
+    index: usize,
+    image_data: String,
+    image_url: Option<String>,
+    revised_prompt: Option<String>,
+) -> DalleImage {
+    let metadata = params.output.as_ref().map(|output| ImageMetadata {
+        prompt: Some(params.prompt.clone()),
+        input_images: input_infos(
+            app,
+            params.input_images.as_deref().unwrap_or_default(),
+            output.input_labels.as_deref(),
+        ),
+        revised_prompt: revised_prompt.clone(),
+        provider: Some("openai".to_string()),
+        model: Some(params.model.clone()),
+        image_size: params.size.clone(),
+        operation: params.is_edit().then(|| "images/edits".to_string()),
+        ..Default::default()
+    });
+    finish_generated_image(
+        app,
+        params.output.as_ref(),
+        metadata,
+        DalleImage {
+            index,
+            image_data: Some(image_data),
+            image_url,
+            revised_prompt,
+            error: None,
+            image: None,
+        },
+    )
+}
+
+/// 指定 output 时保存图片并只返回图片信息（其他图片供应商共用）
+pub(crate) fn finish_generated_image(
+    app: &tauri::AppHandle,
+    output: Option<&GenerationOutputOptions>,
+    metadata: Option<ImageMetadata>,
+    mut result: DalleImage,
+) -> DalleImage {
+    let (Some(output), Some(metadata)) = (output, metadata) else {
+        return result;
+    };
+
+    let saved = result
+        .image_data
+        .as_deref()
+        .ok_or_else(|| "缺少图片数据".to_string())
+        .and_then(decode_base64_image)
+        .and_then(|bytes| save_generated_image(app, &bytes, output, metadata));
+    match saved {
+        Ok(info) => {
+            rust_log!("[Rust] Generated image {} saved: {}", result.index + 1, info.path);
+            result.image_data = None;
+            result.image = Some(info);
+        }
+        Err(e) => {
+            // 保存失败时仍返回图片数据，避免生成结果丢失
+            rust_log!("[Rust] Failed to save generated image {}: {}", result.index + 1, e);
+            result.error = Some(format!("保存生成图片失败: {}", e));
+        }
+    }
+    result
+}
+
+/// 读取图片尺寸（只解析文件头）
+fn image_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    image::ImageReader::new(std::io::Cursor::new(data))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// 构建 /v1/images/edits 的 multipart 表单
+/// 多张输入图片使用 image[] 字段，单张使用 image（兼容 dall-e-2）
+async fn build_edit_form(
+    app: &tauri::AppHandle,
+    params: &DalleRequestParams,
+    stream: bool,
+) -> Result<reqwest::multipart::Form, String> {
+    let images = params.input_images.as_deref().unwrap_or_default();
+    if images.is_empty() {
+        return Err("编辑模式需要至少一张输入图片".to_string());
+    }
+
+    let mut form = reqwest::multipart::Form::new()
+        .text("model", params.model.clone())
+        .text("prompt", params.prompt.clone())
+        .text("n", params.n.unwrap_or(1).to_string());
+
+    let optional_fields = [
+        ("size", params.size.clone()),
+        ("quality", params.quality.clone()),
+        ("background", params.background.clone()),
+        ("output_format", params.output_format.clone()),
+        ("output_compression", params.output_compression.map(|c| c.to_string())),
+        ("moderation", params.moderation.clone()),
+        ("input_fidelity", params.input_fidelity.clone()),
+    ];
+    for (name, value) in optional_fields {
+        if let Some(value) = value {
+            form = form.text(name, value);
+        }
+    }
+    if stream {
+        form = form
+            .text("stream", "true")
+            .text("partial_images", params.partial_images.unwrap_or(2).to_string());
+    } else if params.supports_response_format() {
+        form = form.text("response_format", "b64_json");
+    }
+
+    if params.is_dalle2() && images.len() > 1 {
+        return Err("dall-e-2 编辑只支持一张输入图片".to_string());
+    }
+    let target = if params.is_dalle2() { UploadTarget::DallE2Edit } else { UploadTarget::OpenAiEdit };
+
+    let field_name = if images.len() > 1 { "image[]" } else { "image" };
+    let mut first_size: Option<(u32, u32)> = None;
+    for (index, source) in images.iter().enumerate() {
+        rust_log!("[Rust] Adding edit image {}: {}", index + 1, source.describe());
+        let prepared = match source.load_bytes(app).await {
+            Ok(bytes) => prepare_image(bytes, target, None).await,
+            Err(e) => Err(e),
+        }
+        .map_err(|e| format!("第 {} 张输入图片处理失败: {}", index + 1, e))?;
+        let size = image_dimensions(&prepared.data)
+            .ok_or_else(|| format!("第 {} 张输入图片处理失败: 无法读取尺寸", index + 1))?;
+        if params.is_dalle2() && size.0 != size.1 {
+            return Err(format!("dall-e-2 编辑要求输入图片为正方形，当前为 {}x{}", size.0, size.1));
+        }
+        first_size.get_or_insert(size);
+        let part = reqwest::multipart::Part::bytes(prepared.data)
+            .file_name(format!("image_{}.{}", index + 1, prepared.extension))
+            .mime_str(&prepared.mime_type)
+            .map_err(|e| format!("构建图片字段失败: {}", e))?;
+        form = form.part(field_name, part);
+    }
+
+    // 遮罩必须是带 alpha 通道的 PNG，且与（预处理后的）第一张输入图片尺寸一致
+    if let (Some(mask), Some((width, height))) = (&params.mask, first_size) {
+        let bytes = mask.load_bytes(app).await.map_err(|e| format!("遮罩读取失败: {}", e))?;
+        let png = tokio::task::spawn_blocking(move || {
+            let img = image::load_from_memory(&bytes).map_err(|e| format!("遮罩解析失败: {}", e))?;
+            let img = if (img.width(), img.height()) == (width, height) {
+                img
+            } else {
+                img.resize_exact(width, height, FilterType::Triangle)
+            };
+            encode_image(&image::DynamicImage::ImageRgba8(img.to_rgba8()), OutputFormat::Png, 100)
+        })
+        .await
+        .map_err(|e| format!("遮罩处理任务失败: {}", e))??;
+        if params.is_dalle2() && png.len() >= 4 * 1024 * 1024 {
+            return Err("dall-e-2 遮罩必须小于 4MB".to_string());
+        }
+        let part = reqwest::multipart::Part::bytes(png)
+            .file_name("mask.png")
+            .mime_str("image/png")
+            .map_err(|e| format!("构建遮罩字段失败: {}", e))?;
+        form = form.part("mask", part);
+    }
+
+    Ok(form)
 }
 
 // Tauri 命令：发送 DALL-E API 请求
 #[tauri::command]
-pub async fn dalle_generate_image(params: DalleRequestParams) -> DalleResult {
-    println!("[Rust] dalle_generate_image called");
-    println!("[Rust] base_url: {}", params.base_url);
-    println!("[Rust] model: {}", params.model);
+pub async fn dalle_generate_image(app: tauri::AppHandle, params: DalleRequestParams) -> DalleResult {
+    run_with_failover(params, |params| generate_image(app.clone(), params, false)).await
+}
+
+// Tauri 命令：流式生成（gpt-image 系列），部分图片通过 dalle-partial-image 事件推送
+#[tauri::command]
+pub async fn dalle_generate_image_stream(app: tauri::AppHandle, params: DalleRequestParams) -> DalleResult {
+    run_with_failover(params, |params| generate_image(app.clone(), params, true)).await
+}
+
+async fn generate_image(app: tauri::AppHandle, mut params: DalleRequestParams, stream: bool) -> DalleResult {
+    rust_log!("[Rust] dalle_generate_image called (stream: {})", stream);
+    rust_log!("[Rust] base_url: {}", params.base_url);
+    rust_log!("[Rust] model: {}", params.model);
+
+    if let Err(e) = resolve_api_key(&app, &params.credential_id, &mut params.api_key) {
+        return DalleResult::failure(e);
+    }
+
+    let planned = Usage::images(params.n.unwrap_or(1));
+    let _reservation = match check_budget(&app, &params.usage_context(), "openai", &params.model, &planned) {
+        Ok(reservation) => reservation,
+        Err(e) => return DalleResult::budget_exceeded(e),
+    };
 
     // 构建请求体
     let mut request_body = DalleRequest {
         model: params.model.clone(),
         prompt: params.prompt.clone(),
         size: params.size.clone(),
-        n: Some(1),
-        response_format: Some("b64_json".to_string()),
+        n: Some(params.n.unwrap_or(1) as i32),
+        // 流式输出仅支持 gpt-image，始终返回 base64
+        response_format: (!stream).then(|| "b64_json".to_string()),
         quality: params.quality.clone(),
         style: params.style.clone(),
         image: None,
         negative_prompt: params.negative_prompt.clone(),
+        background: params.background.clone(),
+        output_format: params.output_format.clone(),
+        output_compression: params.output_compression,
+        moderation: params.moderation.clone(),
+        stream: stream.then_some(true),
+        partial_images: stream.then(|| params.partial_images.unwrap_or(2)),
+    };
+
+    // 编辑模式使用 multipart 表单，在下方单独构建
+    let edit_form = if params.is_edit() {
+        match build_edit_form(&app, &params, stream).await {
+            Ok(form) => Some(form),
+            Err(e) => return DalleResult::failure(e),
+        }
+    } else {
+        None
     };
 
     // 如果有输入图片，添加到请求中（垫图模式）
-    if let Some(images) = &params.input_images {
+    if let (None, Some(images)) = (&edit_form, &params.input_images) {
         if let Some(first_image) = images.first() {
-            println!("[Rust] Adding reference image for image-to-image generation");
-            request_body.image = Some(first_image.clone());
+            rust_log!("[Rust] Adding reference image for image-to-image generation: {}", first_image.describe());
+            match first_image.load_base64(&app).await {
+                Ok(data) => request_body.image = Some(data),
+                Err(e) => {
+                    return DalleResult::failure(format!("输入图片读取失败: {}", e));
+                }
+            }
         }
     }
 
     // 构建 URL
-    let url = format!(
-        "{}/v1/images/generations",
-        params.base_url.trim_end_matches('/')
+    let endpoint = OpenAIEndpoint::new(&params.base_url, params.api_flavor);
+    let url = endpoint.url(
+        if edit_form.is_some() { "images/edits" } else { "images/generations" },
+        Some(&params.model),
     );
-    println!("[Rust] Request URL: {}", url);
+    rust_log!("[Rust] Request URL: {}", url);
 
     // 创建 HTTP 客户端
     let client = match Client::builder()
@@ -161,34 +678,27 @@ pub async fn dalle_generate_image(params: DalleRequestParams) -> DalleResult {
     {
         Ok(c) => c,
         Err(e) => {
-            return DalleResult {
-                success: false,
-                image_data: None,
-                image_url: None,
-                revised_prompt: None,
-                error: Some(format!("创建 HTTP 客户端失败: {}", e)),
-            }
+            return DalleResult::failure(format!("创建 HTTP 客户端失败: {}", e))
         }
     };
 
     // 发送请求
-    println!("[Rust] Sending DALL-E request...");
+    rust_log!("[Rust] Sending DALL-E request...");
     let start_time = std::time::Instant::now();
 
-    let response = match client
-        .post(&url)
-        .header("Content-Type", "application/json")
-        .header("Authorization", format!("Bearer {}", params.api_key))
-        .json(&request_body)
-        .send()
-        .await
-    {
+    let request = endpoint.authorize(client.post(&url), &params.api_key);
+    let request = match edit_form {
+        Some(form) => request.multipart(form),
+        None => request.json(&request_body),
+    };
+
+    let response = match request.send().await {
         Ok(r) => {
-            println!("[Rust] Response received in {:?}", start_time.elapsed());
+            rust_log!("[Rust] Response received in {:?}", start_time.elapsed());
             r
         }
         Err(e) => {
-            println!("[Rust] Request failed: {}", e);
+            rust_log!("[Rust] Request failed: {}", e);
             let error_msg = if e.is_timeout() {
                 "请求超时，请稍后重试".to_string()
             } else if e.is_connect() {
@@ -196,134 +706,139 @@ pub async fn dalle_generate_image(params: DalleRequestParams) -> DalleResult {
             } else {
                 format!("请求失败: {}", e)
             };
-            return DalleResult {
-                success: false,
-                image_data: None,
-                image_url: None,
-                revised_prompt: None,
-                error: Some(error_msg),
-            };
+            return DalleResult::request_failure(FailureKind::from_request_error(&e), error_msg);
         }
     };
 
     // 检查 HTTP 状态码
     let status = response.status();
-    println!("[Rust] HTTP status: {}", status);
+    rust_log!("[Rust] HTTP status: {}", status);
 
     if !status.is_success() {
         let error_text = response.text().await.unwrap_or_default();
-        println!("[Rust] Error response: {}", error_text);
-        return DalleResult {
-            success: false,
-            image_data: None,
-            image_url: None,
-            revised_prompt: None,
-            error: Some(format!("API 返回错误 ({}): {}", status, error_text)),
+        rust_log!("[Rust] Error response: {}", error_text);
+        return DalleResult::request_failure(FailureKind::Status(status.as_u16()), format!("API 返回错误 ({}): {}", status, error_text));
+    }
+
+    // 流式响应：推送部分图片，返回最终图片
+    if stream {
+        let completed = match read_image_stream(&app, response, params.stream_id()).await {
+            Ok(c) => c,
+            Err(e) => return DalleResult::failure(e),
         };
+        params.record_usage(&app, Usage::images(completed.len() as u32));
+        let images = completed
+            .into_iter()
+            .enumerate()
+            .map(|(index, (b64, revised_prompt))| finish_image(&app, &params, index, b64, None, revised_prompt))
+            .collect();
+        return DalleResult::from_images(images);
     }
 
     // 解析响应
     let response_text = match response.text().await {
         Ok(t) => t,
         Err(e) => {
-            return DalleResult {
-                success: false,
-                image_data: None,
-                image_url: None,
-                revised_prompt: None,
-                error: Some(format!("获取响应失败: {}", e)),
-            };
+            return DalleResult::failure(format!("获取响应失败: {}", e));
         }
     };
 
-    println!("[Rust] Response length: {} bytes", response_text.len());
+    rust_log!("[Rust] Response length: {} bytes", response_text.len());
 
     let dalle_response: DalleResponse = match serde_json::from_str(&response_text) {
         Ok(r) => r,
         Err(e) => {
-            println!("[Rust] Failed to parse JSON: {}", e);
-            return DalleResult {
-                success: false,
-                image_data: None,
-                image_url: None,
-                revised_prompt: None,
-                error: Some(format!("解析响应失败: {}", e)),
-            };
+            rust_log!("[Rust] Failed to parse JSON: {}", e);
+            return DalleResult::failure(format!("解析响应失败: {}", e));
         }
     };
 
     // 检查 API 错误
     if let Some(err) = dalle_response.error {
-        return DalleResult {
-            success: false,
-            image_data: None,
-            image_url: None,
-            revised_prompt: None,
-            error: Some(err.message),
-        };
+        return DalleResult::failure(err.message);
     }
 
     // 提取结果
-    if let Some(data) = dalle_response.data {
-        if let Some(image_data) = data.first() {
-            println!("[Rust] DALL-E result: has_b64={}, has_url={}",
-                image_data.b64_json.is_some(),
-                image_data.url.is_some()
-            );
+    let data = dalle_response.data.unwrap_or_default();
+    rust_log!("[Rust] DALL-E returned {} images", data.len());
+    let usage = dalle_response
+        .usage
+        .map(|u| Usage::tokens(u.input_tokens, u.output_tokens))
+        .unwrap_or_default();
+    params.record_usage(&app, Usage { images: data.len() as u32, ..usage });
 
-            // 优先使用 base64 数据
-            if let Some(b64) = &image_data.b64_json {
-                return DalleResult {
-                    success: true,
-                    image_data: Some(b64.clone()),
-                    image_url: image_data.url.clone(),
-                    revised_prompt: image_data.revised_prompt.clone(),
-                    error: None,
-                };
-            }
+    // 只有 URL 的图片并发下载
+    let downloads: Vec<_> = data
+        .iter()
+        .map(|item| {
+            let client = client.clone();
+            let url = if item.b64_json.is_none() { item.url.clone() } else { None };
+            tokio::spawn(async move {
+                match url {
+                    Some(url) => Some(download_image_as_base64(&client, &url).await),
+                    None => None,
+                }
+            })
+        })
+        .collect();
 
-            // 如果只有 URL，下载图片并转换为 base64
-            if let Some(url) = &image_data.url {
-                println!("[Rust] No base64 data, downloading from URL...");
-                match download_image_as_base64(&client, url).await {
-                    Ok(base64_data) => {
-                        return DalleResult {
-                            success: true,
-                            image_data: Some(base64_data),
-                            image_url: Some(url.clone()),
-                            revised_prompt: image_data.revised_prompt.clone(),
-                            error: None,
-                        };
-                    }
-                    Err(e) => {
-                        println!("[Rust] Failed to download image: {}", e);
-                        return DalleResult {
-                            success: false,
-                            image_data: None,
-                            image_url: Some(url.clone()),
-                            revised_prompt: image_data.revised_prompt.clone(),
-                            error: Some(format!("图片生成成功但下载失败: {}", e)),
-                        };
-                    }
+    let mut images = Vec::with_capacity(data.len());
+    for (index, (item, download)) in data.into_iter().zip(downloads).enumerate() {
+        rust_log!(
+            "[Rust] Image {}: has_b64={}, has_url={}",
+            index + 1,
+            item.b64_json.is_some(),
+            item.url.is_some()
+        );
+        let downloaded = download
+            .await
+            .unwrap_or_else(|e| Some(Err(format!("下载任务失败: {}", e))));
+        let image = match (item.b64_json, downloaded) {
+            // 优先使用 base64 数据
+            (Some(b64), _) => finish_image(&app, &params, index, b64, item.url, item.revised_prompt),
+            (None, Some(Ok(b64))) => finish_image(&app, &params, index, b64, item.url, item.revised_prompt),
+            (None, Some(Err(e))) => {
+                rust_log!("[Rust] Failed to download image {}: {}", index + 1, e);
+                DalleImage {
+                    index,
+                    image_data: None,
+                    image_url: item.url,
+                    revised_prompt: item.revised_prompt,
+                    error: Some(format!("图片生成成功但下载失败: {}", e)),
+                    image: None,
                 }
             }
-
             // 既没有 base64 也没有 URL
-            return DalleResult {
-                success: false,
+            (None, None) => DalleImage {
+                index,
                 image_data: None,
                 image_url: None,
-                revised_prompt: image_data.revised_prompt.clone(),
+                revised_prompt: item.revised_prompt,
                 error: Some("API 未返回图片数据或 URL".to_string()),
-            };
-        }
+                image: None,
+            },
+        };
+        images.push(image);
     }
 
-    DalleResult {
-        success: false,
-        image_data: None,
-        image_url: None,
-        revised_prompt: None,
-        error: Some("API 未返回有效内容".to_string()),
+    DalleResult::from_images(images)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sse_parser_handles_split_chunks() {
+        let mut parser = SseParser::default();
+        let first = parser.push(b"event: image_generation.partial_image\ndata: {\"b64_json\":\"AA");
+        assert!(first.is_empty());
+
+        let events = parser.push(b"A\",\"partial_image_index\":0}\n\nevent: image_generation.completed\r\ndata: {}\r\n\r\n");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].0.as_deref(), Some("image_generation.partial_image"));
+        let parsed: StreamImageEvent = serde_json::from_str(&events[0].1).unwrap();
+        assert_eq!(parsed.b64_json.as_deref(), Some("AAA"));
+        assert_eq!(events[1].0.as_deref(), Some("image_generation.completed"));
     }
 }
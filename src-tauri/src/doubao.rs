@@ -0,0 +1,359 @@
+// 火山方舟（Doubao Seedream）图片生成
+// 支持多图参考、组图生成（sequential_image_generation）、水印开关和最高 4K 的尺寸预设
+// 返回与 dalle_generate_image 相同的结果结构
+
+use crate::budget::check_budget;
+use crate::dalle::{download_image_as_base64, finish_generated_image, DalleImage, DalleResult};
+use crate::failover::{run_with_failover, FailoverParams, FailureKind, FallbackProvider};
+use crate::image_preprocess::{prepare_image, UploadTarget};
+use crate::image_source::{input_infos, ImageSource};
+use crate::storage::{GenerationOutputOptions, ImageMetadata};
+use crate::usage::{record_usage, Usage, UsageContext};
+use crate::vault::resolve_api_key;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// 2K 档位的推荐尺寸（1K 为一半，4K 为两倍）
+const SIZE_PRESETS_2K: &[(&str, u32, u32)] = &[
+    ("1:1", 2048, 2048),
+    ("4:3", 2304, 1728),
+    ("3:4", 1728, 2304),
+    ("16:9", 2560, 1440),
+    ("9:16", 1440, 2560),
+    ("3:2", 2496, 1664),
+    ("2:3", 1664, 2496),
+    ("21:9", 3024, 1296),
+];
+
+// 前端调用的参数
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DoubaoRequestParams {
+    pub base_url: String,
+    #[serde(default)]
+    pub api_key: String, // 使用 credential_id 时可省略
+    pub credential_id: Option<String>, // 凭据库中的凭据 ID（优先于 api_key）
+    pub model: String,
+    pub prompt: String,
+    pub input_images: Option<Vec<ImageSource>>, // 多图参考（最多 10 张）
+    pub size: Option<String>,                   // 1K / 2K / 4K 或 "2048x2048"
+    pub aspect_ratio: Option<String>,           // 与 1K/2K/4K 档位组合成具体尺寸
+    pub seed: Option<i64>,
+    pub sequential: Option<bool>,               // 组图生成
+    pub max_images: Option<u32>,                // 组图最大数量（1-15）
+    pub watermark: Option<bool>,
+    pub guidance_scale: Option<f32>,
+    pub output: Option<GenerationOutputOptions>,
+    #[serde(default)]
+    pub fallbacks: Vec<FallbackProvider>, // 备用供应商（按顺序）
+    #[serde(flatten)]
+    pub usage_context: UsageContext,
+}
+
+impl FailoverParams for DoubaoRequestParams {
+    fn fallbacks(&self) -> &[FallbackProvider] {
+        &self.fallbacks
+    }
+
+    fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn use_fallback(&mut self, fallback: &FallbackProvider) {
+        fallback.apply(&mut self.base_url, &mut self.api_key, &mut self.credential_id, &mut self.model);
+    }
+}
+
+// Ark API 请求体
+#[derive(Debug, Serialize)]
+struct ArkImageRequest {
+    model: String,
+    prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sequential_image_generation: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sequential_image_generation_options: Option<SequentialOptions>,
+    response_format: String,
+    watermark: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    guidance_scale: Option<f32>,
+}
+
+#[derive(Debug, Serialize)]
+struct SequentialOptions {
+    max_images: u32,
+}
+
+// Ark API 响应
+#[derive(Debug, Deserialize)]
+struct ArkImageResponse {
+    data: Option<Vec<ArkImageData>>,
+    error: Option<ArkError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArkImageData {
+    url: Option<String>,
+    b64_json: Option<String>,
+    error: Option<ArkError>, // 组图中单张失败
+}
+
+#[derive(Debug, Deserialize)]
+struct ArkError {
+    code: Option<String>,
+    message: String,
+}
+
+/// 解析尺寸：1K/2K/4K 档位结合宽高比换算为具体像素，其他值原样透传
+fn resolve_size(size: Option<&str>, aspect_ratio: Option<&str>) -> Option<String> {
+    let size = size?.trim();
+    let scale = match size.to_ascii_uppercase().as_str() {
+        "1K" => 0.5,
+        "2K" => 1.0,
+        "4K" => 2.0,
+        _ => return Some(size.to_string()),
+    };
+    // 未指定宽高比时直接使用档位，由服务端按提示词决定比例
+    let Some(ratio) = aspect_ratio else {
+        return Some(size.to_ascii_uppercase());
+    };
+    SIZE_PRESETS_2K
+        .iter()
+        .find(|(r, _, _)| *r == ratio)
+        .map(|(_, w, h)| {
+            format!(
+                "{}x{}",
+                (*w as f32 * scale) as u32,
+                (*h as f32 * scale) as u32
+            )
+        })
+        .or_else(|| Some(size.to_ascii_uppercase()))
+}
+
+/// 参考图：http 链接直接透传，其余转为 data URL
+async fn load_reference_images(
+    app: &tauri::AppHandle,
+    images: &[ImageSource],
+) -> Result<Vec<String>, String> {
+    let mut result = Vec::with_capacity(images.len());
+    for (index, source) in images.iter().enumerate() {
+        if let ImageSource::Raw(value) = source {
+            if value.starts_with("http://") || value.starts_with("https://") {
+                result.push(value.clone());
+                continue;
+            }
+        }
+        let prepared = match source.load_bytes(app).await {
+            Ok(bytes) => prepare_image(bytes, UploadTarget::ArkReference, None).await,
+            Err(e) => Err(e),
+        }
+        .map_err(|e| format!("第 {} 张参考图处理失败: {}", index + 1, e))?;
+        result.push(format!(
+            "data:{};base64,{}",
+            prepared.mime_type,
+            prepared.to_base64()
+        ));
+    }
+    Ok(result)
+}
+
+// Tauri 命令：火山方舟图片生成
+#[tauri::command]
+pub async fn doubao_generate_image(app: tauri::AppHandle, params: DoubaoRequestParams) -> DalleResult {
+    rust_log!("[Rust] doubao_generate_image called");
+    run_with_failover(params, |params| generate_image(app.clone(), params)).await
+}
+
+async fn generate_image(app: tauri::AppHandle, mut params: DoubaoRequestParams) -> DalleResult {
+    rust_log!("[Rust] base_url: {}", params.base_url);
+    rust_log!("[Rust] model: {}", params.model);
+
+    if let Err(e) = resolve_api_key(&app, &params.credential_id, &mut params.api_key) {
+        return DalleResult::failure(e);
+    }
+
+    // 组图的实际张数由模型决定，按最大张数预留
+    let sequential = params.sequential.unwrap_or(false);
+    let max_images = params.max_images.unwrap_or(4).clamp(1, 15);
+    let planned = Usage::images(if sequential { max_images } else { 1 });
+    let usage_context = params.usage_context.or_output(params.output.as_ref());
+    let _reservation = match check_budget(&app, &usage_context, "doubao", &params.model, &planned) {
+        Ok(reservation) => reservation,
+        Err(e) => return DalleResult::budget_exceeded(e),
+    };
+
+    let images = match &params.input_images {
+        Some(sources) if !sources.is_empty() => match load_reference_images(&app, sources).await {
+            Ok(images) => Some(images),
+            Err(e) => return DalleResult::failure(e),
+        },
+        _ => None,
+    };
+
+    let size = resolve_size(params.size.as_deref(), params.aspect_ratio.as_deref());
+    let request_body = ArkImageRequest {
+        model: params.model.clone(),
+        prompt: params.prompt.clone(),
+        image: images,
+        size: size.clone(),
+        seed: params.seed,
+        sequential_image_generation: Some(if sequential { "auto" } else { "disabled" }.to_string()),
+        sequential_image_generation_options: sequential.then_some(SequentialOptions { max_images }),
+        response_format: "b64_json".to_string(),
+        watermark: params.watermark.unwrap_or(false),
+        guidance_scale: params.guidance_scale,
+    };
+
+    // 兼容 base_url 已包含 /api/v3 的写法
+    let base_url = params.base_url.trim_end_matches('/');
+    let url = if base_url.ends_with("/api/v3") {
+        format!("{}/images/generations", base_url)
+    } else {
+        format!("{}/api/v3/images/generations", base_url)
+    };
+    rust_log!("[Rust] Request URL: {}, size: {:?}", url, size);
+
+    let client = match Client::builder().timeout(Duration::from_secs(300)).build() {
+        Ok(c) => c,
+        Err(e) => return DalleResult::failure(format!("创建 HTTP 客户端失败: {}", e)),
+    };
+
+    rust_log!("[Rust] Sending Ark image request...");
+    let start_time = std::time::Instant::now();
+    let response = match client
+        .post(&url)
+        .header("Authorization", format!("Bearer {}", params.api_key))
+        .json(&request_body)
+        .send()
+        .await
+    {
+        Ok(r) => {
+            rust_log!("[Rust] Response received in {:?}", start_time.elapsed());
+            r
+        }
+        Err(e) => {
+            rust_log!("[Rust] Request failed: {}", e);
+            let error_msg = if e.is_timeout() {
+                "请求超时，请稍后重试".to_string()
+            } else if e.is_connect() {
+                "无法连接到服务器，请检查网络".to_string()
+            } else {
+                format!("请求失败: {}", e)
+            };
+            return DalleResult::request_failure(FailureKind::from_request_error(&e), error_msg);
+        }
+    };
+
+    let status = response.status();
+    rust_log!("[Rust] HTTP status: {}", status);
+    let response_text = match response.text().await {
+        Ok(t) => t,
+        Err(e) => return DalleResult::failure(format!("获取响应失败: {}", e)),
+    };
+    if !status.is_success() {
+        rust_log!("[Rust] Error response: {}", response_text);
+        return DalleResult::request_failure(FailureKind::Status(status.as_u16()), format!("API 返回错误 ({}): {}", status, response_text));
+    }
+
+    let ark_response: ArkImageResponse = match serde_json::from_str(&response_text) {
+        Ok(r) => r,
+        Err(e) => return DalleResult::failure(format!("解析响应失败: {}", e)),
+    };
+    if let Some(err) = ark_response.error {
+        return DalleResult::failure(match err.code {
+            Some(code) => format!("{}: {}", code, err.message),
+            None => err.message,
+        });
+    }
+
+    let data = ark_response.data.unwrap_or_default();
+    rust_log!("[Rust] Ark returned {} images", data.len());
+
+    // 按成功生成的张数计费（组图中失败的图片不计）
+    let generated = data.iter().filter(|item| item.error.is_none()).count();
+    record_usage(&app, &usage_context, "doubao", &params.model, &Usage::images(generated as u32));
+
+    let mut images = Vec::with_capacity(data.len());
+    for (index, item) in data.into_iter().enumerate() {
+        let image_data = match (item.b64_json, &item.url, item.error) {
+            (_, _, Some(err)) => Err(err.message),
+            (Some(b64), _, None) => Ok(b64),
+            (None, Some(url), None) => download_image_as_base64(&client, url)
+                .await
+                .map_err(|e| format!("图片生成成功但下载失败: {}", e)),
+            (None, None, None) => Err("API 未返回图片数据或 URL".to_string()),
+        };
+
+        let image = match image_data {
+            Ok(b64) => {
+                let metadata = params.output.as_ref().map(|output| ImageMetadata {
+                    prompt: Some(params.prompt.clone()),
+                    input_images: input_infos(
+                        &app,
+                        params.input_images.as_deref().unwrap_or_default(),
+                        output.input_labels.as_deref(),
+                    ),
+                    provider: Some("doubao".to_string()),
+                    model: Some(params.model.clone()),
+                    aspect_ratio: params.aspect_ratio.clone(),
+                    image_size: size.clone(),
+                    seed: params.seed,
+                    ..Default::default()
+                });
+                finish_generated_image(
+                    &app,
+                    params.output.as_ref(),
+                    metadata,
+                    DalleImage {
+                        index,
+                        image_data: Some(b64),
+                        image_url: item.url,
+                        revised_prompt: None,
+                        error: None,
+                        image: None,
+                    },
+                )
+            }
+            Err(e) => {
+                rust_log!("[Rust] Image {} failed: {}", index + 1, e);
+                DalleImage {
+                    index,
+                    image_data: None,
+                    image_url: item.url,
+                    revised_prompt: None,
+                    error: Some(e),
+                    image: None,
+                }
+            }
+        };
+        images.push(image);
+    }
+
+    DalleResult::from_images(images)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_size_presets() {
+        assert_eq!(resolve_size(Some("2K"), Some("16:9")).as_deref(), Some("2560x1440"));
+        assert_eq!(resolve_size(Some("4k"), Some("1:1")).as_deref(), Some("4096x4096"));
+        assert_eq!(resolve_size(Some("1K"), Some("3:4")).as_deref(), Some("864x1152"));
+        assert_eq!(resolve_size(Some("2K"), None).as_deref(), Some("2K"));
+        assert_eq!(resolve_size(Some("1920x1080"), Some("1:1")).as_deref(), Some("1920x1080"));
+        assert_eq!(resolve_size(None, Some("1:1")), None);
+    }
+}
@@ -0,0 +1,198 @@
+// 端点构建
+// 统一处理各模块的 Base URL 拼接：去掉末尾的 / 和已包含的版本路径（避免 /v1/v1），
+// 并按供应商类型生成 OpenAI 兼容接口的 URL 和鉴权请求头
+// 类型可由前端指定，未指定时按域名识别：
+// - OpenAI：{base}/v1/{path}，Bearer 鉴权
+// - Azure OpenAI：{resource}/openai/deployments/{部署名}/{path}?api-version=...，api-key 请求头
+// - OpenRouter：https://openrouter.ai/api/v1/{path}
+// - 中转：与 OpenAI 相同
+
+use serde::{Deserialize, Serialize};
+
+// Azure OpenAI 默认 API 版本（可在 Base URL 中通过 ?api-version= 指定）
+const AZURE_DEFAULT_API_VERSION: &str = "2024-10-21";
+
+// Base URL 末尾可省略的版本路径
+const VERSION_SEGMENTS: &[&str] = &["v1", "v1beta", "v1alpha"];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ApiFlavor {
+    OpenAI,
+    Azure,
+    OpenRouter,
+    Relay,
+}
+
+impl ApiFlavor {
+    /// 按域名识别供应商类型
+    pub fn detect(base_url: &str) -> Self {
+        let host = reqwest::Url::parse(base_url.trim())
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_lowercase))
+            .unwrap_or_default();
+        if host.ends_with(".openai.azure.com")
+            || host.ends_with(".cognitiveservices.azure.com")
+            || host.ends_with(".services.ai.azure.com")
+        {
+            ApiFlavor::Azure
+        } else if host == "openrouter.ai" {
+            ApiFlavor::OpenRouter
+        } else if host == "api.openai.com" {
+            ApiFlavor::OpenAI
+        } else {
+            ApiFlavor::Relay
+        }
+    }
+}
+
+/// 去掉 Base URL 末尾的 / 和版本路径（如 https://relay.example.com/v1/ → https://relay.example.com）
+pub fn api_base(base_url: &str) -> &str {
+    let base = base_url.trim().trim_end_matches('/');
+    match base.rsplit_once('/') {
+        Some((rest, last)) if VERSION_SEGMENTS.contains(&last) && !rest.ends_with('/') => rest,
+        _ => base,
+    }
+}
+
+// OpenAI 兼容接口的端点
+#[derive(Debug, Clone)]
+pub struct OpenAIEndpoint {
+    pub flavor: ApiFlavor,
+    base: String,
+    api_version: Option<String>, // 仅 Azure 使用
+}
+
+impl OpenAIEndpoint {
+    pub fn new(base_url: &str, flavor: Option<ApiFlavor>) -> Self {
+        let flavor = flavor.unwrap_or_else(|| ApiFlavor::detect(base_url));
+        let (base_url, query) = base_url.trim().split_once('?').unwrap_or((base_url.trim(), ""));
+        match flavor {
+            ApiFlavor::Azure => {
+                // 只保留资源地址，兼容填写了 /openai 或 /openai/deployments/xxx 的 Base URL
+                // （只在路径中查找，资源名本身可能以 openai 开头）
+                let base = match reqwest::Url::parse(base_url) {
+                    Ok(mut url) => {
+                        let path = url.path().to_string();
+                        url.set_path(path.find("/openai").map_or(&path, |index| &path[..index]));
+                        url.as_str().trim_end_matches('/').to_string()
+                    }
+                    Err(_) => base_url.trim_end_matches('/').to_string(),
+                };
+                let api_version = query
+                    .split('&')
+                    .find_map(|pair| pair.strip_prefix("api-version="))
+                    .filter(|version| !version.is_empty())
+                    .unwrap_or(AZURE_DEFAULT_API_VERSION);
+                OpenAIEndpoint {
+                    flavor,
+                    base,
+                    api_version: Some(api_version.to_string()),
+                }
+            }
+            ApiFlavor::OpenRouter => {
+                let base = api_base(base_url);
+                let base = if base.ends_with("/api") { base.to_string() } else { format!("{}/api", base) };
+                OpenAIEndpoint { flavor, base, api_version: None }
+            }
+            ApiFlavor::OpenAI | ApiFlavor::Relay => OpenAIEndpoint {
+                flavor,
+                base: api_base(base_url).to_string(),
+                api_version: None,
+            },
+        }
+    }
+
+    /// 接口 URL，path 为 /v1 之后的部分（如 chat/completions）；
+    /// Azure 下 deployment 为部署名（通常与模型名一致），为空时使用资源级接口
+    pub fn url(&self, path: &str, deployment: Option<&str>) -> String {
+        let path = path.trim_start_matches('/');
+        match (&self.api_version, deployment) {
+            (Some(version), Some(deployment)) => format!(
+                "{}/openai/deployments/{}/{}?api-version={}",
+                self.base, deployment, path, version
+            ),
+            (Some(version), None) => format!("{}/openai/{}?api-version={}", self.base, path, version),
+            (None, _) => format!("{}/v1/{}", self.base, path),
+        }
+    }
+
+    /// 添加鉴权请求头（Azure 使用 api-key，其余使用 Bearer）
+    pub fn authorize(&self, request: reqwest::RequestBuilder, api_key: &str) -> reqwest::RequestBuilder {
+        match self.flavor {
+            ApiFlavor::Azure => request.header("api-key", api_key),
+            _ => request.header("Authorization", format!("Bearer {}", api_key)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_base() {
+        assert_eq!(api_base("https://relay.example.com"), "https://relay.example.com");
+        assert_eq!(api_base("https://relay.example.com/"), "https://relay.example.com");
+        assert_eq!(api_base("https://relay.example.com/v1"), "https://relay.example.com");
+        assert_eq!(api_base("https://relay.example.com/v1/"), "https://relay.example.com");
+        assert_eq!(api_base("https://relay.example.com/proxy/v1beta"), "https://relay.example.com/proxy");
+        assert_eq!(api_base("https://relay.example.com/openai"), "https://relay.example.com/openai");
+        assert_eq!(api_base("https://v1"), "https://v1");
+    }
+
+    #[test]
+    fn test_openai_endpoint_matrix() {
+        let cases: &[(&str, Option<ApiFlavor>, Option<&str>, &str)] = &[
+            // OpenAI 官方与中转
+            ("https://api.openai.com", None, Some("gpt-5"), "https://api.openai.com/v1/chat/completions"),
+            ("https://api.openai.com/v1", None, Some("gpt-5"), "https://api.openai.com/v1/chat/completions"),
+            ("https://relay.example.com/v1/", None, None, "https://relay.example.com/v1/chat/completions"),
+            ("https://relay.example.com/openai", None, None, "https://relay.example.com/openai/v1/chat/completions"),
+            // OpenRouter
+            ("https://openrouter.ai", None, None, "https://openrouter.ai/api/v1/chat/completions"),
+            ("https://openrouter.ai/api", None, None, "https://openrouter.ai/api/v1/chat/completions"),
+            ("https://openrouter.ai/api/v1", None, None, "https://openrouter.ai/api/v1/chat/completions"),
+            // Azure OpenAI
+            (
+                "https://contoso.openai.azure.com",
+                None,
+                Some("gpt-4o"),
+                "https://contoso.openai.azure.com/openai/deployments/gpt-4o/chat/completions?api-version=2024-10-21",
+            ),
+            (
+                "https://contoso.openai.azure.com/openai/deployments/other/?api-version=2025-04-01-preview",
+                None,
+                Some("gpt-4o"),
+                "https://contoso.openai.azure.com/openai/deployments/gpt-4o/chat/completions?api-version=2025-04-01-preview",
+            ),
+            (
+                "https://openai-prod.openai.azure.com/openai/",
+                None,
+                Some("gpt-4o"),
+                "https://openai-prod.openai.azure.com/openai/deployments/gpt-4o/chat/completions?api-version=2024-10-21",
+            ),
+            (
+                "https://openai-prod.openai.azure.com",
+                None,
+                Some("gpt-4o"),
+                "https://openai-prod.openai.azure.com/openai/deployments/gpt-4o/chat/completions?api-version=2024-10-21",
+            ),
+            (
+                "https://gateway.example.com",
+                Some(ApiFlavor::Azure),
+                Some("gpt-4o"),
+                "https://gateway.example.com/openai/deployments/gpt-4o/chat/completions?api-version=2024-10-21",
+            ),
+        ];
+        for (base_url, flavor, deployment, expected) in cases {
+            let endpoint = OpenAIEndpoint::new(base_url, *flavor);
+            assert_eq!(endpoint.url("chat/completions", *deployment), *expected, "base_url: {}", base_url);
+        }
+
+        let azure = OpenAIEndpoint::new("https://contoso.openai.azure.com", None);
+        assert_eq!(azure.url("models", None), "https://contoso.openai.azure.com/openai/models?api-version=2024-10-21");
+        assert_eq!(ApiFlavor::detect("https://contoso.cognitiveservices.azure.com"), ApiFlavor::Azure);
+        assert_eq!(ApiFlavor::detect("not a url"), ApiFlavor::Relay);
+    }
+}
@@ -0,0 +1,190 @@
+// 供应商故障转移
+// 节点的供应商可以是有序链（如中转 A → 中转 B → 官方 API）：前一个供应商出现连接失败、超时、
+// 5xx、429 或 402 时依次改用 fallbacks 中的供应商；其他状态码、响应内容错误和预算超限不切换。
+// 配置了备用供应商时，结果中的 servedBy 记录实际完成请求的供应商
+
+use crate::endpoint::ApiFlavor;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+// ==================== 数据结构 ====================
+
+// 请求失败的类型（由各模块在发送请求处标注，决定是否切换供应商）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    Status(u16), // 供应商返回的 HTTP 错误状态码
+    Connect,     // 请求未送达（连接、TLS、代理等）
+    Timeout,
+}
+
+// 带类型的请求错误（无法判断类型时 kind 为 None，如请求体过大）
+#[derive(Debug)]
+pub(crate) struct RequestError {
+    pub kind: Option<FailureKind>,
+    pub message: String,
+}
+
+impl From<String> for RequestError {
+    fn from(message: String) -> Self {
+        RequestError { kind: None, message }
+    }
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+// 备用供应商（前端按顺序传入）
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FallbackProvider {
+    pub name: Option<String>, // 供应商名称（用于日志和 servedBy）
+    pub base_url: String,
+    pub api_flavor: Option<ApiFlavor>,
+    #[serde(default)]
+    pub api_key: String, // 使用 credential_id 时可省略
+    pub credential_id: Option<String>, // 凭据库中的凭据 ID（优先于 api_key）
+    pub model: Option<String>, // 未设置时沿用主供应商的模型
+}
+
+impl FallbackProvider {
+    /// 替换请求参数中的地址、密钥和模型
+    pub(crate) fn apply(
+        &self,
+        base_url: &mut String,
+        api_key: &mut String,
+        credential_id: &mut Option<String>,
+        model: &mut String,
+    ) {
+        *base_url = self.base_url.clone();
+        *api_key = self.api_key.clone();
+        *credential_id = self.credential_id.clone();
+        if let Some(fallback_model) = &self.model {
+            *model = fallback_model.clone();
+        }
+    }
+}
+
+// 实际完成请求的供应商
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ServedBy {
+    pub index: usize, // 0 为主供应商，1 起为 fallbacks 中的序号 + 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub base_url: String,
+    pub model: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fallback_errors: Vec<String>, // 之前各供应商的失败原因
+}
+
+// 支持故障转移的请求参数
+pub(crate) trait FailoverParams: Clone {
+    fn fallbacks(&self) -> &[FallbackProvider];
+    fn base_url(&self) -> &str;
+    fn model(&self) -> &str;
+    /// 切换到备用供应商（替换地址、密钥和模型，其余参数不变）
+    fn use_fallback(&mut self, fallback: &FallbackProvider);
+}
+
+// 支持故障转移的结果
+pub(crate) trait FailoverResult {
+    /// 请求失败且带有失败类型时返回类型和错误信息（预算超限、内容拦截等返回 None）
+    fn failover_error(&self) -> Option<(FailureKind, &str)>;
+    fn set_served_by(&mut self, served_by: ServedBy);
+}
+
+// ==================== 错误分类 ====================
+
+impl FailureKind {
+    /// reqwest 发送请求失败时的类型
+    pub(crate) fn from_request_error(error: &reqwest::Error) -> Self {
+        if error.is_timeout() {
+            FailureKind::Timeout
+        } else if let Some(status) = error.status() {
+            FailureKind::Status(status.as_u16())
+        } else {
+            FailureKind::Connect
+        }
+    }
+
+    /// 是否应切换到下一个供应商：连接失败、超时、5xx、429（限流）和 402（余额不足）
+    pub(crate) fn should_fall_through(self) -> bool {
+        match self {
+            FailureKind::Status(status) => status >= 500 || status == 429 || status == 402,
+            FailureKind::Connect | FailureKind::Timeout => true,
+        }
+    }
+}
+
+// ==================== 执行 ====================
+
+/// 依次使用主供应商和备用供应商执行请求，返回第一个成功（或不应切换）的结果
+pub(crate) async fn run_with_failover<P, R, F, Fut>(params: P, mut call: F) -> R
+where
+    P: FailoverParams,
+    R: FailoverResult,
+    F: FnMut(P) -> Fut,
+    Fut: Future<Output = R>,
+{
+    let fallbacks = params.fallbacks().to_vec();
+    let mut current = params.clone();
+    let mut name: Option<String> = None;
+    let mut fallback_errors = Vec::new();
+
+    for index in 0..=fallbacks.len() {
+        let base_url = current.base_url().to_string();
+        let model = current.model().to_string();
+        let mut result = call(current).await;
+
+        let next = fallbacks.get(index);
+        let error = result.failover_error().filter(|(kind, _)| kind.should_fall_through());
+        if let (Some((_, error)), Some(next)) = (error, next) {
+            rust_log!(
+                "[Rust] Provider #{} ({}) failed, falling back to {}: {}",
+                index,
+                base_url,
+                next.name.as_deref().unwrap_or(&next.base_url),
+                error
+            );
+            fallback_errors.push(format!("{}: {}", name.as_deref().unwrap_or(&base_url), error));
+            current = params.clone();
+            current.use_fallback(next);
+            name = next.name.clone();
+            continue;
+        }
+
+        if !fallbacks.is_empty() {
+            result.set_served_by(ServedBy {
+                index,
+                name,
+                base_url,
+                model,
+                fallback_errors,
+            });
+        }
+        return result;
+    }
+    unreachable!("最后一个供应商的结果总会返回")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_fall_through() {
+        assert!(FailureKind::Connect.should_fall_through());
+        assert!(FailureKind::Timeout.should_fall_through());
+        assert!(FailureKind::Status(502).should_fall_through());
+        assert!(FailureKind::Status(429).should_fall_through());
+        assert!(FailureKind::Status(402).should_fall_through());
+
+        assert!(!FailureKind::Status(400).should_fall_through());
+        assert!(!FailureKind::Status(401).should_fall_through());
+        assert!(!FailureKind::Status(403).should_fall_through());
+        assert!(!FailureKind::Status(404).should_fall_through());
+    }
+}
@@ -0,0 +1,437 @@
+// Flux（BFL）异步图片生成
+// 与视频任务相同的轮询方式：flux_create_task 提交任务，前端定时调用 flux_get_status，
+// 任务完成后 flux_get_content 下载结果图片，避免长时间渲染占用单个 HTTP 连接
+
+use crate::budget::{check_budget, BudgetExceeded};
+use crate::endpoint::api_base;
+use crate::dalle::{download_image_as_base64, finish_generated_image, DalleImage, DalleResult};
+use crate::image_source::{input_infos, ImageSource};
+use crate::storage::{GenerationOutputOptions, ImageMetadata};
+use crate::usage::{record_usage, Usage, UsageContext};
+use crate::vault::resolve_api_key;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// BFL 官方域名（提交任务返回的 polling_url 可能指向区域端点，如 api.eu1.bfl.ai）
+const BFL_DOMAINS: &[&str] = &["bfl.ai", "bfl.ml"];
+
+// ==================== 数据结构 ====================
+
+// 创建任务参数
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FluxCreateParams {
+    pub base_url: String,
+    #[serde(default)]
+    pub api_key: String, // 使用 credential_id 时可省略
+    pub credential_id: Option<String>, // 凭据库中的凭据 ID（优先于 api_key）
+    pub model: String, // 如 flux-pro-1.1、flux-pro-1.1-ultra、flux-kontext-pro
+    pub prompt: String,
+    pub input_images: Option<Vec<ImageSource>>, // kontext 模型为 input_image，其余为 image_prompt
+    pub aspect_ratio: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub seed: Option<i64>,
+    pub safety_tolerance: Option<u8>, // 0（最严格）- 6
+    pub raw: Option<bool>,            // ultra 模型的 raw 模式
+    pub image_prompt_strength: Option<f32>,
+    pub prompt_upsampling: Option<bool>,
+    pub output_format: Option<String>, // jpeg / png
+    #[serde(flatten)]
+    pub usage_context: UsageContext,
+}
+
+// BFL API 请求体
+#[derive(Debug, Serialize)]
+struct FluxApiRequest {
+    prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    aspect_ratio: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    safety_tolerance: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_prompt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_prompt_strength: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_image: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prompt_upsampling: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_format: Option<String>,
+}
+
+// 任务响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FluxTaskResult {
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub polling_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>, // BFL 原始状态：Pending / Ready / Request Moderated / Content Moderated / Error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result_url: Option<String>, // Ready 时的图片地址（签名 URL，有效期较短）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_exceeded: Option<BudgetExceeded>, // 超出预算时未提交任务
+}
+
+impl FluxTaskResult {
+    fn failure(error: String) -> Self {
+        FluxTaskResult {
+            success: false,
+            task_id: None,
+            polling_url: None,
+            status: None,
+            result_url: None,
+            seed: None,
+            error: Some(error),
+            budget_exceeded: None,
+        }
+    }
+
+    fn budget_exceeded(exceeded: BudgetExceeded) -> Self {
+        FluxTaskResult {
+            budget_exceeded: Some(exceeded.clone()),
+            ..FluxTaskResult::failure(exceeded.to_string())
+        }
+    }
+}
+
+// 查询状态参数
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FluxStatusParams {
+    pub base_url: String,
+    #[serde(default)]
+    pub api_key: String, // 使用 credential_id 时可省略
+    pub credential_id: Option<String>, // 凭据库中的凭据 ID（优先于 api_key）
+    pub task_id: String,
+    pub polling_url: Option<String>, // 优先使用提交时返回的 polling_url（区域端点）
+}
+
+// 下载结果参数
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FluxContentParams {
+    pub result_url: String,
+    // 以下用于保存元数据
+    pub prompt: Option<String>,
+    pub model: Option<String>,
+    pub seed: Option<i64>,
+    pub aspect_ratio: Option<String>,
+    pub input_images: Option<Vec<ImageSource>>,
+    pub output: Option<GenerationOutputOptions>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FluxSubmitResponse {
+    id: Option<String>,
+    polling_url: Option<String>,
+    detail: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FluxResultResponse {
+    status: Option<String>,
+    result: Option<FluxResultData>,
+    details: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FluxResultData {
+    sample: Option<String>,
+    seed: Option<i64>,
+}
+
+fn build_client(timeout_secs: u64) -> Result<Client, String> {
+    Client::builder()
+        .timeout(Duration::from_secs(timeout_secs))
+        .build()
+        .map_err(|e| format!("创建 HTTP 客户端失败: {}", e))
+}
+
+fn request_error(e: reqwest::Error) -> String {
+    if e.is_timeout() {
+        "请求超时".to_string()
+    } else if e.is_connect() {
+        "无法连接到服务器".to_string()
+    } else {
+        format!("请求失败: {}", e)
+    }
+}
+
+// ==================== 创建任务 ====================
+
+#[tauri::command]
+pub async fn flux_create_task(app: tauri::AppHandle, mut params: FluxCreateParams) -> FluxTaskResult {
+    rust_log!("[Rust] flux_create_task called");
+    rust_log!("[Rust] base_url: {}", params.base_url);
+    rust_log!("[Rust] model: {}", params.model);
+
+    if let Err(e) = resolve_api_key(&app, &params.credential_id, &mut params.api_key) {
+        return FluxTaskResult::failure(e);
+    }
+
+    let _reservation = match check_budget(&app, &params.usage_context, "bfl", &params.model, &Usage::images(1)) {
+        Ok(reservation) => reservation,
+        Err(e) => return FluxTaskResult::budget_exceeded(e),
+    };
+
+    // kontext 系列直接编辑输入图片，其余模型作为图片提示（Redux）
+    let is_kontext = params.model.contains("kontext");
+    let mut input_image = None;
+    let mut image_prompt = None;
+    if let Some(first) = params.input_images.as_ref().and_then(|images| images.first()) {
+        rust_log!("[Rust] Adding input image: {}", first.describe());
+        match first.load_base64(&app).await {
+            Ok(data) if is_kontext => input_image = Some(data),
+            Ok(data) => image_prompt = Some(data),
+            Err(e) => return FluxTaskResult::failure(format!("输入图片读取失败: {}", e)),
+        }
+    }
+
+    let request_body = FluxApiRequest {
+        prompt: params.prompt.clone(),
+        aspect_ratio: params.aspect_ratio.clone(),
+        width: params.width,
+        height: params.height,
+        seed: params.seed,
+        safety_tolerance: params.safety_tolerance,
+        raw: params.raw,
+        image_prompt_strength: image_prompt.as_ref().and(params.image_prompt_strength),
+        image_prompt,
+        input_image,
+        prompt_upsampling: params.prompt_upsampling,
+        output_format: params.output_format.clone(),
+    };
+
+    let url = format!("{}/v1/{}", api_base(&params.base_url), params.model);
+    rust_log!("[Rust] Request URL: {}", url);
+
+    let client = match build_client(60) {
+        Ok(c) => c,
+        Err(e) => return FluxTaskResult::failure(e),
+    };
+
+    let response = match client
+        .post(&url)
+        .header("x-key", &params.api_key)
+        .json(&request_body)
+        .send()
+        .await
+    {
+        Ok(r) => r,
+        Err(e) => return FluxTaskResult::failure(request_error(e)),
+    };
+
+    let status = response.status();
+    let response_text = match response.text().await {
+        Ok(t) => t,
+        Err(e) => return FluxTaskResult::failure(format!("获取响应失败: {}", e)),
+    };
+    rust_log!("[Rust] Flux submit response ({}): {}", status, response_text);
+
+    if !status.is_success() {
+        return FluxTaskResult::failure(format!("API 返回错误 ({}): {}", status, response_text));
+    }
+
+    let submit: FluxSubmitResponse = match serde_json::from_str(&response_text) {
+        Ok(r) => r,
+        Err(e) => return FluxTaskResult::failure(format!("解析响应失败: {}", e)),
+    };
+
+    match submit.id {
+        Some(id) => {
+            // BFL 在提交时扣除额度，按提交成功记录一张图片
+            record_usage(&app, &params.usage_context, "bfl", &params.model, &Usage::images(1));
+            FluxTaskResult {
+                success: true,
+                task_id: Some(id),
+                polling_url: submit.polling_url,
+                status: Some("Pending".to_string()),
+                result_url: None,
+                seed: params.seed,
+                error: None,
+                budget_exceeded: None,
+            }
+        }
+        None => FluxTaskResult::failure(format!(
+            "API 未返回任务 ID: {}",
+            submit.detail.map(|d| d.to_string()).unwrap_or_default()
+        )),
+    }
+}
+
+// ==================== 查询任务状态 ====================
+
+/// 校验 polling_url：https 且主机为 BFL 域名，或与 Base URL 同一主机（中转服务）
+fn trusted_polling_url(polling_url: &str, base_url: &str) -> Option<reqwest::Url> {
+    let url = reqwest::Url::parse(polling_url).ok()?;
+    let host = url.host_str()?.to_ascii_lowercase();
+    let base_host = reqwest::Url::parse(base_url.trim())
+        .ok()
+        .and_then(|base| base.host_str().map(str::to_ascii_lowercase));
+
+    let bfl = url.scheme() == "https"
+        && BFL_DOMAINS
+            .iter()
+            .any(|domain| host == *domain || host.ends_with(&format!(".{}", domain)));
+    let same_host = base_host.as_deref() == Some(host.as_str());
+    if bfl || same_host {
+        Some(url)
+    } else {
+        rust_log!("[Rust] Ignoring untrusted polling_url host: {}", host);
+        None
+    }
+}
+
+#[tauri::command]
+pub async fn flux_get_status(app: tauri::AppHandle, mut params: FluxStatusParams) -> FluxTaskResult {
+    rust_log!("[Rust] flux_get_status called, task_id: {}", params.task_id);
+
+    if let Err(e) = resolve_api_key(&app, &params.credential_id, &mut params.api_key) {
+        return FluxTaskResult::failure(e);
+    }
+
+    let client = match build_client(30) {
+        Ok(c) => c,
+        Err(e) => return FluxTaskResult::failure(e),
+    };
+
+    // 请求携带 x-key，polling_url 只接受 BFL 域名或 Base URL 所在主机，其余按 task_id 查询
+    let polling_url = params
+        .polling_url
+        .as_deref()
+        .and_then(|url| trusted_polling_url(url, &params.base_url));
+    let request = match polling_url {
+        Some(polling_url) => client.get(polling_url),
+        None => client
+            .get(format!("{}/v1/get_result", api_base(&params.base_url)))
+            .query(&[("id", &params.task_id)]),
+    };
+
+    let response = match request.header("x-key", &params.api_key).send().await {
+        Ok(r) => r,
+        Err(e) => return FluxTaskResult::failure(request_error(e)),
+    };
+
+    let status = response.status();
+    let response_text = match response.text().await {
+        Ok(t) => t,
+        Err(e) => return FluxTaskResult::failure(format!("获取响应失败: {}", e)),
+    };
+
+    if !status.is_success() {
+        return FluxTaskResult::failure(format!("API 返回错误 ({}): {}", status, response_text));
+    }
+
+    let result: FluxResultResponse = match serde_json::from_str(&response_text) {
+        Ok(r) => r,
+        Err(e) => return FluxTaskResult::failure(format!("解析响应失败: {}", e)),
+    };
+
+    let task_status = result.status.unwrap_or_else(|| "Pending".to_string());
+    rust_log!("[Rust] Flux task status: {}", task_status);
+
+    // 被审核拦截或出错时返回失败
+    let error = match task_status.as_str() {
+        "Ready" | "Pending" => None,
+        "Request Moderated" => Some("提示词未通过审核".to_string()),
+        "Content Moderated" => Some("生成内容未通过审核".to_string()),
+        "Task not found" => Some("任务不存在或已过期".to_string()),
+        other => Some(format!(
+            "任务失败 ({}){}",
+            other,
+            result.details.map(|d| format!(": {}", d)).unwrap_or_default()
+        )),
+    };
+
+    FluxTaskResult {
+        success: error.is_none(),
+        task_id: Some(params.task_id),
+        polling_url: params.polling_url,
+        status: Some(task_status),
+        result_url: result.result.as_ref().and_then(|r| r.sample.clone()),
+        seed: result.result.and_then(|r| r.seed),
+        error,
+        budget_exceeded: None,
+    }
+}
+
+// ==================== 下载结果 ====================
+
+#[tauri::command]
+pub async fn flux_get_content(app: tauri::AppHandle, params: FluxContentParams) -> DalleResult {
+    rust_log!("[Rust] flux_get_content called");
+
+    let client = match build_client(120) {
+        Ok(c) => c,
+        Err(e) => return DalleResult::failure(e),
+    };
+
+    let image_data = match download_image_as_base64(&client, &params.result_url).await {
+        Ok(data) => data,
+        Err(e) => return DalleResult::failure(e),
+    };
+
+    let metadata = params.output.as_ref().map(|output| ImageMetadata {
+        prompt: params.prompt.clone(),
+        input_images: input_infos(
+            &app,
+            params.input_images.as_deref().unwrap_or_default(),
+            output.input_labels.as_deref(),
+        ),
+        provider: Some("bfl".to_string()),
+        model: params.model.clone(),
+        aspect_ratio: params.aspect_ratio.clone(),
+        seed: params.seed,
+        ..Default::default()
+    });
+
+    let image = finish_generated_image(
+        &app,
+        params.output.as_ref(),
+        metadata,
+        DalleImage {
+            index: 0,
+            image_data: Some(image_data),
+            image_url: Some(params.result_url.clone()),
+            revised_prompt: None,
+            error: None,
+            image: None,
+        },
+    );
+    DalleResult::from_images(vec![image])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trusted_polling_url() {
+        let base = "https://api.bfl.ai";
+        assert!(trusted_polling_url("https://api.eu1.bfl.ai/v1/get_result?id=1", base).is_some());
+        assert!(trusted_polling_url("https://relay.example.com/v1/get_result?id=1", "https://relay.example.com/v1").is_some());
+        assert!(trusted_polling_url("http://api.bfl.ai/v1/get_result?id=1", "https://relay.example.com").is_none());
+        assert!(trusted_polling_url("https://evil.example.com/collect", base).is_none());
+        assert!(trusted_polling_url("https://bfl.ai.evil.com/collect", base).is_none());
+        assert!(trusted_polling_url("not a url", base).is_none());
+    }
+}
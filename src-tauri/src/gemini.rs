@@ -1,7 +1,23 @@
+use crate::budget::{check_budget, BudgetExceeded};
+use crate::endpoint::api_base;
+use crate::failover::{run_with_failover, FailoverParams, FailoverResult, FailureKind, FallbackProvider, RequestError, ServedBy};
+use crate::image_preprocess::{decode_base64_image, prepare_image_within, UploadTarget};
+use crate::image_source::{input_infos, ImageSource};
+use crate::llm::{
+    build_conversation, chat_with_failover, new_tool_call_id, ChatRole, LLMRequestParams, LLMResult, ToolCall, ToolChoice,
+};
+use crate::usage::{record_usage, Usage, UsageContext};
+use crate::vault::resolve_api_key;
+use crate::storage::{save_generated_image, GenerationOutputOptions, ImageInfo, ImageMetadata};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
+// Gemini 单次请求体上限 20MB（inline 图片为 base64，计入请求体）
+const MAX_REQUEST_BYTES: usize = 20 * 1024 * 1024;
+// 为提示词、系统指令等预留的请求体空间
+const REQUEST_OVERHEAD_BYTES: usize = 1024 * 1024;
+
 // Gemini API 请求结构
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -9,10 +25,24 @@ pub struct GeminiRequest {
     pub contents: Vec<Content>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub generation_config: Option<GenerationConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub safety_settings: Option<Vec<SafetySetting>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_instruction: Option<Content>,
+}
+
+// 安全设置（如 category: HARM_CATEGORY_HARASSMENT, threshold: BLOCK_ONLY_HIGH）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SafetySetting {
+    pub category: String,
+    pub threshold: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Content {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>, // user / model（多轮对话时使用）
     pub parts: Vec<Part>,
 }
 
@@ -21,6 +51,31 @@ pub struct Content {
 pub enum Part {
     Text { text: String },
     InlineData { inline_data: InlineData },
+    #[serde(rename_all = "camelCase")]
+    FunctionCall {
+        function_call: FunctionCall,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        thought_signature: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    FunctionResponse { function_response: FunctionResponse },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FunctionCall {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(default)]
+    pub args: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FunctionResponse {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub name: String,
+    pub response: serde_json::Value, // 必须为 JSON 对象
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -37,6 +92,14 @@ pub struct GenerationConfig {
     pub response_modalities: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub image_config: Option<ImageConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub candidate_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -50,14 +113,83 @@ pub struct ImageConfig {
 
 // Gemini API 响应结构
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct GeminiResponse {
     pub candidates: Option<Vec<Candidate>>,
     pub error: Option<GeminiError>,
+    pub prompt_feedback: Option<PromptFeedback>,
+    pub usage_metadata: Option<UsageMetadata>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Candidate {
     pub content: Option<CandidateContent>,
+    pub finish_reason: Option<String>,
+    pub safety_ratings: Option<Vec<SafetyRating>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptFeedback {
+    pub block_reason: Option<String>,
+    pub block_reason_message: Option<String>,
+    pub safety_ratings: Option<Vec<SafetyRating>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SafetyRating {
+    pub category: String,
+    pub probability: Option<String>,
+    #[serde(default)]
+    pub blocked: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageMetadata {
+    pub prompt_token_count: Option<u32>,
+    pub candidates_token_count: Option<u32>,
+    pub thoughts_token_count: Option<u32>,
+    pub total_token_count: Option<u32>,
+}
+
+impl UsageMetadata {
+    /// 转为台账用量（思考 token 按输出计费）
+    pub(crate) fn to_usage(&self) -> Usage {
+        Usage::tokens(
+            self.prompt_token_count.unwrap_or(0) as u64,
+            (self.candidates_token_count.unwrap_or(0) + self.thoughts_token_count.unwrap_or(0)) as u64,
+        )
+    }
+}
+
+/// 图片生成前预留的用量：Gemini 图片模型按输出 token 计费，
+/// 每张约 1290 token（gemini-3-pro-image 1K/2K 约 1120，4K 约 2000）
+pub(crate) fn planned_image_usage(model: &str, image_size: Option<&str>, count: u32) -> Usage {
+    let per_image: u64 = if model.starts_with("gemini-3-pro-image") {
+        if image_size.is_some_and(|size| size.eq_ignore_ascii_case("4K")) {
+            2000
+        } else {
+            1120
+        }
+    } else {
+        1290
+    };
+    Usage {
+        output_tokens: per_image * count as u64,
+        images: count,
+        ..Usage::default()
+    }
+}
+
+/// 实际用量：响应中的 token 计数加上返回的图片张数
+pub(crate) fn generated_image_usage(response: &GeminiResponse, images: usize) -> Usage {
+    Usage {
+        images: images as u32,
+        ..response.usage_metadata.as_ref().map(UsageMetadata::to_usage).unwrap_or_default()
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -70,6 +202,9 @@ pub struct CandidateContent {
 pub struct ResponsePart {
     pub text: Option<String>,
     pub inline_data: Option<InlineData>,
+    pub function_call: Option<FunctionCall>,
+    pub thought_signature: Option<String>,
+    pub thought: Option<bool>, // 思考过程（包括中间草图），不作为生成结果
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -79,16 +214,80 @@ pub struct GeminiError {
 }
 
 // 前端调用的参数
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct GeminiRequestParams {
     pub base_url: String,
-    pub api_key: String,
+    #[serde(default)]
+    pub api_key: String, // 使用 credential_id 时可省略
+    pub credential_id: Option<String>, // 凭据库中的凭据 ID（优先于 api_key）
     pub model: String,
     pub prompt: String,
-    pub input_images: Option<Vec<String>>, // base64 图片数据
+    pub input_images: Option<Vec<ImageSource>>, // 输入图片（base64、存储路径或图片 ID）
     pub aspect_ratio: Option<String>,
     pub image_size: Option<String>,
+    pub candidate_count: Option<u32>, // 候选数量，用于一次请求生成多张变体
+    #[serde(flatten)]
+    pub tuning: GeminiTuning,
+    pub output: Option<GenerationOutputOptions>, // 指定时直接保存生成结果并返回图片信息
+    #[serde(default)]
+    pub fallbacks: Vec<FallbackProvider>, // 备用供应商（按顺序）
+    #[serde(flatten)]
+    pub usage_context: UsageContext,
+}
+
+impl FailoverParams for GeminiRequestParams {
+    fn fallbacks(&self) -> &[FallbackProvider] {
+        &self.fallbacks
+    }
+
+    fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn use_fallback(&mut self, fallback: &FallbackProvider) {
+        fallback.apply(&mut self.base_url, &mut self.api_key, &mut self.credential_id, &mut self.model);
+    }
+}
+
+// 透传给 Gemini 的生成控制参数
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiTuning {
+    pub safety_settings: Option<Vec<SafetySetting>>,
+    pub seed: Option<i64>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub system_instruction: Option<String>,
+}
+
+impl GeminiTuning {
+    /// 系统指令（systemInstruction 不带 role）
+    pub(crate) fn system_content(&self) -> Option<Content> {
+        self.system_instruction
+            .as_ref()
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| Content {
+                role: None,
+                parts: vec![Part::Text { text: s.clone() }],
+            })
+    }
+}
+
+// 单张生成图片（按候选和返回顺序排列）
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiImage {
+    pub candidate_index: usize,
+    pub mime_type: String,
+    pub image_data: Option<String>,
+    pub text: Option<String>, // 该图片附带的文本
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<ImageInfo>,
 }
 
 // 前端返回的结果
@@ -99,93 +298,393 @@ pub struct GeminiResult {
     pub image_data: Option<String>,
     pub text: Option<String>,
     pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<ImageInfo>, // 指定 output 时保存到存储后的图片信息（此时不返回 image_data）
+    pub images: Vec<GeminiImage>, // 全部生成图片；image_data/image 对应第一个候选的最后一张
+    // 生成诊断信息（被拦截时说明原因）
+    pub finish_reason: Option<String>,
+    pub block_reason: Option<String>,
+    pub safety_ratings: Vec<SafetyRating>,
+    pub usage_metadata: Option<UsageMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_exceeded: Option<BudgetExceeded>, // 超出预算时未发出请求
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub served_by: Option<ServedBy>, // 配置了备用供应商时，实际完成请求的供应商
+    #[serde(skip)]
+    pub(crate) failure_kind: Option<FailureKind>, // 请求失败的类型（用于判断是否切换供应商）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>, // 生成成功但有附带问题（如会话历史保存失败）
 }
 
-// Tauri 命令：发送 Gemini API 请求
-#[tauri::command]
-pub async fn gemini_generate_content(params: GeminiRequestParams) -> GeminiResult {
-    println!("[Rust] gemini_generate_content called");
-    println!("[Rust] base_url: {}", params.base_url);
-    println!("[Rust] model: {}", params.model);
-    println!(
-        "[Rust] input_images count: {}",
-        params.input_images.as_ref().map(|v| v.len()).unwrap_or(0)
-    );
+impl FailoverResult for GeminiResult {
+    fn failover_error(&self) -> Option<(FailureKind, &str)> {
+        self.failure_kind.zip(self.error.as_deref()).filter(|_| !self.success)
+    }
 
-    // 构建请求体
-    let mut parts: Vec<Part> = vec![Part::Text {
-        text: params.prompt,
-    }];
+    fn set_served_by(&mut self, served_by: ServedBy) {
+        self.served_by = Some(served_by);
+    }
+}
 
-    // 添加输入图片
-    if let Some(images) = params.input_images {
-        println!("[Rust] Adding {} images to request", images.len());
-        for image_data in images {
-            parts.push(Part::InlineData {
-                inline_data: InlineData {
-                    mime_type: "image/png".to_string(),
-                    data: image_data,
-                },
-            });
+impl From<RequestError> for GeminiResult {
+    fn from(error: RequestError) -> Self {
+        GeminiResult {
+            failure_kind: error.kind,
+            ..GeminiResult::failure(error.message)
         }
     }
+}
 
-    let request_body = GeminiRequest {
-        contents: vec![Content { parts }],
-        generation_config: Some(GenerationConfig {
-            response_modalities: Some(vec!["IMAGE".to_string()]),
-            image_config: Some(ImageConfig {
-                aspect_ratio: params.aspect_ratio,
-                image_size: params.image_size,
-            }),
+impl GeminiResult {
+    pub(crate) fn failure(error: String) -> Self {
+        GeminiResult {
+            success: false,
+            image_data: None,
+            text: None,
+            error: Some(error),
+            image: None,
+            images: Vec::new(),
+            finish_reason: None,
+            block_reason: None,
+            safety_ratings: Vec::new(),
+            usage_metadata: None,
+            budget_exceeded: None,
+            served_by: None,
+            failure_kind: None,
+            warning: None,
+        }
+    }
+
+    pub(crate) fn budget_exceeded(exceeded: BudgetExceeded) -> Self {
+        GeminiResult {
+            budget_exceeded: Some(exceeded.clone()),
+            ..GeminiResult::failure(exceeded.to_string())
+        }
+    }
+
+    /// 由图片列表构建结果，image_data/image 对应第一个候选的最后一张图片
+    /// （与旧版本一致：模型先输出草图时，最后一张才是最终结果）
+    pub(crate) fn from_images(
+        success: bool,
+        images: Vec<GeminiImage>,
+        text: Option<String>,
+        error: Option<String>,
+    ) -> Self {
+        let primary = images
+            .iter()
+            .rev()
+            .find(|i| i.candidate_index == 0)
+            .or(images.last())
+            .cloned();
+        GeminiResult {
+            success,
+            image_data: primary.as_ref().and_then(|i| i.image_data.clone()),
+            text,
+            error,
+            image: primary.and_then(|i| i.image),
+            images,
+            finish_reason: None,
+            block_reason: None,
+            safety_ratings: Vec::new(),
+            usage_metadata: None,
+            budget_exceeded: None,
+            served_by: None,
+            failure_kind: None,
+            warning: None,
+        }
+    }
+
+    /// 附加响应中的结束原因、拦截原因、安全评级和用量
+    pub(crate) fn with_diagnostics(mut self, response: &GeminiResponse) -> Self {
+        let candidate = response.candidates.as_ref().and_then(|c| c.first());
+        self.finish_reason = candidate.and_then(|c| c.finish_reason.clone());
+        self.block_reason = response
+            .prompt_feedback
+            .as_ref()
+            .and_then(|f| f.block_reason.clone());
+        self.safety_ratings = candidate
+            .and_then(|c| c.safety_ratings.clone())
+            .or_else(|| {
+                response
+                    .prompt_feedback
+                    .as_ref()
+                    .and_then(|f| f.safety_ratings.clone())
+            })
+            .unwrap_or_default();
+        self.usage_metadata = response.usage_metadata.clone();
+        self
+    }
+}
+
+/// 没有返回内容时的说明（提示词被拦截或生成被终止）
+pub(crate) fn explain_empty_response(response: &GeminiResponse) -> String {
+    if let Some(feedback) = &response.prompt_feedback {
+        if let Some(reason) = &feedback.block_reason {
+            return match &feedback.block_reason_message {
+                Some(message) => format!("提示词被拦截 ({}): {}", reason, message),
+                None => format!("提示词被拦截 ({})", reason),
+            };
+        }
+    }
+
+    let candidate = response.candidates.as_ref().and_then(|c| c.first());
+    if let Some(reason) = candidate.and_then(|c| c.finish_reason.as_deref()) {
+        if reason != "STOP" {
+            let blocked: Vec<&str> = candidate
+                .and_then(|c| c.safety_ratings.as_ref())
+                .map(|ratings| {
+                    ratings
+                        .iter()
+                        .filter(|r| r.blocked)
+                        .map(|r| r.category.as_str())
+                        .collect()
+                })
+                .unwrap_or_default();
+            return if blocked.is_empty() {
+                format!("生成被终止 ({})", reason)
+            } else {
+                format!("生成被终止 ({}): {}", reason, blocked.join(", "))
+            };
+        }
+    }
+
+    "API 未返回有效内容".to_string()
+}
+
+/// 按顺序提取所有候选中的图片，文本附加到其后的图片上（末尾文本附加到最后一张）
+/// 跳过 thought 部分（思考过程的文本和草图）
+/// 返回图片列表和第一个候选的完整文本
+pub(crate) fn collect_images(response: &GeminiResponse) -> (Vec<GeminiImage>, Option<String>) {
+    let mut images: Vec<GeminiImage> = Vec::new();
+    let mut first_text: Option<String> = None;
+
+    for (candidate_index, candidate) in response.candidates.iter().flatten().enumerate() {
+        let parts = candidate
+            .content
+            .as_ref()
+            .and_then(|c| c.parts.as_ref());
+        let mut pending_text: Vec<String> = Vec::new();
+        let mut all_text: Vec<String> = Vec::new();
+        let first_image_of_candidate = images.len();
+
+        for part in parts.into_iter().flatten() {
+            if part.thought == Some(true) {
+                continue;
+            }
+            if let Some(t) = &part.text {
+                pending_text.push(t.clone());
+                all_text.push(t.clone());
+            }
+            if let Some(inline) = &part.inline_data {
+                images.push(GeminiImage {
+                    candidate_index,
+                    mime_type: inline.mime_type.clone(),
+                    image_data: Some(inline.data.clone()),
+                    text: (!pending_text.is_empty()).then(|| pending_text.join("\n")),
+                    image: None,
+                });
+                pending_text.clear();
+            }
+        }
+
+        if !pending_text.is_empty() && images.len() > first_image_of_candidate {
+            if let Some(last) = images.last_mut() {
+                let trailing = pending_text.join("\n");
+                last.text = Some(match last.text.take() {
+                    Some(existing) => format!("{}\n{}", existing, trailing),
+                    None => trailing,
+                });
+            }
+        }
+
+        if candidate_index == 0 && !all_text.is_empty() {
+            first_text = Some(all_text.join("\n"));
+        }
+    }
+
+    (images, first_text)
+}
+
+/// 读取输入图片，检测真实格式并按 inline_data 限制压缩
+/// 多张图片平分请求体积上限（base64 膨胀 4/3），保证整个请求不超过 20MB
+pub(crate) async fn build_image_parts(
+    app: &tauri::AppHandle,
+    images: &[ImageSource],
+) -> Result<Vec<Part>, String> {
+    let mut parts = Vec::with_capacity(images.len());
+    let inline_budget = (MAX_REQUEST_BYTES - REQUEST_OVERHEAD_BYTES) / 4 * 3;
+    let max_bytes = (inline_budget / images.len().max(1)) as u64;
+    for (index, source) in images.iter().enumerate() {
+        let prepared = match source.load_bytes(app).await {
+            Ok(bytes) => prepare_image_within(bytes, UploadTarget::GeminiInline, max_bytes).await,
+            Err(e) => Err(e),
+        }
+        .map_err(|e| format!("第 {} 张输入图片处理失败: {}", index + 1, e))?;
+        parts.push(Part::InlineData {
+            inline_data: InlineData {
+                mime_type: prepared.mime_type.clone(),
+                data: prepared.to_base64(),
+            },
+        });
+    }
+    Ok(parts)
+}
+
+/// 图片生成的 generationConfig
+pub(crate) fn image_generation_config(
+    aspect_ratio: Option<String>,
+    image_size: Option<String>,
+    candidate_count: Option<u32>,
+    tuning: &GeminiTuning,
+) -> GenerationConfig {
+    GenerationConfig {
+        response_modalities: Some(vec!["IMAGE".to_string()]),
+        image_config: Some(ImageConfig {
+            aspect_ratio,
+            image_size,
         }),
-    };
+        candidate_count,
+        seed: tuning.seed,
+        temperature: tuning.temperature,
+        top_p: tuning.top_p,
+    }
+}
+
+/// Gemini generateContent 端点
+/// 密钥通过 x-goog-api-key 请求头发送，避免出现在 URL 和代理日志中；
+/// 只有中转服务明确表示没有收到密钥（不识别请求头）时才回退到 ?key= 查询参数
+pub(crate) struct GeminiEndpoint {
+    pub url: String,
+    api_key: String,
+}
+
+/// generateContent 的响应（状态码和响应文本）
+pub(crate) struct EndpointResponse {
+    pub status: reqwest::StatusCode,
+    pub text: String,
+}
+
+// 401/403 响应中表示服务端没有收到密钥的提示（密钥错误或已吊销时不回退，避免密钥进入 URL）
+const KEY_NOT_RECEIVED_MARKERS: &[&str] = &[
+    "unregistered callers",
+    "missing api key",
+    "api key is missing",
+    "no api key",
+    "api key required",
+    "missing authentication",
+    "未提供",
+];
+
+/// 401/403 是否因为服务端不识别 x-goog-api-key 请求头
+fn key_not_received(status: reqwest::StatusCode, body: &str) -> bool {
+    if status != reqwest::StatusCode::UNAUTHORIZED && status != reqwest::StatusCode::FORBIDDEN {
+        return false;
+    }
+    let body = body.to_lowercase();
+    KEY_NOT_RECEIVED_MARKERS.iter().any(|marker| body.contains(marker))
+}
+
+impl GeminiEndpoint {
+    pub fn generate_content(base_url: &str, model: &str, api_key: &str) -> Self {
+        GeminiEndpoint {
+            url: format!("{}/v1beta/models/{}:generateContent", api_base(base_url), model),
+            api_key: api_key.to_string(),
+        }
+    }
+
+    /// 返回的错误不含 URL（回退到查询参数后 URL 中包含密钥，错误信息会返回给前端）
+    pub async fn post<T: Serialize + ?Sized>(
+        &self,
+        client: &Client,
+        body: &T,
+    ) -> Result<EndpointResponse, reqwest::Error> {
+        let response = client
+            .post(&self.url)
+            .header("x-goog-api-key", &self.api_key)
+            .json(body)
+            .send()
+            .await
+            .map_err(reqwest::Error::without_url)?;
+        let status = response.status();
+        let text = response.text().await.map_err(reqwest::Error::without_url)?;
+        if !key_not_received(status, &text) {
+            return Ok(EndpointResponse { status, text });
+        }
+
+        rust_log!("[Rust] Header auth not recognized ({}), retrying with query key", status);
+        let response = client
+            .post(&self.url)
+            .query(&[("key", &self.api_key)])
+            .json(body)
+            .send()
+            .await
+            .map_err(reqwest::Error::without_url)?;
+        let status = response.status();
+        let text = response.text().await.map_err(reqwest::Error::without_url)?;
+        Ok(EndpointResponse { status, text })
+    }
+}
+
+// 只统计写入字节数的 Writer，用于计算请求体大小而不分配内存
+struct ByteCounter(usize);
+
+impl std::io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
 
+/// 检查请求体是否超过 Gemini 的 20MB 上限（超限时服务端直接返回 400，提前给出明确提示）
+fn check_request_size<T: Serialize + ?Sized>(body: &T) -> Result<(), String> {
+    let mut counter = ByteCounter(0);
+    serde_json::to_writer(&mut counter, body).map_err(|e| format!("序列化请求失败: {}", e))?;
+    if counter.0 > MAX_REQUEST_BYTES {
+        return Err(format!(
+            "请求体 {:.1}MB 超过 Gemini 单次请求 20MB 上限，请减少输入图片或对话历史",
+            counter.0 as f64 / 1024.0 / 1024.0
+        ));
+    }
+    Ok(())
+}
+
+/// 发送 generateContent 请求，返回响应文本
+pub(crate) async fn post_generate_content<T: Serialize>(
+    base_url: &str,
+    api_key: &str,
+    model: &str,
+    request_body: &T,
+) -> Result<String, RequestError> {
     // 构建 URL
-    let url = format!(
-        "{}/v1beta/models/{}:generateContent?key={}",
-        params.base_url.trim_end_matches('/'),
-        params.model,
-        params.api_key
-    );
-    println!(
-        "[Rust] Request URL (without key): {}/v1beta/models/{}:generateContent",
-        params.base_url.trim_end_matches('/'),
-        params.model
-    );
+    check_request_size(request_body)?;
+    let endpoint = GeminiEndpoint::generate_content(base_url, model, api_key);
+    rust_log!("[Rust] Request URL: {}", endpoint.url);
 
     // 创建 HTTP 客户端，设置较长的超时时间（10分钟）
-    println!("[Rust] Creating HTTP client with 600s timeout...");
+    rust_log!("[Rust] Creating HTTP client with 600s timeout...");
     let client = match Client::builder().timeout(Duration::from_secs(600)).build() {
         Ok(c) => c,
         Err(e) => {
-            println!("[Rust] Failed to create HTTP client: {}", e);
-            return GeminiResult {
-                success: false,
-                image_data: None,
-                text: None,
-                error: Some(format!("创建 HTTP 客户端失败: {}", e)),
-            };
+            rust_log!("[Rust] Failed to create HTTP client: {}", e);
+            return Err(format!("创建 HTTP 客户端失败: {}", e).into());
         }
     };
 
     // 发送请求
-    println!("[Rust] Sending POST request...");
+    rust_log!("[Rust] Sending POST request...");
     let start_time = std::time::Instant::now();
 
-    let response = match client
-        .post(&url)
-        .header("Content-Type", "application/json")
-        .json(&request_body)
-        .send()
-        .await
-    {
+    let response = match endpoint.post(&client, request_body).await {
         Ok(r) => {
-            println!("[Rust] Response received in {:?}", start_time.elapsed());
+            rust_log!("[Rust] Response received in {:?}", start_time.elapsed());
             r
         }
         Err(e) => {
-            println!(
+            rust_log!(
                 "[Rust] Request failed after {:?}: {}",
                 start_time.elapsed(),
                 e
@@ -197,169 +696,217 @@ pub async fn gemini_generate_content(params: GeminiRequestParams) -> GeminiResul
             } else {
                 format!("请求失败: {}", e)
             };
-            return GeminiResult {
-                success: false,
-                image_data: None,
-                text: None,
-                error: Some(error_msg),
-            };
+            return Err(RequestError {
+                kind: Some(FailureKind::from_request_error(&e)),
+                message: error_msg,
+            });
         }
     };
 
     // 检查 HTTP 状态码
-    let status = response.status();
-    println!("[Rust] HTTP status: {}", status);
+    let status = response.status;
+    rust_log!("[Rust] HTTP status: {}", status);
     if !status.is_success() {
-        let error_text = response.text().await.unwrap_or_default();
-        println!("[Rust] Error response: {}", error_text);
-        return GeminiResult {
-            success: false,
-            image_data: None,
-            text: None,
-            error: Some(format!("API 返回错误 ({}): {}", status, error_text)),
-        };
+        rust_log!("[Rust] Error response: {}", response.text);
+        return Err(RequestError {
+            kind: Some(FailureKind::Status(status.as_u16())),
+            message: format!("API 返回错误 ({}): {}", status, response.text),
+        });
     }
+    let response_text = response.text;
 
-    // 先获取响应文本，再解析 JSON
-    println!("[Rust] Getting response text...");
-    let response_text = match response.text().await {
-        Ok(t) => t,
-        Err(e) => {
-            println!("[Rust] Failed to get response text: {}", e);
-            return GeminiResult {
-                success: false,
-                image_data: None,
-                text: None,
-                error: Some(format!("获取响应失败: {}", e)),
-            };
-        }
-    };
-
-    println!("[Rust] Response text length: {} bytes", response_text.len());
+    rust_log!("[Rust] Response text length: {} bytes", response_text.len());
     // 打印前 500 个字符用于调试
     let preview = if response_text.len() > 500 {
         format!("{}...(truncated)", &response_text[..500])
     } else {
         response_text.clone()
     };
-    println!("[Rust] Response preview: {}", preview);
+    rust_log!("[Rust] Response preview: {}", preview);
+
+    Ok(response_text)
+
+}
+
+/// 将生成图片逐张保存到存储（保存成功的图片不再保留 base64）
+pub(crate) fn save_gemini_images(
+    app: &tauri::AppHandle,
+    images: &mut [GeminiImage],
+    output: &GenerationOutputOptions,
+    metadata: &ImageMetadata,
+) -> Result<(), String> {
+    for (index, item) in images.iter_mut().enumerate() {
+        let Some(data) = &item.image_data else { continue };
+        match decode_base64_image(data)
+            .and_then(|bytes| save_generated_image(app, &bytes, output, metadata.clone()))
+        {
+            Ok(info) => {
+                rust_log!("[Rust] Generated image {} saved: {}", index + 1, info.path);
+                item.image = Some(info);
+                item.image_data = None;
+            }
+            Err(e) => {
+                rust_log!("[Rust] Failed to save generated image {}: {}", index + 1, e);
+                return Err(format!("保存第 {} 张生成图片失败: {}", index + 1, e));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Tauri 命令：发送 Gemini API 请求
+#[tauri::command]
+pub async fn gemini_generate_content(app: tauri::AppHandle, params: GeminiRequestParams) -> GeminiResult {
+    rust_log!("[Rust] gemini_generate_content called");
+    run_with_failover(params, |params| generate_content(app.clone(), params)).await
+}
+
+async fn generate_content(app: tauri::AppHandle, mut params: GeminiRequestParams) -> GeminiResult {
+    rust_log!("[Rust] base_url: {}", params.base_url);
+    rust_log!("[Rust] model: {}", params.model);
+
+    if let Err(e) = resolve_api_key(&app, &params.credential_id, &mut params.api_key) {
+        return GeminiResult::failure(e);
+    }
+
+    rust_log!(
+        "[Rust] input_images count: {}",
+        params.input_images.as_ref().map(|v| v.len()).unwrap_or(0)
+    );
+
+    // 构建请求体
+    let mut parts: Vec<Part> = vec![Part::Text {
+        text: params.prompt.clone(),
+    }];
+
+    // 添加输入图片
+    if let Some(images) = &params.input_images {
+        rust_log!("[Rust] Adding {} images to request", images.len());
+        match build_image_parts(&app, images).await {
+            Ok(image_parts) => parts.extend(image_parts),
+            Err(e) => return GeminiResult::failure(e),
+        }
+    }
+
+    let request_body = GeminiRequest {
+        contents: vec![Content { role: None, parts }],
+        generation_config: Some(image_generation_config(
+            params.aspect_ratio.clone(),
+            params.image_size.clone(),
+            params.candidate_count,
+            &params.tuning,
+        )),
+        safety_settings: params.tuning.safety_settings.clone(),
+        system_instruction: params.tuning.system_content(),
+    };
+
+    let usage_context = params.usage_context.or_output(params.output.as_ref());
+    let planned = planned_image_usage(
+        &params.model,
+        params.image_size.as_deref(),
+        params.candidate_count.unwrap_or(1).max(1),
+    );
+    let _reservation = match check_budget(&app, &usage_context, "gemini", &params.model, &planned) {
+        Ok(reservation) => reservation,
+        Err(e) => return GeminiResult::budget_exceeded(e),
+    };
+
+    let response_text =
+        match post_generate_content(&params.base_url, &params.api_key, &params.model, &request_body).await {
+            Ok(t) => t,
+            Err(e) => return e.into(),
+        };
 
     // 解析 JSON
-    println!("[Rust] Parsing JSON...");
+    rust_log!("[Rust] Parsing JSON...");
     let gemini_response: GeminiResponse = match serde_json::from_str(&response_text) {
         Ok(r) => r,
         Err(e) => {
-            println!("[Rust] Failed to parse JSON: {}", e);
-            println!(
+            rust_log!("[Rust] Failed to parse JSON: {}", e);
+            rust_log!(
                 "[Rust] JSON error location: line {}, column {}",
                 e.line(),
                 e.column()
             );
-            return GeminiResult {
-                success: false,
-                image_data: None,
-                text: None,
-                error: Some(format!("解析响应失败: {}", e)),
-            };
+            return GeminiResult::failure(format!("解析响应失败: {}", e));
         }
     };
 
     // 检查 API 错误
-    if let Some(err) = gemini_response.error {
-        println!("[Rust] API error: {}", err.message);
-        return GeminiResult {
-            success: false,
-            image_data: None,
-            text: None,
-            error: Some(err.message),
-        };
+    if let Some(err) = &gemini_response.error {
+        rust_log!("[Rust] API error: {}", err.message);
+        return GeminiResult::failure(err.message.clone());
     }
 
     // 提取结果
-    let mut image_data: Option<String> = None;
-    let mut text: Option<String> = None;
-
-    if let Some(candidates) = gemini_response.candidates {
-        if let Some(candidate) = candidates.first() {
-            if let Some(content) = &candidate.content {
-                if let Some(parts) = &content.parts {
-                    for part in parts {
-                        if let Some(inline) = &part.inline_data {
-                            image_data = Some(inline.data.clone());
-                        }
-                        if let Some(t) = &part.text {
-                            text = Some(t.clone());
-                        }
-                    }
-                }
-            }
-        }
-    }
+    let (mut images, text) = collect_images(&gemini_response);
+
+    // 图片模型按 token 计费，被拦截的请求同样计入用量
+    let usage = generated_image_usage(&gemini_response, images.len());
+    record_usage(&app, &usage_context, "gemini", &params.model, &usage);
 
-    println!(
-        "[Rust] Result: has_image={}, has_text={}",
-        image_data.is_some(),
+    rust_log!(
+        "[Rust] Result: images={}, has_text={}",
+        images.len(),
         text.is_some()
     );
 
-    if image_data.is_none() && text.is_none() {
-        return GeminiResult {
-            success: false,
-            image_data: None,
-            text: None,
-            error: Some("API 未返回有效内容".to_string()),
-        };
+    if images.is_empty() && text.is_none() {
+        let reason = explain_empty_response(&gemini_response);
+        rust_log!("[Rust] No content: {}", reason);
+        return GeminiResult::failure(reason).with_diagnostics(&gemini_response);
     }
 
-    GeminiResult {
-        success: true,
-        image_data,
-        text,
-        error: None,
+    // 指定 output 时逐张保存到存储，只返回图片信息
+    if let Some(output) = &params.output {
+        let metadata = ImageMetadata {
+            prompt: Some(params.prompt.clone()),
+            input_images: input_infos(
+                &app,
+                params.input_images.as_deref().unwrap_or_default(),
+                output.input_labels.as_deref(),
+            ),
+            provider: Some("gemini".to_string()),
+            model: Some(params.model.clone()),
+            aspect_ratio: params.aspect_ratio.clone(),
+            image_size: params.image_size.clone(),
+            seed: params.tuning.seed,
+            ..Default::default()
+        };
+        if let Err(e) = save_gemini_images(&app, &mut images, output, &metadata) {
+            // 保存失败时仍返回图片数据，避免生成结果丢失
+            return GeminiResult::from_images(false, images, text, Some(e))
+                .with_diagnostics(&gemini_response);
+        }
     }
-}
 
-// 文件数据结构（用于LLM内容生成）
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct FileData {
-    pub data: String,              // base64 编码的文件数据
-    pub mime_type: String,         // 文件MIME类型
-    pub file_name: Option<String>, // 文件名（可选）
+    GeminiResult::from_images(true, images, text, None).with_diagnostics(&gemini_response)
 }
 
-// LLM 文本生成请求参数
-#[derive(Debug, Deserialize)]
+// LLM 专用请求体（请求参数与 llm.rs 中的 OpenAI / Claude 命令共用 LLMRequestParams）
+#[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct LLMRequestParams {
-    pub base_url: String,
-    pub api_key: String,
-    pub model: String,
-    pub prompt: String,
-    pub system_prompt: Option<String>,
-    pub output_format: Option<String>, // "text" or "json"
-    pub temperature: Option<f64>,
-    pub max_tokens: Option<i32>,
-    pub files: Option<Vec<FileData>>, // 文件数据（PDF、图片等）
-    pub response_json_schema: Option<serde_json::Value>, // 结构化输出的 JSON Schema
+pub struct LLMRequest {
+    pub contents: Vec<Content>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generation_config: Option<LLMGenerationConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<GeminiTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_config: Option<serde_json::Value>,
 }
 
-// LLM 文本生成结果
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct LLMResult {
-    pub success: bool,
-    pub content: Option<String>,
-    pub error: Option<String>,
+pub struct GeminiTool {
+    pub function_declarations: Vec<FunctionDeclaration>,
 }
 
-// LLM 专用请求体
 #[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct LLMRequest {
-    pub contents: Vec<Content>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub generation_config: Option<LLMGenerationConfig>,
+pub struct FunctionDeclaration {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
 }
 
 #[derive(Debug, Serialize)]
@@ -375,36 +922,119 @@ pub struct LLMGenerationConfig {
     pub max_output_tokens: Option<i32>,
 }
 
+/// Gemini 工具声明与 toolConfig
+fn gemini_tools(params: &LLMRequestParams) -> (Option<Vec<GeminiTool>>, Option<serde_json::Value>) {
+    if params.tools().is_empty() {
+        return (None, None);
+    }
+    let declarations = params
+        .tools()
+        .iter()
+        .map(|tool| FunctionDeclaration {
+            name: tool.name.clone(),
+            description: tool.description.clone(),
+            parameters: tool.parameters.clone(),
+        })
+        .collect();
+    let tool_config = params.tool_choice().map(|choice| {
+        let config = match choice {
+            ToolChoice::Auto => serde_json::json!({"mode": "AUTO"}),
+            ToolChoice::None => serde_json::json!({"mode": "NONE"}),
+            ToolChoice::Required => serde_json::json!({"mode": "ANY"}),
+            ToolChoice::Tool(name) => serde_json::json!({"mode": "ANY", "allowedFunctionNames": [name]}),
+        };
+        serde_json::json!({"functionCallingConfig": config})
+    });
+    (Some(vec![GeminiTool { function_declarations: declarations }]), tool_config)
+}
+
+/// 工具结果转为 functionResponse.response（必须为对象）
+fn function_response_value(content: &str) -> serde_json::Value {
+    match serde_json::from_str::<serde_json::Value>(content) {
+        Ok(value @ serde_json::Value::Object(_)) => value,
+        _ => serde_json::json!({ "result": content }),
+    }
+}
+
 // Tauri 命令：LLM 文本生成
 #[tauri::command]
-pub async fn gemini_generate_text(params: LLMRequestParams) -> LLMResult {
-    println!("[Rust] gemini_generate_text called");
-    println!("[Rust] base_url: {}", params.base_url);
-    println!("[Rust] model: {}", params.model);
-    println!(
+pub async fn gemini_generate_text(app: tauri::AppHandle, params: LLMRequestParams) -> LLMResult {
+    rust_log!("[Rust] gemini_generate_text called");
+    chat_with_failover(&app, "google", params).await
+}
+
+/// Gemini 文本生成请求（命令与 Agent 循环共用）
+pub(crate) async fn gemini_chat(params: &LLMRequestParams) -> LLMResult {
+    rust_log!("[Rust] base_url: {}", params.base_url);
+    rust_log!("[Rust] model: {}", params.model);
+    let _reservation = match params.check_budget("gemini") {
+        Ok(reservation) => reservation,
+        Err(e) => return LLMResult::budget_exceeded(e),
+    };
+    rust_log!(
         "[Rust] files count: {}",
         params.files.as_ref().map(|v| v.len()).unwrap_or(0)
     );
 
-    // 构建请求内容
-    let prompt_text = if let Some(system_prompt) = &params.system_prompt {
-        if !system_prompt.is_empty() {
-            format!("系统指令：{}\n\n用户请求：{}", system_prompt, params.prompt)
-        } else {
-            params.prompt.clone()
+    // 构建对话内容：系统指令并入第一条用户消息，助手消息使用 model 角色
+    let conversation = build_conversation(
+        params.system_prompt.as_deref(),
+        params.messages.as_deref().unwrap_or_default(),
+        &params.prompt,
+        params.files.as_deref().unwrap_or_default(),
+    );
+    let mut system_text = conversation.system;
+    let mut contents: Vec<Content> = Vec::with_capacity(conversation.turns.len());
+    for turn in &conversation.turns {
+        // 工具结果以 functionResponse 放在用户消息中，连续的结果合并为一条
+        if turn.role == ChatRole::Tool {
+            let part = Part::FunctionResponse {
+                function_response: FunctionResponse {
+                    id: None,
+                    name: turn.tool_name.to_string(),
+                    response: function_response_value(turn.text),
+                },
+            };
+            match contents.last_mut() {
+                Some(last)
+                    if last.role.as_deref() == Some("user")
+                        && last.parts.iter().all(|p| matches!(p, Part::FunctionResponse { .. })) =>
+                {
+                    last.parts.push(part)
+                }
+                _ => contents.push(Content { role: Some("user".to_string()), parts: vec![part] }),
+            }
+            continue;
+        }
+        if !turn.tool_calls.is_empty() {
+            let mut parts: Vec<Part> = Vec::new();
+            if !turn.text.is_empty() {
+                parts.push(Part::Text { text: turn.text.to_string() });
+            }
+            parts.extend(turn.tool_calls.iter().map(|call| Part::FunctionCall {
+                function_call: FunctionCall {
+                    id: None,
+                    name: call.name.clone(),
+                    args: call.arguments.clone(),
+                },
+                thought_signature: call.signature.clone(),
+            }));
+            contents.push(Content { role: Some("model".to_string()), parts });
+            continue;
         }
-    } else {
-        params.prompt.clone()
-    };
 
-    // 构建 parts：先添加文本，再添加文件
-    let mut parts: Vec<Part> = vec![Part::Text { text: prompt_text }];
+        let text = match system_text.take_if(|_| turn.role == ChatRole::User) {
+            Some(system_prompt) => format!("系统指令：{}\n\n用户请求：{}", system_prompt, turn.text),
+            None => turn.text.to_string(),
+        };
 
-    // 添加文件（PDF、图片等）
-    if let Some(files) = &params.files {
-        println!("[Rust] Adding {} files to request", files.len());
-        for file in files {
-            println!(
+        // 构建 parts：先添加文本，再添加文件（PDF、图片等），空文本不发送
+        let mut parts: Vec<Part> = Vec::new();
+        if !text.is_empty() {
+            parts.push(Part::Text { text });
+        }
+        for file in turn.files {
+            rust_log!(
                 "[Rust] Adding file: mime_type={}, name={:?}",
                 file.mime_type, file.file_name
             );
@@ -415,10 +1045,24 @@ pub async fn gemini_generate_text(params: LLMRequestParams) -> LLMResult {
                 },
             });
         }
+        if parts.is_empty() {
+            continue;
+        }
+        contents.push(Content {
+            role: Some(if turn.role == ChatRole::Assistant { "model" } else { "user" }.to_string()),
+            parts,
+        });
+    }
+    if contents.is_empty() {
+        return LLMResult::failure("请求中没有用户消息".to_string());
     }
+    let skipped_files = conversation.skipped_files;
 
+    let (tools, tool_config) = gemini_tools(params);
     let request_body = LLMRequest {
-        contents: vec![Content { parts }],
+        contents,
+        tools,
+        tool_config,
         generation_config: Some(LLMGenerationConfig {
             response_mime_type: if params.response_json_schema.is_some()
                 || params.output_format.as_deref() == Some("json")
@@ -427,54 +1071,39 @@ pub async fn gemini_generate_text(params: LLMRequestParams) -> LLMResult {
             } else {
                 None
             },
-            response_schema: params.response_json_schema,
+            response_schema: params.response_json_schema.clone(),
             temperature: params.temperature,
             max_output_tokens: params.max_tokens,
         }),
     };
 
+    if let Err(e) = check_request_size(&request_body) {
+        return LLMResult::failure(e);
+    }
+
     // 构建 URL
-    let url = format!(
-        "{}/v1beta/models/{}:generateContent?key={}",
-        params.base_url.trim_end_matches('/'),
-        params.model,
-        params.api_key
-    );
-    println!(
-        "[Rust] Request URL (without key): {}/v1beta/models/{}:generateContent",
-        params.base_url.trim_end_matches('/'),
-        params.model
-    );
+    let endpoint = GeminiEndpoint::generate_content(&params.base_url, &params.model, &params.api_key);
+    rust_log!("[Rust] Request URL: {}", endpoint.url);
 
     // 创建 HTTP 客户端
     let client = match Client::builder().timeout(Duration::from_secs(300)).build() {
         Ok(c) => c,
         Err(e) => {
-            return LLMResult {
-                success: false,
-                content: None,
-                error: Some(format!("创建 HTTP 客户端失败: {}", e)),
-            }
+            return LLMResult::failure(format!("创建 HTTP 客户端失败: {}", e))
         }
     };
 
     // 发送请求
-    println!("[Rust] Sending LLM request...");
+    rust_log!("[Rust] Sending LLM request...");
     let start_time = std::time::Instant::now();
 
-    let response = match client
-        .post(&url)
-        .header("Content-Type", "application/json")
-        .json(&request_body)
-        .send()
-        .await
-    {
+    let response = match endpoint.post(&client, &request_body).await {
         Ok(r) => {
-            println!("[Rust] LLM response received in {:?}", start_time.elapsed());
+            rust_log!("[Rust] LLM response received in {:?}", start_time.elapsed());
             r
         }
         Err(e) => {
-            println!("[Rust] LLM request failed: {}", e);
+            rust_log!("[Rust] LLM request failed: {}", e);
             let error_msg = if e.is_timeout() {
                 "请求超时，请稍后重试".to_string()
             } else if e.is_connect() {
@@ -482,95 +1111,182 @@ pub async fn gemini_generate_text(params: LLMRequestParams) -> LLMResult {
             } else {
                 format!("请求失败: {}", e)
             };
-            return LLMResult {
-                success: false,
-                content: None,
-                error: Some(error_msg),
-            };
+            return LLMResult::request_failure(FailureKind::from_request_error(&e), error_msg);
         }
     };
 
     // 检查 HTTP 状态码
-    let status = response.status();
+    let status = response.status;
     if !status.is_success() {
-        let error_text = response.text().await.unwrap_or_default();
-        println!("[Rust] LLM error response: {}", error_text);
-        return LLMResult {
-            success: false,
-            content: None,
-            error: Some(format!("API 返回错误 ({}): {}", status, error_text)),
-        };
+        rust_log!("[Rust] LLM error response: {}", response.text);
+        return LLMResult::request_failure(FailureKind::Status(status.as_u16()), format!("API 返回错误 ({}): {}", status, response.text));
     }
 
     // 解析响应
-    let response_text = match response.text().await {
-        Ok(t) => t,
-        Err(e) => {
-            return LLMResult {
-                success: false,
-                content: None,
-                error: Some(format!("获取响应失败: {}", e)),
-            };
-        }
-    };
+    let response_text = response.text;
 
     let gemini_response: GeminiResponse = match serde_json::from_str(&response_text) {
         Ok(r) => r,
         Err(e) => {
-            return LLMResult {
-                success: false,
-                content: None,
-                error: Some(format!("解析响应失败: {}", e)),
-            };
+            return LLMResult::failure(format!("解析响应失败: {}", e));
         }
     };
 
     // 检查 API 错误
     if let Some(err) = gemini_response.error {
-        return LLMResult {
-            success: false,
-            content: None,
-            error: Some(err.message),
-        };
+        return LLMResult::failure(err.message);
     }
 
-    // 提取文本内容
-    let mut content: Option<String> = None;
-
-    if let Some(candidates) = gemini_response.candidates {
-        if let Some(candidate) = candidates.first() {
-            if let Some(candidate_content) = &candidate.content {
-                if let Some(parts) = &candidate_content.parts {
-                    let mut text_parts: Vec<String> = Vec::new();
-                    for part in parts {
-                        if let Some(t) = &part.text {
-                            text_parts.push(t.clone());
-                        }
-                    }
-                    if !text_parts.is_empty() {
-                        content = Some(text_parts.join(""));
-                    }
-                }
-            }
+    // 提取文本内容和函数调用
+    let usage = gemini_response.usage_metadata.as_ref().map(UsageMetadata::to_usage);
+    let mut text_parts: Vec<String> = Vec::new();
+    let mut tool_calls: Vec<ToolCall> = Vec::new();
+    let parts = gemini_response
+        .candidates
+        .and_then(|candidates| candidates.into_iter().next())
+        .and_then(|candidate| candidate.content)
+        .and_then(|content| content.parts)
+        .unwrap_or_default();
+    for part in parts {
+        if let Some(call) = part.function_call {
+            tool_calls.push(ToolCall {
+                id: call.id.unwrap_or_else(new_tool_call_id),
+                name: call.name,
+                arguments: call.args,
+                signature: part.thought_signature,
+            });
+        } else if let Some(t) = part.text {
+            text_parts.push(t);
         }
     }
+    let content = (!text_parts.is_empty()).then(|| text_parts.join(""));
 
-    if content.is_none() {
-        return LLMResult {
-            success: false,
-            content: None,
-            error: Some("API 未返回有效内容".to_string()),
-        };
+    rust_log!(
+        "[Rust] LLM result: content length = {}, tool calls = {}",
+        content.as_ref().map(|c| c.len()).unwrap_or(0),
+        tool_calls.len()
+    );
+
+    LLMResult::from_output(content, tool_calls, skipped_files, usage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_not_received() {
+        use reqwest::StatusCode;
+        assert!(key_not_received(
+            StatusCode::FORBIDDEN,
+            r#"{"error":{"message":"Method doesn't allow unregistered callers (callers without established identity)."}}"#
+        ));
+        assert!(key_not_received(StatusCode::UNAUTHORIZED, r#"{"error":{"message":"未提供令牌"}}"#));
+        // 密钥错误或已吊销时不回退到查询参数
+        assert!(!key_not_received(StatusCode::UNAUTHORIZED, r#"{"error":{"message":"无效的令牌"}}"#));
+        assert!(!key_not_received(StatusCode::FORBIDDEN, r#"{"error":{"status":"PERMISSION_DENIED"}}"#));
+        assert!(!key_not_received(StatusCode::BAD_REQUEST, "missing api key"));
     }
 
-    println!(
-        "[Rust] LLM result: content length = {}",
-        content.as_ref().map(|c| c.len()).unwrap_or(0)
-    );
+    #[test]
+    fn test_planned_image_usage() {
+        let flash = planned_image_usage("gemini-2.5-flash-image", None, 2);
+        assert_eq!((flash.output_tokens, flash.images), (2580, 2));
+        assert_eq!(planned_image_usage("gemini-3-pro-image-preview", Some("2K"), 1).output_tokens, 1120);
+        assert_eq!(planned_image_usage("gemini-3-pro-image-preview", Some("4k"), 1).output_tokens, 2000);
+    }
+
+    #[test]
+    fn test_collect_images_keeps_order_and_text() {
+        let response: GeminiResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [
+                {"content": {"parts": [
+                    {"text": "第一张"},
+                    {"inlineData": {"mimeType": "image/png", "data": "AAA"}},
+                    {"inlineData": {"mimeType": "image/jpeg", "data": "BBB"}},
+                    {"text": "结尾说明"}
+                ]}},
+                {"content": {"parts": [
+                    {"inlineData": {"mimeType": "image/png", "data": "CCC"}}
+                ]}}
+            ]
+        }))
+        .unwrap();
+
+        let (images, text) = collect_images(&response);
+        assert_eq!(images.len(), 3);
+        assert_eq!(images[0].text.as_deref(), Some("第一张"));
+        assert_eq!(images[1].mime_type, "image/jpeg");
+        assert_eq!(images[1].text.as_deref(), Some("结尾说明"));
+        assert_eq!(images[2].candidate_index, 1);
+        assert_eq!(images[2].image_data.as_deref(), Some("CCC"));
+        assert_eq!(text.as_deref(), Some("第一张\n结尾说明"));
+
+        let result = GeminiResult::from_images(true, images, text, None);
+        assert_eq!(result.image_data.as_deref(), Some("BBB"));
+    }
+
+    #[test]
+    fn test_collect_images_skips_thoughts() {
+        let response: GeminiResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [
+                {"content": {"parts": [
+                    {"text": "构思中", "thought": true},
+                    {"inlineData": {"mimeType": "image/png", "data": "DRAFT"}, "thought": true},
+                    {"inlineData": {"mimeType": "image/png", "data": "FINAL"}}
+                ]}}
+            ]
+        }))
+        .unwrap();
+
+        let (images, text) = collect_images(&response);
+        assert_eq!(images.len(), 1);
+        assert_eq!(text, None);
+        let result = GeminiResult::from_images(true, images, text, None);
+        assert_eq!(result.image_data.as_deref(), Some("FINAL"));
+    }
+
+    #[test]
+    fn test_explain_blocked_response() {
+        let blocked: GeminiResponse = serde_json::from_value(serde_json::json!({
+            "promptFeedback": {"blockReason": "SAFETY"},
+            "usageMetadata": {"promptTokenCount": 12, "totalTokenCount": 12}
+        }))
+        .unwrap();
+        assert_eq!(explain_empty_response(&blocked), "提示词被拦截 (SAFETY)");
+        let result = GeminiResult::failure(explain_empty_response(&blocked)).with_diagnostics(&blocked);
+        assert_eq!(result.block_reason.as_deref(), Some("SAFETY"));
+        assert_eq!(result.usage_metadata.unwrap().prompt_token_count, Some(12));
+
+        let stopped: GeminiResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [{
+                "finishReason": "IMAGE_SAFETY",
+                "safetyRatings": [{"category": "HARM_CATEGORY_DANGEROUS_CONTENT", "probability": "HIGH", "blocked": true}]
+            }]
+        }))
+        .unwrap();
+        assert_eq!(
+            explain_empty_response(&stopped),
+            "生成被终止 (IMAGE_SAFETY): HARM_CATEGORY_DANGEROUS_CONTENT"
+        );
+    }
+
+    #[test]
+    fn test_function_call_parts_round_trip() {
+        let part = Part::FunctionCall {
+            function_call: FunctionCall {
+                id: None,
+                name: "generate_image".to_string(),
+                args: serde_json::json!({"prompt": "cat"}),
+            },
+            thought_signature: Some("sig".to_string()),
+        };
+        assert_eq!(
+            serde_json::to_value(&part).unwrap(),
+            serde_json::json!({"functionCall": {"name": "generate_image", "args": {"prompt": "cat"}}, "thoughtSignature": "sig"})
+        );
 
-    LLMResult {
-        success: true,
-        content,
-        error: None,
+        assert_eq!(function_response_value("{\"ok\": true}"), serde_json::json!({"ok": true}));
+        assert_eq!(function_response_value("完成"), serde_json::json!({"result": "完成"}));
     }
 }
@@ -0,0 +1,413 @@
+// Gemini 多轮图片编辑会话
+// 保存每轮的用户输入和模型回复（含生成图片与 thoughtSignature），后续编辑在同一上下文中继续
+// 会话持久化在 app_data/gemini_sessions/{session_id}.json，重启后可继续
+
+use crate::budget::check_budget;
+use crate::gemini::{
+    build_image_parts, collect_images, generated_image_usage, image_generation_config, post_generate_content,
+    explain_empty_response, planned_image_usage, save_gemini_images, Content, GeminiResponse, GeminiResult,
+    GeminiTuning, GenerationConfig, Part, SafetySetting,
+};
+use crate::image_source::{input_infos, ImageSource};
+use crate::storage::{validate_id, write_file_atomic, GenerationOutputOptions, ImageMetadata};
+use crate::usage::{record_usage, UsageContext};
+use crate::vault::resolve_api_key;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tauri::Manager;
+
+const SESSIONS_DIR_NAME: &str = "gemini_sessions";
+// 重发历史时保留图片数据的最近消息数（最近两轮），更早的图片替换为占位文本，避免请求随轮数无限增长
+const HISTORY_IMAGE_CONTENTS: usize = 4;
+
+lazy_static::lazy_static! {
+    // 每个会话的发送锁：同一会话的编辑依次执行，后一轮基于前一轮写入的历史
+    static ref SESSION_LOCKS: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>> =
+        Mutex::new(HashMap::new());
+}
+
+// 持久化的会话
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiEditSession {
+    pub session_id: String,
+    pub model: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+    // 完整对话历史，按 API 的 contents 格式原样保存（保留 thoughtSignature 等字段）
+    pub contents: Vec<Value>,
+}
+
+// 会话摘要（列表用，不含图片数据）
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiEditSessionSummary {
+    pub session_id: String,
+    pub model: String,
+    pub turns: usize,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+// 前端调用的参数
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiEditSessionParams {
+    pub session_id: String,
+    pub base_url: String,
+    #[serde(default)]
+    pub api_key: String, // 使用 credential_id 时可省略
+    pub credential_id: Option<String>, // 凭据库中的凭据 ID（优先于 api_key）
+    pub model: String,
+    pub prompt: String,
+    pub images: Option<Vec<ImageSource>>, // 本轮新增的输入图片
+    pub aspect_ratio: Option<String>,
+    pub image_size: Option<String>,
+    #[serde(flatten)]
+    pub tuning: GeminiTuning,
+    pub output: Option<GenerationOutputOptions>,
+    #[serde(flatten)]
+    pub usage_context: UsageContext,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionRequest<'a> {
+    contents: &'a [Value],
+    generation_config: GenerationConfig,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    safety_settings: Option<Vec<SafetySetting>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<Content>,
+}
+
+fn get_sessions_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let app_data = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("无法获取应用数据目录: {}", e))?;
+    let dir = app_data.join(SESSIONS_DIR_NAME);
+    if !dir.exists() {
+        fs::create_dir_all(&dir).map_err(|e| format!("创建会话目录失败: {}", e))?;
+    }
+    Ok(dir)
+}
+
+// 会话 ID 用作文件名，只允许字母、数字、- 和 _
+fn session_path(app: &tauri::AppHandle, session_id: &str) -> Result<PathBuf, String> {
+    validate_id(session_id, "会话")?;
+    Ok(get_sessions_dir(app)?.join(format!("{}.json", session_id)))
+}
+
+fn load_session(app: &tauri::AppHandle, session_id: &str) -> Result<Option<GeminiEditSession>, String> {
+    let path = session_path(app, session_id)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path).map_err(|e| format!("读取会话失败: {}", e))?;
+    serde_json::from_str(&content)
+        .map(Some)
+        .map_err(|e| format!("解析会话失败: {}", e))
+}
+
+fn save_session(app: &tauri::AppHandle, session: &GeminiEditSession) -> Result<(), String> {
+    let path = session_path(app, &session.session_id)?;
+    let json = serde_json::to_string(session).map_err(|e| format!("序列化会话失败: {}", e))?;
+    write_file_atomic(&path, json.as_bytes())
+}
+
+// 会话发送锁的引用：释放时如果没有其他任务在使用同一会话的锁，从锁表中移除，避免锁表一直增长
+struct SessionLock {
+    session_id: String,
+    lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Drop for SessionLock {
+    fn drop(&mut self) {
+        let mut locks = SESSION_LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+        // 引用计数只在持有锁表时增加：只剩锁表和自身两份时没有其他任务在等待
+        let unused = locks
+            .get(&self.session_id)
+            .is_some_and(|lock| Arc::ptr_eq(lock, &self.lock) && Arc::strong_count(&self.lock) == 2);
+        if unused {
+            locks.remove(&self.session_id);
+        }
+    }
+}
+
+fn session_lock(session_id: &str) -> SessionLock {
+    let mut locks = SESSION_LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+    SessionLock {
+        session_id: session_id.to_string(),
+        lock: locks.entry(session_id.to_string()).or_default().clone(),
+    }
+}
+
+/// 构建重发的历史：只有最近的消息保留图片数据，更早的 inlineData 替换为占位文本
+/// （保留 thoughtSignature，持久化的会话仍保存完整历史）
+fn history_for_request(contents: &[Value]) -> Vec<Value> {
+    let keep_from = contents.len().saturating_sub(HISTORY_IMAGE_CONTENTS);
+    contents
+        .iter()
+        .enumerate()
+        .map(|(index, content)| {
+            let mut content = content.clone();
+            if index >= keep_from {
+                return content;
+            }
+            let parts = content.get_mut("parts").and_then(|p| p.as_array_mut());
+            for part in parts.into_iter().flatten() {
+                let Some(obj) = part.as_object_mut() else { continue };
+                if obj.remove("inlineData").is_some() || obj.remove("inline_data").is_some() {
+                    obj.insert("text".to_string(), Value::String("[较早的图片已省略]".to_string()));
+                }
+            }
+            content
+        })
+        .collect()
+}
+
+/// 取第一个候选的模型回复，原样加入历史（缺少 role 时补为 model）
+fn model_turn(response: &Value) -> Option<Value> {
+    let mut content = response.pointer("/candidates/0/content")?.clone();
+    let has_parts = content
+        .get("parts")
+        .and_then(|p| p.as_array())
+        .is_some_and(|p| !p.is_empty());
+    if !has_parts {
+        return None;
+    }
+    if let Some(obj) = content.as_object_mut() {
+        obj.entry("role").or_insert_with(|| Value::String("model".to_string()));
+    }
+    Some(content)
+}
+
+// Tauri 命令：在会话中继续编辑
+#[tauri::command]
+pub async fn gemini_edit_session_send(app: tauri::AppHandle, mut params: GeminiEditSessionParams) -> GeminiResult {
+    rust_log!("[Rust] gemini_edit_session_send called: session={}", params.session_id);
+
+    if let Err(e) = resolve_api_key(&app, &params.credential_id, &mut params.api_key) {
+        return GeminiResult::failure(e);
+    }
+
+    // 同一会话的并发发送排队执行，避免两轮基于同一份历史且互相覆盖
+    let session_lock = session_lock(&params.session_id);
+    let _guard = session_lock.lock.lock().await;
+
+    let mut session = match load_session(&app, &params.session_id) {
+        Ok(Some(s)) => s,
+        Ok(None) => {
+            let now = chrono::Utc::now().timestamp_millis();
+            GeminiEditSession {
+                session_id: params.session_id.clone(),
+                model: params.model.clone(),
+                created_at: now,
+                updated_at: now,
+                contents: Vec::new(),
+            }
+        }
+        Err(e) => return GeminiResult::failure(e),
+    };
+    rust_log!("[Rust] Session history: {} turns", session.contents.len());
+
+    // 本轮用户输入
+    let mut parts: Vec<Part> = vec![Part::Text {
+        text: params.prompt.clone(),
+    }];
+    if let Some(images) = &params.images {
+        match build_image_parts(&app, images).await {
+            Ok(image_parts) => parts.extend(image_parts),
+            Err(e) => return GeminiResult::failure(e),
+        }
+    }
+    let user_turn = match serde_json::to_value(&parts) {
+        Ok(parts) => serde_json::json!({ "role": "user", "parts": parts }),
+        Err(e) => return GeminiResult::failure(format!("构建请求失败: {}", e)),
+    };
+
+    let mut contents = history_for_request(&session.contents);
+    contents.push(user_turn.clone());
+    let request_body = SessionRequest {
+        contents: &contents,
+        generation_config: image_generation_config(
+            params.aspect_ratio.clone(),
+            params.image_size.clone(),
+            None,
+            &params.tuning,
+        ),
+        safety_settings: params.tuning.safety_settings.clone(),
+        system_instruction: params.tuning.system_content(),
+    };
+
+    let usage_context = params.usage_context.or_output(params.output.as_ref());
+    let planned = planned_image_usage(&params.model, params.image_size.as_deref(), 1);
+    let _reservation = match check_budget(&app, &usage_context, "gemini", &params.model, &planned) {
+        Ok(reservation) => reservation,
+        Err(e) => return GeminiResult::budget_exceeded(e),
+    };
+
+    let response_text =
+        match post_generate_content(&params.base_url, &params.api_key, &params.model, &request_body).await {
+            Ok(t) => t,
+            Err(e) => return e.into(),
+        };
+
+    // 同时保留原始 JSON（用于写入历史）和结构化结果
+    let raw: Value = match serde_json::from_str(&response_text) {
+        Ok(v) => v,
+        Err(e) => return GeminiResult::failure(format!("解析响应失败: {}", e)),
+    };
+    let gemini_response: GeminiResponse = match serde_json::from_value(raw.clone()) {
+        Ok(r) => r,
+        Err(e) => return GeminiResult::failure(format!("解析响应失败: {}", e)),
+    };
+    if let Some(err) = &gemini_response.error {
+        return GeminiResult::failure(err.message.clone());
+    }
+    let (mut images, text) = collect_images(&gemini_response);
+    let usage = generated_image_usage(&gemini_response, images.len());
+    record_usage(&app, &usage_context, "gemini", &params.model, &usage);
+    let Some(model_content) = model_turn(&raw) else {
+        return GeminiResult::failure(explain_empty_response(&gemini_response))
+            .with_diagnostics(&gemini_response);
+    };
+
+    // 仅在成功时写入历史，失败的轮次不影响后续编辑
+    session.contents.push(user_turn);
+    session.contents.push(model_content);
+    session.model = params.model.clone();
+    session.updated_at = chrono::Utc::now().timestamp_millis();
+    let warning = match save_session(&app, &session) {
+        Ok(()) => None,
+        Err(e) => {
+            rust_log!("[Rust] Failed to save session: {}", e);
+            Some(format!("会话历史保存失败，下一轮编辑将无法延续本轮结果: {}", e))
+        }
+    };
+
+    if let Some(output) = &params.output {
+        let metadata = ImageMetadata {
+            prompt: Some(params.prompt.clone()),
+            input_images: input_infos(
+                &app,
+                params.images.as_deref().unwrap_or_default(),
+                output.input_labels.as_deref(),
+            ),
+            provider: Some("gemini".to_string()),
+            model: Some(params.model.clone()),
+            aspect_ratio: params.aspect_ratio.clone(),
+            image_size: params.image_size.clone(),
+            seed: params.tuning.seed,
+            operation: Some(format!("edit session {} turn {}", session.session_id, session.contents.len() / 2)),
+            ..Default::default()
+        };
+        if let Err(e) = save_gemini_images(&app, &mut images, output, &metadata) {
+            return GeminiResult {
+                warning,
+                ..GeminiResult::from_images(false, images, text, Some(e)).with_diagnostics(&gemini_response)
+            };
+        }
+    }
+
+    GeminiResult {
+        warning,
+        ..GeminiResult::from_images(true, images, text, None).with_diagnostics(&gemini_response)
+    }
+}
+
+// Tauri 命令：列出所有编辑会话
+#[tauri::command]
+pub fn gemini_edit_session_list(app: tauri::AppHandle) -> Result<Vec<GeminiEditSessionSummary>, String> {
+    let dir = get_sessions_dir(&app)?;
+    let mut sessions = Vec::new();
+    for entry in fs::read_dir(&dir).map_err(|e| format!("读取会话目录失败: {}", e))?.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let Ok(content) = fs::read_to_string(&path) else { continue };
+        if let Ok(session) = serde_json::from_str::<GeminiEditSession>(&content) {
+            sessions.push(GeminiEditSessionSummary {
+                session_id: session.session_id,
+                model: session.model,
+                turns: session.contents.len() / 2,
+                created_at: session.created_at,
+                updated_at: session.updated_at,
+            });
+        }
+    }
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.updated_at));
+    Ok(sessions)
+}
+
+// Tauri 命令：删除编辑会话（重新开始编辑）
+#[tauri::command]
+pub fn gemini_edit_session_delete(app: tauri::AppHandle, session_id: String) -> Result<(), String> {
+    let path = session_path(&app, &session_id)?;
+    if path.exists() {
+        fs::remove_file(&path).map_err(|e| format!("删除会话失败: {}", e))?;
+    }
+    SESSION_LOCKS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(&session_id);
+    rust_log!("[Rust] Gemini edit session deleted: {}", session_id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_lock_removed_when_unused() {
+        let session_id = "test-session-lock";
+        let first = session_lock(session_id);
+        let second = session_lock(session_id);
+        assert!(Arc::ptr_eq(&first.lock, &second.lock));
+
+        drop(first);
+        assert!(SESSION_LOCKS.lock().unwrap().contains_key(session_id));
+        drop(second);
+        assert!(!SESSION_LOCKS.lock().unwrap().contains_key(session_id));
+    }
+
+    #[test]
+    fn test_model_turn_keeps_thought_signature() {
+        let response = serde_json::json!({
+            "candidates": [{"content": {"parts": [
+                {"text": "好的", "thoughtSignature": "sig-1"},
+                {"inlineData": {"mimeType": "image/png", "data": "AAA"}, "thoughtSignature": "sig-2"}
+            ]}}]
+        });
+        let turn = model_turn(&response).unwrap();
+        assert_eq!(turn["role"], "model");
+        assert_eq!(turn["parts"][1]["thoughtSignature"], "sig-2");
+        assert!(model_turn(&serde_json::json!({"candidates": []})).is_none());
+    }
+
+    #[test]
+    fn test_history_for_request_drops_old_images() {
+        let image_turn = |role: &str, data: &str| {
+            serde_json::json!({"role": role, "parts": [
+                {"inlineData": {"mimeType": "image/png", "data": data}, "thoughtSignature": "sig"}
+            ]})
+        };
+        let contents: Vec<Value> = (0..6)
+            .map(|i| image_turn(if i % 2 == 0 { "user" } else { "model" }, &format!("IMG{}", i)))
+            .collect();
+
+        let history = history_for_request(&contents);
+        assert_eq!(history.len(), 6);
+        assert!(history[0]["parts"][0].get("inlineData").is_none());
+        assert_eq!(history[1]["parts"][0]["thoughtSignature"], "sig");
+        assert_eq!(history[2]["parts"][0]["inlineData"]["data"], "IMG2");
+        assert_eq!(history[5]["parts"][0]["inlineData"]["data"], "IMG5");
+    }
+}
@@ -0,0 +1,276 @@
+// 上传前图片预处理
+// 检测输入图片的真实 MIME 类型，按各供应商的上传限制缩放/重新编码，并修正声明的 MIME
+
+use crate::image_processing::{encode_within_bytes, OutputFormat};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use image::imageops::FilterType;
+use image::ImageReader;
+use std::io::Cursor;
+
+/// 上传目标（不同供应商/字段的限制不同）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadTarget {
+    /// Gemini generateContent 的 inline_data
+    GeminiInline,
+    /// Sora（OpenAI videos）multipart 的 input_reference
+    SoraReference,
+    /// Veo 的 images / reference_images
+    VeoReference,
+    /// Kling image2video 的 image
+    KlingImage,
+    /// OpenAI images/edits 的 image[]
+    OpenAiEdit,
+    /// dall-e-2 images/edits 的 image（正方形 PNG，小于 4MB）
+    DallE2Edit,
+    /// 火山方舟（Seedream）图片生成的参考图
+    ArkReference,
+}
+
+/// 供应商上传限制
+#[derive(Debug, Clone, Copy)]
+pub struct UploadLimits {
+    /// 单张图片最大字节数（原始字节，非 base64）
+    pub max_bytes: u64,
+    /// 最长边上限
+    pub max_dimension: u32,
+    /// 允许的格式（第一个为重新编码时的首选无损格式）
+    pub allowed_formats: &'static [OutputFormat],
+}
+
+impl UploadTarget {
+    pub fn limits(&self) -> UploadLimits {
+        match self {
+            // Gemini 单次请求 inline 数据上限 20MB（base64 膨胀约 4/3），图片内部会缩放到 3072 以内
+            UploadTarget::GeminiInline => UploadLimits {
+                max_bytes: 7 * 1024 * 1024,
+                max_dimension: 3072,
+                allowed_formats: &[OutputFormat::Png, OutputFormat::Jpeg, OutputFormat::Webp],
+            },
+            // Sora 参考图支持 JPEG/PNG/WebP，分辨率应与输出视频一致
+            UploadTarget::SoraReference => UploadLimits {
+                max_bytes: 10 * 1024 * 1024,
+                max_dimension: 1920,
+                allowed_formats: &[OutputFormat::Png, OutputFormat::Jpeg, OutputFormat::Webp],
+            },
+            // Veo 参考图仅支持 PNG/JPEG
+            UploadTarget::VeoReference => UploadLimits {
+                max_bytes: 8 * 1024 * 1024,
+                max_dimension: 4096,
+                allowed_formats: &[OutputFormat::Png, OutputFormat::Jpeg],
+            },
+            // Kling 图片不超过 10MB，仅支持 JPG/PNG
+            UploadTarget::KlingImage => UploadLimits {
+                max_bytes: 10 * 1024 * 1024,
+                max_dimension: 4096,
+                allowed_formats: &[OutputFormat::Png, OutputFormat::Jpeg],
+            },
+            // gpt-image 编辑输入单张不超过 50MB，支持 PNG/JPEG/WebP
+            UploadTarget::OpenAiEdit => UploadLimits {
+                max_bytes: 50 * 1024 * 1024,
+                max_dimension: 4096,
+                allowed_formats: &[OutputFormat::Png, OutputFormat::Jpeg, OutputFormat::Webp],
+            },
+            // dall-e-2 编辑仅支持小于 4MB 的正方形 PNG（正方形由调用方检查）
+            UploadTarget::DallE2Edit => UploadLimits {
+                max_bytes: 4 * 1024 * 1024 - 1,
+                max_dimension: 1024,
+                allowed_formats: &[OutputFormat::Png],
+            },
+            // Seedream 参考图不超过 10MB，宽高不超过 6000
+            UploadTarget::ArkReference => UploadLimits {
+                max_bytes: 10 * 1024 * 1024,
+                max_dimension: 6000,
+                allowed_formats: &[OutputFormat::Png, OutputFormat::Jpeg, OutputFormat::Webp],
+            },
+        }
+    }
+}
+
+/// 预处理后的图片
+#[derive(Debug, Clone)]
+pub struct PreparedImage {
+    pub data: Vec<u8>,
+    pub mime_type: String,
+    pub extension: &'static str,
+}
+
+impl PreparedImage {
+    pub fn to_base64(&self) -> String {
+        BASE64.encode(&self.data)
+    }
+}
+
+/// 解码 base64 图片（兼容 data URL 前缀）
+pub fn decode_base64_image(input: &str) -> Result<Vec<u8>, String> {
+    let payload = match input.split_once(";base64,") {
+        Some((prefix, data)) if prefix.starts_with("data:") => data,
+        _ => input,
+    };
+    BASE64
+        .decode(payload.trim())
+        .map_err(|e| format!("Base64 解码失败: {}", e))
+}
+
+/// 按上传目标预处理 base64 图片
+pub async fn prepare_base64_image(input: &str, target: UploadTarget) -> Result<PreparedImage, String> {
+    let data = decode_base64_image(input)?;
+    prepare_image(data, target, None).await
+}
+
+/// 按上传目标预处理图片字节
+/// 已满足限制的图片原样返回（仅修正 MIME），否则缩放并重新编码
+/// exact_size 指定时缩放并居中裁剪到该尺寸（如 Sora 要求参考图与视频分辨率一致）
+pub async fn prepare_image(
+    data: Vec<u8>,
+    target: UploadTarget,
+    exact_size: Option<(u32, u32)>,
+) -> Result<PreparedImage, String> {
+    run_blocking(move || prepare_image_bytes(data, target, target.limits(), exact_size)).await
+}
+
+/// 按上传目标预处理图片字节，并将体积限制收紧到 max_bytes（多张图片共享请求体积上限时使用）
+pub async fn prepare_image_within(
+    data: Vec<u8>,
+    target: UploadTarget,
+    max_bytes: u64,
+) -> Result<PreparedImage, String> {
+    let mut limits = target.limits();
+    limits.max_bytes = limits.max_bytes.min(max_bytes);
+    run_blocking(move || prepare_image_bytes(data, target, limits, None)).await
+}
+
+/// 在阻塞线程中执行缩放和重新编码（Lanczos 缩放和体积二分搜索耗时较长，避免阻塞异步运行时）
+async fn run_blocking<F>(task: F) -> Result<PreparedImage, String>
+where
+    F: FnOnce() -> Result<PreparedImage, String> + Send + 'static,
+{
+    tokio::task::spawn_blocking(task)
+        .await
+        .map_err(|e| format!("图片预处理任务失败: {}", e))?
+}
+
+fn prepare_image_bytes(
+    data: Vec<u8>,
+    target: UploadTarget,
+    limits: UploadLimits,
+    exact_size: Option<(u32, u32)>,
+) -> Result<PreparedImage, String> {
+    let source_format = image::guess_format(&data).ok();
+    let allowed_source = source_format
+        .map(|f| OutputFormat::from_image_format(Some(f)))
+        .filter(|f| Some(f.image_format()) == source_format && limits.allowed_formats.contains(f));
+
+    let dimensions = ImageReader::new(Cursor::new(&data))
+        .with_guessed_format()
+        .ok()
+        .and_then(|r| r.into_dimensions().ok());
+
+    let Some((width, height)) = dimensions else {
+        return Err("无法识别的图片格式".to_string());
+    };
+
+    let fits_size = data.len() as u64 <= limits.max_bytes;
+    let fits_dimension = width.max(height) <= limits.max_dimension;
+    let fits_exact = exact_size.is_none_or(|size| size == (width, height));
+
+    if let Some(format) = allowed_source {
+        if fits_size && fits_dimension && fits_exact {
+            return Ok(PreparedImage {
+                data,
+                mime_type: format.mime_type().to_string(),
+                extension: format.extension(),
+            });
+        }
+    }
+
+    rust_log!(
+        "[Rust] Preprocessing image for {:?}: {:?} {}x{}, {} bytes",
+        target,
+        source_format,
+        width,
+        height,
+        data.len()
+    );
+
+    let mut img = image::load_from_memory(&data).map_err(|e| format!("图片解析失败: {}", e))?;
+    if let Some((w, h)) = exact_size {
+        img = img.resize_to_fill(w, h, FilterType::Lanczos3);
+    } else if !fits_dimension {
+        img = img.resize(limits.max_dimension, limits.max_dimension, FilterType::Lanczos3);
+    }
+
+    // 优先保留源格式；不被支持时使用首选格式；体积超限时尝试 JPEG
+    let preferred = allowed_source.unwrap_or(limits.allowed_formats[0]);
+    let mut candidates = vec![preferred];
+    if preferred != OutputFormat::Jpeg && limits.allowed_formats.contains(&OutputFormat::Jpeg) {
+        candidates.push(OutputFormat::Jpeg);
+    }
+
+    let mut last_error = String::new();
+    for format in candidates {
+        match encode_within_bytes(&img, format, limits.max_bytes, 256) {
+            Ok((encoded, result)) => {
+                rust_log!(
+                    "[Rust] Image prepared: {} {}x{}, {} bytes",
+                    format.mime_type(),
+                    result.width(),
+                    result.height(),
+                    encoded.len()
+                );
+                return Ok(PreparedImage {
+                    data: encoded,
+                    mime_type: format.mime_type().to_string(),
+                    extension: format.extension(),
+                });
+            }
+            Err(e) => last_error = e,
+        }
+    }
+
+    Err(last_error)
+}
+
+/// 解析 "1280x720" 形式的尺寸
+pub fn parse_size(size: &str) -> Option<(u32, u32)> {
+    let (w, h) = size.split_once(['x', 'X', '*'])?;
+    Some((w.trim().parse().ok()?, h.trim().parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_processing::encode_image;
+    use image::{DynamicImage, ImageFormat};
+
+    fn sample_image(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(image::RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([(x % 256) as u8, (y % 256) as u8, 128])
+        }))
+    }
+
+    #[test]
+    fn test_jpeg_is_labelled_as_jpeg() {
+        let jpeg = encode_image(&sample_image(64, 64), OutputFormat::Jpeg, 90).unwrap();
+        let prepared = prepare_image_bytes(jpeg.clone(), UploadTarget::SoraReference, UploadTarget::SoraReference.limits(), None).unwrap();
+        assert_eq!(prepared.mime_type, "image/jpeg");
+        assert_eq!(prepared.data, jpeg);
+    }
+
+    #[test]
+    fn test_webp_converted_for_veo() {
+        let webp = encode_image(&sample_image(64, 64), OutputFormat::Webp, 90).unwrap();
+        let prepared = prepare_image_bytes(webp, UploadTarget::VeoReference, UploadTarget::VeoReference.limits(), None).unwrap();
+        assert_eq!(prepared.mime_type, "image/png");
+        assert_eq!(image::guess_format(&prepared.data).unwrap(), ImageFormat::Png);
+    }
+
+    #[test]
+    fn test_exact_size_and_data_url() {
+        let png = encode_image(&sample_image(100, 50), OutputFormat::Png, 90).unwrap();
+        let data_url = format!("data:image/png;base64,{}", BASE64.encode(&png));
+        let decoded = decode_base64_image(&data_url).unwrap();
+        let prepared = prepare_image_bytes(decoded, UploadTarget::SoraReference, UploadTarget::SoraReference.limits(), parse_size("40x40")).unwrap();
+        let img = image::load_from_memory(&prepared.data).unwrap();
+        assert_eq!((img.width(), img.height()), (40, 40));
+    }
+}
@@ -0,0 +1,579 @@
+// 本地图片处理
+// 基于 image crate 实现缩放、裁剪、旋转/翻转、补边、格式转换与按体积压缩
+
+use crate::storage::{read_stored_image, store_image_bytes, ImageInfo, ImageMetadata, ImageType, InputImageInfo};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageEncoder, ImageFormat, Rgba, RgbaImage};
+use serde::Deserialize;
+
+// ==================== 通用数据结构 ====================
+
+/// 输出格式
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Png,
+    #[serde(alias = "jpg")]
+    Jpeg,
+    Webp,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Webp => "webp",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Png => "image/png",
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::Webp => "image/webp",
+        }
+    }
+
+    pub fn image_format(&self) -> ImageFormat {
+        match self {
+            OutputFormat::Png => ImageFormat::Png,
+            OutputFormat::Jpeg => ImageFormat::Jpeg,
+            OutputFormat::Webp => ImageFormat::WebP,
+        }
+    }
+
+    /// 从源图片格式推断输出格式（不支持编码的格式回退为 PNG）
+    pub fn from_image_format(format: Option<ImageFormat>) -> Self {
+        match format {
+            Some(ImageFormat::Jpeg) => OutputFormat::Jpeg,
+            Some(ImageFormat::WebP) => OutputFormat::Webp,
+            _ => OutputFormat::Png,
+        }
+    }
+}
+
+/// 输出选项（所有处理命令共用）
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageOutputOptions {
+    /// 输出格式，默认沿用源图片格式
+    pub format: Option<OutputFormat>,
+    /// JPEG 质量（1-100），默认 90；WebP 仅支持无损编码，设置后报错
+    pub quality: Option<u8>,
+    pub canvas_id: Option<String>,
+    pub node_id: Option<String>,
+}
+
+impl ImageOutputOptions {
+    /// 确定输出格式（未指定时沿用源格式），并校验 quality 是否适用
+    fn resolve_format(&self, source_format: Option<ImageFormat>) -> Result<OutputFormat, String> {
+        let format = self.format.unwrap_or_else(|| OutputFormat::from_image_format(source_format));
+        if format == OutputFormat::Webp && self.quality.is_some() {
+            return Err("WebP 仅支持无损编码，不支持设置质量".to_string());
+        }
+        Ok(format)
+    }
+}
+
+/// 缩放参数
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageResizeParams {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    /// 缩放模式：fit（等比缩放至框内，默认）、fill（等比缩放并居中裁剪）、exact（拉伸）
+    pub mode: Option<String>,
+    /// 滤波器：nearest、triangle、catmullrom、gaussian、lanczos3（默认）
+    pub filter: Option<String>,
+    #[serde(default)]
+    pub output: ImageOutputOptions,
+}
+
+/// 裁剪参数（像素坐标）
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageCropParams {
+    pub path: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub output: ImageOutputOptions,
+}
+
+/// 旋转/翻转参数
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageRotateParams {
+    pub path: String,
+    /// 顺时针旋转角度：0、90、180、270
+    pub degrees: Option<u32>,
+    pub flip_horizontal: Option<bool>,
+    pub flip_vertical: Option<bool>,
+    #[serde(default)]
+    pub output: ImageOutputOptions,
+}
+
+/// 补边参数
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImagePadParams {
+    pub path: String,
+    /// 目标宽高比，如 "16:9"
+    pub aspect_ratio: String,
+    /// 填充颜色（#RRGGBB 或 #RRGGBBAA），默认透明（JPEG 输出时为白色）
+    pub color: Option<String>,
+    #[serde(default)]
+    pub output: ImageOutputOptions,
+}
+
+/// 格式转换参数
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageConvertParams {
+    pub path: String,
+    #[serde(default)]
+    pub output: ImageOutputOptions,
+}
+
+/// 按体积压缩参数
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageCompressParams {
+    pub path: String,
+    /// 目标最大字节数（如供应商上传限制）
+    pub max_bytes: u64,
+    /// 允许的最小边长，低于此尺寸不再缩小，默认 256
+    pub min_dimension: Option<u32>,
+    #[serde(default)]
+    pub output: ImageOutputOptions,
+}
+
+// ==================== 核心处理函数 ====================
+
+/// 解析缩放滤波器
+pub fn parse_filter(name: Option<&str>) -> Result<FilterType, String> {
+    match name.unwrap_or("lanczos3").to_lowercase().as_str() {
+        "nearest" => Ok(FilterType::Nearest),
+        "triangle" | "bilinear" => Ok(FilterType::Triangle),
+        "catmullrom" | "bicubic" => Ok(FilterType::CatmullRom),
+        "gaussian" => Ok(FilterType::Gaussian),
+        "lanczos3" | "lanczos" => Ok(FilterType::Lanczos3),
+        other => Err(format!("不支持的缩放滤波器: {}", other)),
+    }
+}
+
+/// 解析宽高比字符串（"16:9" / "16x9" / "1.5"）
+pub fn parse_aspect_ratio(ratio: &str) -> Result<f64, String> {
+    let value = if let Some((w, h)) = ratio.split_once([':', 'x', '/']) {
+        let w: f64 = w.trim().parse().map_err(|_| format!("无效的宽高比: {}", ratio))?;
+        let h: f64 = h.trim().parse().map_err(|_| format!("无效的宽高比: {}", ratio))?;
+        if h == 0.0 {
+            return Err(format!("无效的宽高比: {}", ratio));
+        }
+        w / h
+    } else {
+        ratio.trim().parse().map_err(|_| format!("无效的宽高比: {}", ratio))?
+    };
+
+    if value <= 0.0 || !value.is_finite() {
+        return Err(format!("无效的宽高比: {}", ratio));
+    }
+    Ok(value)
+}
+
+/// 解析十六进制颜色
+fn parse_color(color: &str) -> Result<Rgba<u8>, String> {
+    let hex = color.trim().trim_start_matches('#');
+    // 按字节切片，非 ASCII 字符会落在字符边界之外
+    if !hex.is_ascii() {
+        return Err(format!("无效的颜色: {}", color));
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| format!("无效的颜色: {}", color))
+    };
+    match hex.len() {
+        6 => Ok(Rgba([channel(0)?, channel(2)?, channel(4)?, 255])),
+        8 => Ok(Rgba([channel(0)?, channel(2)?, channel(4)?, channel(6)?])),
+        _ => Err(format!("无效的颜色: {}", color)),
+    }
+}
+
+/// 输出图片的最大边长
+pub const MAX_OUTPUT_DIMENSION: u32 = 16384;
+
+/// 补边允许的最大宽高比（及其倒数）
+pub const MAX_PAD_ASPECT_RATIO: f64 = 100.0;
+
+/// 缩放图片
+pub fn resize_image(img: &DynamicImage, width: u32, height: u32, mode: &str, filter: FilterType) -> Result<DynamicImage, String> {
+    if width == 0 || height == 0 {
+        return Err("目标尺寸必须大于 0".to_string());
+    }
+    if width > MAX_OUTPUT_DIMENSION || height > MAX_OUTPUT_DIMENSION {
+        return Err(format!("目标尺寸不能超过 {}", MAX_OUTPUT_DIMENSION));
+    }
+    match mode {
+        "fit" => Ok(img.resize(width, height, filter)),
+        "fill" => Ok(img.resize_to_fill(width, height, filter)),
+        "exact" => Ok(img.resize_exact(width, height, filter)),
+        other => Err(format!("不支持的缩放模式: {}", other)),
+    }
+}
+
+/// 补边至目标宽高比（居中放置原图）
+pub fn pad_to_aspect(img: &DynamicImage, ratio: f64, color: Rgba<u8>) -> Result<DynamicImage, String> {
+    if !(1.0 / MAX_PAD_ASPECT_RATIO..=MAX_PAD_ASPECT_RATIO).contains(&ratio) {
+        return Err(format!("宽高比需在 1:{0} 到 {0}:1 之间", MAX_PAD_ASPECT_RATIO));
+    }
+    let (width, height) = (img.width(), img.height());
+    let current = width as f64 / height as f64;
+
+    let (new_width, new_height) = if (current - ratio).abs() < 1e-3 {
+        return Ok(img.clone());
+    } else if current > ratio {
+        (width as f64, (width as f64 / ratio).round())
+    } else {
+        ((height as f64 * ratio).round(), height as f64)
+    };
+    if new_width.max(new_height) > MAX_OUTPUT_DIMENSION as f64 {
+        return Err(format!(
+            "补边后尺寸 {}x{} 超过上限 {}",
+            new_width, new_height, MAX_OUTPUT_DIMENSION
+        ));
+    }
+    let (new_width, new_height) = (new_width as u32, new_height as u32);
+
+    let mut canvas = RgbaImage::from_pixel(new_width, new_height, color);
+    let offset_x = (new_width - width) / 2;
+    let offset_y = (new_height - height) / 2;
+    image::imageops::overlay(&mut canvas, &img.to_rgba8(), offset_x as i64, offset_y as i64);
+    Ok(DynamicImage::ImageRgba8(canvas))
+}
+
+/// 编码图片
+pub fn encode_image(img: &DynamicImage, format: OutputFormat, quality: u8) -> Result<Vec<u8>, String> {
+    let mut buffer = Vec::new();
+    match format {
+        OutputFormat::Png => {
+            let rgba = img.to_rgba8();
+            PngEncoder::new(&mut buffer)
+                .write_image(&rgba, rgba.width(), rgba.height(), image::ExtendedColorType::Rgba8)
+                .map_err(|e| format!("PNG 编码失败: {}", e))?;
+        }
+        OutputFormat::Jpeg => {
+            // JPEG 不支持透明通道，先合成到白色背景
+            let rgb = flatten_alpha(img);
+            JpegEncoder::new_with_quality(&mut buffer, quality.clamp(1, 100))
+                .write_image(&rgb, rgb.width(), rgb.height(), image::ExtendedColorType::Rgb8)
+                .map_err(|e| format!("JPEG 编码失败: {}", e))?;
+        }
+        OutputFormat::Webp => {
+            let rgba = img.to_rgba8();
+            WebPEncoder::new_lossless(&mut buffer)
+                .write_image(&rgba, rgba.width(), rgba.height(), image::ExtendedColorType::Rgba8)
+                .map_err(|e| format!("WebP 编码失败: {}", e))?;
+        }
+    }
+    Ok(buffer)
+}
+
+/// 将透明像素合成到白色背景
+fn flatten_alpha(img: &DynamicImage) -> image::RgbImage {
+    if !img.color().has_alpha() {
+        return img.to_rgb8();
+    }
+    let rgba = img.to_rgba8();
+    image::RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let p = rgba.get_pixel(x, y).0;
+        let alpha = p[3] as u32;
+        let blend = |c: u8| ((c as u32 * alpha + 255 * (255 - alpha)) / 255) as u8;
+        image::Rgb([blend(p[0]), blend(p[1]), blend(p[2])])
+    })
+}
+
+/// 压缩到指定体积以内
+/// JPEG 先二分查找质量，仍超限时逐步缩小尺寸；PNG/WebP 仅能通过缩小尺寸压缩
+pub fn encode_within_bytes(
+    img: &DynamicImage,
+    format: OutputFormat,
+    max_bytes: u64,
+    min_dimension: u32,
+) -> Result<(Vec<u8>, DynamicImage), String> {
+    let mut current = img.clone();
+
+    loop {
+        let encoded = if format == OutputFormat::Jpeg {
+            let mut best: Option<Vec<u8>> = None;
+            let (mut low, mut high) = (30u8, 95u8);
+            while low <= high {
+                let quality = low + (high - low) / 2;
+                let data = encode_image(&current, format, quality)?;
+                if data.len() as u64 <= max_bytes {
+                    best = Some(data);
+                    low = quality + 1;
+                } else {
+                    high = quality - 1;
+                }
+            }
+            best
+        } else {
+            let data = encode_image(&current, format, 100)?;
+            (data.len() as u64 <= max_bytes).then_some(data)
+        };
+
+        if let Some(data) = encoded {
+            return Ok((data, current));
+        }
+
+        let (width, height) = (current.width(), current.height());
+        if width.min(height) <= min_dimension {
+            return Err(format!(
+                "无法将图片压缩到 {} 字节以内（已缩小至 {}x{}）",
+                max_bytes, width, height
+            ));
+        }
+
+        let next_width = ((width as f64) * 0.85).round().max(1.0) as u32;
+        let next_height = ((height as f64) * 0.85).round().max(1.0) as u32;
+        current = current.resize_exact(next_width, next_height, FilterType::Lanczos3);
+    }
+}
+
+// ==================== 内部辅助函数 ====================
+
+/// 读取已存储的图片并解码，同时返回源格式
+fn load_stored_image(app: &tauri::AppHandle, path: &str) -> Result<(DynamicImage, Option<ImageFormat>), String> {
+    let data = read_stored_image(app, path)?;
+    let format = image::guess_format(&data).ok();
+    let img = image::load_from_memory(&data).map_err(|e| format!("图片解析失败: {}", e))?;
+    Ok((img, format))
+}
+
+/// 编码并保存为新图片
+fn save_processed(
+    app: &tauri::AppHandle,
+    img: &DynamicImage,
+    source_path: &str,
+    source_format: Option<ImageFormat>,
+    output: ImageOutputOptions,
+    operation: String,
+) -> Result<ImageInfo, String> {
+    let format = output.resolve_format(source_format)?;
+    let data = encode_image(img, format, output.quality.unwrap_or(90))?;
+    store_processed_bytes(app, &data, format, source_path, output, operation)
+}
+
+/// 保存已编码的图片数据，并在元数据中记录来源与操作
+fn store_processed_bytes(
+    app: &tauri::AppHandle,
+    data: &[u8],
+    format: OutputFormat,
+    source_path: &str,
+    output: ImageOutputOptions,
+    operation: String,
+) -> Result<ImageInfo, String> {
+    rust_log!("[Rust] Image processed: {} -> {} bytes ({})", operation, data.len(), format.extension());
+
+    let metadata = ImageMetadata {
+        prompt: None,
+        input_images: vec![InputImageInfo {
+            path: Some(source_path.to_string()),
+            label: "源图片".to_string(),
+        }],
+        node_id: output.node_id.clone(),
+        canvas_id: output.canvas_id.clone(),
+        operation: Some(operation),
+        ..Default::default()
+    };
+
+    store_image_bytes(
+        app,
+        data,
+        format.extension(),
+        output.canvas_id,
+        output.node_id,
+        Some(metadata),
+        Some(ImageType::Input),
+    )
+}
+
+/// 在阻塞线程中执行图片处理（避免阻塞异步运行时）
+async fn run_blocking<F>(task: F) -> Result<ImageInfo, String>
+where
+    F: FnOnce() -> Result<ImageInfo, String> + Send + 'static,
+{
+    tokio::task::spawn_blocking(task)
+        .await
+        .map_err(|e| format!("图片处理任务失败: {}", e))?
+}
+
+// ==================== Tauri 命令 ====================
+
+/// 缩放图片
+#[tauri::command]
+pub async fn image_resize(app: tauri::AppHandle, params: ImageResizeParams) -> Result<ImageInfo, String> {
+    rust_log!("[Rust] image_resize called: {}x{}", params.width, params.height);
+    run_blocking(move || {
+        let (img, source_format) = load_stored_image(&app, &params.path)?;
+        let filter = parse_filter(params.filter.as_deref())?;
+        let mode = params.mode.as_deref().unwrap_or("fit");
+        let resized = resize_image(&img, params.width, params.height, mode, filter)?;
+        let operation = format!("resize {}x{} ({})", resized.width(), resized.height(), mode);
+        save_processed(&app, &resized, &params.path, source_format, params.output, operation)
+    })
+    .await
+}
+
+/// 裁剪图片
+#[tauri::command]
+pub async fn image_crop(app: tauri::AppHandle, params: ImageCropParams) -> Result<ImageInfo, String> {
+    rust_log!("[Rust] image_crop called: {}x{} at ({}, {})", params.width, params.height, params.x, params.y);
+    run_blocking(move || {
+        let (img, source_format) = load_stored_image(&app, &params.path)?;
+        if params.width == 0
+            || params.height == 0
+            || params.x.saturating_add(params.width) > img.width()
+            || params.y.saturating_add(params.height) > img.height()
+        {
+            return Err(format!(
+                "裁剪区域超出图片范围（图片尺寸 {}x{}）",
+                img.width(),
+                img.height()
+            ));
+        }
+        let cropped = img.crop_imm(params.x, params.y, params.width, params.height);
+        let operation = format!("crop {}x{}+{}+{}", params.width, params.height, params.x, params.y);
+        save_processed(&app, &cropped, &params.path, source_format, params.output, operation)
+    })
+    .await
+}
+
+/// 旋转/翻转图片
+#[tauri::command]
+pub async fn image_rotate(app: tauri::AppHandle, params: ImageRotateParams) -> Result<ImageInfo, String> {
+    rust_log!("[Rust] image_rotate called: {:?}°", params.degrees);
+    run_blocking(move || {
+        let (img, source_format) = load_stored_image(&app, &params.path)?;
+        let degrees = params.degrees.unwrap_or(0) % 360;
+        let mut result = match degrees {
+            0 => img,
+            90 => img.rotate90(),
+            180 => img.rotate180(),
+            270 => img.rotate270(),
+            other => return Err(format!("仅支持 90 度倍数的旋转: {}", other)),
+        };
+
+        let mut operations = vec![format!("rotate {}", degrees)];
+        if params.flip_horizontal.unwrap_or(false) {
+            result = result.fliph();
+            operations.push("flip horizontal".to_string());
+        }
+        if params.flip_vertical.unwrap_or(false) {
+            result = result.flipv();
+            operations.push("flip vertical".to_string());
+        }
+
+        save_processed(&app, &result, &params.path, source_format, params.output, operations.join(", "))
+    })
+    .await
+}
+
+/// 补边至目标宽高比
+#[tauri::command]
+pub async fn image_pad(app: tauri::AppHandle, params: ImagePadParams) -> Result<ImageInfo, String> {
+    rust_log!("[Rust] image_pad called: {}", params.aspect_ratio);
+    run_blocking(move || {
+        let (img, source_format) = load_stored_image(&app, &params.path)?;
+        let ratio = parse_aspect_ratio(&params.aspect_ratio)?;
+        let color = match &params.color {
+            Some(c) => parse_color(c)?,
+            None => Rgba([255, 255, 255, 0]),
+        };
+        let padded = pad_to_aspect(&img, ratio, color)?;
+        let operation = format!("pad {} ({}x{})", params.aspect_ratio, padded.width(), padded.height());
+        save_processed(&app, &padded, &params.path, source_format, params.output, operation)
+    })
+    .await
+}
+
+/// 格式转换
+#[tauri::command]
+pub async fn image_convert(app: tauri::AppHandle, params: ImageConvertParams) -> Result<ImageInfo, String> {
+    rust_log!("[Rust] image_convert called: {:?}", params.output.format);
+    run_blocking(move || {
+        let (img, source_format) = load_stored_image(&app, &params.path)?;
+        let format = params.output.resolve_format(source_format)?;
+        let operation = format!("convert {}", format.extension());
+        save_processed(&app, &img, &params.path, source_format, params.output, operation)
+    })
+    .await
+}
+
+/// 压缩到指定体积以内（适配供应商上传限制）
+#[tauri::command]
+pub async fn image_compress(app: tauri::AppHandle, params: ImageCompressParams) -> Result<ImageInfo, String> {
+    rust_log!("[Rust] image_compress called: max_bytes={}", params.max_bytes);
+    run_blocking(move || {
+        let (img, source_format) = load_stored_image(&app, &params.path)?;
+        let format = params.output.resolve_format(source_format)?;
+        let (data, result) = encode_within_bytes(&img, format, params.max_bytes, params.min_dimension.unwrap_or(256))?;
+        let operation = format!(
+            "compress <= {} bytes ({}x{}, {})",
+            params.max_bytes,
+            result.width(),
+            result.height(),
+            format.extension()
+        );
+        store_processed_bytes(&app, &data, format, &params.path, params.output, operation)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_aspect_ratio() {
+        assert!((parse_aspect_ratio("16:9").unwrap() - 16.0 / 9.0).abs() < 1e-9);
+        assert!((parse_aspect_ratio("1.5").unwrap() - 1.5).abs() < 1e-9);
+        assert!(parse_aspect_ratio("1:0").is_err());
+        assert!(parse_aspect_ratio("abc").is_err());
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#ff000080").unwrap(), Rgba([255, 0, 0, 128]));
+        assert_eq!(parse_color("00ff00").unwrap(), Rgba([0, 255, 0, 255]));
+        assert!(parse_color("#12").is_err());
+        assert!(parse_color("#ff€0").is_err());
+    }
+
+    #[test]
+    fn test_pad_to_aspect() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::new(100, 100));
+        let padded = pad_to_aspect(&img, 2.0, Rgba([0, 0, 0, 0])).unwrap();
+        assert_eq!((padded.width(), padded.height()), (200, 100));
+        assert!(pad_to_aspect(&img, 1000.0, Rgba([0, 0, 0, 0])).is_err());
+        let wide = DynamicImage::ImageRgba8(RgbaImage::new(10_000, 100));
+        assert!(pad_to_aspect(&wide, 0.5, Rgba([0, 0, 0, 0])).is_err());
+    }
+
+    #[test]
+    fn test_encode_within_bytes_shrinks() {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(512, 512, |x, y| {
+            image::Rgb([(x * 7 % 256) as u8, (y * 13 % 256) as u8, ((x ^ y) % 256) as u8])
+        }));
+        let (data, result) = encode_within_bytes(&img, OutputFormat::Jpeg, 20_000, 16).unwrap();
+        assert!(data.len() as u64 <= 20_000);
+        assert!(result.width() <= 512);
+        assert_eq!(image::guess_format(&data).unwrap(), ImageFormat::Jpeg);
+    }
+}
@@ -0,0 +1,115 @@
+// 图片输入来源
+// 命令参数可直接传 base64，也可传应用存储路径或图片 ID，由 Rust 侧读取文件，避免图片两次跨越 IPC
+
+use crate::image_preprocess::decode_base64_image;
+use crate::storage::{find_image_by_id, resolve_storage_file, InputImageInfo};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::Deserialize;
+
+/// 图片来源
+/// 兼容旧格式：直接传字符串时视为 base64 数据
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum ImageSource {
+    /// 应用存储目录中的图片路径：{ "path": "..." }
+    Path { path: String },
+    /// 存储的图片 ID：{ "assetId": "...", "canvasId": "..." }
+    #[serde(rename_all = "camelCase")]
+    Asset {
+        asset_id: String,
+        canvas_id: Option<String>,
+    },
+    /// base64 数据：{ "data": "..." }
+    Base64 { data: String },
+    /// 旧格式：base64 字符串
+    Raw(String),
+}
+
+impl ImageSource {
+    /// 读取图片原始字节
+    pub async fn load_bytes(&self, app: &tauri::AppHandle) -> Result<Vec<u8>, String> {
+        match self {
+            ImageSource::Path { path } => {
+                let file = resolve_storage_file(app, path)?;
+                tokio::fs::read(&file)
+                    .await
+                    .map_err(|e| format!("读取图片失败 {}: {}", path, e))
+            }
+            ImageSource::Asset { asset_id, canvas_id } => {
+                let file = find_image_by_id(app, asset_id, canvas_id.as_deref())?;
+                tokio::fs::read(&file)
+                    .await
+                    .map_err(|e| format!("读取图片失败 {}: {}", asset_id, e))
+            }
+            ImageSource::Base64 { data } | ImageSource::Raw(data) => decode_base64_image(data),
+        }
+    }
+
+    /// 读取图片并编码为 base64（已是 base64 时直接返回，去掉 data URL 前缀）
+    pub async fn load_base64(&self, app: &tauri::AppHandle) -> Result<String, String> {
+        match self {
+            ImageSource::Base64 { data } | ImageSource::Raw(data) => Ok(match data.split_once(";base64,") {
+                Some((prefix, payload)) if prefix.starts_with("data:") => payload.to_string(),
+                _ => data.clone(),
+            }),
+            _ => Ok(BASE64.encode(self.load_bytes(app).await?)),
+        }
+    }
+
+    /// 生成元数据中记录的输入图片信息（base64 输入没有路径）
+    pub fn to_input_info(&self, app: &tauri::AppHandle, label: String) -> InputImageInfo {
+        let path = match self {
+            ImageSource::Path { path } => Some(
+                resolve_storage_file(app, path)
+                    .map(|p| p.to_string_lossy().to_string())
+                    .unwrap_or_else(|_| path.clone()),
+            ),
+            ImageSource::Asset { asset_id, canvas_id } => find_image_by_id(app, asset_id, canvas_id.as_deref())
+                .ok()
+                .map(|p| p.to_string_lossy().to_string()),
+            ImageSource::Base64 { .. } | ImageSource::Raw(_) => None,
+        };
+        InputImageInfo { path, label }
+    }
+
+    /// 日志描述（不输出图片数据）
+    pub fn describe(&self) -> String {
+        match self {
+            ImageSource::Path { path } => format!("path:{}", path),
+            ImageSource::Asset { asset_id, .. } => format!("asset:{}", asset_id),
+            ImageSource::Base64 { data } | ImageSource::Raw(data) => format!("base64:{} chars", data.len()),
+        }
+    }
+}
+
+/// 构建输入图片信息列表（优先使用前端提供的标签）
+pub fn input_infos(app: &tauri::AppHandle, sources: &[ImageSource], labels: Option<&[String]>) -> Vec<InputImageInfo> {
+    sources
+        .iter()
+        .enumerate()
+        .map(|(index, source)| {
+            let label = labels
+                .and_then(|l| l.get(index).cloned())
+                .unwrap_or_else(|| format!("图{}", index + 1));
+            source.to_input_info(app, label)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_image_source() {
+        let sources: Vec<ImageSource> = serde_json::from_str(
+            r#"["aGVsbG8=", {"path": "/a/b.png"}, {"assetId": "abc", "canvasId": "c1"}, {"data": "aGk="}]"#,
+        )
+        .unwrap();
+
+        assert!(matches!(&sources[0], ImageSource::Raw(d) if d == "aGVsbG8="));
+        assert!(matches!(&sources[1], ImageSource::Path { path } if path == "/a/b.png"));
+        assert!(matches!(&sources[2], ImageSource::Asset { asset_id, canvas_id } if asset_id == "abc" && canvas_id.as_deref() == Some("c1")));
+        assert!(matches!(&sources[3], ImageSource::Base64 { data } if data == "aGk="));
+    }
+}
@@ -1,16 +1,42 @@
+#[macro_use]
+mod log_redact; // 日志脱敏（rust_log! 宏需在其他模块之前声明）
 mod storage;
 mod gemini;
+mod gemini_session;
 mod llm;
+mod llm_agent;
 mod video;
 mod dalle;
+mod flux;
+mod doubao;
 mod text_removal;
+mod image_processing;
+mod image_preprocess;
+mod image_source;
+mod usage;
+mod budget;
+mod vault;
+mod endpoint;
+mod failover;
+mod models;
+mod provider_check;
 
 use storage::*;
 use gemini::*;
+use gemini_session::*;
 use llm::*;
+use llm_agent::*;
 use video::*;
 use dalle::*;
+use flux::*;
+use doubao::*;
 use text_removal::*;
+use image_processing::*;
+use usage::*;
+use budget::*;
+use vault::*;
+use models::*;
+use provider_check::*;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -19,6 +45,13 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_store::Builder::default().build())
+        .setup(|app| {
+            // 自定义存储目录需要在启动时授权 asset 协议访问
+            if let Err(e) = allow_storage_scope(app.handle()) {
+                rust_log!("[Rust] {}", e);
+            }
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             save_image,
             read_image,
@@ -29,12 +62,23 @@ pub fn run() {
             clear_cache,
             clear_all_images,
             get_storage_path,
+            get_storage_root_info,
+            set_storage_root,
+            migrate_storage,
             list_canvas_images,
             gemini_generate_content,
             gemini_generate_text,
+            // Gemini 多轮编辑会话
+            gemini_edit_session_send,
+            gemini_edit_session_list,
+            gemini_edit_session_delete,
             // LLM 代理命令
             openai_chat_completion,
             claude_chat_completion,
+            // LLM Agent 工具调用循环
+            llm_agent_run,
+            llm_submit_tool_result,
+            llm_agent_cancel,
             // 视频服务代理命令
             video_create_task,
             video_get_status,
@@ -50,13 +94,48 @@ pub fn run() {
             kling_download_video,
             // DALL-E 图片生成命令
             dalle_generate_image,
+            dalle_generate_image_stream,
+            // Flux（BFL）异步图片生成命令
+            flux_create_task,
+            flux_get_status,
+            flux_get_content,
+            // 火山方舟（Seedream）图片生成命令
+            doubao_generate_image,
+            // 本地图片处理命令
+            image_resize,
+            image_crop,
+            image_rotate,
+            image_pad,
+            image_convert,
+            image_compress,
             // 文字去除功能（本地化）
             remove_text_from_image,
             detect_text_regions,
             inpaint_background,
             // 批量处理命令
             process_pages_batch,
-            stop_batch_processing
+            stop_batch_processing,
+            // 用量与费用台账
+            usage_canvas_totals,
+            usage_daily_totals,
+            usage_get_price_table,
+            usage_set_price_table,
+            // 预算限制
+            budget_get_config,
+            budget_set_config,
+            budget_get_status,
+            // 凭据库
+            credential_add,
+            credential_list,
+            credential_rotate,
+            credential_delete,
+            vault_status,
+            vault_unlock,
+            vault_lock,
+            vault_set_passphrase,
+            // 模型列表与连接测试
+            list_models,
+            test_provider
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
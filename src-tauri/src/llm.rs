@@ -1,32 +1,254 @@
+use crate::budget::{BudgetExceeded, BudgetReservation};
+use crate::endpoint::{api_base, ApiFlavor, OpenAIEndpoint};
+use crate::failover::{run_with_failover, FailoverParams, FailoverResult, FailureKind, FallbackProvider, ServedBy};
+use crate::image_preprocess::decode_base64_image;
+use crate::usage::{record_usage, Usage, UsageContext, UsageLedger};
+use crate::vault::resolve_api_key;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::time::Duration;
 
 // ==================== 通用数据结构 ====================
 
 // 文件数据结构（用于多模态输入）
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct FileData {
     pub data: String,      // base64 编码的文件数据
     pub mime_type: String, // 文件MIME类型
-    #[allow(dead_code)]
-    pub file_name: Option<String>, // 文件名（可选，保留用于扩展）
+    pub file_name: Option<String>, // 文件名（可选）
 }
 
 // LLM 请求参数（前端传入）
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct LLMRequestParams {
     pub base_url: String,
-    pub api_key: String,
+    pub api_flavor: Option<ApiFlavor>, // OpenAI 兼容接口类型（未设置时按域名识别）
+    #[serde(default)]
+    pub api_key: String, // 使用 credential_id 时可省略
+    pub credential_id: Option<String>, // 凭据库中的凭据 ID（优先于 api_key）
     pub model: String,
-    pub prompt: String,
+    #[serde(default)]
+    pub prompt: String, // 本轮用户输入（messages 已包含完整对话时可为空）
     pub system_prompt: Option<String>,
+    pub messages: Option<Vec<ChatMessage>>, // 多轮对话历史，按顺序排在本轮 prompt 之前
     pub temperature: Option<f64>,
     pub max_tokens: Option<i32>,
     pub files: Option<Vec<FileData>>,
     pub response_json_schema: Option<serde_json::Value>,
+    pub output_format: Option<String>, // "text" or "json"（仅 Gemini 使用）
+    pub tools: Option<Vec<ToolDefinition>>, // 可供模型调用的工具
+    pub tool_choice: Option<String>,        // auto / none / required / 指定工具名
+    #[serde(default)]
+    pub fallbacks: Vec<FallbackProvider>, // 备用供应商（按顺序），需与主供应商使用相同协议
+    #[serde(flatten)]
+    pub usage_context: UsageContext, // 用量归属的画布和节点
+    #[serde(skip)]
+    pub ledger: Option<UsageLedger>, // 命令入口设置，用于请求前的预算检查
+}
+
+// 工具选择策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ToolChoice<'a> {
+    Auto,
+    None,
+    Required,
+    Tool(&'a str),
+}
+
+impl LLMRequestParams {
+    /// 命令入口调用：解析凭据并打开用量台账（台账不可用时不做预算检查）
+    pub(crate) fn prepare(&mut self, app: &tauri::AppHandle) -> Result<(), String> {
+        resolve_api_key(app, &self.credential_id, &mut self.api_key)?;
+        self.ledger = UsageLedger::open(app, self.usage_context.clone()).ok();
+        Ok(())
+    }
+
+    /// 发出请求前检查预算
+    pub(crate) fn check_budget(&self, provider: &str) -> Result<Option<BudgetReservation>, BudgetExceeded> {
+        match &self.ledger {
+            Some(ledger) => ledger.check_budget(provider, &self.model, &self.planned_usage()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// 预留用量：输入按约 4 字符 1 token 估算，输出按 max_tokens（未设置时 1024）
+    fn planned_usage(&self) -> Usage {
+        let history: usize = self.messages.iter().flatten().map(|m| m.content.len()).sum();
+        let chars = self.prompt.len() + self.system_prompt.as_ref().map_or(0, String::len) + history;
+        let output = self.max_tokens.filter(|&n| n > 0).unwrap_or(1024);
+        Usage::tokens(chars.div_ceil(4) as u64, output as u64)
+    }
+
+    pub(crate) fn tools(&self) -> &[ToolDefinition] {
+        self.tools.as_deref().unwrap_or_default()
+    }
+
+    pub(crate) fn tool_choice(&self) -> Option<ToolChoice<'_>> {
+        self.tool_choice.as_deref().map(|choice| match choice {
+            "auto" => ToolChoice::Auto,
+            "none" => ToolChoice::None,
+            "required" | "any" => ToolChoice::Required,
+            name => ToolChoice::Tool(name),
+        })
+    }
+}
+
+// 与协议无关的工具定义，parameters 为 JSON Schema
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default = "empty_object_schema")]
+    pub parameters: serde_json::Value,
+}
+
+fn empty_object_schema() -> serde_json::Value {
+    serde_json::json!({"type": "object", "properties": {}})
+}
+
+// 模型发起的工具调用
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub arguments: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>, // Gemini thoughtSignature，回传历史时需原样带上
+}
+
+// 对话角色
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    System,
+    User,
+    #[serde(alias = "model")]
+    Assistant,
+    Tool, // 工具执行结果
+}
+
+// 对话消息
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    #[serde(default)]
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<FileData>>, // 该消息附带的文件（仅用户消息）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>, // 助手消息发起的工具调用
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>, // 工具消息对应的调用 ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>, // 工具消息对应的工具名（缺省时按调用 ID 查找）
+}
+
+impl ChatMessage {
+    pub(crate) fn new(role: ChatRole, content: String) -> Self {
+        ChatMessage {
+            role,
+            content,
+            files: None,
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+            name: None,
+        }
+    }
+}
+
+// 整理后的一轮对话（系统消息已合并到 Conversation::system）
+pub(crate) struct ChatTurn<'a> {
+    pub role: ChatRole,
+    pub text: &'a str,
+    pub files: &'a [FileData],
+    pub tool_calls: &'a [ToolCall],
+    pub tool_call_id: &'a str,
+    pub tool_name: &'a str,
+}
+
+impl<'a> ChatTurn<'a> {
+    fn new(role: ChatRole, text: &'a str, files: &'a [FileData]) -> Self {
+        ChatTurn {
+            role,
+            text,
+            files,
+            tool_calls: &[],
+            tool_call_id: "",
+            tool_name: "",
+        }
+    }
+}
+
+pub(crate) struct Conversation<'a> {
+    pub system: Option<String>,
+    pub turns: Vec<ChatTurn<'a>>,
+    pub skipped_files: Vec<SkippedFile>,
+}
+
+/// 整理对话：合并 system_prompt 与历史中的系统消息，历史之后追加本轮 prompt 与 files
+pub(crate) fn build_conversation<'a>(
+    system_prompt: Option<&'a str>,
+    messages: &'a [ChatMessage],
+    prompt: &'a str,
+    files: &'a [FileData],
+) -> Conversation<'a> {
+    let mut system_parts: Vec<&str> = system_prompt.into_iter().filter(|s| !s.is_empty()).collect();
+    let mut turns = Vec::with_capacity(messages.len() + 1);
+    let mut skipped_files = Vec::new();
+    let mut tool_names: HashMap<&str, &str> = HashMap::new();
+
+    for message in messages {
+        let message_files = message.files.as_deref().unwrap_or_default();
+        match message.role {
+            ChatRole::System => {
+                if !message.content.is_empty() {
+                    system_parts.push(&message.content);
+                }
+            }
+            ChatRole::User => turns.push(ChatTurn::new(message.role, &message.content, message_files)),
+            // 各协议的助手消息和工具消息都不接受附件
+            ChatRole::Assistant | ChatRole::Tool => {
+                skipped_files.extend(message_files.iter().map(|file| SkippedFile {
+                    file_name: file.file_name.clone(),
+                    mime_type: file.mime_type.clone(),
+                    reason: "仅用户消息支持附件".to_string(),
+                }));
+                for call in &message.tool_calls {
+                    tool_names.insert(&call.id, &call.name);
+                }
+                let tool_call_id = message.tool_call_id.as_deref().unwrap_or_default();
+                let tool_name = message
+                    .name
+                    .as_deref()
+                    .or_else(|| tool_names.get(tool_call_id).copied())
+                    .unwrap_or_default();
+                turns.push(ChatTurn {
+                    tool_calls: &message.tool_calls,
+                    tool_call_id,
+                    tool_name,
+                    ..ChatTurn::new(message.role, &message.content, &[])
+                });
+            }
+        }
+    }
+
+    if !prompt.is_empty() || !files.is_empty() {
+        turns.push(ChatTurn::new(ChatRole::User, prompt, files));
+    }
+
+    Conversation {
+        system: (!system_parts.is_empty()).then(|| system_parts.join("\n\n")),
+        turns,
+        skipped_files,
+    }
 }
 
 // LLM 响应结果
@@ -36,6 +258,201 @@ pub struct LLMResult {
     pub success: bool,
     pub content: Option<String>,
     pub error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped_files: Vec<SkippedFile>, // 未能发送给模型的文件及原因
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>, // 模型请求的工具调用
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>, // token 用量（多次请求时为合计）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_exceeded: Option<BudgetExceeded>, // 超出预算时未发出请求
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub served_by: Option<ServedBy>, // 配置了备用供应商时，实际完成请求的供应商
+    #[serde(skip)]
+    pub(crate) failure_kind: Option<FailureKind>, // 请求失败的类型（用于判断是否切换供应商）
+}
+
+impl LLMResult {
+    pub(crate) fn failure(error: String) -> Self {
+        LLMResult {
+            success: false,
+            content: None,
+            error: Some(error),
+            skipped_files: Vec::new(),
+            tool_calls: Vec::new(),
+            usage: None,
+            budget_exceeded: None,
+            served_by: None,
+            failure_kind: None,
+        }
+    }
+
+    pub(crate) fn request_failure(kind: FailureKind, error: String) -> Self {
+        LLMResult {
+            failure_kind: Some(kind),
+            ..LLMResult::failure(error)
+        }
+    }
+
+    pub(crate) fn budget_exceeded(exceeded: BudgetExceeded) -> Self {
+        LLMResult {
+            budget_exceeded: Some(exceeded.clone()),
+            ..LLMResult::failure(exceeded.to_string())
+        }
+    }
+
+    /// 文本和工具调用至少有一项才算有效结果
+    pub(crate) fn from_output(
+        content: Option<String>,
+        tool_calls: Vec<ToolCall>,
+        skipped_files: Vec<SkippedFile>,
+        usage: Option<Usage>,
+    ) -> Self {
+        let content = content.filter(|c| !c.is_empty());
+        if content.is_none() && tool_calls.is_empty() {
+            return LLMResult {
+                usage,
+                ..LLMResult::failure("API 未返回有效内容".to_string())
+            };
+        }
+        LLMResult {
+            success: true,
+            content,
+            error: None,
+            skipped_files,
+            tool_calls,
+            usage,
+            budget_exceeded: None,
+            served_by: None,
+            failure_kind: None,
+        }
+    }
+}
+
+/// 累加多次请求的用量
+pub(crate) fn add_usage(total: Option<Usage>, usage: Option<Usage>) -> Option<Usage> {
+    match (total, usage) {
+        (Some(mut total), Some(usage)) => {
+            total.add(&usage);
+            Some(total)
+        }
+        (total, usage) => total.or(usage),
+    }
+}
+
+/// 协议对应的供应商名（用于用量台账）
+pub(crate) fn provider_name(protocol: &str) -> &'static str {
+    match protocol {
+        "openai" => "openai",
+        "claude" => "claude",
+        _ => "gemini",
+    }
+}
+
+/// 记录 LLM 调用用量
+pub(crate) fn record_llm_usage(app: &tauri::AppHandle, protocol: &str, params: &LLMRequestParams, result: &LLMResult) {
+    if let Some(usage) = &result.usage {
+        record_usage(app, &params.usage_context, provider_name(protocol), &params.model, usage);
+    }
+}
+
+/// 解析工具参数（模型偶尔返回非法 JSON，此时保留原始字符串）
+pub(crate) fn parse_tool_arguments(arguments: &str) -> serde_json::Value {
+    if arguments.trim().is_empty() {
+        return serde_json::json!({});
+    }
+    serde_json::from_str(arguments).unwrap_or_else(|_| serde_json::Value::String(arguments.to_string()))
+}
+
+/// 生成工具调用 ID（部分协议不返回 ID）
+pub(crate) fn new_tool_call_id() -> String {
+    format!("call_{}", uuid::Uuid::new_v4().simple())
+}
+
+// 跳过的文件
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedFile {
+    pub file_name: Option<String>,
+    pub mime_type: String,
+    pub reason: String,
+}
+
+// 按类型归类后的输入文件
+enum PreparedFile {
+    Image { mime_type: String, data: String },
+    Pdf { file_name: String, data: String },
+    Text { file_name: String, content: String },
+}
+
+// 作为纯文本内联的文件扩展名
+const TEXT_EXTENSIONS: &[&str] = &["txt", "md", "markdown", "csv", "tsv", "json"];
+
+fn is_text_file(file: &FileData) -> bool {
+    let mime = file.mime_type.to_ascii_lowercase();
+    if mime.starts_with("text/") || mime == "application/json" || mime == "application/csv" {
+        return true;
+    }
+    file.file_name
+        .as_deref()
+        .and_then(|name| name.rsplit_once('.'))
+        .is_some_and(|(_, ext)| TEXT_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// 归类输入文件：图片、PDF 原样发送，文本类文件解码后内联，其余跳过并记录原因
+fn prepare_files(files: &[FileData]) -> (Vec<PreparedFile>, Vec<SkippedFile>) {
+    let mut prepared = Vec::new();
+    let mut skipped = Vec::new();
+
+    for (index, file) in files.iter().enumerate() {
+        let file_name = file
+            .file_name
+            .clone()
+            .unwrap_or_else(|| format!("file_{}", index + 1));
+        let skip = |reason: String| SkippedFile {
+            file_name: file.file_name.clone(),
+            mime_type: file.mime_type.clone(),
+            reason,
+        };
+
+        if file.mime_type.starts_with("image/") {
+            prepared.push(PreparedFile::Image {
+                mime_type: file.mime_type.clone(),
+                data: file.data.clone(),
+            });
+        } else if file.mime_type == "application/pdf" {
+            prepared.push(PreparedFile::Pdf {
+                file_name,
+                data: file.data.clone(),
+            });
+        } else if is_text_file(file) {
+            match decode_base64_image(&file.data).map(String::from_utf8) {
+                Ok(Ok(content)) => prepared.push(PreparedFile::Text {
+                    file_name,
+                    content: content.trim_start_matches('\u{feff}').to_string(),
+                }),
+                Ok(Err(_)) => skipped.push(skip("文件不是有效的 UTF-8 文本".to_string())),
+                Err(e) => skipped.push(skip(e)),
+            }
+        } else {
+            skipped.push(skip(format!("不支持的文件类型: {}", file.mime_type)));
+        }
+    }
+
+    for file in &skipped {
+        rust_log!(
+            "[Rust] Skipping file {:?} ({}): {}",
+            file.file_name,
+            file.mime_type,
+            file.reason
+        );
+    }
+    (prepared, skipped)
+}
+
+/// 文本文件内联格式
+fn inline_text_file(file_name: &str, content: &str) -> String {
+    format!("文件 {}:\n```\n{}\n```", file_name, content)
 }
 
 // ==================== OpenAI 协议结构 ====================
@@ -50,12 +467,54 @@ struct OpenAIRequest {
     max_tokens: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     response_format: Option<OpenAIResponseFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<OpenAITool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Serialize)]
 struct OpenAIMessage {
     role: String,
-    content: OpenAIContent,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<OpenAIContent>, // 仅包含工具调用的助手消息可省略
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<Vec<OpenAIToolCall>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAITool {
+    #[serde(rename = "type")]
+    tool_type: String,
+    function: OpenAIFunctionDefinition,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAIFunctionDefinition {
+    name: String,
+    description: String,
+    parameters: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OpenAIToolCall {
+    id: String,
+    #[serde(rename = "type", default = "function_type")]
+    call_type: String,
+    function: OpenAIFunctionCall,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OpenAIFunctionCall {
+    name: String,
+    #[serde(default)]
+    arguments: String, // JSON 字符串
+}
+
+fn function_type() -> String {
+    "function".to_string()
 }
 
 #[derive(Debug, Serialize)]
@@ -72,6 +531,8 @@ enum OpenAIContentPart {
     Text { text: String },
     #[serde(rename = "image_url")]
     ImageUrl { image_url: OpenAIImageUrl },
+    #[serde(rename = "file")]
+    File { file: OpenAIFile },
 }
 
 #[derive(Debug, Serialize)]
@@ -79,6 +540,12 @@ struct OpenAIImageUrl {
     url: String,
 }
 
+#[derive(Debug, Serialize)]
+struct OpenAIFile {
+    filename: String,
+    file_data: String, // data URL
+}
+
 #[derive(Debug, Serialize)]
 struct OpenAIResponseFormat {
     #[serde(rename = "type")]
@@ -97,6 +564,15 @@ struct OpenAIJsonSchema {
 struct OpenAIResponse {
     choices: Option<Vec<OpenAIChoice>>,
     error: Option<OpenAIError>,
+    usage: Option<OpenAIUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -107,6 +583,7 @@ struct OpenAIChoice {
 #[derive(Debug, Deserialize)]
 struct OpenAIMessageResponse {
     content: Option<String>,
+    tool_calls: Option<Vec<OpenAIToolCall>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -125,6 +602,17 @@ struct ClaudeRequest {
     system: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<ClaudeTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct ClaudeTool {
+    name: String,
+    description: String,
+    input_schema: serde_json::Value,
 }
 
 #[derive(Debug, Serialize)]
@@ -147,6 +635,16 @@ enum ClaudeContentPart {
     Text { text: String },
     #[serde(rename = "image")]
     Image { source: ClaudeImageSource },
+    #[serde(rename = "document")]
+    Document { source: ClaudeImageSource },
+    #[serde(rename = "tool_use")]
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    #[serde(rename = "tool_result")]
+    ToolResult { tool_use_id: String, content: String },
 }
 
 #[derive(Debug, Serialize)]
@@ -161,11 +659,29 @@ struct ClaudeImageSource {
 struct ClaudeResponse {
     content: Option<Vec<ClaudeContentBlock>>,
     error: Option<ClaudeError>,
+    usage: Option<ClaudeUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+    #[serde(default)]
+    cache_creation_input_tokens: u64,
+    #[serde(default)]
+    cache_read_input_tokens: u64,
 }
 
 #[derive(Debug, Deserialize)]
 struct ClaudeContentBlock {
+    #[serde(rename = "type")]
+    block_type: Option<String>,
     text: Option<String>,
+    id: Option<String>,
+    name: Option<String>,
+    input: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -173,55 +689,483 @@ struct ClaudeError {
     message: String,
 }
 
-// ==================== OpenAI API 代理命令 ====================
+// ==================== 消息映射 ====================
 
-#[tauri::command]
-pub async fn openai_chat_completion(params: LLMRequestParams) -> LLMResult {
-    println!("[Rust] openai_chat_completion called");
-    println!("[Rust] base_url: {}", params.base_url);
-    println!("[Rust] model: {}", params.model);
+fn role_name(role: ChatRole) -> &'static str {
+    match role {
+        ChatRole::System => "system",
+        ChatRole::User => "user",
+        ChatRole::Assistant => "assistant",
+        ChatRole::Tool => "tool",
+    }
+}
 
-    // 构建消息数组
+/// OpenAI 工具定义与 tool_choice
+fn openai_tools(params: &LLMRequestParams) -> (Option<Vec<OpenAITool>>, Option<serde_json::Value>) {
+    if params.tools().is_empty() {
+        return (None, None);
+    }
+    let tools = params
+        .tools()
+        .iter()
+        .map(|tool| OpenAITool {
+            tool_type: function_type(),
+            function: OpenAIFunctionDefinition {
+                name: tool.name.clone(),
+                description: tool.description.clone(),
+                parameters: tool.parameters.clone(),
+            },
+        })
+        .collect();
+    let tool_choice = params.tool_choice().map(|choice| match choice {
+        ToolChoice::Auto => serde_json::json!("auto"),
+        ToolChoice::None => serde_json::json!("none"),
+        ToolChoice::Required => serde_json::json!("required"),
+        ToolChoice::Tool(name) => serde_json::json!({"type": "function", "function": {"name": name}}),
+    });
+    (Some(tools), tool_choice)
+}
+
+/// OpenAI：系统消息在最前，文件按 image_url / file / text 部分附在文本之后
+fn openai_messages(conversation: Conversation) -> (Vec<OpenAIMessage>, Vec<SkippedFile>) {
+    let mut skipped_files = conversation.skipped_files;
     let mut messages: Vec<OpenAIMessage> = Vec::new();
 
-    // 添加系统消息
-    if let Some(system_prompt) = &params.system_prompt {
-        if !system_prompt.is_empty() {
+    if let Some(system) = conversation.system {
+        messages.push(OpenAIMessage {
+            role: "system".to_string(),
+            content: Some(OpenAIContent::Text(system)),
+            tool_calls: None,
+            tool_call_id: None,
+        });
+    }
+
+    for turn in conversation.turns {
+        if turn.role == ChatRole::Tool {
             messages.push(OpenAIMessage {
-                role: "system".to_string(),
-                content: OpenAIContent::Text(system_prompt.clone()),
+                role: "tool".to_string(),
+                content: Some(OpenAIContent::Text(turn.text.to_string())),
+                tool_calls: None,
+                tool_call_id: Some(turn.tool_call_id.to_string()),
             });
+            continue;
+        }
+        if !turn.tool_calls.is_empty() {
+            messages.push(OpenAIMessage {
+                role: "assistant".to_string(),
+                content: (!turn.text.is_empty()).then(|| OpenAIContent::Text(turn.text.to_string())),
+                tool_calls: Some(
+                    turn.tool_calls
+                        .iter()
+                        .map(|call| OpenAIToolCall {
+                            id: call.id.clone(),
+                            call_type: function_type(),
+                            function: OpenAIFunctionCall {
+                                name: call.name.clone(),
+                                arguments: call.arguments.to_string(),
+                            },
+                        })
+                        .collect(),
+                ),
+                tool_call_id: None,
+            });
+            continue;
         }
-    }
 
-    // 构建用户消息
-    let user_content = if let Some(files) = &params.files {
-        if !files.is_empty() {
-            // 多模态消息
-            let mut parts: Vec<OpenAIContentPart> = vec![
-                OpenAIContentPart::Text { text: params.prompt.clone() }
-            ];
-            for file in files {
-                if file.mime_type.starts_with("image/") {
-                    parts.push(OpenAIContentPart::ImageUrl {
+        let (prepared_files, skipped) = prepare_files(turn.files);
+        skipped_files.extend(skipped);
+        let content = if prepared_files.is_empty() {
+            OpenAIContent::Text(turn.text.to_string())
+        } else {
+            // 多模态消息（只有文件时不发送空文本）
+            let mut parts = Vec::new();
+            if !turn.text.is_empty() {
+                parts.push(OpenAIContentPart::Text { text: turn.text.to_string() });
+            }
+            for file in prepared_files {
+                parts.push(match file {
+                    PreparedFile::Image { mime_type, data } => OpenAIContentPart::ImageUrl {
                         image_url: OpenAIImageUrl {
-                            url: format!("data:{};base64,{}", file.mime_type, file.data),
+                            url: format!("data:{};base64,{}", mime_type, data),
                         },
-                    });
-                }
+                    },
+                    PreparedFile::Pdf { file_name, data } => OpenAIContentPart::File {
+                        file: OpenAIFile {
+                            filename: file_name,
+                            file_data: format!("data:application/pdf;base64,{}", data),
+                        },
+                    },
+                    PreparedFile::Text { file_name, content } => OpenAIContentPart::Text {
+                        text: inline_text_file(&file_name, &content),
+                    },
+                });
             }
             OpenAIContent::Parts(parts)
+        };
+        messages.push(OpenAIMessage {
+            role: role_name(turn.role).to_string(),
+            content: Some(content),
+            tool_calls: None,
+            tool_call_id: None,
+        });
+    }
+
+    (messages, skipped_files)
+}
+
+/// 历史以助手消息开头时补在最前的用户消息
+const CONVERSATION_START_TEXT: &str = "（对话开始）";
+
+/// Claude：系统消息由调用方放入 system 字段，图片和文档需在文本之前
+fn claude_messages(conversation: Conversation) -> (Vec<ClaudeMessage>, Vec<SkippedFile>) {
+    let mut skipped_files = conversation.skipped_files;
+    let mut messages: Vec<ClaudeMessage> = Vec::new();
+
+    for turn in conversation.turns {
+        // 工具结果以 tool_result 放在用户消息中，连续的结果合并为一条
+        if turn.role == ChatRole::Tool {
+            let part = ClaudeContentPart::ToolResult {
+                tool_use_id: turn.tool_call_id.to_string(),
+                content: turn.text.to_string(),
+            };
+            match messages.last_mut() {
+                Some(ClaudeMessage { role, content: ClaudeContent::Parts(parts) })
+                    if role == "user"
+                        && parts.iter().all(|p| matches!(p, ClaudeContentPart::ToolResult { .. })) =>
+                {
+                    parts.push(part)
+                }
+                _ => messages.push(ClaudeMessage {
+                    role: "user".to_string(),
+                    content: ClaudeContent::Parts(vec![part]),
+                }),
+            }
+            continue;
+        }
+        if !turn.tool_calls.is_empty() {
+            let mut parts: Vec<ClaudeContentPart> = Vec::new();
+            if !turn.text.is_empty() {
+                parts.push(ClaudeContentPart::Text { text: turn.text.to_string() });
+            }
+            parts.extend(turn.tool_calls.iter().map(|call| ClaudeContentPart::ToolUse {
+                id: call.id.clone(),
+                name: call.name.clone(),
+                input: call.arguments.clone(),
+            }));
+            messages.push(ClaudeMessage {
+                role: "assistant".to_string(),
+                content: ClaudeContent::Parts(parts),
+            });
+            continue;
+        }
+
+        let (prepared_files, skipped) = prepare_files(turn.files);
+        skipped_files.extend(skipped);
+        // Claude 拒绝空文本内容，没有文本也没有文件的消息直接跳过
+        if prepared_files.is_empty() && turn.text.is_empty() {
+            continue;
+        }
+        let content = if prepared_files.is_empty() {
+            ClaudeContent::Text(turn.text.to_string())
         } else {
-            OpenAIContent::Text(params.prompt.clone())
+            let mut parts: Vec<ClaudeContentPart> = Vec::new();
+            for file in prepared_files {
+                parts.push(match file {
+                    PreparedFile::Image { mime_type, data } => ClaudeContentPart::Image {
+                        source: ClaudeImageSource {
+                            source_type: "base64".to_string(),
+                            media_type: mime_type,
+                            data,
+                        },
+                    },
+                    PreparedFile::Pdf { data, .. } => ClaudeContentPart::Document {
+                        source: ClaudeImageSource {
+                            source_type: "base64".to_string(),
+                            media_type: "application/pdf".to_string(),
+                            data,
+                        },
+                    },
+                    PreparedFile::Text { file_name, content } => ClaudeContentPart::Text {
+                        text: inline_text_file(&file_name, &content),
+                    },
+                });
+            }
+            if !turn.text.is_empty() {
+                parts.push(ClaudeContentPart::Text { text: turn.text.to_string() });
+            }
+            ClaudeContent::Parts(parts)
+        };
+        messages.push(ClaudeMessage {
+            role: role_name(turn.role).to_string(),
+            content,
+        });
+    }
+
+    // Claude 要求第一条消息来自用户（历史可能以助手消息开头）
+    if messages.first().is_some_and(|m| m.role != "user") {
+        messages.insert(0, ClaudeMessage {
+            role: "user".to_string(),
+            content: ClaudeContent::Text(CONVERSATION_START_TEXT.to_string()),
+        });
+    }
+
+    (messages, skipped_files)
+}
+
+/// Claude 工具定义与 tool_choice
+fn claude_tools(params: &LLMRequestParams) -> (Option<Vec<ClaudeTool>>, Option<serde_json::Value>) {
+    let mut tools: Vec<ClaudeTool> = params
+        .tools()
+        .iter()
+        .map(|tool| ClaudeTool {
+            name: tool.name.clone(),
+            description: tool.description.clone(),
+            input_schema: tool.parameters.clone(),
+        })
+        .collect();
+    let mut tool_choice = params.tool_choice().map(|choice| match choice {
+        ToolChoice::Auto => serde_json::json!({"type": "auto"}),
+        ToolChoice::None => serde_json::json!({"type": "none"}),
+        ToolChoice::Required => serde_json::json!({"type": "any"}),
+        ToolChoice::Tool(name) => serde_json::json!({"type": "tool", "name": name}),
+    });
+
+    // 结构化输出：Claude 没有 response_format，通过强制调用 structured_output 工具获得符合 Schema 的 input
+    if let Some(schema) = &params.response_json_schema {
+        tools.push(ClaudeTool {
+            name: STRUCTURED_OUTPUT_TOOL.to_string(),
+            description: "以结构化 JSON 返回最终结果".to_string(),
+            input_schema: claude_input_schema(schema),
+        });
+        // 同时提供了其他工具时不强制，避免模型无法调用它们
+        if params.tools().is_empty() {
+            tool_choice = Some(serde_json::json!({"type": "tool", "name": STRUCTURED_OUTPUT_TOOL}));
         }
+    }
+
+    if tools.is_empty() {
+        return (None, None);
+    }
+    (Some(tools), tool_choice)
+}
+
+fn is_object_schema(schema: &serde_json::Value) -> bool {
+    schema.get("type").and_then(|t| t.as_str()) == Some("object")
+}
+
+/// Claude 的 input_schema 必须是 object，其他类型包一层 result 字段
+fn claude_input_schema(schema: &serde_json::Value) -> serde_json::Value {
+    if is_object_schema(schema) {
+        schema.clone()
     } else {
-        OpenAIContent::Text(params.prompt.clone())
+        serde_json::json!({
+            "type": "object",
+            "properties": {"result": schema},
+            "required": ["result"]
+        })
+    }
+}
+
+/// 按协议分发请求（与前端 getCommandByProtocol 一致，未知协议按 Google 处理）
+pub(crate) async fn chat_by_protocol(protocol: &str, params: &LLMRequestParams) -> LLMResult {
+    match protocol {
+        "openai" => openai_chat(params).await,
+        "claude" => claude_chat(params).await,
+        _ => crate::gemini::gemini_chat(params).await,
+    }
+}
+
+// ==================== 结构化输出校验 ====================
+
+const STRUCTURED_OUTPUT_TOOL: &str = "structured_output";
+const MAX_REPAIR_ROUNDS: usize = 2;
+
+/// 去掉 Markdown 代码块包裹，返回 JSON 文本
+fn extract_json_text(content: &str) -> &str {
+    let trimmed = content.trim();
+    trimmed
+        .strip_prefix("```")
+        .map(|rest| rest.trim_start_matches(|c: char| c.is_ascii_alphanumeric()))
+        .and_then(|rest| rest.trim_end().strip_suffix("```"))
+        .map(str::trim)
+        .unwrap_or(trimmed)
+}
+
+/// 按 Schema 校验输出，返回不符合的原因（最多 10 条）
+fn schema_errors(validator: &jsonschema::Validator, content: &str) -> Vec<String> {
+    let value: serde_json::Value = match serde_json::from_str(extract_json_text(content)) {
+        Ok(v) => v,
+        Err(e) => return vec![format!("不是有效的 JSON: {}", e)],
     };
+    validator
+        .iter_errors(&value)
+        .take(10)
+        .map(|e| {
+            let path = e.instance_path.to_string();
+            if path.is_empty() {
+                e.to_string()
+            } else {
+                format!("{}: {}", path, e)
+            }
+        })
+        .collect()
+}
 
-    messages.push(OpenAIMessage {
-        role: "user".to_string(),
-        content: user_content,
-    });
+/// 修复请求：只发送原始输出与校验错误，不重复发送历史和文件
+fn repair_request(
+    params: &LLMRequestParams,
+    schema: &serde_json::Value,
+    content: &str,
+    errors: &[String],
+) -> LLMRequestParams {
+    let prompt = format!(
+        "下面的输出不符合要求的 JSON Schema。请根据校验错误修正，尽量保留原有内容，只输出修正后的 JSON。\n\n\
+         JSON Schema:\n{}\n\n校验错误:\n- {}\n\n原始输出:\n{}",
+        schema,
+        errors.join("\n- "),
+        content
+    );
+    LLMRequestParams {
+        base_url: params.base_url.clone(),
+        api_flavor: params.api_flavor,
+        api_key: params.api_key.clone(),
+        credential_id: None,
+        model: params.model.clone(),
+        prompt,
+        system_prompt: Some("你是 JSON 修复助手，只输出符合 Schema 的 JSON，不要输出解释或 Markdown 代码块。".to_string()),
+        messages: None,
+        temperature: Some(0.0),
+        max_tokens: params.max_tokens,
+        files: None,
+        response_json_schema: params.response_json_schema.clone(),
+        output_format: params.output_format.clone(),
+        tools: None,
+        tool_choice: None,
+        fallbacks: Vec::new(),
+        usage_context: params.usage_context.clone(),
+        ledger: params.ledger.clone(),
+    }
+}
+
+/// 发送请求；设置了 response_json_schema 时校验输出，不符合则自动发起修复请求
+pub(crate) async fn chat_with_schema(protocol: &str, params: &LLMRequestParams) -> LLMResult {
+    let Some(schema) = params.response_json_schema.as_ref() else {
+        return chat_by_protocol(protocol, params).await;
+    };
+    let validator = match jsonschema::validator_for(schema) {
+        Ok(v) => v,
+        Err(e) => return LLMResult::failure(format!("JSON Schema 无效: {}", e)),
+    };
+
+    let mut result = chat_by_protocol(protocol, params).await;
+    let skipped_files = std::mem::take(&mut result.skipped_files);
+    let mut total_usage = result.usage;
+    let mut round = 0;
+    loop {
+        // 请求失败或模型改为调用工具时不做校验
+        if !result.success || !result.tool_calls.is_empty() {
+            break;
+        }
+        let content = result.content.take().unwrap_or_default();
+        let errors = schema_errors(&validator, &content);
+        if errors.is_empty() {
+            result.content = Some(extract_json_text(&content).to_string());
+            break;
+        }
+
+        rust_log!("[Rust] Structured output mismatch (round {}): {}", round + 1, errors.join("; "));
+        if round >= MAX_REPAIR_ROUNDS {
+            result = LLMResult {
+                success: false,
+                content: Some(content),
+                error: Some(format!("输出不符合 JSON Schema: {}", errors.join("; "))),
+                ..result
+            };
+            break;
+        }
+        round += 1;
+        rust_log!("[Rust] Sending structured output repair request ({}/{})", round, MAX_REPAIR_ROUNDS);
+        result = chat_by_protocol(protocol, &repair_request(params, schema, &content, &errors)).await;
+        total_usage = add_usage(total_usage, result.usage);
+    }
+
+    LLMResult {
+        skipped_files,
+        usage: total_usage,
+        ..result
+    }
+}
+
+// ==================== OpenAI API 代理命令 ====================
+
+/// 命令入口：按供应商链依次请求，记录实际供应商的用量
+pub(crate) async fn chat_with_failover(app: &tauri::AppHandle, protocol: &str, params: LLMRequestParams) -> LLMResult {
+    run_with_failover(params, |mut params| async move {
+        if let Err(e) = params.prepare(app) {
+            return LLMResult::failure(e);
+        }
+        let result = chat_with_schema(protocol, &params).await;
+        record_llm_usage(app, protocol, &params, &result);
+        result
+    })
+    .await
+}
+
+impl FailoverParams for LLMRequestParams {
+    fn fallbacks(&self) -> &[FallbackProvider] {
+        &self.fallbacks
+    }
+
+    fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn use_fallback(&mut self, fallback: &FallbackProvider) {
+        fallback.apply(&mut self.base_url, &mut self.api_key, &mut self.credential_id, &mut self.model);
+        self.api_flavor = fallback.api_flavor;
+    }
+}
+
+impl FailoverResult for LLMResult {
+    fn failover_error(&self) -> Option<(FailureKind, &str)> {
+        self.failure_kind.zip(self.error.as_deref()).filter(|_| !self.success)
+    }
+
+    fn set_served_by(&mut self, served_by: ServedBy) {
+        self.served_by = Some(served_by);
+    }
+}
+
+#[tauri::command]
+pub async fn openai_chat_completion(app: tauri::AppHandle, params: LLMRequestParams) -> LLMResult {
+    rust_log!("[Rust] openai_chat_completion called");
+    chat_with_failover(&app, "openai", params).await
+}
+
+/// OpenAI Chat Completions 请求（命令与 Agent 循环共用）
+pub(crate) async fn openai_chat(params: &LLMRequestParams) -> LLMResult {
+    rust_log!("[Rust] base_url: {}", params.base_url);
+    rust_log!("[Rust] model: {}", params.model);
+    let _reservation = match params.check_budget("openai") {
+        Ok(reservation) => reservation,
+        Err(e) => return LLMResult::budget_exceeded(e),
+    };
+
+    // 构建消息数组
+    let conversation = build_conversation(
+        params.system_prompt.as_deref(),
+        params.messages.as_deref().unwrap_or_default(),
+        &params.prompt,
+        params.files.as_deref().unwrap_or_default(),
+    );
+    let (messages, skipped_files) = openai_messages(conversation);
+    if messages.iter().all(|m| m.role == "system") {
+        return LLMResult::failure("请求中没有用户消息".to_string());
+    }
 
     // 构建响应格式
     let response_format = params.response_json_schema.as_ref().map(|schema| {
@@ -235,20 +1179,21 @@ pub async fn openai_chat_completion(params: LLMRequestParams) -> LLMResult {
     });
 
     // 构建请求体
+    let (tools, tool_choice) = openai_tools(params);
     let request_body = OpenAIRequest {
         model: params.model.clone(),
         messages,
         temperature: params.temperature,
         max_tokens: params.max_tokens,
         response_format,
+        tools,
+        tool_choice,
     };
 
     // 构建 URL
-    let url = format!(
-        "{}/v1/chat/completions",
-        params.base_url.trim_end_matches('/')
-    );
-    println!("[Rust] Request URL: {}", url);
+    let endpoint = OpenAIEndpoint::new(&params.base_url, params.api_flavor);
+    let url = endpoint.url("chat/completions", Some(&params.model));
+    rust_log!("[Rust] Request URL: {}", url);
 
     // 创建 HTTP 客户端
     let client = match Client::builder()
@@ -257,32 +1202,27 @@ pub async fn openai_chat_completion(params: LLMRequestParams) -> LLMResult {
     {
         Ok(c) => c,
         Err(e) => {
-            return LLMResult {
-                success: false,
-                content: None,
-                error: Some(format!("创建 HTTP 客户端失败: {}", e)),
-            }
+            return LLMResult::failure(format!("创建 HTTP 客户端失败: {}", e))
         }
     };
 
     // 发送请求
-    println!("[Rust] Sending OpenAI request...");
+    rust_log!("[Rust] Sending OpenAI request...");
     let start_time = std::time::Instant::now();
 
-    let response = match client
-        .post(&url)
+    let response = match endpoint
+        .authorize(client.post(&url), &params.api_key)
         .header("Content-Type", "application/json")
-        .header("Authorization", format!("Bearer {}", params.api_key))
         .json(&request_body)
         .send()
         .await
     {
         Ok(r) => {
-            println!("[Rust] Response received in {:?}", start_time.elapsed());
+            rust_log!("[Rust] Response received in {:?}", start_time.elapsed());
             r
         },
         Err(e) => {
-            println!("[Rust] Request failed: {}", e);
+            rust_log!("[Rust] Request failed: {}", e);
             let error_msg = if e.is_timeout() {
                 "请求超时，请稍后重试".to_string()
             } else if e.is_connect() {
@@ -290,11 +1230,7 @@ pub async fn openai_chat_completion(params: LLMRequestParams) -> LLMResult {
             } else {
                 format!("请求失败: {}", e)
             };
-            return LLMResult {
-                success: false,
-                content: None,
-                error: Some(error_msg),
-            };
+            return LLMResult::request_failure(FailureKind::from_request_error(&e), error_msg);
         }
     };
 
@@ -302,124 +1238,111 @@ pub async fn openai_chat_completion(params: LLMRequestParams) -> LLMResult {
     let status = response.status();
     if !status.is_success() {
         let error_text = response.text().await.unwrap_or_default();
-        println!("[Rust] Error response: {}", error_text);
-        return LLMResult {
-            success: false,
-            content: None,
-            error: Some(format!("API 返回错误 ({}): {}", status, error_text)),
-        };
+        rust_log!("[Rust] Error response: {}", error_text);
+        return LLMResult::request_failure(FailureKind::Status(status.as_u16()), format!("API 返回错误 ({}): {}", status, error_text));
     }
 
     // 解析响应
     let response_text = match response.text().await {
         Ok(t) => t,
         Err(e) => {
-            return LLMResult {
-                success: false,
-                content: None,
-                error: Some(format!("获取响应失败: {}", e)),
-            };
+            return LLMResult::failure(format!("获取响应失败: {}", e));
         }
     };
 
     let openai_response: OpenAIResponse = match serde_json::from_str(&response_text) {
         Ok(r) => r,
         Err(e) => {
-            println!("[Rust] Failed to parse JSON: {}", e);
-            return LLMResult {
-                success: false,
-                content: None,
-                error: Some(format!("解析响应失败: {}", e)),
-            };
+            rust_log!("[Rust] Failed to parse JSON: {}", e);
+            return LLMResult::failure(format!("解析响应失败: {}", e));
         }
     };
 
     // 检查 API 错误
     if let Some(err) = openai_response.error {
-        return LLMResult {
-            success: false,
-            content: None,
-            error: Some(err.message),
-        };
+        return LLMResult::failure(err.message);
     }
 
-    // 提取内容
-    let content = openai_response
+    // 提取内容和工具调用
+    let usage = openai_response
+        .usage
+        .map(|u| Usage::tokens(u.prompt_tokens, u.completion_tokens));
+    let Some(message) = openai_response
         .choices
         .and_then(|choices| choices.into_iter().next())
         .and_then(|choice| choice.message)
-        .and_then(|msg| msg.content);
-
-    if content.is_none() {
+    else {
         return LLMResult {
-            success: false,
-            content: None,
-            error: Some("API 未返回有效内容".to_string()),
+            usage,
+            ..LLMResult::failure("API 未返回有效内容".to_string())
         };
-    }
+    };
+    let tool_calls: Vec<ToolCall> = message
+        .tool_calls
+        .unwrap_or_default()
+        .into_iter()
+        .map(|call| ToolCall {
+            id: call.id,
+            name: call.function.name,
+            arguments: parse_tool_arguments(&call.function.arguments),
+            signature: None,
+        })
+        .collect();
 
-    println!("[Rust] OpenAI result: content length = {}", content.as_ref().map(|c| c.len()).unwrap_or(0));
+    rust_log!(
+        "[Rust] OpenAI result: content length = {}, tool calls = {}",
+        message.content.as_ref().map(|c| c.len()).unwrap_or(0),
+        tool_calls.len()
+    );
 
-    LLMResult {
-        success: true,
-        content,
-        error: None,
-    }
+    LLMResult::from_output(message.content, tool_calls, skipped_files, usage)
 }
 
 // ==================== Claude API 代理命令 ====================
 
 #[tauri::command]
-pub async fn claude_chat_completion(params: LLMRequestParams) -> LLMResult {
-    println!("[Rust] claude_chat_completion called");
-    println!("[Rust] base_url: {}", params.base_url);
-    println!("[Rust] model: {}", params.model);
-
-    // 构建用户消息
-    let user_content = if let Some(files) = &params.files {
-        if !files.is_empty() {
-            // 多模态消息：Claude 要求图片在文本之前
-            let mut parts: Vec<ClaudeContentPart> = Vec::new();
-            for file in files {
-                if file.mime_type.starts_with("image/") {
-                    parts.push(ClaudeContentPart::Image {
-                        source: ClaudeImageSource {
-                            source_type: "base64".to_string(),
-                            media_type: file.mime_type.clone(),
-                            data: file.data.clone(),
-                        },
-                    });
-                }
-            }
-            parts.push(ClaudeContentPart::Text { text: params.prompt.clone() });
-            ClaudeContent::Parts(parts)
-        } else {
-            ClaudeContent::Text(params.prompt.clone())
-        }
-    } else {
-        ClaudeContent::Text(params.prompt.clone())
+pub async fn claude_chat_completion(app: tauri::AppHandle, params: LLMRequestParams) -> LLMResult {
+    rust_log!("[Rust] claude_chat_completion called");
+    chat_with_failover(&app, "claude", params).await
+}
+
+/// Claude Messages 请求（命令与 Agent 循环共用）
+pub(crate) async fn claude_chat(params: &LLMRequestParams) -> LLMResult {
+    rust_log!("[Rust] base_url: {}", params.base_url);
+    rust_log!("[Rust] model: {}", params.model);
+    let _reservation = match params.check_budget("claude") {
+        Ok(reservation) => reservation,
+        Err(e) => return LLMResult::budget_exceeded(e),
     };
 
-    let messages = vec![ClaudeMessage {
-        role: "user".to_string(),
-        content: user_content,
-    }];
+    // 构建消息数组（系统消息通过 system 字段传递）
+    let conversation = build_conversation(
+        params.system_prompt.as_deref(),
+        params.messages.as_deref().unwrap_or_default(),
+        &params.prompt,
+        params.files.as_deref().unwrap_or_default(),
+    );
+    let system = conversation.system.clone();
+    let (messages, skipped_files) = claude_messages(conversation);
+    if messages.is_empty() {
+        return LLMResult::failure("请求中没有用户消息".to_string());
+    }
 
     // 构建请求体
+    let (tools, tool_choice) = claude_tools(params);
     let request_body = ClaudeRequest {
         model: params.model.clone(),
         messages,
         max_tokens: params.max_tokens.unwrap_or(4096),
-        system: params.system_prompt.clone(),
+        system,
         temperature: params.temperature,
+        tools,
+        tool_choice,
     };
 
     // 构建 URL
-    let url = format!(
-        "{}/v1/messages",
-        params.base_url.trim_end_matches('/')
-    );
-    println!("[Rust] Request URL: {}", url);
+    let url = format!("{}/v1/messages", api_base(&params.base_url));
+    rust_log!("[Rust] Request URL: {}", url);
 
     // 创建 HTTP 客户端
     let client = match Client::builder()
@@ -428,16 +1351,12 @@ pub async fn claude_chat_completion(params: LLMRequestParams) -> LLMResult {
     {
         Ok(c) => c,
         Err(e) => {
-            return LLMResult {
-                success: false,
-                content: None,
-                error: Some(format!("创建 HTTP 客户端失败: {}", e)),
-            }
+            return LLMResult::failure(format!("创建 HTTP 客户端失败: {}", e))
         }
     };
 
     // 发送请求
-    println!("[Rust] Sending Claude request...");
+    rust_log!("[Rust] Sending Claude request...");
     let start_time = std::time::Instant::now();
 
     let response = match client
@@ -450,11 +1369,11 @@ pub async fn claude_chat_completion(params: LLMRequestParams) -> LLMResult {
         .await
     {
         Ok(r) => {
-            println!("[Rust] Response received in {:?}", start_time.elapsed());
+            rust_log!("[Rust] Response received in {:?}", start_time.elapsed());
             r
         },
         Err(e) => {
-            println!("[Rust] Request failed: {}", e);
+            rust_log!("[Rust] Request failed: {}", e);
             let error_msg = if e.is_timeout() {
                 "请求超时，请稍后重试".to_string()
             } else if e.is_connect() {
@@ -462,11 +1381,7 @@ pub async fn claude_chat_completion(params: LLMRequestParams) -> LLMResult {
             } else {
                 format!("请求失败: {}", e)
             };
-            return LLMResult {
-                success: false,
-                content: None,
-                error: Some(error_msg),
-            };
+            return LLMResult::request_failure(FailureKind::from_request_error(&e), error_msg);
         }
     };
 
@@ -474,66 +1389,210 @@ pub async fn claude_chat_completion(params: LLMRequestParams) -> LLMResult {
     let status = response.status();
     if !status.is_success() {
         let error_text = response.text().await.unwrap_or_default();
-        println!("[Rust] Error response: {}", error_text);
-        return LLMResult {
-            success: false,
-            content: None,
-            error: Some(format!("API 返回错误 ({}): {}", status, error_text)),
-        };
+        rust_log!("[Rust] Error response: {}", error_text);
+        return LLMResult::request_failure(FailureKind::Status(status.as_u16()), format!("API 返回错误 ({}): {}", status, error_text));
     }
 
     // 解析响应
     let response_text = match response.text().await {
         Ok(t) => t,
         Err(e) => {
-            return LLMResult {
-                success: false,
-                content: None,
-                error: Some(format!("获取响应失败: {}", e)),
-            };
+            return LLMResult::failure(format!("获取响应失败: {}", e));
         }
     };
 
     let claude_response: ClaudeResponse = match serde_json::from_str(&response_text) {
         Ok(r) => r,
         Err(e) => {
-            println!("[Rust] Failed to parse JSON: {}", e);
-            return LLMResult {
-                success: false,
-                content: None,
-                error: Some(format!("解析响应失败: {}", e)),
-            };
+            rust_log!("[Rust] Failed to parse JSON: {}", e);
+            return LLMResult::failure(format!("解析响应失败: {}", e));
         }
     };
 
     // 检查 API 错误
     if let Some(err) = claude_response.error {
-        return LLMResult {
-            success: false,
-            content: None,
-            error: Some(err.message),
-        };
+        return LLMResult::failure(err.message);
+    }
+
+    // 提取内容：拼接所有 text 块，tool_use 块转为工具调用
+    let mut text_parts: Vec<String> = Vec::new();
+    let mut tool_calls: Vec<ToolCall> = Vec::new();
+    let mut structured_output: Option<serde_json::Value> = None;
+    let usage = claude_response.usage.map(|u| {
+        Usage::tokens(
+            u.input_tokens + u.cache_creation_input_tokens + u.cache_read_input_tokens,
+            u.output_tokens,
+        )
+    });
+    for block in claude_response.content.unwrap_or_default() {
+        match block.block_type.as_deref() {
+            Some("tool_use") if block.name.as_deref() == Some(STRUCTURED_OUTPUT_TOOL) => {
+                structured_output = block.input;
+            }
+            Some("tool_use") => tool_calls.push(ToolCall {
+                id: block.id.unwrap_or_else(new_tool_call_id),
+                name: block.name.unwrap_or_default(),
+                arguments: block.input.unwrap_or_else(|| serde_json::json!({})),
+                signature: None,
+            }),
+            _ => text_parts.extend(block.text),
+        }
     }
+    let content = match (structured_output, &params.response_json_schema) {
+        // 结构化输出的工具参数即为结果（非 object Schema 需取出 result 字段）
+        (Some(mut input), Some(schema)) => {
+            if !is_object_schema(schema) {
+                input = input.get_mut("result").map(serde_json::Value::take).unwrap_or_default();
+            }
+            Some(input.to_string())
+        }
+        _ => (!text_parts.is_empty()).then(|| text_parts.join("")),
+    };
 
-    // 提取内容
-    let content = claude_response
-        .content
-        .and_then(|blocks| blocks.into_iter().next())
-        .and_then(|block| block.text);
+    rust_log!(
+        "[Rust] Claude result: content length = {}, tool calls = {}",
+        content.as_ref().map(|c| c.len()).unwrap_or(0),
+        tool_calls.len()
+    );
 
-    if content.is_none() {
-        return LLMResult {
-            success: false,
-            content: None,
-            error: Some("API 未返回有效内容".to_string()),
-        };
+    LLMResult::from_output(content, tool_calls, skipped_files, usage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+    fn file(mime_type: &str, name: &str, data: &[u8]) -> FileData {
+        FileData {
+            data: BASE64.encode(data),
+            mime_type: mime_type.to_string(),
+            file_name: Some(name.to_string()),
+        }
     }
 
-    println!("[Rust] Claude result: content length = {}", content.as_ref().map(|c| c.len()).unwrap_or(0));
+    #[test]
+    fn test_prepare_files_classifies_inputs() {
+        let files = vec![
+            file("application/pdf", "slides.pdf", b"%PDF-1.4"),
+            file("application/octet-stream", "notes.md", "# 标题".as_bytes()),
+            file("text/csv", "data.csv", b"a,b\n1,2"),
+            file("application/vnd.openxmlformats-officedocument.wordprocessingml.document", "a.docx", b"PK"),
+            file("text/plain", "bad.txt", &[0xff, 0xfe, 0x00]),
+        ];
+        let (prepared, skipped) = prepare_files(&files);
+        assert_eq!(prepared.len(), 3);
+        assert!(matches!(&prepared[0], PreparedFile::Pdf { file_name, .. } if file_name == "slides.pdf"));
+        assert!(matches!(&prepared[1], PreparedFile::Text { content, .. } if content == "# 标题"));
+        assert_eq!(skipped.len(), 2);
+        assert_eq!(skipped[0].file_name.as_deref(), Some("a.docx"));
+        assert_eq!(skipped[1].reason, "文件不是有效的 UTF-8 文本");
+    }
 
-    LLMResult {
-        success: true,
-        content,
-        error: None,
+    #[test]
+    fn test_conversation_maps_roles() {
+        let messages: Vec<ChatMessage> = serde_json::from_value(serde_json::json!([
+            {"role": "system", "content": "只输出大纲"},
+            {"role": "user", "content": "写一份大纲"},
+            {"role": "model", "content": "1. 引言", "files": [{"data": "AA==", "mimeType": "image/png"}]}
+        ]))
+        .unwrap();
+        let files = vec![file("text/markdown", "draft.md", b"# draft")];
+
+        let conversation = build_conversation(Some("你是编辑"), &messages, "请修改第一点", &files);
+        assert_eq!(conversation.system.as_deref(), Some("你是编辑\n\n只输出大纲"));
+        assert_eq!(conversation.skipped_files.len(), 1);
+
+        let (openai, skipped) = openai_messages(conversation);
+        assert!(skipped.len() == 1);
+        let roles: Vec<&str> = openai.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["system", "user", "assistant", "user"]);
+        assert!(matches!(&openai[3].content, Some(OpenAIContent::Parts(parts)) if parts.len() == 2));
+
+        let conversation = build_conversation(None, &messages, "", &[]);
+        let (claude, _) = claude_messages(conversation);
+        let roles: Vec<&str> = claude.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["user", "assistant"]);
+    }
+
+    #[test]
+    fn test_tool_turns_map_to_wire_formats() {
+        let messages: Vec<ChatMessage> = serde_json::from_value(serde_json::json!([
+            {"role": "user", "content": "生成一张猫的图片"},
+            {"role": "assistant", "content": "", "toolCalls": [
+                {"id": "call_1", "name": "generate_image", "arguments": {"prompt": "cat"}},
+                {"id": "call_2", "name": "find_prompt", "arguments": {"query": "cat"}}
+            ]},
+            {"role": "tool", "toolCallId": "call_1", "content": "{\"imageId\": \"a\"}"},
+            {"role": "tool", "toolCallId": "call_2", "content": "暂无"}
+        ]))
+        .unwrap();
+
+        let conversation = build_conversation(None, &messages, "", &[]);
+        assert_eq!(conversation.turns[3].tool_name, "find_prompt");
+
+        let (openai, _) = openai_messages(conversation);
+        let openai = serde_json::to_value(&openai).unwrap();
+        assert!(openai[1].get("content").is_none());
+        assert_eq!(openai[1]["tool_calls"][0]["function"]["arguments"], "{\"prompt\":\"cat\"}");
+        assert_eq!(openai[3]["role"], "tool");
+        assert_eq!(openai[3]["tool_call_id"], "call_2");
+
+        let (claude, _) = claude_messages(build_conversation(None, &messages, "", &[]));
+        let claude = serde_json::to_value(&claude).unwrap();
+        assert_eq!(claude.as_array().unwrap().len(), 3);
+        assert_eq!(claude[1]["content"][1]["type"], "tool_use");
+        assert_eq!(claude[2]["content"][1]["tool_use_id"], "call_2");
+    }
+
+    #[test]
+    fn test_claude_messages_skip_empty_and_start_with_user() {
+        let messages: Vec<ChatMessage> = serde_json::from_value(serde_json::json!([
+            {"role": "assistant", "content": "你好，需要什么帮助？"},
+            {"role": "user", "content": ""},
+            {"role": "user", "content": "画一只猫"}
+        ]))
+        .unwrap();
+
+        let (claude, _) = claude_messages(build_conversation(None, &messages, "", &[]));
+        let claude = serde_json::to_value(&claude).unwrap();
+        assert_eq!(claude.as_array().unwrap().len(), 3);
+        assert_eq!(claude[0]["role"], "user");
+        assert_eq!(claude[0]["content"], CONVERSATION_START_TEXT);
+        assert_eq!(claude[2]["content"], "画一只猫");
+
+        // 只有图片的消息不发送空文本
+        let messages: Vec<ChatMessage> = serde_json::from_value(serde_json::json!([
+            {"role": "user", "content": "", "files": [{"data": "AAAA", "mimeType": "image/png"}]}
+        ]))
+        .unwrap();
+        let (openai, _) = openai_messages(build_conversation(None, &messages, "", &[]));
+        let openai = serde_json::to_value(&openai).unwrap();
+        let parts = openai[0]["content"].as_array().unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0]["type"], "image_url");
+    }
+
+    #[test]
+    fn test_structured_output_validation() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {"title": {"type": "string"}, "pages": {"type": "integer"}},
+            "required": ["title", "pages"]
+        });
+        let validator = jsonschema::validator_for(&schema).unwrap();
+
+        let fenced = "```json\n{\"title\": \"大纲\", \"pages\": 3}\n```";
+        assert_eq!(extract_json_text(fenced), "{\"title\": \"大纲\", \"pages\": 3}");
+        assert!(schema_errors(&validator, fenced).is_empty());
+
+        let errors = schema_errors(&validator, r#"{"title": "大纲", "pages": "三"}"#);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("/pages: "));
+        assert!(schema_errors(&validator, "好的，这是大纲")[0].starts_with("不是有效的 JSON"));
+
+        let array_schema = serde_json::json!({"type": "array", "items": {"type": "string"}});
+        assert_eq!(claude_input_schema(&array_schema)["properties"]["result"], array_schema);
+        assert_eq!(claude_input_schema(&schema), schema);
     }
 }
@@ -0,0 +1,319 @@
+// LLM Agent 循环
+// 由 Rust 驱动多步工具调用：模型返回工具调用时通过 llm-tool-call 事件交给前端执行，
+// 前端执行完毕后调用 llm_submit_tool_result 回传结果，循环继续，直到模型给出最终回答或达到最大步数
+
+use crate::llm::{
+    add_usage, chat_with_schema, record_llm_usage, ChatMessage, ChatRole, LLMRequestParams, LLMResult, ToolCall,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::Emitter;
+use tokio::sync::oneshot;
+
+pub const LLM_TOOL_CALL_EVENT: &str = "llm-tool-call";
+
+const DEFAULT_MAX_STEPS: u32 = 8;
+const MAX_STEPS_LIMIT: u32 = 32;
+const DEFAULT_TOOL_TIMEOUT_SECS: u64 = 300;
+
+// ==================== 全局状态 ====================
+
+lazy_static::lazy_static! {
+    // 等待前端回传结果的工具调用：(run_id, call_id) -> 结果通道
+    static ref PENDING_TOOL_CALLS: Mutex<HashMap<(String, String), oneshot::Sender<ToolResult>>> =
+        Mutex::new(HashMap::new());
+    // 正在进行的运行
+    static ref ACTIVE_RUNS: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+    // 已请求取消的运行（只记录正在进行的运行，结束时移除）
+    static ref CANCELLED_RUNS: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+fn is_cancelled(run_id: &str) -> bool {
+    CANCELLED_RUNS.lock().map(|runs| runs.contains(run_id)).unwrap_or(false)
+}
+
+/// 运行期间登记在 ACTIVE_RUNS 中，结束（包括被丢弃）时清除登记和取消标记
+struct ActiveRun(String);
+
+impl ActiveRun {
+    fn start(run_id: &str) -> Self {
+        if let Ok(mut runs) = ACTIVE_RUNS.lock() {
+            runs.insert(run_id.to_string());
+        }
+        ActiveRun(run_id.to_string())
+    }
+}
+
+impl Drop for ActiveRun {
+    fn drop(&mut self) {
+        if let Ok(mut runs) = ACTIVE_RUNS.lock() {
+            runs.remove(&self.0);
+        }
+        if let Ok(mut runs) = CANCELLED_RUNS.lock() {
+            runs.remove(&self.0);
+        }
+    }
+}
+
+// ==================== 数据结构 ====================
+
+// Agent 运行参数（LLM 请求参数平铺，额外指定协议和步数限制）
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LLMAgentParams {
+    pub run_id: Option<String>, // 用于关联工具调用事件，缺省时自动生成
+    pub protocol: String,       // openai / claude / google
+    pub max_steps: Option<u32>, // 最多调用模型的次数（默认 8，上限 32）
+    pub tool_timeout_secs: Option<u64>, // 单次工具执行的等待时间
+    #[serde(flatten)]
+    pub request: LLMRequestParams,
+}
+
+// 工具调用事件
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolCallEvent {
+    pub run_id: String,
+    pub step: u32,
+    pub call: ToolCall,
+}
+
+// 前端回传的工具结果
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolResultParams {
+    pub run_id: String,
+    pub call_id: String,
+    pub content: String,
+    pub is_error: Option<bool>,
+}
+
+#[derive(Debug)]
+struct ToolResult {
+    content: String,
+    is_error: bool,
+}
+
+// Agent 运行结果
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LLMAgentResult {
+    #[serde(flatten)]
+    pub result: LLMResult,
+    pub run_id: String,
+    pub steps: u32,
+    pub messages: Vec<ChatMessage>, // 本次运行新增的对话（用户输入、工具调用、工具结果与最终回答）
+}
+
+// ==================== 工具执行 ====================
+
+/// 发出工具调用事件并等待前端回传结果
+async fn run_tool(
+    app: &tauri::AppHandle,
+    run_id: &str,
+    step: u32,
+    call: &ToolCall,
+    timeout: Duration,
+) -> Result<String, String> {
+    let key = (run_id.to_string(), call.id.clone());
+    let (sender, receiver) = oneshot::channel();
+    PENDING_TOOL_CALLS
+        .lock()
+        .map_err(|e| format!("工具调用状态异常: {}", e))?
+        .insert(key.clone(), sender);
+
+    let event = ToolCallEvent {
+        run_id: run_id.to_string(),
+        step,
+        call: call.clone(),
+    };
+    let outcome = match app.emit(LLM_TOOL_CALL_EVENT, event) {
+        Ok(()) => match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(result)) if result.is_error => Err(result.content),
+            Ok(Ok(result)) => Ok(result.content),
+            Ok(Err(_)) => Err("工具调用已取消".to_string()),
+            Err(_) => Err(format!("等待工具结果超时（{} 秒）", timeout.as_secs())),
+        },
+        Err(e) => Err(format!("发送工具调用事件失败: {}", e)),
+    };
+
+    if let Ok(mut pending) = PENDING_TOOL_CALLS.lock() {
+        pending.remove(&key);
+    }
+    outcome
+}
+
+// ==================== 命令实现 ====================
+
+#[tauri::command]
+pub async fn llm_agent_run(app: tauri::AppHandle, params: LLMAgentParams) -> LLMAgentResult {
+    let run_id = params
+        .run_id
+        .clone()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let max_steps = params.max_steps.unwrap_or(DEFAULT_MAX_STEPS).clamp(1, MAX_STEPS_LIMIT);
+    let timeout = Duration::from_secs(params.tool_timeout_secs.unwrap_or(DEFAULT_TOOL_TIMEOUT_SECS));
+    rust_log!(
+        "[Rust] llm_agent_run called, run_id: {}, protocol: {}, tools: {}, max_steps: {}",
+        run_id,
+        params.protocol,
+        params.request.tools().len(),
+        max_steps
+    );
+
+    let active_run = ActiveRun::start(&run_id);
+
+    // 本轮 prompt 与文件并入对话历史，后续每一步都发送完整对话
+    let mut request = params.request;
+    if let Err(e) = request.prepare(&app) {
+        return LLMAgentResult {
+            result: LLMResult::failure(e),
+            run_id,
+            steps: 0,
+            messages: Vec::new(),
+        };
+    }
+    let mut transcript = request.messages.take().unwrap_or_default();
+    let history_len = transcript.len();
+    if !request.prompt.is_empty() || request.files.is_some() {
+        transcript.push(ChatMessage {
+            files: request.files.take(),
+            ..ChatMessage::new(ChatRole::User, std::mem::take(&mut request.prompt))
+        });
+    }
+
+    let mut skipped_files = Vec::new();
+    let mut total_usage = None;
+    let mut step = 0;
+    let result = 'agent: loop {
+        if is_cancelled(&run_id) {
+            break LLMResult::failure("Agent 已取消".to_string());
+        }
+        step += 1;
+
+        request.messages = Some(transcript);
+        // 模型给出最终回答时按 response_json_schema 校验并修复，工具调用步骤不校验
+        let mut result = chat_with_schema(&params.protocol, &request).await;
+        transcript = request.messages.take().unwrap_or_default();
+        total_usage = add_usage(total_usage, result.usage.take());
+        if step == 1 {
+            skipped_files = std::mem::take(&mut result.skipped_files);
+        }
+        if !result.success || result.tool_calls.is_empty() {
+            if result.success {
+                transcript.push(ChatMessage::new(
+                    ChatRole::Assistant,
+                    result.content.clone().unwrap_or_default(),
+                ));
+            }
+            break result;
+        }
+        if step >= max_steps {
+            // 未执行的工具调用不写入对话，通过结果的 toolCalls 返回
+            break LLMResult {
+                error: Some(format!("已达到最大步数 ({})，模型仍在请求工具调用", max_steps)),
+                success: false,
+                ..result
+            };
+        }
+
+        rust_log!("[Rust] Agent step {}: {} tool calls", step, result.tool_calls.len());
+        let step_start = transcript.len();
+        transcript.push(ChatMessage {
+            tool_calls: result.tool_calls.clone(),
+            ..ChatMessage::new(ChatRole::Assistant, result.content.unwrap_or_default())
+        });
+        for call in result.tool_calls {
+            // 模型响应期间收到的取消不会唤醒工具等待，执行每个工具前都要检查
+            if is_cancelled(&run_id) {
+                // 未完成的工具调用不写入对话，避免对话中留下没有结果的调用
+                transcript.truncate(step_start);
+                break 'agent LLMResult::failure("Agent 已取消".to_string());
+            }
+            // 工具失败时把错误交给模型，由模型决定重试或换用其他方式
+            let content = run_tool(&app, &run_id, step, &call, timeout)
+                .await
+                .unwrap_or_else(|e| {
+                    rust_log!("[Rust] Tool {} failed: {}", call.name, e);
+                    format!("工具执行失败: {}", e)
+                });
+            transcript.push(ChatMessage {
+                tool_call_id: Some(call.id),
+                name: Some(call.name),
+                ..ChatMessage::new(ChatRole::Tool, content)
+            });
+        }
+    };
+
+    drop(active_run);
+    rust_log!("[Rust] Agent {} finished after {} steps, success: {}", run_id, step, result.success);
+    let result = LLMResult {
+        skipped_files,
+        usage: total_usage,
+        ..result
+    };
+    record_llm_usage(&app, &params.protocol, &request, &result);
+
+    LLMAgentResult {
+        result,
+        run_id,
+        steps: step,
+        messages: transcript.split_off(history_len),
+    }
+}
+
+#[tauri::command]
+pub fn llm_submit_tool_result(params: ToolResultParams) -> Result<(), String> {
+    let sender = PENDING_TOOL_CALLS
+        .lock()
+        .map_err(|e| format!("工具调用状态异常: {}", e))?
+        .remove(&(params.run_id, params.call_id));
+    match sender {
+        Some(sender) => sender
+            .send(ToolResult {
+                content: params.content,
+                is_error: params.is_error.unwrap_or(false),
+            })
+            .map_err(|_| "Agent 已停止等待该工具结果".to_string()),
+        None => Err("工具调用不存在或已超时".to_string()),
+    }
+}
+
+/// 取消运行：正在等待的工具调用立即结束，下一步开始前退出循环
+#[tauri::command]
+pub fn llm_agent_cancel(run_id: String) -> Result<(), String> {
+    rust_log!("[Rust] llm_agent_cancel called, run_id: {}", run_id);
+    {
+        let active = ACTIVE_RUNS.lock().map_err(|e| format!("取消状态异常: {}", e))?;
+        if !active.contains(&run_id) {
+            return Err("运行不存在或已结束".to_string());
+        }
+        let mut cancelled = CANCELLED_RUNS.lock().map_err(|e| format!("取消状态异常: {}", e))?;
+        // 顺带清理已结束运行遗留的取消标记
+        cancelled.retain(|id| active.contains(id));
+        cancelled.insert(run_id.clone());
+    }
+    if let Ok(mut pending) = PENDING_TOOL_CALLS.lock() {
+        pending.retain(|(id, _), _| *id != run_id);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_only_marks_active_runs() {
+        assert!(llm_agent_cancel("finished-run".to_string()).is_err());
+        assert!(!is_cancelled("finished-run"));
+
+        let run = ActiveRun::start("active-run");
+        assert!(llm_agent_cancel("active-run".to_string()).is_ok());
+        assert!(is_cancelled("active-run"));
+        drop(run);
+        assert!(!is_cancelled("active-run"));
+    }
+}
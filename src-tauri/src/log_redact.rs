@@ -0,0 +1,63 @@
+// 日志脱敏
+// 所有 [Rust] 日志经 rust_log! 输出，打印前屏蔽 API 密钥、Bearer 令牌和 base64 数据
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+lazy_static! {
+    // URL 查询参数中的密钥：?key=xxx、&api_key=xxx
+    static ref QUERY_KEY_RE: Regex =
+        Regex::new(r"(?i)([?&](?:key|api_key|api-key|apikey|access_token|token)=)[^&\s'\x22]+").unwrap();
+    // Authorization: Bearer xxx
+    static ref BEARER_RE: Regex = Regex::new(r"(?i)(bearer\s+)[A-Za-z0-9._~+/=\-]+").unwrap();
+    // JSON / 请求头形式的密钥字段
+    static ref FIELD_KEY_RE: Regex = Regex::new(
+        r#"(?i)("?(?:api_?key|x-api-key|x-goog-api-key|api-key|access_token)"?\s*[:=]\s*"?)[^"\s,}&]+"#
+    )
+    .unwrap();
+    // 常见密钥格式（OpenAI/Anthropic 的 sk-、Google 的 AIza）
+    static ref KNOWN_KEY_RE: Regex =
+        Regex::new(r"\b(sk-(?:ant-)?|AIza)[A-Za-z0-9_\-]{12,}").unwrap();
+    // 长 base64 串（图片数据等）
+    static ref BASE64_RE: Regex = Regex::new(r"[A-Za-z0-9+/]{200,}={0,2}").unwrap();
+}
+
+/// 屏蔽日志中的敏感信息
+pub fn redact(input: &str) -> String {
+    let output = BASE64_RE.replace_all(input, |caps: &Captures| {
+        format!("<base64 {} chars>", caps[0].len())
+    });
+    let output = QUERY_KEY_RE.replace_all(&output, "${1}***");
+    let output = BEARER_RE.replace_all(&output, "${1}***");
+    let output = FIELD_KEY_RE.replace_all(&output, "${1}***");
+    let output = KNOWN_KEY_RE.replace_all(&output, "${1}***");
+    output.into_owned()
+}
+
+/// 输出脱敏后的日志（用法同 println!）
+#[macro_export]
+macro_rules! rust_log {
+    ($($arg:tt)*) => {
+        println!("{}", $crate::log_redact::redact(&format!($($arg)*)))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_secrets() {
+        assert_eq!(
+            redact("[Rust] url: https://x.com/v1beta/models/m:generateContent?key=AIzaSyA1234567890abcdef&alt=sse"),
+            "[Rust] url: https://x.com/v1beta/models/m:generateContent?key=***&alt=sse"
+        );
+        assert_eq!(redact("Authorization: Bearer sk-abcdef1234567890"), "Authorization: Bearer ***");
+        assert_eq!(redact(r#"{"api_key": "secret-value"}"#), r#"{"api_key": "***"}"#);
+        assert_eq!(redact("key sk-ant-REDACTED used"), "key sk-ant-*** used");
+
+        let payload = format!("data:image/png;base64,{}", "A".repeat(300));
+        assert_eq!(redact(&payload), "data:image/png;base64,<base64 300 chars>");
+        assert_eq!(redact("[Rust] plain message 123"), "[Rust] plain message 123");
+    }
+}
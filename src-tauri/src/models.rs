@@ -0,0 +1,361 @@
+// 模型列表
+// 通过供应商的 /models 端点获取可用模型，并归一化为统一的能力信息，
+// 供前端在中转服务实际提供的模型中选择（OpenAI 兼容、Anthropic、Gemini）
+
+use crate::endpoint::{api_base, ApiFlavor, OpenAIEndpoint};
+use crate::vault::resolve_api_key;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// 分页请求的最大页数（防止中转服务返回异常分页时死循环）
+const MAX_PAGES: usize = 20;
+
+// ==================== 数据结构 ====================
+
+// 前端传入的参数
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListModelsParams {
+    pub provider: String, // 协议：openai / claude / google
+    pub base_url: String,
+    pub api_flavor: Option<ApiFlavor>, // OpenAI 兼容接口类型（未设置时按域名识别）
+    #[serde(default)]
+    pub api_key: String, // 使用 credential_id 时可省略
+    pub credential_id: Option<String>, // 凭据库中的凭据 ID（优先于 api_key）
+}
+
+// 归一化后的模型信息
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInfo {
+    pub id: String, // 请求时使用的模型名
+    pub display_name: String,
+    pub text_output: bool,  // 可生成文本
+    pub image_output: bool, // 可生成图片
+    pub vision_input: bool, // 可接收图片输入
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_length: Option<u64>, // 上下文长度（供应商未提供时为空）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_token_limit: Option<u64>,
+}
+
+// OpenAI 兼容 /v1/models 响应（OpenRouter 等中转会附带上下文长度和模态信息）
+#[derive(Debug, Deserialize)]
+struct OpenAIModelList {
+    data: Vec<OpenAIModel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIModel {
+    id: String,
+    name: Option<String>,
+    context_length: Option<u64>,
+    context_window: Option<u64>,
+    architecture: Option<OpenAIModelArchitecture>,
+    top_provider: Option<OpenAITopProvider>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIModelArchitecture {
+    #[serde(default)]
+    input_modalities: Vec<String>,
+    #[serde(default)]
+    output_modalities: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAITopProvider {
+    max_completion_tokens: Option<u64>,
+}
+
+// Anthropic /v1/models 响应
+#[derive(Debug, Deserialize)]
+struct ClaudeModelList {
+    data: Vec<ClaudeModel>,
+    #[serde(default)]
+    has_more: bool,
+    last_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeModel {
+    id: String,
+    display_name: Option<String>,
+}
+
+// Gemini v1beta/models 响应
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiModelList {
+    #[serde(default)]
+    models: Vec<GeminiModel>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiModel {
+    name: String, // models/gemini-2.5-flash
+    display_name: Option<String>,
+    input_token_limit: Option<u64>,
+    output_token_limit: Option<u64>,
+    #[serde(default)]
+    supported_generation_methods: Vec<String>,
+}
+
+// ==================== 能力推断 ====================
+
+// 只生成图片的模型名特征
+const IMAGE_MODEL_MARKERS: &[&str] = &["dall-e", "gpt-image", "imagen", "flux", "seedream", "seededit", "midjourney"];
+// 不生成文本的模型名特征（嵌入、语音、审核）
+const NON_TEXT_MODEL_MARKERS: &[&str] = &["embedding", "tts", "whisper", "transcribe", "moderation", "sora", "veo"];
+// 支持图片输入的模型名特征
+const VISION_MODEL_MARKERS: &[&str] = &[
+    "gpt-4o", "gpt-4.1", "gpt-4-turbo", "gpt-5", "o1", "o3", "o4", "vision", "-vl", "claude", "gemini", "qwen-vl",
+    "glm-4v", "grok-4", "llama-4",
+];
+
+fn has_marker(id: &str, markers: &[&str]) -> bool {
+    let id = id.to_lowercase();
+    markers.iter().any(|marker| id.contains(marker))
+}
+
+/// OpenAI 兼容模型：优先使用响应中的模态信息，否则按模型名推断
+fn normalize_openai(model: OpenAIModel) -> ModelInfo {
+    let (text_output, image_output, vision_input) = match &model.architecture {
+        Some(arch) if !arch.output_modalities.is_empty() => (
+            arch.output_modalities.iter().any(|m| m == "text"),
+            arch.output_modalities.iter().any(|m| m == "image"),
+            arch.input_modalities.iter().any(|m| m == "image"),
+        ),
+        _ => {
+            let image_only = has_marker(&model.id, IMAGE_MODEL_MARKERS);
+            (
+                !image_only && !has_marker(&model.id, NON_TEXT_MODEL_MARKERS),
+                image_only,
+                !image_only && has_marker(&model.id, VISION_MODEL_MARKERS),
+            )
+        }
+    };
+    ModelInfo {
+        display_name: model.name.unwrap_or_else(|| model.id.clone()),
+        id: model.id,
+        text_output,
+        image_output,
+        vision_input,
+        context_length: model.context_length.or(model.context_window),
+        output_token_limit: model.top_provider.and_then(|p| p.max_completion_tokens),
+    }
+}
+
+/// Claude 模型：均为文本输出，claude-2 之前的模型不支持图片输入
+fn normalize_claude(model: ClaudeModel) -> ModelInfo {
+    let legacy = model.id.starts_with("claude-2") || model.id.starts_with("claude-instant");
+    ModelInfo {
+        display_name: model.display_name.unwrap_or_else(|| model.id.clone()),
+        id: model.id,
+        text_output: true,
+        image_output: false,
+        vision_input: !legacy,
+        context_length: None,
+        output_token_limit: None,
+    }
+}
+
+/// Gemini 模型：按 supportedGenerationMethods 判断，Imagen 使用 predict 生成图片
+fn normalize_gemini(model: GeminiModel) -> ModelInfo {
+    let id = model.name.strip_prefix("models/").unwrap_or(&model.name).to_string();
+    let generates = model.supported_generation_methods.iter().any(|m| m == "generateContent");
+    let predicts = model.supported_generation_methods.iter().any(|m| m == "predict");
+    let image_model = id.contains("image") || id.starts_with("imagen");
+    ModelInfo {
+        display_name: model.display_name.unwrap_or_else(|| id.clone()),
+        text_output: generates && !id.starts_with("imagen"),
+        image_output: image_model && (generates || predicts),
+        vision_input: generates && id.starts_with("gemini"),
+        context_length: model.input_token_limit,
+        output_token_limit: model.output_token_limit,
+        id,
+    }
+}
+
+// ==================== 请求 ====================
+
+// 获取模型列表失败（供连接测试区分网络错误和 HTTP 状态）
+#[derive(Debug)]
+pub(crate) enum ListModelsError {
+    Request(reqwest::Error),
+    Status(reqwest::StatusCode, String),
+    Parse(String),
+}
+
+impl std::fmt::Display for ListModelsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ListModelsError::Request(e) => write!(f, "请求模型列表失败: {}", e),
+            ListModelsError::Status(status, body) => write!(f, "获取模型列表失败 ({}): {}", status, body),
+            ListModelsError::Parse(e) => write!(f, "解析模型列表失败: {}", e),
+        }
+    }
+}
+
+async fn get_json<T: for<'de> Deserialize<'de>>(request: reqwest::RequestBuilder) -> Result<T, ListModelsError> {
+    let response = request.send().await.map_err(ListModelsError::Request)?;
+    let status = response.status();
+    let body = response.text().await.map_err(ListModelsError::Request)?;
+    if !status.is_success() {
+        return Err(ListModelsError::Status(status, body));
+    }
+    serde_json::from_str(&body).map_err(|e| ListModelsError::Parse(e.to_string()))
+}
+
+/// 模型列表端点
+pub(crate) fn models_url(provider: &str, base_url: &str, flavor: Option<ApiFlavor>) -> String {
+    match provider {
+        "google" | "gemini" => format!("{}/v1beta/models", api_base(base_url)),
+        "claude" | "anthropic" => format!("{}/v1/models", api_base(base_url)),
+        _ => OpenAIEndpoint::new(base_url, flavor).url("models", None),
+    }
+}
+
+async fn list_openai_models(
+    client: &Client,
+    endpoint: &OpenAIEndpoint,
+    url: &str,
+    api_key: &str,
+) -> Result<Vec<ModelInfo>, ListModelsError> {
+    let list: OpenAIModelList = get_json(endpoint.authorize(client.get(url), api_key)).await?;
+    Ok(list.data.into_iter().map(normalize_openai).collect())
+}
+
+async fn list_claude_models(client: &Client, url: &str, api_key: &str) -> Result<Vec<ModelInfo>, ListModelsError> {
+    let mut models = Vec::new();
+    let mut after_id: Option<String> = None;
+    for _ in 0..MAX_PAGES {
+        let mut request = client
+            .get(url)
+            .header("x-api-key", api_key)
+            .header("anthropic-version", "2023-06-01")
+            .query(&[("limit", "1000")]);
+        if let Some(after_id) = &after_id {
+            request = request.query(&[("after_id", after_id)]);
+        }
+        let page: ClaudeModelList = get_json(request).await?;
+        models.extend(page.data.into_iter().map(normalize_claude));
+        match page.last_id {
+            Some(last_id) if page.has_more => after_id = Some(last_id),
+            _ => break,
+        }
+    }
+    Ok(models)
+}
+
+async fn list_gemini_models(client: &Client, url: &str, api_key: &str) -> Result<Vec<ModelInfo>, ListModelsError> {
+    let mut models = Vec::new();
+    let mut page_token: Option<String> = None;
+    for _ in 0..MAX_PAGES {
+        let mut request = client
+            .get(url)
+            .header("x-goog-api-key", api_key)
+            .query(&[("pageSize", "1000")]);
+        if let Some(token) = &page_token {
+            request = request.query(&[("pageToken", token)]);
+        }
+        let page: GeminiModelList = get_json(request).await?;
+        models.extend(page.models.into_iter().map(normalize_gemini));
+        match page.next_page_token {
+            Some(token) if !token.is_empty() => page_token = Some(token),
+            _ => break,
+        }
+    }
+    Ok(models)
+}
+
+/// 按协议获取模型列表（provider 需已校验）
+pub(crate) async fn fetch_models(
+    client: &Client,
+    provider: &str,
+    base_url: &str,
+    flavor: Option<ApiFlavor>,
+    api_key: &str,
+) -> Result<Vec<ModelInfo>, ListModelsError> {
+    let url = models_url(provider, base_url, flavor);
+    rust_log!("[Rust] Request URL: {}", url);
+    match provider {
+        "claude" | "anthropic" => list_claude_models(client, &url, api_key).await,
+        "google" | "gemini" => list_gemini_models(client, &url, api_key).await,
+        _ => list_openai_models(client, &OpenAIEndpoint::new(base_url, flavor), &url, api_key).await,
+    }
+}
+
+/// 校验协议名
+pub(crate) fn check_provider(provider: &str) -> Result<(), String> {
+    match provider {
+        "openai" | "claude" | "anthropic" | "google" | "gemini" => Ok(()),
+        other => Err(format!("不支持的供应商协议: {}", other)),
+    }
+}
+
+// ==================== 命令 ====================
+
+#[tauri::command]
+pub async fn list_models(app: tauri::AppHandle, mut params: ListModelsParams) -> Result<Vec<ModelInfo>, String> {
+    rust_log!("[Rust] list_models called, provider: {}", params.provider);
+    check_provider(&params.provider)?;
+    resolve_api_key(&app, &params.credential_id, &mut params.api_key)?;
+
+    let client = Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+        .map_err(|e| format!("创建 HTTP 客户端失败: {}", e))?;
+
+    let mut models = fetch_models(&client, &params.provider, &params.base_url, params.api_flavor, &params.api_key)
+        .await
+        .map_err(|e| e.to_string())?;
+    models.sort_by(|a, b| a.id.cmp(&b.id));
+    rust_log!("[Rust] list_models returned {} models", models.len());
+    Ok(models)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_model_capabilities() {
+        let list: OpenAIModelList = serde_json::from_str(
+            r#"{"data":[
+                {"id":"gpt-4o-mini","object":"model","owned_by":"openai"},
+                {"id":"gpt-image-1","object":"model"},
+                {"id":"text-embedding-3-small","object":"model"},
+                {"id":"google/gemini-2.5-flash-image","name":"Nano Banana","context_length":32768,
+                 "architecture":{"input_modalities":["image","text"],"output_modalities":["image","text"]}}
+            ]}"#,
+        )
+        .unwrap();
+        let models: Vec<ModelInfo> = list.data.into_iter().map(normalize_openai).collect();
+        assert!(models[0].text_output && models[0].vision_input && !models[0].image_output);
+        assert!(models[1].image_output && !models[1].text_output);
+        assert!(!models[2].text_output && !models[2].vision_input);
+        assert!(models[3].image_output && models[3].text_output && models[3].vision_input);
+        assert_eq!(models[3].context_length, Some(32768));
+        assert_eq!(models[3].display_name, "Nano Banana");
+
+        let list: GeminiModelList = serde_json::from_str(
+            r#"{"models":[
+                {"name":"models/gemini-2.5-flash","displayName":"Gemini 2.5 Flash","inputTokenLimit":1048576,
+                 "outputTokenLimit":65536,"supportedGenerationMethods":["generateContent","countTokens"]},
+                {"name":"models/imagen-4.0-generate-001","supportedGenerationMethods":["predict"]},
+                {"name":"models/text-embedding-004","supportedGenerationMethods":["embedContent"]}
+            ]}"#,
+        )
+        .unwrap();
+        let models: Vec<ModelInfo> = list.models.into_iter().map(normalize_gemini).collect();
+        assert_eq!(models[0].id, "gemini-2.5-flash");
+        assert!(models[0].text_output && models[0].vision_input && !models[0].image_output);
+        assert_eq!(models[0].context_length, Some(1048576));
+        assert!(models[1].image_output && !models[1].text_output);
+        assert!(!models[2].text_output && !models[2].image_output);
+    }
+}
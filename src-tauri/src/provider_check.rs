@@ -0,0 +1,260 @@
+// 供应商连接测试
+// 依次检查 DNS、TLS 连通性、鉴权和模型可用性，返回耗时和诊断类型，
+// 使用不计费的模型列表接口，避免配置错误时要等到生成失败才发现
+
+use crate::endpoint::{api_base, ApiFlavor};
+use crate::models::{check_provider, fetch_models, models_url, ListModelsError};
+use crate::vault::resolve_api_key;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::error::Error as _;
+use std::time::{Duration, Instant};
+
+// ==================== 数据结构 ====================
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderTestParams {
+    pub provider: String, // 协议：openai / claude / google
+    pub base_url: String,
+    pub api_flavor: Option<ApiFlavor>, // OpenAI 兼容接口类型（未设置时按域名识别）
+    #[serde(default)]
+    pub api_key: String, // 使用 credential_id 时可省略
+    pub credential_id: Option<String>, // 凭据库中的凭据 ID（优先于 api_key）
+    pub model: Option<String>, // 需要确认可用的模型
+}
+
+// 诊断类型
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ProviderDiagnosis {
+    Ok,
+    InvalidUrl,         // Base URL 无法解析
+    DnsFailed,          // 域名解析失败
+    ConnectFailed,      // 无法建立连接
+    TlsFailed,          // TLS 握手或证书错误
+    ProxyFailed,        // 代理不可用或要求认证
+    Timeout,            // 请求超时
+    DuplicatedVersion,  // Base URL 包含多余的版本路径（/v1、/v1beta 会自动去掉）
+    WrongBasePath,      // 端点不存在或返回的不是 API 响应
+    Unauthorized,       // 401/403，API Key 无效
+    ModelNotFound,      // 模型不在供应商的模型列表中
+    RateLimited,        // 429，额度或频率限制
+    ServerError,        // 5xx
+    Unknown,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderTestResult {
+    pub success: bool,
+    pub diagnosis: ProviderDiagnosis,
+    pub message: String,
+    pub url: String,      // 实际请求的端点
+    pub latency_ms: u64,  // 模型列表请求耗时
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dns_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_count: Option<usize>,
+}
+
+impl ProviderTestResult {
+    fn new(diagnosis: ProviderDiagnosis, message: String, url: &str) -> Self {
+        ProviderTestResult {
+            success: diagnosis == ProviderDiagnosis::Ok,
+            diagnosis,
+            message,
+            url: url.to_string(),
+            latency_ms: 0,
+            dns_ms: None,
+            status: None,
+            model_count: None,
+        }
+    }
+}
+
+// ==================== 诊断 ====================
+
+/// 是否配置了系统代理（reqwest 默认读取这些环境变量）
+fn proxy_configured() -> bool {
+    ["HTTPS_PROXY", "https_proxy", "HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"]
+        .iter()
+        .any(|name| std::env::var(name).is_ok_and(|value| !value.is_empty()))
+}
+
+/// Base URL 是否以无法自动去掉的版本路径结尾（如 https://api.example.com/v3）
+fn ends_with_version(base_url: &str) -> bool {
+    let base = api_base(base_url);
+    let last = base.rsplit('/').next().unwrap_or_default();
+    last.len() >= 2
+        && last.starts_with('v')
+        && last[1..].chars().next().is_some_and(|c| c.is_ascii_digit())
+}
+
+/// 网络层错误的诊断（包含底层错误链中的 TLS 信息）
+fn diagnose_request_error(error: &reqwest::Error, proxy: bool) -> (ProviderDiagnosis, String) {
+    let mut detail = error.to_string();
+    let mut source = error.source();
+    while let Some(e) = source {
+        detail = format!("{}: {}", detail, e);
+        source = e.source();
+    }
+    let lower = detail.to_lowercase();
+    let diagnosis = if error.is_timeout() {
+        ProviderDiagnosis::Timeout
+    } else if ["certificate", "tls", "ssl", "handshake"].iter().any(|k| lower.contains(k)) {
+        ProviderDiagnosis::TlsFailed
+    } else if lower.contains("proxy") || (proxy && error.is_connect()) {
+        ProviderDiagnosis::ProxyFailed
+    } else if lower.contains("dns") || lower.contains("resolve") {
+        ProviderDiagnosis::DnsFailed
+    } else if error.is_connect() {
+        ProviderDiagnosis::ConnectFailed
+    } else {
+        ProviderDiagnosis::Unknown
+    };
+    (diagnosis, detail)
+}
+
+/// HTTP 状态的诊断
+fn diagnose_status(status: StatusCode, base_url: &str) -> ProviderDiagnosis {
+    match status.as_u16() {
+        401 | 403 => ProviderDiagnosis::Unauthorized,
+        404 | 405 if ends_with_version(base_url) => ProviderDiagnosis::DuplicatedVersion,
+        404 | 405 => ProviderDiagnosis::WrongBasePath,
+        407 => ProviderDiagnosis::ProxyFailed,
+        429 => ProviderDiagnosis::RateLimited,
+        500..=599 => ProviderDiagnosis::ServerError,
+        _ => ProviderDiagnosis::Unknown,
+    }
+}
+
+fn diagnosis_message(diagnosis: ProviderDiagnosis) -> &'static str {
+    match diagnosis {
+        ProviderDiagnosis::Ok => "连接正常",
+        ProviderDiagnosis::InvalidUrl => "Base URL 格式错误",
+        ProviderDiagnosis::DnsFailed => "域名解析失败，请检查 Base URL 或网络",
+        ProviderDiagnosis::ConnectFailed => "无法连接到服务器",
+        ProviderDiagnosis::TlsFailed => "TLS 握手失败，请检查证书或协议（http/https）",
+        ProviderDiagnosis::ProxyFailed => "代理连接失败，请检查代理设置",
+        ProviderDiagnosis::Timeout => "请求超时",
+        ProviderDiagnosis::DuplicatedVersion => "Base URL 不应包含 /v1 等版本路径，请去掉末尾的版本号",
+        ProviderDiagnosis::WrongBasePath => "接口不存在，请检查 Base URL 路径",
+        ProviderDiagnosis::Unauthorized => "API Key 无效或无权限",
+        ProviderDiagnosis::ModelNotFound => "供应商未提供该模型",
+        ProviderDiagnosis::RateLimited => "请求被限流或额度不足",
+        ProviderDiagnosis::ServerError => "供应商服务异常",
+        ProviderDiagnosis::Unknown => "未知错误",
+    }
+}
+
+// ==================== 命令 ====================
+
+#[tauri::command]
+pub async fn test_provider(app: tauri::AppHandle, mut params: ProviderTestParams) -> Result<ProviderTestResult, String> {
+    rust_log!("[Rust] test_provider called, provider: {}", params.provider);
+    check_provider(&params.provider)?;
+    resolve_api_key(&app, &params.credential_id, &mut params.api_key)?;
+
+    let url = models_url(&params.provider, &params.base_url, params.api_flavor);
+    let fail = |diagnosis: ProviderDiagnosis, detail: String| {
+        let message = format!("{}: {}", diagnosis_message(diagnosis), detail);
+        rust_log!("[Rust] Provider test failed: {}", message);
+        ProviderTestResult::new(diagnosis, message, &url)
+    };
+
+    // 1. 解析 Base URL 和域名（配置代理时由代理解析，本地解析失败不作为错误）
+    let parsed = match reqwest::Url::parse(&params.base_url) {
+        Ok(parsed) => parsed,
+        Err(e) => return Ok(fail(ProviderDiagnosis::InvalidUrl, e.to_string())),
+    };
+    let Some(host) = parsed.host_str() else {
+        return Ok(fail(ProviderDiagnosis::InvalidUrl, params.base_url.clone()));
+    };
+    let proxy = proxy_configured();
+    let port = parsed.port_or_known_default().unwrap_or(443);
+    let dns_start = Instant::now();
+    let dns_result = tokio::net::lookup_host((host, port)).await;
+    let dns_ms = dns_start.elapsed().as_millis() as u64;
+    if let Err(e) = dns_result {
+        if !proxy {
+            let mut result = fail(ProviderDiagnosis::DnsFailed, e.to_string());
+            result.dns_ms = Some(dns_ms);
+            return Ok(result);
+        }
+        rust_log!("[Rust] Local DNS lookup failed, continuing through proxy: {}", e);
+    }
+
+    // 2. 请求模型列表（同时验证 TLS、鉴权和 Base URL 路径）
+    let client = Client::builder()
+        .timeout(Duration::from_secs(20))
+        .build()
+        .map_err(|e| format!("创建 HTTP 客户端失败: {}", e))?;
+    let start = Instant::now();
+    let models = fetch_models(&client, &params.provider, &params.base_url, params.api_flavor, &params.api_key).await;
+    let latency_ms = start.elapsed().as_millis() as u64;
+
+    let mut result = match models {
+        Ok(models) => {
+            // 3. 确认模型可用
+            match params.model.as_deref().filter(|m| !m.is_empty()) {
+                Some(model) if !models.iter().any(|m| m.id == model) => {
+                    let mut result = fail(ProviderDiagnosis::ModelNotFound, model.to_string());
+                    result.model_count = Some(models.len());
+                    result
+                }
+                _ => {
+                    let mut result = ProviderTestResult::new(
+                        ProviderDiagnosis::Ok,
+                        diagnosis_message(ProviderDiagnosis::Ok).to_string(),
+                        &url,
+                    );
+                    result.model_count = Some(models.len());
+                    result
+                }
+            }
+        }
+        Err(ListModelsError::Request(e)) => {
+            let (diagnosis, detail) = diagnose_request_error(&e, proxy);
+            fail(diagnosis, detail)
+        }
+        Err(ListModelsError::Status(status, body)) => {
+            let mut result = fail(diagnose_status(status, &params.base_url), format!("{} {}", status, body));
+            result.status = Some(status.as_u16());
+            result
+        }
+        // 返回 200 但不是模型列表（通常是网页），路径配置错误
+        Err(ListModelsError::Parse(e)) => {
+            let diagnosis = if ends_with_version(&params.base_url) {
+                ProviderDiagnosis::DuplicatedVersion
+            } else {
+                ProviderDiagnosis::WrongBasePath
+            };
+            fail(diagnosis, e)
+        }
+    };
+    result.latency_ms = latency_ms;
+    result.dns_ms = Some(dns_ms);
+    rust_log!("[Rust] Provider test finished: {:?} in {}ms", result.diagnosis, latency_ms);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnose_status() {
+        let status = |code: u16, base_url: &str| diagnose_status(StatusCode::from_u16(code).unwrap(), base_url);
+        assert_eq!(status(401, "https://api.openai.com"), ProviderDiagnosis::Unauthorized);
+        assert_eq!(status(404, "https://relay.example.com/v3"), ProviderDiagnosis::DuplicatedVersion);
+        assert_eq!(status(404, "https://relay.example.com/v1/v2/"), ProviderDiagnosis::DuplicatedVersion);
+        assert_eq!(status(404, "https://relay.example.com/v1"), ProviderDiagnosis::WrongBasePath);
+        assert_eq!(status(404, "https://relay.example.com/api"), ProviderDiagnosis::WrongBasePath);
+        assert_eq!(status(404, "https://relay.example.com/video"), ProviderDiagnosis::WrongBasePath);
+        assert_eq!(status(407, "https://relay.example.com"), ProviderDiagnosis::ProxyFailed);
+        assert_eq!(status(503, "https://relay.example.com"), ProviderDiagnosis::ServerError);
+    }
+}
@@ -1,8 +1,9 @@
 use base64::{engine::general_purpose, Engine as _};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fs;
-use std::path::PathBuf;
-use tauri::Manager;
+use std::path::{Path, PathBuf};
+use tauri::{Emitter, Manager};
 use uuid::Uuid;
 
 // 图片类型枚举
@@ -14,7 +15,7 @@ pub enum ImageType {
 }
 
 // 图片信息结构
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ImageInfo {
     pub id: String,
     pub filename: String,
@@ -27,13 +28,41 @@ pub struct ImageInfo {
 }
 
 // 图片元数据结构（持久化存储）
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct ImageMetadata {
     pub prompt: Option<String>,
     pub input_images: Vec<InputImageInfo>,
     pub node_id: Option<String>,
     pub canvas_id: Option<String>,
     pub created_at: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operation: Option<String>, // 本地处理操作描述（如 resize 1024x1024）
+    // 以下为生成命令直接保存时记录的完整生成信息
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revised_prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aspect_ratio: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_size: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+}
+
+// 生成结果的存储选项（生成命令直接保存输出，不再返回 base64）
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerationOutputOptions {
+    pub canvas_id: Option<String>,
+    pub node_id: Option<String>,
+    pub input_labels: Option<Vec<String>>, // 输入图片标签，默认为 "图1"、"图2"...
 }
 
 // 输入图片信息
@@ -73,6 +102,50 @@ pub struct CanvasImageStats {
     pub total_size: u64,
 }
 
+// 存储配置（持久化在应用数据目录的 storage_config.json 中）
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct StorageConfig {
+    pub storage_root: Option<String>, // 自定义存储根目录，None 表示使用应用数据目录
+    #[serde(default)]
+    pub previous_roots: Vec<String>, // 历史存储根目录，用于解析迁移前保存的旧路径
+    #[serde(default)]
+    pub migrated_roots: Vec<String>, // 文件已通过迁移复制到当前目录的历史根目录（切换目录时清空）
+}
+
+// 存储位置信息（用于前端显示）
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StorageRootInfo {
+    pub storage_root: String,
+    pub images_dir: String,
+    pub default_root: String,
+    pub is_custom: bool,
+    pub previous_images_dirs: Vec<String>, // 已迁移到当前目录的历史图片目录，前端据此将旧路径映射到当前目录
+}
+
+// 迁移进度事件
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageMigrateProgress {
+    pub phase: String, // scanning, copying, verifying, committing, cleanup, completed
+    pub current: usize,
+    pub total: usize,
+    pub bytes_copied: u64,
+    pub total_bytes: u64,
+    pub file: Option<String>,
+}
+
+// 迁移结果
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StorageMigrateResult {
+    pub old_root: String,
+    pub new_root: String,
+    pub file_count: usize,
+    pub total_bytes: u64,
+    pub source_removed: bool,
+}
+
+const STORAGE_CONFIG_FILE: &str = "storage_config.json";
+const IMAGES_DIR_NAME: &str = "images";
+
 // 获取应用数据目录
 fn get_app_data_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
     app.path()
@@ -80,16 +153,154 @@ fn get_app_data_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
         .map_err(|e| format!("无法获取应用数据目录: {}", e))
 }
 
+// 读取存储配置（文件不存在时使用默认配置；文件损坏时返回错误，避免误用默认目录）
+fn load_storage_config(app: &tauri::AppHandle) -> Result<StorageConfig, String> {
+    let config_path = get_app_data_dir(app)?.join(STORAGE_CONFIG_FILE);
+    if !config_path.exists() {
+        return Ok(StorageConfig::default());
+    }
+    let content = fs::read_to_string(&config_path)
+        .map_err(|e| format!("读取存储配置失败: {}", e))?;
+    serde_json::from_str(&content)
+        .map_err(|e| format!("存储配置文件已损坏 ({}): {}", config_path.display(), e))
+}
+
+// 原子写入存储配置：先写临时文件再重命名，失败时旧配置保持不变
+fn save_storage_config(app: &tauri::AppHandle, config: &StorageConfig) -> Result<(), String> {
+    let app_data = get_app_data_dir(app)?;
+    if !app_data.exists() {
+        fs::create_dir_all(&app_data).map_err(|e| format!("创建应用数据目录失败: {}", e))?;
+    }
+    let config_json = serde_json::to_string_pretty(config)
+        .map_err(|e| format!("序列化存储配置失败: {}", e))?;
+    write_file_atomic(&app_data.join(STORAGE_CONFIG_FILE), config_json.as_bytes())
+}
+
+// 获取存储根目录（图片目录的父目录）
+fn get_storage_root(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    match load_storage_config(app)?.storage_root {
+        Some(root) => Ok(PathBuf::from(root)),
+        None => get_app_data_dir(app),
+    }
+}
+
 // 获取图片存储目录
 fn get_images_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
-    let app_data = get_app_data_dir(app)?;
-    let images_dir = app_data.join("images");
+    let images_dir = get_storage_root(app)?.join(IMAGES_DIR_NAME);
     if !images_dir.exists() {
         fs::create_dir_all(&images_dir).map_err(|e| format!("创建图片目录失败: {}", e))?;
     }
     Ok(images_dir)
 }
 
+// 将迁移前的旧路径映射到当前存储目录（文件存在时原样返回）
+fn resolve_image_path(app: &tauri::AppHandle, path: &str) -> PathBuf {
+    let original = PathBuf::from(path);
+    if original.exists() {
+        return original;
+    }
+
+    let (Ok(config), Ok(images_dir)) = (load_storage_config(app), get_images_dir(app)) else {
+        return original;
+    };
+
+    let mut old_roots: Vec<PathBuf> = config.previous_roots.iter().map(PathBuf::from).collect();
+    if let Ok(app_data) = get_app_data_dir(app) {
+        old_roots.push(app_data);
+    }
+
+    for old_root in old_roots {
+        if let Some(candidate) = remap_path(&original, &old_root.join(IMAGES_DIR_NAME), &images_dir) {
+            if candidate.exists() {
+                return candidate;
+            }
+        }
+    }
+
+    original
+}
+
+// 路径前缀替换：path 位于 from 目录下时返回 to 目录下的对应路径
+fn remap_path(path: &Path, from: &Path, to: &Path) -> Option<PathBuf> {
+    path.strip_prefix(from).ok().map(|rel| to.join(rel))
+}
+
+// 解析并校验应用存储目录内的图片路径（通过路径传图时禁止读取存储目录以外的文件）
+pub fn resolve_storage_file(app: &tauri::AppHandle, path: &str) -> Result<PathBuf, String> {
+    let resolved = resolve_image_path(app, path)
+        .canonicalize()
+        .map_err(|e| format!("图片不存在: {} ({})", path, e))?;
+    let images_dir = get_images_dir(app)?
+        .canonicalize()
+        .map_err(|e| format!("无法解析图片目录: {}", e))?;
+
+    if !resolved.starts_with(&images_dir) {
+        return Err(format!("仅允许读取应用存储目录中的图片: {}", path));
+    }
+    Ok(resolved)
+}
+
+// 校验用作文件名或目录名的 ID，只允许字母、数字、- 和 _（防止 ../ 等路径穿越）
+pub(crate) fn validate_id(id: &str, kind: &str) -> Result<(), String> {
+    let valid = !id.is_empty()
+        && id.len() <= 128
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(format!("无效的{} ID: {}", kind, id));
+    }
+    Ok(())
+}
+
+// 画布图片目录
+fn canvas_images_dir(images_dir: &Path, canvas_id: &str) -> Result<PathBuf, String> {
+    validate_id(canvas_id, "画布")?;
+    Ok(images_dir.join(canvas_id))
+}
+
+// 按图片 ID 查找存储的图片文件（文件名格式: {id}_{timestamp}.{ext}）
+pub fn find_image_by_id(
+    app: &tauri::AppHandle,
+    id: &str,
+    canvas_id: Option<&str>,
+) -> Result<PathBuf, String> {
+    validate_id(id, "图片")?;
+    let images_dir = get_images_dir(app)?;
+    let prefix = format!("{}_", id);
+
+    let mut search_dirs = Vec::new();
+    match canvas_id {
+        Some(cid) => search_dirs.push(canvas_images_dir(&images_dir, cid)?),
+        None => {
+            search_dirs.push(images_dir.clone());
+            if let Ok(entries) = fs::read_dir(&images_dir) {
+                search_dirs.extend(entries.flatten().map(|e| e.path()).filter(|p| p.is_dir()));
+            }
+        }
+    }
+
+    for dir in search_dirs {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let filename = entry.file_name().to_string_lossy().to_string();
+            if filename.starts_with(&prefix) && !filename.ends_with(".meta.json") {
+                return Ok(entry.path());
+            }
+        }
+    }
+
+    Err(format!("未找到图片: {}", id))
+}
+
+// 允许 asset 协议访问自定义存储目录（默认目录已在 tauri.conf.json 中授权）
+pub fn allow_storage_scope(app: &tauri::AppHandle) -> Result<(), String> {
+    let images_dir = get_images_dir(app)?;
+    app.asset_protocol_scope()
+        .allow_directory(&images_dir, true)
+        .map_err(|e| format!("授权存储目录访问失败: {}", e))
+}
+
 // 获取缓存目录
 fn get_cache_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
     let app_data = get_app_data_dir(app)?;
@@ -111,11 +322,42 @@ pub fn save_image(
     input_images: Option<Vec<InputImageInfo>>,
     image_type: Option<ImageType>,  // 新增：图片类型
 ) -> Result<ImageInfo, String> {
-    let images_dir = get_images_dir(&app)?;
+    // 解码 base64
+    let image_data = general_purpose::STANDARD
+        .decode(&base64_data)
+        .map_err(|e| format!("Base64 解码失败: {}", e))?;
+
+    // 保存元数据文件（如果有提示词或输入图片）
+    let metadata = if prompt.is_some() || input_images.is_some() {
+        Some(ImageMetadata {
+            prompt,
+            input_images: input_images.unwrap_or_default(),
+            node_id: node_id.clone(),
+            canvas_id: canvas_id.clone(),
+            ..Default::default()
+        })
+    } else {
+        None
+    };
+
+    store_image_bytes(&app, &image_data, "png", canvas_id, node_id, metadata, image_type)
+}
+
+// 写入图片文件及元数据，返回存储后的图片信息（供其他模块复用）
+pub fn store_image_bytes(
+    app: &tauri::AppHandle,
+    image_data: &[u8],
+    extension: &str,
+    canvas_id: Option<String>,
+    node_id: Option<String>,
+    metadata: Option<ImageMetadata>,
+    image_type: Option<ImageType>,
+) -> Result<ImageInfo, String> {
+    let images_dir = get_images_dir(app)?;
 
     // 根据 canvas_id 创建子目录
     let target_dir = if let Some(ref cid) = canvas_id {
-        let canvas_dir = images_dir.join(cid);
+        let canvas_dir = canvas_images_dir(&images_dir, cid)?;
         if !canvas_dir.exists() {
             fs::create_dir_all(&canvas_dir).map_err(|e| format!("创建画布目录失败: {}", e))?;
         }
@@ -124,37 +366,22 @@ pub fn save_image(
         images_dir
     };
 
-    // 解码 base64
-    let image_data = general_purpose::STANDARD
-        .decode(&base64_data)
-        .map_err(|e| format!("Base64 解码失败: {}", e))?;
-
     // 生成唯一文件名
     let id = Uuid::new_v4().to_string();
     let timestamp = chrono::Utc::now().timestamp();
-    let filename = format!("{}_{}.png", id, timestamp);
+    let filename = format!("{}_{}.{}", id, timestamp, extension);
     let file_path = target_dir.join(&filename);
 
     // 写入图片文件
-    fs::write(&file_path, &image_data).map_err(|e| format!("写入文件失败: {}", e))?;
-
-    // 保存元数据文件（如果有提示词或输入图片）
-    if prompt.is_some() || input_images.is_some() {
-        let metadata = ImageMetadata {
-            prompt: prompt.clone(),
-            input_images: input_images.unwrap_or_default(),
-            node_id: node_id.clone(),
-            canvas_id: canvas_id.clone(),
-            created_at: timestamp,
-        };
+    fs::write(&file_path, image_data).map_err(|e| format!("写入文件失败: {}", e))?;
 
-        let meta_filename = format!("{}_{}.meta.json", id, timestamp);
-        let meta_path = target_dir.join(&meta_filename);
+    if let Some(mut metadata) = metadata {
+        metadata.created_at = timestamp;
 
         let meta_json = serde_json::to_string_pretty(&metadata)
             .map_err(|e| format!("序列化元数据失败: {}", e))?;
 
-        fs::write(&meta_path, meta_json).map_err(|e| format!("写入元数据失败: {}", e))?;
+        fs::write(meta_path_for(&file_path), meta_json).map_err(|e| format!("写入元数据失败: {}", e))?;
     }
 
     let path_str = file_path
@@ -174,24 +401,63 @@ pub fn save_image(
     })
 }
 
+// 保存生成的图片（按真实格式确定扩展名，并记录尺寸）
+pub fn save_generated_image(
+    app: &tauri::AppHandle,
+    image_data: &[u8],
+    output: &GenerationOutputOptions,
+    mut metadata: ImageMetadata,
+) -> Result<ImageInfo, String> {
+    let extension = match image::guess_format(image_data) {
+        Ok(image::ImageFormat::Jpeg) => "jpg",
+        Ok(image::ImageFormat::WebP) => "webp",
+        _ => "png",
+    };
+    if let Ok((width, height)) = image::ImageReader::new(std::io::Cursor::new(image_data))
+        .with_guessed_format()
+        .map_err(|e| e.to_string())
+        .and_then(|r| r.into_dimensions().map_err(|e| e.to_string()))
+    {
+        metadata.width = Some(width);
+        metadata.height = Some(height);
+    }
+    metadata.canvas_id = output.canvas_id.clone();
+    metadata.node_id = output.node_id.clone();
+
+    store_image_bytes(
+        app,
+        image_data,
+        extension,
+        output.canvas_id.clone(),
+        output.node_id.clone(),
+        Some(metadata),
+        Some(ImageType::Generated),
+    )
+}
+
+// 读取已存储的图片文件（兼容迁移前的旧路径）
+pub fn read_stored_image(app: &tauri::AppHandle, path: &str) -> Result<Vec<u8>, String> {
+    fs::read(resolve_image_path(app, path)).map_err(|e| format!("读取文件失败: {}", e))
+}
+
 // 读取图片（返回 base64）
 #[tauri::command]
-pub fn read_image(path: String) -> Result<String, String> {
-    let data = fs::read(&path).map_err(|e| format!("读取文件失败: {}", e))?;
+pub fn read_image(app: tauri::AppHandle, path: String) -> Result<String, String> {
+    let data = read_stored_image(&app, &path)?;
     Ok(general_purpose::STANDARD.encode(&data))
 }
 
 // 删除图片
 #[tauri::command]
-pub fn delete_image(path: String) -> Result<(), String> {
-    fs::remove_file(&path).map_err(|e| format!("删除文件失败: {}", e))
+pub fn delete_image(app: tauri::AppHandle, path: String) -> Result<(), String> {
+    fs::remove_file(resolve_image_path(&app, &path)).map_err(|e| format!("删除文件失败: {}", e))
 }
 
 // 删除画布的所有图片
 #[tauri::command]
 pub fn delete_canvas_images(app: tauri::AppHandle, canvas_id: String) -> Result<u64, String> {
     let images_dir = get_images_dir(&app)?;
-    let canvas_dir = images_dir.join(&canvas_id);
+    let canvas_dir = canvas_images_dir(&images_dir, &canvas_id)?;
 
     if !canvas_dir.exists() {
         return Ok(0);
@@ -323,16 +589,241 @@ pub fn clear_all_images(app: tauri::AppHandle) -> Result<u64, String> {
     Ok(cleared_size)
 }
 
-// 获取应用数据目录路径（供前端显示）
+// 获取存储根目录路径（供前端显示）
 #[tauri::command]
 pub fn get_storage_path(app: tauri::AppHandle) -> Result<String, String> {
-    let app_data = get_app_data_dir(&app)?;
-    app_data
+    let storage_root = get_storage_root(&app)?;
+    storage_root
         .to_str()
         .map(|s| s.to_string())
         .ok_or("路径转换失败".to_string())
 }
 
+// 获取存储位置详情
+#[tauri::command]
+pub fn get_storage_root_info(app: tauri::AppHandle) -> Result<StorageRootInfo, String> {
+    let config = load_storage_config(&app)?;
+    let storage_root = get_storage_root(&app)?;
+    let default_root = get_app_data_dir(&app)?;
+
+    // 迁移后画布中保存的仍是旧路径，前端显示图片时需要映射到当前目录
+    // 仅包含文件确实复制到当前目录的根目录，直接切换的目录不做映射
+    let mut previous_images_dirs = Vec::new();
+    for root in config.migrated_roots.iter().map(PathBuf::from) {
+        if root == storage_root {
+            continue;
+        }
+        let dir = root.join(IMAGES_DIR_NAME).to_string_lossy().to_string();
+        if !previous_images_dirs.contains(&dir) {
+            previous_images_dirs.push(dir);
+        }
+    }
+
+    Ok(StorageRootInfo {
+        storage_root: storage_root.to_string_lossy().to_string(),
+        images_dir: storage_root.join(IMAGES_DIR_NAME).to_string_lossy().to_string(),
+        default_root: default_root.to_string_lossy().to_string(),
+        is_custom: storage_root != default_root,
+        previous_images_dirs,
+    })
+}
+
+// 切换存储根目录（不迁移文件，适用于指向已有的同步目录）
+// new_root 为 None 时恢复为应用数据目录
+#[tauri::command]
+pub fn set_storage_root(
+    app: tauri::AppHandle,
+    new_root: Option<String>,
+) -> Result<StorageRootInfo, String> {
+    let mut config = load_storage_config(&app)?;
+    let old_root = get_storage_root(&app)?;
+    let default_root = get_app_data_dir(&app)?;
+
+    let new_root_path = match &new_root {
+        Some(root) => validate_storage_root(root)?,
+        None => default_root.clone(),
+    };
+
+    if new_root_path != old_root {
+        push_previous_root(&mut config, &old_root);
+        // 新目录中没有迁移过来的文件，旧路径不再映射
+        config.migrated_roots.clear();
+    }
+    config.storage_root = if new_root_path == default_root {
+        None
+    } else {
+        Some(new_root_path.to_string_lossy().to_string())
+    };
+    save_storage_config(&app, &config)?;
+    allow_storage_scope(&app)?;
+
+    rust_log!("[Rust] Storage root set to: {}", new_root_path.display());
+    get_storage_root_info(app)
+}
+
+// 迁移存储目录：复制所有文件并逐个校验 SHA-256，全部成功后才原子切换配置
+// 任一步骤失败都会清理已复制的文件，旧目录保持不变
+#[tauri::command]
+pub async fn migrate_storage(
+    app: tauri::AppHandle,
+    new_root: String,
+    keep_source: Option<bool>,
+) -> Result<StorageMigrateResult, String> {
+    let keep_source = keep_source.unwrap_or(false);
+    let app_handle = app.clone();
+
+    tokio::task::spawn_blocking(move || migrate_storage_blocking(&app_handle, &new_root, keep_source))
+        .await
+        .map_err(|e| format!("迁移任务失败: {}", e))?
+}
+
+fn migrate_storage_blocking(
+    app: &tauri::AppHandle,
+    new_root: &str,
+    keep_source: bool,
+) -> Result<StorageMigrateResult, String> {
+    let old_root = get_storage_root(app)?;
+    let new_root = validate_storage_root(new_root)?;
+    let old_images_dir = old_root.join(IMAGES_DIR_NAME);
+    let new_images_dir = new_root.join(IMAGES_DIR_NAME);
+
+    if new_root == old_root {
+        return Err("新目录与当前存储目录相同".to_string());
+    }
+    if new_images_dir.starts_with(&old_images_dir) || old_images_dir.starts_with(&new_images_dir) {
+        return Err("新目录不能与当前图片目录互相嵌套".to_string());
+    }
+
+    rust_log!(
+        "[Rust] migrate_storage: {} -> {}",
+        old_root.display(),
+        new_root.display()
+    );
+
+    let emit_progress = |phase: &str, current: usize, total: usize, bytes_copied: u64, total_bytes: u64, file: Option<String>| {
+        let _ = app.emit(
+            "storage-migrate-progress",
+            StorageMigrateProgress {
+                phase: phase.to_string(),
+                current,
+                total,
+                bytes_copied,
+                total_bytes,
+                file,
+            },
+        );
+    };
+
+    // 1. 扫描源目录
+    emit_progress("scanning", 0, 0, 0, 0, None);
+    let mut files: Vec<PathBuf> = Vec::new();
+    if old_images_dir.exists() {
+        collect_files(&old_images_dir, &mut files)
+            .map_err(|e| format!("扫描存储目录失败: {}", e))?;
+    }
+    let total = files.len();
+    let total_bytes: u64 = files
+        .iter()
+        .filter_map(|f| fs::metadata(f).ok())
+        .map(|m| m.len())
+        .sum();
+    rust_log!("[Rust] Migrating {} files ({} bytes)", total, total_bytes);
+
+    // 2. 复制并校验（失败时回滚已复制的文件）
+    let mut created: Vec<PathBuf> = Vec::new();
+    let mut copy_files = || -> Result<u64, String> {
+        let mut bytes_copied: u64 = 0;
+        for (index, source) in files.iter().enumerate() {
+            let rel = source
+                .strip_prefix(&old_images_dir)
+                .map_err(|_| format!("无法计算相对路径: {}", source.display()))?;
+            let target = new_images_dir.join(rel);
+            let rel_display = rel.to_string_lossy().to_string();
+
+            emit_progress("copying", index, total, bytes_copied, total_bytes, Some(rel_display.clone()));
+
+            let data = fs::read(source).map_err(|e| format!("读取文件失败 {}: {}", rel_display, e))?;
+            // 元数据中记录的输入图片路径需要指向新目录
+            let data = if rel_display.ends_with(".meta.json") {
+                rewrite_metadata_paths(&data, &old_images_dir, &new_images_dir)
+            } else {
+                data
+            };
+            let expected = sha256_hex(&data);
+
+            if target.exists() {
+                let existing = fs::read(&target).map_err(|e| format!("读取目标文件失败 {}: {}", rel_display, e))?;
+                if sha256_hex(&existing) != expected {
+                    return Err(format!("目标目录已存在不同内容的同名文件: {}", rel_display));
+                }
+            } else {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent).map_err(|e| format!("创建目录失败: {}", e))?;
+                }
+                fs::write(&target, &data).map_err(|e| format!("写入文件失败 {}: {}", rel_display, e))?;
+                created.push(target.clone());
+            }
+
+            emit_progress("verifying", index, total, bytes_copied, total_bytes, Some(rel_display.clone()));
+            let written = fs::read(&target).map_err(|e| format!("校验读取失败 {}: {}", rel_display, e))?;
+            if sha256_hex(&written) != expected {
+                return Err(format!("校验和不匹配: {}", rel_display));
+            }
+
+            bytes_copied += data.len() as u64;
+        }
+        Ok(bytes_copied)
+    };
+
+    let bytes_copied = match copy_files() {
+        Ok(b) => b,
+        Err(e) => {
+            rust_log!("[Rust] Migration failed, rolling back: {}", e);
+            rollback_created_files(&created, &new_images_dir);
+            return Err(e);
+        }
+    };
+
+    // 3. 原子切换配置
+    emit_progress("committing", total, total, bytes_copied, total_bytes, None);
+    let mut config = load_storage_config(app)?;
+    push_previous_root(&mut config, &old_root);
+    // 旧目录（及此前迁移到旧目录的根目录）的文件已全部复制到新目录
+    push_migrated_root(&mut config, &old_root);
+    let new_root_str = new_root.to_string_lossy().to_string();
+    config.migrated_roots.retain(|root| *root != new_root_str);
+    config.storage_root = if new_root == get_app_data_dir(app)? {
+        None
+    } else {
+        Some(new_root.to_string_lossy().to_string())
+    };
+    if let Err(e) = save_storage_config(app, &config) {
+        rollback_created_files(&created, &new_images_dir);
+        return Err(e);
+    }
+    allow_storage_scope(app)?;
+
+    // 4. 清理旧目录（配置已切换，清理失败不影响数据完整性）
+    if !keep_source {
+        emit_progress("cleanup", total, total, bytes_copied, total_bytes, None);
+        for source in &files {
+            let _ = fs::remove_file(source);
+        }
+        remove_empty_dirs(&old_images_dir);
+    }
+
+    emit_progress("completed", total, total, bytes_copied, total_bytes, None);
+    rust_log!("[Rust] Migration completed: {} files", total);
+
+    Ok(StorageMigrateResult {
+        old_root: old_root.to_string_lossy().to_string(),
+        new_root: new_root.to_string_lossy().to_string(),
+        file_count: total,
+        total_bytes: bytes_copied,
+        source_removed: !keep_source,
+    })
+}
+
 // 列出画布的所有图片（带元数据）
 #[tauri::command]
 pub fn list_canvas_images(
@@ -340,7 +831,7 @@ pub fn list_canvas_images(
     canvas_id: String,
 ) -> Result<Vec<ImageInfoWithMetadata>, String> {
     let images_dir = get_images_dir(&app)?;
-    let canvas_dir = images_dir.join(&canvas_id);
+    let canvas_dir = canvas_images_dir(&images_dir, &canvas_id)?;
 
     let mut images: Vec<ImageInfoWithMetadata> = Vec::new();
 
@@ -371,7 +862,9 @@ pub fn list_canvas_images(
                     // 尝试从文件名获取时间戳，否则使用文件创建时间
                     let created_at = if parts.len() >= 2 {
                         parts[1]
-                            .trim_end_matches(".png")
+                            .split('.')
+                            .next()
+                            .unwrap_or("")
                             .parse::<i64>()
                             .unwrap_or_else(|_| {
                                 file_metadata
@@ -395,8 +888,7 @@ pub fn list_canvas_images(
                     };
 
                     // 尝试读取对应的元数据文件
-                    let meta_filename = filename.replace(".png", ".meta.json");
-                    let meta_path = canvas_dir.join(&meta_filename);
+                    let meta_path = meta_path_for(&path);
                     let metadata = if meta_path.exists() {
                         fs::read_to_string(&meta_path)
                             .ok()
@@ -442,11 +934,14 @@ pub fn list_canvas_images(
 
 // 读取单个图片的元数据
 #[tauri::command]
-pub fn read_image_metadata(image_path: String) -> Result<Option<ImageMetadata>, String> {
+pub fn read_image_metadata(
+    app: tauri::AppHandle,
+    image_path: String,
+) -> Result<Option<ImageMetadata>, String> {
     // 从图片路径构造元数据文件路径
-    let meta_path = image_path.replace(".png", ".meta.json");
+    let meta_path = meta_path_for(&resolve_image_path(&app, &image_path));
 
-    if !std::path::Path::new(&meta_path).exists() {
+    if !meta_path.exists() {
         return Ok(None);
     }
 
@@ -459,6 +954,11 @@ pub fn read_image_metadata(image_path: String) -> Result<Option<ImageMetadata>,
     Ok(Some(metadata))
 }
 
+// 辅助函数：图片对应的元数据文件路径（{id}_{timestamp}.{ext} -> {id}_{timestamp}.meta.json）
+fn meta_path_for(image_path: &Path) -> PathBuf {
+    image_path.with_extension("meta.json")
+}
+
 // 辅助函数：计算目录大小
 fn calculate_dir_size(path: &PathBuf) -> u64 {
     let mut size: u64 = 0;
@@ -476,3 +976,159 @@ fn calculate_dir_size(path: &PathBuf) -> u64 {
 
     size
 }
+
+// 辅助函数：校验并创建存储根目录
+fn validate_storage_root(root: &str) -> Result<PathBuf, String> {
+    let trimmed = root.trim();
+    if trimmed.is_empty() {
+        return Err("存储目录不能为空".to_string());
+    }
+    let path = PathBuf::from(trimmed);
+    if !path.is_absolute() {
+        return Err("存储目录必须是绝对路径".to_string());
+    }
+    fs::create_dir_all(&path).map_err(|e| format!("创建存储目录失败: {}", e))?;
+
+    // 写入探测文件确认目录可写
+    let probe = path.join(format!(".nextcreator_probe_{}", Uuid::new_v4()));
+    fs::write(&probe, b"ok").map_err(|e| format!("存储目录不可写: {}", e))?;
+    let _ = fs::remove_file(&probe);
+
+    Ok(path)
+}
+
+// 辅助函数：记录历史根目录（去重）
+fn push_previous_root(config: &mut StorageConfig, old_root: &Path) {
+    let old = old_root.to_string_lossy().to_string();
+    if !config.previous_roots.contains(&old) {
+        config.previous_roots.push(old);
+    }
+}
+
+// 辅助函数：记录已迁移的根目录（去重）
+fn push_migrated_root(config: &mut StorageConfig, old_root: &Path) {
+    let old = old_root.to_string_lossy().to_string();
+    if !config.migrated_roots.contains(&old) {
+        config.migrated_roots.push(old);
+    }
+}
+
+// 辅助函数：原子写入文件
+pub(crate) fn write_file_atomic(path: &Path, data: &[u8]) -> Result<(), String> {
+    let tmp_path = path.with_extension(format!("tmp-{}", Uuid::new_v4()));
+    fs::write(&tmp_path, data).map_err(|e| format!("写入临时文件失败: {}", e))?;
+    fs::rename(&tmp_path, path).map_err(|e| {
+        let _ = fs::remove_file(&tmp_path);
+        format!("替换文件失败: {}", e)
+    })
+}
+
+// 辅助函数：递归收集目录下的所有文件
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// 辅助函数：回滚迁移过程中已创建的文件
+fn rollback_created_files(created: &[PathBuf], images_dir: &Path) {
+    for path in created.iter().rev() {
+        let _ = fs::remove_file(path);
+    }
+    remove_empty_dirs(images_dir);
+}
+
+// 辅助函数：自底向上删除空目录（保留根目录本身）
+fn remove_empty_dirs(dir: &Path) {
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                remove_empty_dirs(&path);
+                let _ = fs::remove_dir(&path);
+            }
+        }
+    }
+}
+
+// 辅助函数：计算 SHA-256
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+// 辅助函数：重写元数据中指向旧图片目录的输入图片路径
+fn rewrite_metadata_paths(data: &[u8], old_images_dir: &Path, new_images_dir: &Path) -> Vec<u8> {
+    let Ok(mut metadata) = serde_json::from_slice::<ImageMetadata>(data) else {
+        return data.to_vec();
+    };
+
+    for input in metadata.input_images.iter_mut() {
+        if let Some(path) = &input.path {
+            if let Some(new_path) = remap_path(Path::new(path), old_images_dir, new_images_dir) {
+                input.path = Some(new_path.to_string_lossy().to_string());
+            }
+        }
+    }
+
+    serde_json::to_vec_pretty(&metadata).unwrap_or_else(|_| data.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canvas_images_dir_rejects_traversal() {
+        let images_dir = Path::new("/root/images");
+        assert_eq!(
+            canvas_images_dir(images_dir, "3f2c9a1e-7b4d-4c2a-9e1f-0a1b2c3d4e5f").unwrap(),
+            PathBuf::from("/root/images/3f2c9a1e-7b4d-4c2a-9e1f-0a1b2c3d4e5f")
+        );
+        assert!(canvas_images_dir(images_dir, "../secrets").is_err());
+        assert!(canvas_images_dir(images_dir, "/etc").is_err());
+        assert!(canvas_images_dir(images_dir, "").is_err());
+    }
+
+    #[test]
+    fn test_remap_path() {
+        let from = Path::new("/old/root/images");
+        let to = Path::new("/new/root/images");
+
+        let mapped = remap_path(Path::new("/old/root/images/canvas/a_1.png"), from, to);
+        assert_eq!(mapped, Some(PathBuf::from("/new/root/images/canvas/a_1.png")));
+
+        assert_eq!(remap_path(Path::new("/elsewhere/a_1.png"), from, to), None);
+    }
+
+    #[test]
+    fn test_rewrite_metadata_paths() {
+        let metadata = ImageMetadata {
+            prompt: Some("test".to_string()),
+            input_images: vec![
+                InputImageInfo {
+                    path: Some("/old/images/c1/in_1.png".to_string()),
+                    label: "图1".to_string(),
+                },
+                InputImageInfo {
+                    path: Some("/external/ref.png".to_string()),
+                    label: "图2".to_string(),
+                },
+            ],
+            node_id: None,
+            canvas_id: Some("c1".to_string()),
+            ..Default::default()
+        };
+        let data = serde_json::to_vec(&metadata).unwrap();
+
+        let rewritten = rewrite_metadata_paths(&data, Path::new("/old/images"), Path::new("/new/images"));
+        let parsed: ImageMetadata = serde_json::from_slice(&rewritten).unwrap();
+        assert_eq!(parsed.input_images[0].path.as_deref(), Some("/new/images/c1/in_1.png"));
+        assert_eq!(parsed.input_images[1].path.as_deref(), Some("/external/ref.png"));
+    }
+}
@@ -1,9 +1,12 @@
 // 批量处理服务
 // 并发 Gemini 检测 + 自适应背景修复
 
-use super::gemini_detector::{detect_text, extract_text_styles, GeminiConfig, TextRegion};
-use super::service::{build_text_boxes, TextBoxData};
+use super::gemini_detector::{extract_text_styles, TextRegion};
+use super::service::{build_text_boxes, detect_text_with_failover, GeminiProviderParams, TextBoxData};
 use super::adaptive_inpainter::adaptive_inpaint;
+use crate::budget::BudgetExceeded;
+use crate::image_source::ImageSource;
+use crate::vault::resolve_api_key;
 
 use base64::{engine::general_purpose::STANDARD, Engine};
 use image::{DynamicImage, ImageFormat, RgbImage};
@@ -21,8 +24,8 @@ use tauri::{AppHandle, Emitter};
 pub struct PageInput {
     /// 页面索引
     pub page_index: usize,
-    /// base64 编码的图片
-    pub image_data: String,
+    /// 页面图片（base64、存储路径或图片 ID）
+    pub image_data: ImageSource,
 }
 
 /// 批量处理请求参数
@@ -31,12 +34,9 @@ pub struct PageInput {
 pub struct BatchProcessParams {
     /// 待处理的页面列表
     pub pages: Vec<PageInput>,
-    /// Gemini API 基础 URL
-    pub gemini_base_url: String,
-    /// Gemini API Key
-    pub gemini_api_key: String,
-    /// Gemini 模型名称
-    pub gemini_model: String,
+    /// Gemini 供应商（每个页面的检测各自按顺序使用备用供应商）
+    #[serde(flatten)]
+    pub provider: GeminiProviderParams,
 }
 
 /// 页面处理进度事件
@@ -59,6 +59,9 @@ pub struct PageProgressEvent {
     pub image_height: Option<u32>,
     /// 检测到的文字区域数量
     pub regions_count: Option<usize>,
+    /// 超出预算时未发出检测请求
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_exceeded: Option<BudgetExceeded>,
 }
 
 /// 批量处理完成事件
@@ -108,21 +111,23 @@ fn set_stop_signal() {
 
 /// 批量处理页面
 #[tauri::command]
-pub async fn process_pages_batch(app: AppHandle, params: BatchProcessParams) -> BatchProcessResult {
-    println!(
+pub async fn process_pages_batch(app: AppHandle, mut params: BatchProcessParams) -> BatchProcessResult {
+    rust_log!(
         "[Rust] process_pages_batch 开始处理 {} 个页面",
         params.pages.len()
     );
 
+    let provider = &mut params.provider;
+    if let Err(e) = resolve_api_key(&app, &provider.gemini_credential_id, &mut provider.gemini_api_key) {
+        return BatchProcessResult {
+            success: false,
+            message: e,
+        };
+    }
+
     // 重置停止信号
     reset_stop_signal();
 
-    let gemini_config = GeminiConfig {
-        base_url: params.gemini_base_url.clone(),
-        api_key: params.gemini_api_key.clone(),
-        model: params.gemini_model.clone(),
-    };
-
     let app_handle = app.clone();
 
     // 统计
@@ -138,7 +143,7 @@ pub async fn process_pages_batch(app: AppHandle, params: BatchProcessParams) ->
             break;
         }
 
-        let gemini_cfg = gemini_config.clone();
+        let provider = params.provider.clone();
         let app_for_detect = app_handle.clone();
         let error_for_detect = error_count.clone();
         let success_for_detect = success_count.clone();
@@ -162,24 +167,48 @@ pub async fn process_pages_batch(app: AppHandle, params: BatchProcessParams) ->
                     image_width: None,
                     image_height: None,
                     regions_count: None,
+                    budget_exceeded: None,
                 },
             );
 
+            // 读取页面图片
+            let image_data = match page.image_data.load_base64(&app_for_detect).await {
+                Ok(data) => data,
+                Err(e) => {
+                    error_for_detect.fetch_add(1, Ordering::SeqCst);
+                    let _ = app_for_detect.emit(
+                        "batch-page-progress",
+                        PageProgressEvent {
+                            page_index,
+                            status: "error".to_string(),
+                            error: Some(format!("读取图片失败: {}", e)),
+                            background_image: None,
+                            text_boxes: None,
+                            image_width: None,
+                            image_height: None,
+                            regions_count: None,
+                            budget_exceeded: None,
+                        },
+                    );
+                    return;
+                }
+            };
+
             // 执行检测
-            match detect_text(&page.image_data, &gemini_cfg).await {
-                Ok(result) => {
+            match detect_text_with_failover(&app_for_detect, &image_data, provider).await.result {
+                Ok(detected) => {
                     if is_stop_requested() {
                         return;
                     }
 
-                    println!(
+                    rust_log!(
                         "[Rust] 页面 {} 检测到 {} 个文本区域",
                         page_index,
-                        result.regions.len()
+                        detected.regions.len()
                     );
 
                     // 解码图片（用于尺寸/样式/文本框计算）
-                    let image_bytes = match STANDARD.decode(&page.image_data) {
+                    let image_bytes = match STANDARD.decode(&image_data) {
                         Ok(b) => b,
                         Err(e) => {
                             error_for_detect.fetch_add(1, Ordering::SeqCst);
@@ -194,6 +223,7 @@ pub async fn process_pages_batch(app: AppHandle, params: BatchProcessParams) ->
                                     image_width: None,
                                     image_height: None,
                                     regions_count: None,
+                                    budget_exceeded: None,
                                 },
                             );
                             return;
@@ -215,6 +245,7 @@ pub async fn process_pages_batch(app: AppHandle, params: BatchProcessParams) ->
                                     image_width: None,
                                     image_height: None,
                                     regions_count: None,
+                                    budget_exceeded: None,
                                 },
                             );
                             return;
@@ -226,7 +257,7 @@ pub async fn process_pages_batch(app: AppHandle, params: BatchProcessParams) ->
                     let rgb_image = img.to_rgb8();
 
                     // 如果没有检测到文字，直接完成
-                    if result.regions.is_empty() {
+                    if detected.regions.is_empty() {
                         success_for_detect.fetch_add(1, Ordering::SeqCst);
                         let _ = app_for_detect.emit(
                             "batch-page-progress",
@@ -234,26 +265,27 @@ pub async fn process_pages_batch(app: AppHandle, params: BatchProcessParams) ->
                                 page_index,
                                 status: "completed".to_string(),
                                 error: None,
-                                background_image: Some(page.image_data.clone()),
+                                background_image: Some(image_data.clone()),
                                 text_boxes: Some(vec![]),
                                 image_width: Some(image_width),
                                 image_height: Some(image_height),
                                 regions_count: Some(0),
+                                budget_exceeded: None,
                             },
                         );
                         return;
                     }
 
-                    let styles = match extract_text_styles(&page.image_data, &result.regions, &gemini_cfg).await {
+                    let styles = match extract_text_styles(&image_data, &detected.regions, &detected.config).await {
                         Ok(s) => s,
                         Err(e) => {
-                            println!("[Rust] 样式提取失败，使用默认样式: {}", e);
+                            rust_log!("[Rust] 样式提取失败，使用默认样式: {}", e);
                             vec![]
                         }
                     };
 
                     let text_boxes = build_text_boxes(
-                        &result.regions,
+                        &detected.regions,
                         &styles,
                         image_width,
                         image_height,
@@ -271,7 +303,8 @@ pub async fn process_pages_batch(app: AppHandle, params: BatchProcessParams) ->
                             text_boxes: None,
                             image_width: None,
                             image_height: None,
-                            regions_count: Some(result.regions.len()),
+                            regions_count: Some(detected.regions.len()),
+                            budget_exceeded: None,
                         },
                     );
 
@@ -279,7 +312,7 @@ pub async fn process_pages_batch(app: AppHandle, params: BatchProcessParams) ->
                         return;
                     }
 
-                    match process_inpaint(rgb_image, &result.regions).await {
+                    match process_inpaint(rgb_image, &detected.regions).await {
                         Ok(bg_image) => {
                             success_for_detect.fetch_add(1, Ordering::SeqCst);
                             let _ = app_for_detect.emit(
@@ -292,7 +325,8 @@ pub async fn process_pages_batch(app: AppHandle, params: BatchProcessParams) ->
                                     text_boxes: Some(text_boxes),
                                     image_width: Some(image_width),
                                     image_height: Some(image_height),
-                                    regions_count: Some(result.regions.len()),
+                                    regions_count: Some(detected.regions.len()),
+                                    budget_exceeded: None,
                                 },
                             );
                         }
@@ -309,6 +343,7 @@ pub async fn process_pages_batch(app: AppHandle, params: BatchProcessParams) ->
                                     image_width: None,
                                     image_height: None,
                                     regions_count: None,
+                                    budget_exceeded: None,
                                 },
                             );
                         }
@@ -327,6 +362,7 @@ pub async fn process_pages_batch(app: AppHandle, params: BatchProcessParams) ->
                             image_width: None,
                             image_height: None,
                             regions_count: None,
+                            budget_exceeded: e.budget_exceeded(),
                         },
                     );
                 }
@@ -355,7 +391,7 @@ pub async fn process_pages_batch(app: AppHandle, params: BatchProcessParams) ->
         },
     );
 
-    println!(
+    rust_log!(
         "[Rust] 批量处理完成: 成功 {}, 失败 {}",
         total_success, total_errors
     );
@@ -375,7 +411,7 @@ pub async fn process_pages_batch(app: AppHandle, params: BatchProcessParams) ->
 /// 停止批量处理
 #[tauri::command]
 pub async fn stop_batch_processing() -> BatchProcessResult {
-    println!("[Rust] 收到停止批量处理请求");
+    rust_log!("[Rust] 收到停止批量处理请求");
     set_stop_signal();
     BatchProcessResult {
         success: true,
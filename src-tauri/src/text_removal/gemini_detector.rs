@@ -1,6 +1,10 @@
 // Gemini 文字检测器
 // 使用 Gemini API 进行两轮调用检测 PPT 图片中的文字
 
+use crate::budget::{BudgetExceeded, BudgetReservation};
+use crate::failover::FailureKind;
+use crate::gemini::{GeminiEndpoint, UsageMetadata};
+use crate::usage::{Usage, UsageLedger};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
@@ -21,12 +25,79 @@ pub struct TextDetectionResult {
     pub raw_response: String,
 }
 
+/// 检测错误（超出预算时单独返回，便于前端与请求失败区分）
+#[derive(Debug)]
+pub enum DetectError {
+    BudgetExceeded(BudgetExceeded),
+    Request(FailureKind, String), // 请求未送达或供应商返回错误状态码（可切换供应商重试）
+    Failed(String),
+}
+
+impl DetectError {
+    pub fn budget_exceeded(&self) -> Option<BudgetExceeded> {
+        match self {
+            DetectError::BudgetExceeded(e) => Some(e.clone()),
+            DetectError::Request(..) | DetectError::Failed(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for DetectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DetectError::BudgetExceeded(e) => e.fmt(f),
+            DetectError::Request(_, message) | DetectError::Failed(message) => f.write_str(message),
+        }
+    }
+}
+
+impl From<String> for DetectError {
+    fn from(message: String) -> Self {
+        DetectError::Failed(message)
+    }
+}
+
+impl From<&str> for DetectError {
+    fn from(message: &str) -> Self {
+        DetectError::Failed(message.to_string())
+    }
+}
+
 /// Gemini API 配置
 #[derive(Debug, Clone)]
 pub struct GeminiConfig {
     pub base_url: String,
     pub api_key: String,
     pub model: String,
+    pub ledger: Option<UsageLedger>, // 用量台账（为空时不记录）
+}
+
+// 单次检测请求预留的用量：一张图片加提示词的输入，以及代码执行和坐标输出
+const PLANNED_DETECTION_USAGE: Usage = Usage {
+    input_tokens: 1_500,
+    output_tokens: 2_000,
+    images: 0,
+    video_seconds: 0.0,
+};
+
+impl GeminiConfig {
+    /// 每次成功返回的调用都按 token 记录用量（包括第一轮的重试）
+    fn record_usage(&self, usage: Option<&UsageMetadata>) {
+        if let (Some(ledger), Some(usage)) = (&self.ledger, usage) {
+            ledger.record("gemini", &self.model, &usage.to_usage());
+        }
+    }
+
+    /// 每次请求前检查预算，超出时不再发出请求
+    fn check_budget(&self) -> Result<Option<BudgetReservation>, DetectError> {
+        match &self.ledger {
+            Some(ledger) => ledger
+                .check_budget("gemini", &self.model, &PLANNED_DETECTION_USAGE)
+                .map(Some)
+                .map_err(DetectError::BudgetExceeded),
+            None => Ok(None),
+        }
+    }
 }
 
 /// 第一轮检测提示词（代码执行 + 视觉思维）
@@ -60,6 +131,8 @@ const ROUND1_PROMPT: &str = r#"
 struct GeminiResponse {
     candidates: Option<Vec<Candidate>>,
     error: Option<GeminiError>,
+    #[serde(rename = "usageMetadata")]
+    usage_metadata: Option<UsageMetadata>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -142,28 +215,23 @@ fn is_valid_detection_result(text: &str) -> bool {
 pub async fn detect_text(
     image_base64: &str,
     config: &GeminiConfig,
-) -> Result<TextDetectionResult, String> {
+) -> Result<TextDetectionResult, DetectError> {
     let client = Client::builder()
         .timeout(Duration::from_secs(120))
         .build()
         .map_err(|e| format!("创建 HTTP 客户端失败: {}", e))?;
 
-    let url = format!(
-        "{}/v1beta/models/{}:generateContent?key={}",
-        config.base_url.trim_end_matches('/'),
-        config.model,
-        config.api_key
-    );
+    let endpoint = GeminiEndpoint::generate_content(&config.base_url, &config.model, &config.api_key);
 
     // 第一轮：自由格式输出（带重试）
-    println!("[Rust] Gemini 第一轮检测...");
-    let round1_result = detect_text_round1(&client, &url, image_base64).await?;
-    println!("[Rust] 第一轮结果长度: {} 字符, 有效: {}", round1_result.text.len(), round1_result.is_valid);
+    rust_log!("[Rust] Gemini 第一轮检测...");
+    let round1_result = detect_text_round1(&client, &endpoint, config, image_base64).await?;
+    rust_log!("[Rust] 第一轮结果长度: {} 字符, 有效: {}", round1_result.text.len(), round1_result.is_valid);
 
     // 第二轮：结构化规范化（无论第一轮是否包含 box_2d 都执行）
-    println!("[Rust] Gemini 第二轮结构化...");
-    let regions = normalize_detection_result(&client, &url, &round1_result.text).await?;
-    println!("[Rust] 最终检测到 {} 个文本区域", regions.len());
+    rust_log!("[Rust] Gemini 第二轮结构化...");
+    let regions = normalize_detection_result(&client, &endpoint, config, &round1_result.text).await?;
+    rust_log!("[Rust] 最终检测到 {} 个文本区域", regions.len());
 
     Ok(TextDetectionResult {
         regions,
@@ -176,7 +244,7 @@ pub async fn extract_text_styles(
     image_base64: &str,
     regions: &[TextRegion],
     config: &GeminiConfig,
-) -> Result<Vec<TextStyleInfo>, String> {
+) -> Result<Vec<TextStyleInfo>, DetectError> {
     if regions.is_empty() {
         return Ok(vec![]);
     }
@@ -186,12 +254,7 @@ pub async fn extract_text_styles(
         .build()
         .map_err(|e| format!("创建 HTTP 客户端失败: {}", e))?;
 
-    let url = format!(
-        "{}/v1beta/models/{}:generateContent?key={}",
-        config.base_url.trim_end_matches('/'),
-        config.model,
-        config.api_key
-    );
+    let endpoint = GeminiEndpoint::generate_content(&config.base_url, &config.model, &config.api_key);
 
     let mut list_lines = Vec::new();
     for (i, r) in regions.iter().enumerate() {
@@ -253,25 +316,28 @@ pub async fn extract_text_styles(
         }
     });
 
-    let response = client
-        .post(url)
-        .json(&request_body)
-        .send()
+    let _reservation = config.check_budget()?;
+    let response = endpoint
+        .post(&client, &request_body)
         .await
-        .map_err(|e| format!("样式提取请求失败: {}", e))?;
+        .map_err(|e| DetectError::Request(FailureKind::from_request_error(&e), format!("样式提取请求失败: {}", e)))?;
 
-    let status = response.status();
-    let response_text = response.text().await.map_err(|e| format!("读取响应失败: {}", e))?;
+    let status = response.status;
+    let response_text = response.text;
 
     if !status.is_success() {
-        return Err(format!("API 返回错误 ({}): {}", status, response_text));
+        return Err(DetectError::Request(
+            FailureKind::Status(status.as_u16()),
+            format!("API 返回错误 ({}): {}", status, response_text),
+        ));
     }
 
     let gemini_response: GeminiResponse = serde_json::from_str(&response_text)
         .map_err(|e| format!("解析响应失败: {} - {}", e, response_text))?;
+    config.record_usage(gemini_response.usage_metadata.as_ref());
 
     if let Some(error) = gemini_response.error {
-        return Err(format!("Gemini API 错误: {}", error.message));
+        return Err(format!("Gemini API 错误: {}", error.message).into());
     }
 
     let text = gemini_response.candidates
@@ -300,9 +366,10 @@ struct Round1Result {
 /// 即使结果不包含 box_2d，也返回原始响应用于第二轮处理
 async fn detect_text_round1(
     client: &Client,
-    url: &str,
+    endpoint: &GeminiEndpoint,
+    config: &GeminiConfig,
     image_base64: &str,
-) -> Result<Round1Result, String> {
+) -> Result<Round1Result, DetectError> {
     let request_body = serde_json::json!({
         "contents": [{
             "parts": [
@@ -327,31 +394,38 @@ async fn detect_text_round1(
 
     let max_retries = 3;
     let mut last_raw_result: Option<String> = None;
+    let mut last_request_error: Option<DetectError> = None;
 
     for attempt in 0..max_retries {
-        match client.post(url).json(&request_body).send().await {
+        let _reservation = config.check_budget()?;
+        match endpoint.post(client, &request_body).await {
             Ok(resp) => {
-                let status = resp.status();
-                let response_text = resp.text().await.map_err(|e| format!("读取响应失败: {}", e))?;
+                last_request_error = None;
+                let status = resp.status;
+                let response_text = resp.text;
 
                 if !status.is_success() {
-                    println!("[Rust] 第一轮尝试 {}/{} API 错误: {}", attempt + 1, max_retries, response_text);
+                    rust_log!("[Rust] 第一轮尝试 {}/{} API 错误: {}", attempt + 1, max_retries, response_text);
                     if attempt < max_retries - 1 {
                         tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
                         continue;
                     }
-                    return Err(format!("API 返回错误 ({}): {}", status, response_text));
+                    return Err(DetectError::Request(
+                        FailureKind::Status(status.as_u16()),
+                        format!("API 返回错误 ({}): {}", status, response_text),
+                    ));
                 }
 
                 match serde_json::from_str::<GeminiResponse>(&response_text) {
                     Ok(response) => {
+                        config.record_usage(response.usage_metadata.as_ref());
                         if let Some(error) = response.error {
-                            println!("[Rust] 第一轮尝试 {}/{} API 错误: {}", attempt + 1, max_retries, error.message);
+                            rust_log!("[Rust] 第一轮尝试 {}/{} API 错误: {}", attempt + 1, max_retries, error.message);
                             if attempt < max_retries - 1 {
                                 tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
                                 continue;
                             }
-                            return Err(format!("Gemini API 错误: {}", error.message));
+                            return Err(format!("Gemini API 错误: {}", error.message).into());
                         }
 
                         if let Some(parts) = response.candidates
@@ -372,24 +446,28 @@ async fn detect_text_round1(
                             last_raw_result = Some(text.clone());
 
                             if is_valid_detection_result(&text) {
-                                println!("[Rust] 第一轮尝试 {}/{}: 成功，检测到有效结果", attempt + 1, max_retries);
+                                rust_log!("[Rust] 第一轮尝试 {}/{}: 成功，检测到有效结果", attempt + 1, max_retries);
                                 return Ok(Round1Result {
                                     text: text.clone(),
                                     is_valid: true,
                                 });
                             } else {
-                                println!("[Rust] 第一轮尝试 {}/{}: 结果不含 box_2d，将交给第二轮处理", attempt + 1, max_retries);
+                                rust_log!("[Rust] 第一轮尝试 {}/{}: 结果不含 box_2d，将交给第二轮处理", attempt + 1, max_retries);
                                 // 继续重试，但保存响应以备用
                             }
                         }
                     }
                     Err(e) => {
-                        println!("[Rust] 第一轮尝试 {}/{} 解析失败: {}", attempt + 1, max_retries, e);
+                        rust_log!("[Rust] 第一轮尝试 {}/{} 解析失败: {}", attempt + 1, max_retries, e);
                     }
                 }
             }
             Err(e) => {
-                println!("[Rust] 第一轮尝试 {}/{} 网络失败: {}", attempt + 1, max_retries, e);
+                rust_log!("[Rust] 第一轮尝试 {}/{} 网络失败: {}", attempt + 1, max_retries, e);
+                last_request_error = Some(DetectError::Request(
+                    FailureKind::from_request_error(&e),
+                    format!("第一轮请求失败: {}", e),
+                ));
             }
         }
 
@@ -400,22 +478,24 @@ async fn detect_text_round1(
 
     // 如果有原始响应，即使不包含 box_2d，也返回给第二轮处理
     if let Some(text) = last_raw_result {
-        println!("[Rust] 第一轮未获得有效 box_2d 结果，将原始响应传给第二轮");
+        rust_log!("[Rust] 第一轮未获得有效 box_2d 结果，将原始响应传给第二轮");
         return Ok(Round1Result {
             text,
             is_valid: false,
         });
     }
 
-    Err("第一轮调用失败：未获得任何有效响应".to_string())
+    // 最后一次尝试网络失败时返回请求错误，便于切换到备用供应商
+    Err(last_request_error.unwrap_or_else(|| "第一轮调用失败：未获得任何有效响应".into()))
 }
 
 /// 第二轮调用：规范化输出格式（结构化输出）
 async fn normalize_detection_result(
     client: &Client,
-    url: &str,
+    endpoint: &GeminiEndpoint,
+    config: &GeminiConfig,
     raw_result: &str,
-) -> Result<Vec<TextRegion>, String> {
+) -> Result<Vec<TextRegion>, DetectError> {
     let prompt = format!(r#"
 请解析以下文字检测结果，转换为规范化的格式。
 
@@ -476,25 +556,28 @@ async fn normalize_detection_result(
         }
     });
 
-    let response = client
-        .post(url)
-        .json(&request_body)
-        .send()
+    let _reservation = config.check_budget()?;
+    let response = endpoint
+        .post(client, &request_body)
         .await
-        .map_err(|e| format!("第二轮请求失败: {}", e))?;
+        .map_err(|e| DetectError::Request(FailureKind::from_request_error(&e), format!("第二轮请求失败: {}", e)))?;
 
-    let status = response.status();
-    let response_text = response.text().await.map_err(|e| format!("读取响应失败: {}", e))?;
+    let status = response.status;
+    let response_text = response.text;
 
     if !status.is_success() {
-        return Err(format!("API 返回错误 ({}): {}", status, response_text));
+        return Err(DetectError::Request(
+            FailureKind::Status(status.as_u16()),
+            format!("API 返回错误 ({}): {}", status, response_text),
+        ));
     }
 
     let gemini_response: GeminiResponse = serde_json::from_str(&response_text)
         .map_err(|e| format!("解析响应失败: {} - {}", e, response_text))?;
+    config.record_usage(gemini_response.usage_metadata.as_ref());
 
     if let Some(error) = gemini_response.error {
-        return Err(format!("Gemini API 错误: {}", error.message));
+        return Err(format!("Gemini API 错误: {}", error.message).into());
     }
 
     let text = gemini_response.candidates
@@ -508,7 +591,7 @@ async fn normalize_detection_result(
     let result: StructuredResult = serde_json::from_str(text)
         .map_err(|e| format!("解析结构化结果失败: {} - {}", e, text))?;
 
-    println!("[Rust] 第二轮规范化完成: {} 个文字块", result.regions.len());
+    rust_log!("[Rust] 第二轮规范化完成: {} 个文字块", result.regions.len());
 
     Ok(result.regions)
 }
@@ -1,8 +1,13 @@
 // 文字去除服务
 // 整合 Gemini 检测、掩码生成、自适应背景修复的完整流程
 
-use super::gemini_detector::{detect_text, extract_text_styles, GeminiConfig, TextRegion, TextStyleInfo};
+use super::gemini_detector::{detect_text, extract_text_styles, DetectError, GeminiConfig, TextRegion, TextStyleInfo};
 use super::adaptive_inpainter::adaptive_inpaint;
+use crate::budget::BudgetExceeded;
+use crate::failover::{run_with_failover, FailoverParams, FailoverResult, FailureKind, FallbackProvider, ServedBy};
+use crate::image_source::ImageSource;
+use crate::usage::{UsageContext, UsageLedger};
+use crate::vault::resolve_api_key;
 
 use base64::{engine::general_purpose::STANDARD, Engine};
 use image::{DynamicImage, ImageFormat};
@@ -12,32 +17,70 @@ use std::collections::HashMap;
 use std::io::Cursor;
 use tauri::AppHandle;
 
-/// 文字去除请求参数
-#[derive(Debug, Deserialize)]
+/// 文字检测使用的 Gemini 供应商
+#[derive(Debug, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
-pub struct TextRemovalParams {
-    /// base64 编码的 PNG 图片
-    pub image_data: String,
+pub struct GeminiProviderParams {
     /// Gemini API 基础 URL
     pub gemini_base_url: String,
-    /// Gemini API Key
+    /// Gemini API Key（使用 gemini_credential_id 时可省略）
+    #[serde(default)]
     pub gemini_api_key: String,
+    /// 凭据库中的凭据 ID（优先于 gemini_api_key）
+    pub gemini_credential_id: Option<String>,
     /// Gemini 模型名称
     pub gemini_model: String,
+    /// 备用供应商（按顺序，主供应商连接失败、超时、5xx、429 或 402 时使用）
+    #[serde(default)]
+    pub gemini_fallbacks: Vec<FallbackProvider>,
+    /// 用量归属（画布、节点）
+    #[serde(flatten)]
+    pub usage_context: UsageContext,
+}
+
+impl FailoverParams for GeminiProviderParams {
+    fn fallbacks(&self) -> &[FallbackProvider] {
+        &self.gemini_fallbacks
+    }
+
+    fn base_url(&self) -> &str {
+        &self.gemini_base_url
+    }
+
+    fn model(&self) -> &str {
+        &self.gemini_model
+    }
+
+    fn use_fallback(&mut self, fallback: &FallbackProvider) {
+        fallback.apply(
+            &mut self.gemini_base_url,
+            &mut self.gemini_api_key,
+            &mut self.gemini_credential_id,
+            &mut self.gemini_model,
+        );
+    }
+}
+
+/// 文字去除请求参数
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextRemovalParams {
+    /// 输入图片（base64、存储路径或图片 ID）
+    pub image_data: ImageSource,
+    /// Gemini 供应商
+    #[serde(flatten)]
+    pub provider: GeminiProviderParams,
 }
 
 /// 文字检测请求参数
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TextDetectionParams {
-    /// base64 编码的 PNG 图片
-    pub image_data: String,
-    /// Gemini API 基础 URL
-    pub gemini_base_url: String,
-    /// Gemini API Key
-    pub gemini_api_key: String,
-    /// Gemini 模型名称
-    pub gemini_model: String,
+    /// 输入图片（base64、存储路径或图片 ID）
+    pub image_data: ImageSource,
+    /// Gemini 供应商
+    #[serde(flatten)]
+    pub provider: GeminiProviderParams,
 }
 
 /// 背景修复请求参数
@@ -85,6 +128,25 @@ pub struct TextRemovalResult {
     pub text_boxes: Vec<TextBoxData>,
     /// 错误信息
     pub error: Option<String>,
+    /// 超出预算时未发出检测请求
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_exceeded: Option<BudgetExceeded>,
+    /// 配置了备用供应商时，实际完成检测的供应商
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub served_by: Option<ServedBy>,
+}
+
+impl TextRemovalResult {
+    fn failure(error: String) -> Self {
+        TextRemovalResult {
+            success: false,
+            background_image: None,
+            text_boxes: vec![],
+            error: Some(error),
+            budget_exceeded: None,
+            served_by: None,
+        }
+    }
 }
 
 /// 文字检测结果
@@ -96,6 +158,49 @@ pub struct TextDetectionResult {
     pub regions: Vec<TextRegionData>,
     /// 错误信息
     pub error: Option<String>,
+    /// 超出预算时未发出检测请求
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_exceeded: Option<BudgetExceeded>,
+    /// 配置了备用供应商时，实际完成检测的供应商
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub served_by: Option<ServedBy>,
+}
+
+impl TextDetectionResult {
+    fn failure(error: String) -> Self {
+        TextDetectionResult {
+            success: false,
+            regions: vec![],
+            error: Some(error),
+            budget_exceeded: None,
+            served_by: None,
+        }
+    }
+}
+
+/// 检测到的文字区域，以及完成检测的供应商配置（样式提取沿用同一供应商）
+pub(crate) struct DetectedText {
+    pub(crate) regions: Vec<TextRegion>,
+    pub(crate) config: GeminiConfig,
+}
+
+/// 文字检测结果（含故障转移信息）
+pub(crate) struct ProviderDetection {
+    pub(crate) result: Result<DetectedText, DetectError>,
+    pub(crate) served_by: Option<ServedBy>,
+}
+
+impl FailoverResult for ProviderDetection {
+    fn failover_error(&self) -> Option<(FailureKind, &str)> {
+        match &self.result {
+            Err(DetectError::Request(kind, message)) => Some((*kind, message)),
+            _ => None,
+        }
+    }
+
+    fn set_served_by(&mut self, served_by: ServedBy) {
+        self.served_by = Some(served_by);
+    }
 }
 
 /// 背景修复结果
@@ -111,44 +216,82 @@ pub struct InpaintResult {
 
 /// 阶段一：仅执行文字检测（可并发调用）
 #[tauri::command]
-pub async fn detect_text_regions(params: TextDetectionParams) -> TextDetectionResult {
-    println!("[Rust] detect_text_regions 开始处理");
+pub async fn detect_text_regions(app: AppHandle, params: TextDetectionParams) -> TextDetectionResult {
+    rust_log!("[Rust] detect_text_regions 开始处理");
 
-    let gemini_config = GeminiConfig {
-        base_url: params.gemini_base_url,
-        api_key: params.gemini_api_key,
-        model: params.gemini_model,
+    let image_base64 = match params.image_data.load_base64(&app).await {
+        Ok(data) => data,
+        Err(e) => return TextDetectionResult::failure(format!("读取图片失败: {}", e)),
     };
 
-    match detect_text(&params.image_data, &gemini_config).await {
-        Ok(result) => {
-            println!("[Rust] 检测到 {} 个文本区域", result.regions.len());
-            TextDetectionResult {
-                success: true,
-                regions: result
-                    .regions
-                    .into_iter()
-                    .map(|r| TextRegionData {
-                        box_2d: r.box_2d,
-                        label: r.label,
-                        polygon: r.polygon,
-                    })
-                    .collect(),
-                error: None,
-            }
-        }
+    let detection = detect_text_with_failover(&app, &image_base64, params.provider).await;
+    match detection.result {
+        Ok(detected) => TextDetectionResult {
+            success: true,
+            regions: detected
+                .regions
+                .into_iter()
+                .map(|r| TextRegionData {
+                    box_2d: r.box_2d,
+                    label: r.label,
+                    polygon: r.polygon,
+                })
+                .collect(),
+            error: None,
+            budget_exceeded: None,
+            served_by: detection.served_by,
+        },
         Err(e) => TextDetectionResult {
-            success: false,
-            regions: vec![],
-            error: Some(format!("文字检测失败: {}", e)),
+            budget_exceeded: e.budget_exceeded(),
+            served_by: detection.served_by,
+            ..TextDetectionResult::failure(format!("文字检测失败: {}", e))
         },
     }
 }
 
+/// 依次使用主供应商和备用供应商执行文字检测（连接失败、超时、5xx 等时切换）
+pub(crate) async fn detect_text_with_failover(
+    app: &AppHandle,
+    image_base64: &str,
+    provider: GeminiProviderParams,
+) -> ProviderDetection {
+    run_with_failover(provider, |provider| detect_with_provider(app, image_base64, provider)).await
+}
+
+/// 使用单个供应商执行文字检测
+async fn detect_with_provider(
+    app: &AppHandle,
+    image_base64: &str,
+    mut provider: GeminiProviderParams,
+) -> ProviderDetection {
+    let result = match resolve_api_key(app, &provider.gemini_credential_id, &mut provider.gemini_api_key) {
+        Ok(()) => {
+            let config = GeminiConfig {
+                base_url: provider.gemini_base_url,
+                api_key: provider.gemini_api_key,
+                model: provider.gemini_model,
+                ledger: UsageLedger::open(app, provider.usage_context).ok(),
+            };
+            detect_text(image_base64, &config).await.map(|result| {
+                rust_log!("[Rust] 检测到 {} 个文本区域", result.regions.len());
+                DetectedText {
+                    regions: result.regions,
+                    config,
+                }
+            })
+        }
+        Err(e) => Err(e.into()),
+    };
+    ProviderDetection {
+        result,
+        served_by: None,
+    }
+}
+
 /// 阶段二：仅执行背景修复（自适应轻量方案）
 #[tauri::command]
 pub async fn inpaint_background(_app: AppHandle, params: InpaintParams) -> InpaintResult {
-    println!("[Rust] inpaint_background 开始处理");
+    rust_log!("[Rust] inpaint_background 开始处理");
 
     // 如果没有检测到文字，直接返回原图
     if params.regions.is_empty() {
@@ -194,7 +337,7 @@ pub async fn inpaint_background(_app: AppHandle, params: InpaintParams) -> Inpai
         .collect();
 
     // 执行自适应修复
-    println!("[Rust] 执行自适应背景修复...");
+    rust_log!("[Rust] 执行自适应背景修复...");
     let rgb_image = img.to_rgb8();
     let inpainted = match tokio::task::spawn_blocking(move || {
         adaptive_inpaint(&rgb_image, &regions)
@@ -232,7 +375,7 @@ pub async fn inpaint_background(_app: AppHandle, params: InpaintParams) -> Inpai
 
     let result_base64 = STANDARD.encode(output_buffer.into_inner());
 
-    println!("[Rust] 背景修复完成");
+    rust_log!("[Rust] 背景修复完成");
 
     InpaintResult {
         success: true,
@@ -244,87 +387,70 @@ pub async fn inpaint_background(_app: AppHandle, params: InpaintParams) -> Inpai
 /// 执行文字去除
 #[tauri::command]
 pub async fn remove_text_from_image(
-    _app: AppHandle,
+    app: AppHandle,
     params: TextRemovalParams,
 ) -> TextRemovalResult {
-    println!("[Rust] remove_text_from_image 开始处理");
+    rust_log!("[Rust] remove_text_from_image 开始处理");
 
-    // 1. 解码图片
-    let image_bytes = match STANDARD.decode(&params.image_data) {
+    // 1. 读取并解码图片
+    let image_base64 = match params.image_data.load_base64(&app).await {
+        Ok(data) => data,
+        Err(e) => return TextRemovalResult::failure(format!("读取图片失败: {}", e)),
+    };
+
+    let image_bytes = match STANDARD.decode(&image_base64) {
         Ok(b) => b,
-        Err(e) => {
-            return TextRemovalResult {
-                success: false,
-                background_image: None,
-                text_boxes: vec![],
-                error: Some(format!("Base64 解码失败: {}", e)),
-            }
-        }
+        Err(e) => return TextRemovalResult::failure(format!("Base64 解码失败: {}", e)),
     };
 
     let img = match image::load_from_memory(&image_bytes) {
         Ok(i) => i,
-        Err(e) => {
-            return TextRemovalResult {
-                success: false,
-                background_image: None,
-                text_boxes: vec![],
-                error: Some(format!("图片解析失败: {}", e)),
-            }
-        }
+        Err(e) => return TextRemovalResult::failure(format!("图片解析失败: {}", e)),
     };
 
     let width = img.width();
     let height = img.height();
-    println!("[Rust] 图片尺寸: {}x{}", width, height);
-
-    // 2. 使用 Gemini 检测文字
-    println!("[Rust] 开始 Gemini 文字检测...");
-    let gemini_config = GeminiConfig {
-        base_url: params.gemini_base_url,
-        api_key: params.gemini_api_key,
-        model: params.gemini_model,
-    };
-
-    let detection_result = match detect_text(&params.image_data, &gemini_config).await {
-        Ok(r) => r,
+    rust_log!("[Rust] 图片尺寸: {}x{}", width, height);
+
+    // 2. 使用 Gemini 检测文字（主供应商失败时依次使用备用供应商）
+    rust_log!("[Rust] 开始 Gemini 文字检测...");
+    let detection = detect_text_with_failover(&app, &image_base64, params.provider).await;
+    let served_by = detection.served_by;
+    let detected = match detection.result {
+        Ok(detected) => detected,
         Err(e) => {
             return TextRemovalResult {
-                success: false,
-                background_image: None,
-                text_boxes: vec![],
-                error: Some(format!("文字检测失败: {}", e)),
+                budget_exceeded: e.budget_exceeded(),
+                served_by,
+                ..TextRemovalResult::failure(format!("文字检测失败: {}", e))
             }
         }
     };
 
-    println!(
-        "[Rust] 检测到 {} 个文本区域",
-        detection_result.regions.len()
-    );
-
     // 如果没有检测到文字，直接返回原图
-    if detection_result.regions.is_empty() {
+    if detected.regions.is_empty() {
         return TextRemovalResult {
             success: true,
-            background_image: Some(params.image_data),
+            background_image: Some(image_base64),
             text_boxes: vec![],
             error: None,
+            budget_exceeded: None,
+            served_by,
         };
     }
 
     // 3. 提取样式信息（失败则回退到默认样式）
-    let styles = match extract_text_styles(&params.image_data, &detection_result.regions, &gemini_config).await {
+    let styles = match extract_text_styles(&image_base64, &detected.regions, &detected.config).await {
         Ok(s) => s,
         Err(e) => {
-            println!("[Rust] 样式提取失败，使用默认样式: {}", e);
+            rust_log!("[Rust] 样式提取失败，使用默认样式: {}", e);
             vec![]
         }
     };
 
     let rgb_image = img.to_rgb8();
     let text_boxes = build_text_boxes(
-        &detection_result.regions,
+        &detected.regions,
         &styles,
         width,
         height,
@@ -332,8 +458,8 @@ pub async fn remove_text_from_image(
     );
 
     // 4. 执行自适应修复
-    println!("[Rust] 执行自适应背景修复...");
-    let regions = detection_result.regions.clone();
+    rust_log!("[Rust] 执行自适应背景修复...");
+    let regions = detected.regions;
     let inpainted = match tokio::task::spawn_blocking(move || {
         adaptive_inpaint(&rgb_image, &regions)
     })
@@ -342,18 +468,16 @@ pub async fn remove_text_from_image(
         Ok(Ok(result)) => result,
         Ok(Err(e)) => {
             return TextRemovalResult {
-                success: false,
-                background_image: None,
                 text_boxes,
-                error: Some(format!("背景修复失败: {}", e)),
+                served_by,
+                ..TextRemovalResult::failure(format!("背景修复失败: {}", e))
             }
         }
         Err(e) => {
             return TextRemovalResult {
-                success: false,
-                background_image: None,
                 text_boxes,
-                error: Some(format!("背景修复任务失败: {}", e)),
+                served_by,
+                ..TextRemovalResult::failure(format!("背景修复任务失败: {}", e))
             }
         }
     };
@@ -363,22 +487,23 @@ pub async fn remove_text_from_image(
     if let Err(e) = DynamicImage::ImageRgb8(inpainted).write_to(&mut output_buffer, ImageFormat::Png)
     {
         return TextRemovalResult {
-            success: false,
-            background_image: None,
             text_boxes,
-            error: Some(format!("图片编码失败: {}", e)),
+            served_by,
+            ..TextRemovalResult::failure(format!("图片编码失败: {}", e))
         };
     }
 
     let result_base64 = STANDARD.encode(output_buffer.into_inner());
 
-    println!("[Rust] 文字去除完成");
+    rust_log!("[Rust] 文字去除完成");
 
     TextRemovalResult {
         success: true,
         background_image: Some(result_base64),
         text_boxes,
         error: None,
+        budget_exceeded: None,
+        served_by,
     }
 }
 
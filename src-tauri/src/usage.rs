@@ -0,0 +1,440 @@
+// 用量与费用台账
+// 每次成功调用在 app_data/usage/ledger.jsonl 追加一行记录（供应商、模型、画布、节点、tokens、图片数、视频秒数），
+// 费用按 app_data/usage/prices.json 中的价格表估算，未配置时使用内置默认价格
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::Manager;
+
+const USAGE_DIR_NAME: &str = "usage";
+const LEDGER_FILE_NAME: &str = "ledger.jsonl";
+const PRICE_TABLE_FILE_NAME: &str = "prices.json";
+
+lazy_static::lazy_static! {
+    // 串行化台账写入，避免并发调用交错写入同一行
+    static ref LEDGER_LOCK: Mutex<()> = Mutex::new(());
+}
+
+// ==================== 数据结构 ====================
+
+// 用量归属（前端在请求参数中平铺传入 canvasId / nodeId）
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageContext {
+    pub canvas_id: Option<String>,
+    pub node_id: Option<String>,
+}
+
+impl UsageContext {
+    /// 未指定归属时使用图片保存选项中的画布和节点
+    pub fn or_output(&self, output: Option<&crate::storage::GenerationOutputOptions>) -> UsageContext {
+        UsageContext {
+            canvas_id: self.canvas_id.clone().or_else(|| output.and_then(|o| o.canvas_id.clone())),
+            node_id: self.node_id.clone().or_else(|| output.and_then(|o| o.node_id.clone())),
+        }
+    }
+}
+
+// 单次调用的用量
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Usage {
+    #[serde(default)]
+    pub input_tokens: u64,
+    #[serde(default)]
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub images: u32,
+    #[serde(default)]
+    pub video_seconds: f64,
+}
+
+impl Usage {
+    pub fn tokens(input_tokens: u64, output_tokens: u64) -> Self {
+        Usage { input_tokens, output_tokens, ..Default::default() }
+    }
+
+    pub fn images(images: u32) -> Self {
+        Usage { images, ..Default::default() }
+    }
+
+    pub fn video_seconds(video_seconds: f64) -> Self {
+        Usage { video_seconds, ..Default::default() }
+    }
+
+    pub fn add(&mut self, other: &Usage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.images += other.images;
+        self.video_seconds += other.video_seconds;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Usage::default()
+    }
+}
+
+// 台账记录
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageRecord {
+    pub id: String,
+    pub timestamp: String, // RFC 3339
+    pub date: String,      // 本地日期 YYYY-MM-DD
+    pub provider: String,
+    pub model: String,
+    pub canvas_id: Option<String>,
+    pub node_id: Option<String>,
+    #[serde(flatten)]
+    pub usage: Usage,
+    pub cost: f64, // 按价格表估算
+}
+
+// 价格表（单位与 currency 一致）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PriceTable {
+    pub currency: String,
+    pub prices: Vec<ModelPrice>,
+}
+
+// 模型价格，model 支持以 * 结尾的前缀匹配，多条匹配时取最长的模式
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelPrice {
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub input_per_million: f64,
+    #[serde(default)]
+    pub output_per_million: f64,
+    #[serde(default)]
+    pub per_image: f64,
+    #[serde(default)]
+    pub per_video_second: f64,
+}
+
+impl ModelPrice {
+    fn new(model: &str, input_per_million: f64, output_per_million: f64, per_image: f64, per_video_second: f64) -> Self {
+        ModelPrice {
+            model: model.to_string(),
+            provider: None,
+            input_per_million,
+            output_per_million,
+            per_image,
+            per_video_second,
+        }
+    }
+
+    fn cost(&self, usage: &Usage) -> f64 {
+        usage.input_tokens as f64 * self.input_per_million / 1_000_000.0
+            + usage.output_tokens as f64 * self.output_per_million / 1_000_000.0
+            + usage.images as f64 * self.per_image
+            + usage.video_seconds * self.per_video_second
+    }
+}
+
+impl Default for PriceTable {
+    // 内置参考价格（美元），实际价格以供应商账单为准，可通过 usage_set_price_table 修改
+    fn default() -> Self {
+        PriceTable {
+            currency: "USD".to_string(),
+            prices: vec![
+                ModelPrice::new("gpt-4o-mini*", 0.15, 0.6, 0.0, 0.0),
+                ModelPrice::new("gpt-4o*", 2.5, 10.0, 0.0, 0.0),
+                ModelPrice::new("gpt-4.1-mini*", 0.4, 1.6, 0.0, 0.0),
+                ModelPrice::new("gpt-4.1*", 2.0, 8.0, 0.0, 0.0),
+                ModelPrice::new("gpt-5*", 1.25, 10.0, 0.0, 0.0),
+                ModelPrice::new("claude-sonnet-4*", 3.0, 15.0, 0.0, 0.0),
+                ModelPrice::new("claude-opus-4*", 15.0, 75.0, 0.0, 0.0),
+                ModelPrice::new("claude-haiku-4*", 1.0, 5.0, 0.0, 0.0),
+                ModelPrice::new("gemini-2.5-pro*", 1.25, 10.0, 0.0, 0.0),
+                ModelPrice::new("gemini-2.5-flash*", 0.3, 2.5, 0.0, 0.0),
+                ModelPrice::new("gemini-3-pro*", 2.0, 12.0, 0.0, 0.0),
+                // Gemini 图片模型按输出 token 计费（每张图约 1290 / 1120 tokens）
+                ModelPrice::new("gemini-2.5-flash-image*", 0.3, 30.0, 0.0, 0.0),
+                ModelPrice::new("gemini-3-pro-image*", 2.0, 120.0, 0.0, 0.0),
+                ModelPrice::new("dall-e-3*", 0.0, 0.0, 0.04, 0.0),
+                ModelPrice::new("gpt-image-1*", 5.0, 40.0, 0.0, 0.0),
+                ModelPrice::new("flux-*", 0.0, 0.0, 0.04, 0.0),
+                ModelPrice::new("doubao-seedream*", 0.0, 0.0, 0.03, 0.0),
+                ModelPrice::new("veo-3*", 0.0, 0.0, 0.0, 0.4),
+                ModelPrice::new("veo-2*", 0.0, 0.0, 0.0, 0.35),
+                ModelPrice::new("sora-2-pro*", 0.0, 0.0, 0.0, 0.3),
+                ModelPrice::new("sora-2*", 0.0, 0.0, 0.0, 0.1),
+                ModelPrice::new("kling*", 0.0, 0.0, 0.0, 0.07),
+            ],
+        }
+    }
+}
+
+impl PriceTable {
+    /// 查找模型价格：先按供应商过滤，再取最长匹配
+    fn find(&self, provider: &str, model: &str) -> Option<&ModelPrice> {
+        let model = model.to_ascii_lowercase();
+        self.prices
+            .iter()
+            .filter(|price| price.provider.as_deref().is_none_or(|p| p.eq_ignore_ascii_case(provider)))
+            .filter(|price| {
+                let pattern = price.model.to_ascii_lowercase();
+                match pattern.strip_suffix('*') {
+                    Some(prefix) => model.starts_with(prefix),
+                    None => model == pattern,
+                }
+            })
+            .max_by_key(|price| price.model.len())
+    }
+
+    pub fn estimate(&self, provider: &str, model: &str, usage: &Usage) -> f64 {
+        self.find(provider, model).map(|price| price.cost(usage)).unwrap_or(0.0)
+    }
+}
+
+// 汇总结果
+#[derive(Debug, Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageSummary {
+    pub key: String, // 画布 ID 或日期
+    pub requests: u32,
+    #[serde(flatten)]
+    pub usage: Usage,
+    pub cost: f64,
+    pub by_model: BTreeMap<String, f64>, // 各模型费用
+}
+
+impl UsageSummary {
+    fn add(&mut self, record: &UsageRecord) {
+        self.requests += 1;
+        self.usage.add(&record.usage);
+        self.cost += record.cost;
+        *self.by_model.entry(record.model.clone()).or_default() += record.cost;
+    }
+}
+
+// ==================== 台账读写 ====================
+
+pub(crate) fn get_usage_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let app_data = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("无法获取应用数据目录: {}", e))?;
+    let dir = app_data.join(USAGE_DIR_NAME);
+    if !dir.exists() {
+        fs::create_dir_all(&dir).map_err(|e| format!("创建用量目录失败: {}", e))?;
+    }
+    Ok(dir)
+}
+
+pub(crate) fn load_price_table(dir: &std::path::Path) -> PriceTable {
+    let path = dir.join(PRICE_TABLE_FILE_NAME);
+    match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            rust_log!("[Rust] Invalid price table, using defaults: {}", e);
+            PriceTable::default()
+        }),
+        Err(_) => PriceTable::default(),
+    }
+}
+
+pub(crate) fn read_records(dir: &std::path::Path) -> Result<Vec<UsageRecord>, String> {
+    let path = dir.join(LEDGER_FILE_NAME);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| format!("读取用量台账失败: {}", e))?;
+    // 跳过损坏的行（如写入时崩溃留下的半行）
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// 台账句柄：只包含目录和归属信息，可在没有 AppHandle 的模块（如文字检测）中使用
+#[derive(Debug, Clone)]
+pub struct UsageLedger {
+    dir: PathBuf,
+    context: UsageContext,
+}
+
+impl UsageLedger {
+    pub fn open(app: &tauri::AppHandle, context: UsageContext) -> Result<Self, String> {
+        Ok(UsageLedger { dir: get_usage_dir(app)?, context })
+    }
+
+    pub(crate) fn dir(&self) -> &std::path::Path {
+        &self.dir
+    }
+
+    pub(crate) fn context(&self) -> &UsageContext {
+        &self.context
+    }
+
+    /// 追加一条记录，失败只记日志，不影响调用结果
+    pub fn record(&self, provider: &str, model: &str, usage: &Usage) {
+        if usage.is_empty() {
+            return;
+        }
+        if let Err(e) = self.append(provider, model, usage) {
+            rust_log!("[Rust] Failed to record usage: {}", e);
+        }
+    }
+
+    fn append(&self, provider: &str, model: &str, usage: &Usage) -> Result<(), String> {
+        let now = chrono::Local::now();
+        let record = UsageRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: now.to_rfc3339(),
+            date: now.format("%Y-%m-%d").to_string(),
+            provider: provider.to_string(),
+            model: model.to_string(),
+            canvas_id: self.context.canvas_id.clone(),
+            node_id: self.context.node_id.clone(),
+            usage: *usage,
+            cost: load_price_table(&self.dir).estimate(provider, model, usage),
+        };
+        let line = serde_json::to_string(&record).map_err(|e| format!("序列化用量记录失败: {}", e))?;
+
+        let _guard = LEDGER_LOCK.lock().map_err(|e| format!("用量台账锁异常: {}", e))?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(LEDGER_FILE_NAME))
+            .map_err(|e| format!("打开用量台账失败: {}", e))?;
+        writeln!(file, "{}", line).map_err(|e| format!("写入用量台账失败: {}", e))?;
+
+        rust_log!(
+            "[Rust] Usage recorded: {} / {} tokens {}+{}, images {}, video {}s, cost {:.4}",
+            provider,
+            model,
+            usage.input_tokens,
+            usage.output_tokens,
+            usage.images,
+            usage.video_seconds,
+            record.cost
+        );
+        Ok(())
+    }
+}
+
+/// 记录一次调用的用量（命令中使用）
+pub fn record_usage(app: &tauri::AppHandle, context: &UsageContext, provider: &str, model: &str, usage: &Usage) {
+    match UsageLedger::open(app, context.clone()) {
+        Ok(ledger) => ledger.record(provider, model, usage),
+        Err(e) => rust_log!("[Rust] Failed to record usage: {}", e),
+    }
+}
+
+fn summarize<F>(records: &[UsageRecord], key: F) -> Vec<UsageSummary>
+where
+    F: Fn(&UsageRecord) -> Option<String>,
+{
+    let mut groups: BTreeMap<String, UsageSummary> = BTreeMap::new();
+    for record in records {
+        if let Some(k) = key(record) {
+            groups
+                .entry(k.clone())
+                .or_insert_with(|| UsageSummary { key: k, ..Default::default() })
+                .add(record);
+        }
+    }
+    groups.into_values().collect()
+}
+
+// ==================== 命令 ====================
+
+// 按画布汇总（canvas_id 为空时返回所有画布，未归属的记录汇总在空字符串下）
+#[tauri::command]
+pub async fn usage_canvas_totals(
+    app: tauri::AppHandle,
+    canvas_id: Option<String>,
+) -> Result<Vec<UsageSummary>, String> {
+    let records = read_records(&get_usage_dir(&app)?)?;
+    Ok(summarize(&records, |record| {
+        let key = record.canvas_id.clone().unwrap_or_default();
+        match &canvas_id {
+            Some(id) if *id != key => None,
+            _ => Some(key),
+        }
+    }))
+}
+
+// 按日汇总（日期格式 YYYY-MM-DD，包含起止日期）
+#[tauri::command]
+pub async fn usage_daily_totals(
+    app: tauri::AppHandle,
+    start_date: Option<String>,
+    end_date: Option<String>,
+) -> Result<Vec<UsageSummary>, String> {
+    let records = read_records(&get_usage_dir(&app)?)?;
+    Ok(summarize(&records, |record| {
+        let after_start = start_date.as_ref().is_none_or(|start| record.date >= *start);
+        let before_end = end_date.as_ref().is_none_or(|end| record.date <= *end);
+        (after_start && before_end).then(|| record.date.clone())
+    }))
+}
+
+#[tauri::command]
+pub async fn usage_get_price_table(app: tauri::AppHandle) -> Result<PriceTable, String> {
+    Ok(load_price_table(&get_usage_dir(&app)?))
+}
+
+#[tauri::command]
+pub async fn usage_set_price_table(app: tauri::AppHandle, table: PriceTable) -> Result<(), String> {
+    let path = get_usage_dir(&app)?.join(PRICE_TABLE_FILE_NAME);
+    let content = serde_json::to_vec_pretty(&table).map_err(|e| format!("序列化价格表失败: {}", e))?;
+    crate::storage::write_file_atomic(&path, &content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(date: &str, canvas: Option<&str>, model: &str, cost: f64) -> UsageRecord {
+        UsageRecord {
+            id: String::new(),
+            timestamp: String::new(),
+            date: date.to_string(),
+            provider: "gemini".to_string(),
+            model: model.to_string(),
+            canvas_id: canvas.map(str::to_string),
+            node_id: None,
+            usage: Usage::images(1),
+            cost,
+        }
+    }
+
+    #[test]
+    fn test_price_lookup_prefers_longest_match() {
+        let table = PriceTable::default();
+        let usage = Usage::tokens(1_000_000, 100_000);
+        assert!((table.estimate("openai", "gpt-4o-mini-2024-07-18", &usage) - 0.21).abs() < 1e-9);
+        assert!((table.estimate("openai", "gpt-4o", &usage) - 3.5).abs() < 1e-9);
+        assert!((table.estimate("veo", "veo-3.0-generate-001", &Usage::video_seconds(8.0)) - 3.2).abs() < 1e-9);
+        assert_eq!(table.estimate("custom", "unknown-model", &usage), 0.0);
+    }
+
+    #[test]
+    fn test_summarize_groups_records() {
+        let records = vec![
+            record("2026-10-01", Some("deck-a"), "flux-pro-1.1", 0.04),
+            record("2026-10-01", Some("deck-b"), "flux-pro-1.1", 0.04),
+            record("2026-10-02", Some("deck-a"), "veo-3.0", 3.2),
+        ];
+        let by_canvas = summarize(&records, |r| r.canvas_id.clone());
+        assert_eq!(by_canvas.len(), 2);
+        assert_eq!(by_canvas[0].key, "deck-a");
+        assert_eq!(by_canvas[0].requests, 2);
+        assert_eq!(by_canvas[0].usage.images, 2);
+        assert!((by_canvas[0].cost - 3.24).abs() < 1e-9);
+        assert_eq!(by_canvas[0].by_model.len(), 2);
+
+        let by_day = summarize(&records, |r| Some(r.date.clone()));
+        assert_eq!(by_day.iter().map(|s| s.key.as_str()).collect::<Vec<_>>(), ["2026-10-01", "2026-10-02"]);
+    }
+}
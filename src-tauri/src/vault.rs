@@ -0,0 +1,436 @@
+// 凭据库
+// API Key 使用 AES-256-GCM 加密保存在 app_data/credentials.vault，命令参数通过 credentialId 引用，
+// 明文密钥不再经过前端。默认使用设备 ID 派生的密钥；设置口令后改用口令派生（Argon2id），
+// 此时每次启动需要先调用 vault_unlock
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::Manager;
+
+const VAULT_FILE_NAME: &str = "credentials.vault";
+const VAULT_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+const MACHINE_SECRET_PREFIX: &str = "nextcreator-vault:";
+
+lazy_static::lazy_static! {
+    // 已派生的密钥（按盐值缓存，避免每次请求都执行 Argon2；口令模式下即为解锁状态）
+    static ref VAULT_KEY: Mutex<Option<DerivedKey>> = Mutex::new(None);
+    // 串行化凭据库的读-改-写
+    static ref VAULT_LOCK: Mutex<()> = Mutex::new(());
+}
+
+// ==================== 数据结构 ====================
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum KeySource {
+    Machine,    // 设备 ID 派生
+    Passphrase, // 用户口令派生
+}
+
+// 加密后的文件内容
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VaultFile {
+    version: u32,
+    key_source: KeySource,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+// 凭据（含明文密钥，不实现 Debug，避免误打印）
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Credential {
+    id: String,
+    name: String,
+    provider: String,
+    api_key: String,
+    created_at: i64,
+    updated_at: i64,
+}
+
+#[derive(Clone)]
+struct DerivedKey {
+    salt: Vec<u8>,
+    key: [u8; 32],
+}
+
+// 解密后的凭据库
+struct Vault {
+    key_source: KeySource,
+    key: DerivedKey,
+    credentials: Vec<Credential>,
+}
+
+// 返回前端的凭据信息（密钥已遮盖）
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialSummary {
+    pub id: String,
+    pub name: String,
+    pub provider: String,
+    pub masked_key: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl From<&Credential> for CredentialSummary {
+    fn from(credential: &Credential) -> Self {
+        CredentialSummary {
+            id: credential.id.clone(),
+            name: credential.name.clone(),
+            provider: credential.provider.clone(),
+            masked_key: mask_key(&credential.api_key),
+            created_at: credential.created_at,
+            updated_at: credential.updated_at,
+        }
+    }
+}
+
+// 添加凭据参数
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialParams {
+    pub name: String,
+    pub provider: String, // openai / claude / gemini 等，仅用于展示和筛选
+    pub api_key: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultStatus {
+    pub exists: bool,
+    pub key_source: KeySource,
+    pub locked: bool,
+}
+
+// ==================== 加解密 ====================
+
+/// 遮盖密钥，只保留前 3 位和后 4 位
+fn mask_key(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() <= 10 {
+        return "*".repeat(chars.len().max(4));
+    }
+    let prefix: String = chars[..3].iter().collect();
+    let suffix: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}...{}", prefix, suffix)
+}
+
+fn derive_key(secret: &[u8], salt: &[u8]) -> Result<DerivedKey, String> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(secret, salt, &mut key)
+        .map_err(|e| format!("派生密钥失败: {}", e))?;
+    Ok(DerivedKey { salt: salt.to_vec(), key })
+}
+
+fn new_salt() -> Vec<u8> {
+    let mut salt = vec![0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    salt
+}
+
+fn machine_secret() -> Result<String, String> {
+    let id = machine_uid::get().map_err(|e| format!("无法读取设备 ID: {}", e))?;
+    Ok(format!("{}{}", MACHINE_SECRET_PREFIX, id))
+}
+
+fn seal(key_source: KeySource, key: &DerivedKey, credentials: &[Credential]) -> Result<VaultFile, String> {
+    let plaintext = serde_json::to_vec(credentials).map_err(|e| format!("序列化凭据失败: {}", e))?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key.key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_ref())
+        .map_err(|_| "加密凭据失败".to_string())?;
+    Ok(VaultFile {
+        version: VAULT_VERSION,
+        key_source,
+        salt: BASE64.encode(&key.salt),
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    })
+}
+
+fn unseal(file: &VaultFile, key: &DerivedKey) -> Result<Vec<Credential>, String> {
+    let nonce = BASE64.decode(&file.nonce).map_err(|e| format!("凭据库已损坏: {}", e))?;
+    let ciphertext = BASE64.decode(&file.ciphertext).map_err(|e| format!("凭据库已损坏: {}", e))?;
+    if nonce.len() != 12 {
+        return Err("凭据库已损坏: nonce 长度错误".to_string());
+    }
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key.key));
+    // GCM 校验失败说明密钥不对（口令错误或更换了设备）
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+        .map_err(|_| "无法解密凭据库：口令错误或凭据库来自其他设备".to_string())?;
+    serde_json::from_slice(&plaintext).map_err(|e| format!("解析凭据失败: {}", e))
+}
+
+// ==================== 凭据库读写 ====================
+
+fn vault_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let app_data = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("无法获取应用数据目录: {}", e))?;
+    if !app_data.exists() {
+        fs::create_dir_all(&app_data).map_err(|e| format!("创建应用数据目录失败: {}", e))?;
+    }
+    Ok(app_data.join(VAULT_FILE_NAME))
+}
+
+fn read_vault_file(app: &tauri::AppHandle) -> Result<Option<VaultFile>, String> {
+    let path = vault_path(app)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path).map_err(|e| format!("读取凭据库失败: {}", e))?;
+    let file: VaultFile = serde_json::from_str(&content).map_err(|e| format!("凭据库已损坏: {}", e))?;
+    if file.version > VAULT_VERSION {
+        return Err(format!("不支持的凭据库版本: {}", file.version));
+    }
+    Ok(Some(file))
+}
+
+/// 取得文件对应的密钥：优先使用缓存，设备密钥可直接派生，口令密钥需先解锁
+fn key_for(file: &VaultFile) -> Result<DerivedKey, String> {
+    let salt = BASE64.decode(&file.salt).map_err(|e| format!("凭据库已损坏: {}", e))?;
+    if let Some(cached) = VAULT_KEY.lock().map_err(|e| format!("凭据库状态异常: {}", e))?.as_ref() {
+        if cached.salt == salt {
+            return Ok(cached.clone());
+        }
+    }
+    match file.key_source {
+        KeySource::Machine => {
+            let key = derive_key(machine_secret()?.as_bytes(), &salt)?;
+            cache_key(&key);
+            Ok(key)
+        }
+        KeySource::Passphrase => Err("凭据库已锁定，请先输入口令解锁".to_string()),
+    }
+}
+
+fn cache_key(key: &DerivedKey) {
+    if let Ok(mut cached) = VAULT_KEY.lock() {
+        *cached = Some(key.clone());
+    }
+}
+
+/// 读取并解密凭据库，不存在时返回使用设备密钥的空库
+fn load_vault(app: &tauri::AppHandle) -> Result<Vault, String> {
+    match read_vault_file(app)? {
+        Some(file) => {
+            let key = key_for(&file)?;
+            let credentials = unseal(&file, &key)?;
+            Ok(Vault { key_source: file.key_source, key, credentials })
+        }
+        None => {
+            let key = derive_key(machine_secret()?.as_bytes(), &new_salt())?;
+            cache_key(&key);
+            Ok(Vault { key_source: KeySource::Machine, key, credentials: Vec::new() })
+        }
+    }
+}
+
+fn save_vault(app: &tauri::AppHandle, vault: &Vault) -> Result<(), String> {
+    let file = seal(vault.key_source, &vault.key, &vault.credentials)?;
+    let content = serde_json::to_vec_pretty(&file).map_err(|e| format!("序列化凭据库失败: {}", e))?;
+    crate::storage::write_file_atomic(&vault_path(app)?, &content)
+}
+
+/// 修改凭据库（加锁完成读-改-写）
+fn update_vault<T>(app: &tauri::AppHandle, f: impl FnOnce(&mut Vault) -> Result<T, String>) -> Result<T, String> {
+    let _guard = VAULT_LOCK.lock().map_err(|e| format!("凭据库状态异常: {}", e))?;
+    let mut vault = load_vault(app)?;
+    let result = f(&mut vault)?;
+    save_vault(app, &vault)?;
+    Ok(result)
+}
+
+/// 指定 credential_id 时用凭据库中的密钥替换参数中的 api_key（命令入口调用）
+pub fn resolve_api_key(
+    app: &tauri::AppHandle,
+    credential_id: &Option<String>,
+    api_key: &mut String,
+) -> Result<(), String> {
+    let Some(id) = credential_id else {
+        return Ok(());
+    };
+    let vault = {
+        let _guard = VAULT_LOCK.lock().map_err(|e| format!("凭据库状态异常: {}", e))?;
+        load_vault(app)?
+    };
+    let credential = vault
+        .credentials
+        .iter()
+        .find(|c| c.id == *id)
+        .ok_or_else(|| format!("凭据不存在: {}", id))?;
+    *api_key = credential.api_key.clone();
+    Ok(())
+}
+
+// ==================== 命令 ====================
+
+#[tauri::command]
+pub async fn credential_add(app: tauri::AppHandle, params: CredentialParams) -> Result<CredentialSummary, String> {
+    rust_log!("[Rust] credential_add called, provider: {}", params.provider);
+    if params.api_key.trim().is_empty() {
+        return Err("API Key 不能为空".to_string());
+    }
+    update_vault(&app, |vault| {
+        let now = chrono::Utc::now().timestamp_millis();
+        let credential = Credential {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: params.name,
+            provider: params.provider,
+            api_key: params.api_key.trim().to_string(),
+            created_at: now,
+            updated_at: now,
+        };
+        let summary = CredentialSummary::from(&credential);
+        vault.credentials.push(credential);
+        Ok(summary)
+    })
+}
+
+#[tauri::command]
+pub async fn credential_list(app: tauri::AppHandle) -> Result<Vec<CredentialSummary>, String> {
+    let _guard = VAULT_LOCK.lock().map_err(|e| format!("凭据库状态异常: {}", e))?;
+    if read_vault_file(&app)?.is_none() {
+        return Ok(Vec::new());
+    }
+    Ok(load_vault(&app)?.credentials.iter().map(CredentialSummary::from).collect())
+}
+
+// 更换凭据的 API Key（ID 不变，引用该凭据的节点无需修改）
+#[tauri::command]
+pub async fn credential_rotate(
+    app: tauri::AppHandle,
+    credential_id: String,
+    api_key: String,
+) -> Result<CredentialSummary, String> {
+    rust_log!("[Rust] credential_rotate called, id: {}", credential_id);
+    if api_key.trim().is_empty() {
+        return Err("API Key 不能为空".to_string());
+    }
+    update_vault(&app, |vault| {
+        let credential = vault
+            .credentials
+            .iter_mut()
+            .find(|c| c.id == credential_id)
+            .ok_or_else(|| format!("凭据不存在: {}", credential_id))?;
+        credential.api_key = api_key.trim().to_string();
+        credential.updated_at = chrono::Utc::now().timestamp_millis();
+        Ok(CredentialSummary::from(&*credential))
+    })
+}
+
+#[tauri::command]
+pub async fn credential_delete(app: tauri::AppHandle, credential_id: String) -> Result<(), String> {
+    rust_log!("[Rust] credential_delete called, id: {}", credential_id);
+    update_vault(&app, |vault| {
+        let before = vault.credentials.len();
+        vault.credentials.retain(|c| c.id != credential_id);
+        if vault.credentials.len() == before {
+            return Err(format!("凭据不存在: {}", credential_id));
+        }
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub async fn vault_status(app: tauri::AppHandle) -> Result<VaultStatus, String> {
+    let Some(file) = read_vault_file(&app)? else {
+        return Ok(VaultStatus { exists: false, key_source: KeySource::Machine, locked: false });
+    };
+    Ok(VaultStatus {
+        exists: true,
+        key_source: file.key_source,
+        locked: file.key_source == KeySource::Passphrase && key_for(&file).is_err(),
+    })
+}
+
+// 口令模式下解锁凭据库（密钥只保存在内存中，重启后需重新解锁）
+#[tauri::command]
+pub async fn vault_unlock(app: tauri::AppHandle, passphrase: String) -> Result<(), String> {
+    let Some(file) = read_vault_file(&app)? else {
+        return Err("凭据库不存在".to_string());
+    };
+    if file.key_source != KeySource::Passphrase {
+        return Ok(());
+    }
+    let salt = BASE64.decode(&file.salt).map_err(|e| format!("凭据库已损坏: {}", e))?;
+    let key = derive_key(passphrase.as_bytes(), &salt)?;
+    unseal(&file, &key)?;
+    cache_key(&key);
+    rust_log!("[Rust] Vault unlocked");
+    Ok(())
+}
+
+#[tauri::command]
+pub fn vault_lock() -> Result<(), String> {
+    *VAULT_KEY.lock().map_err(|e| format!("凭据库状态异常: {}", e))? = None;
+    Ok(())
+}
+
+// 设置或清除口令（passphrase 为空时改回设备密钥），使用新的盐值重新加密
+#[tauri::command]
+pub async fn vault_set_passphrase(app: tauri::AppHandle, passphrase: Option<String>) -> Result<(), String> {
+    let passphrase = passphrase.filter(|p| !p.is_empty());
+    update_vault(&app, |vault| {
+        let salt = new_salt();
+        vault.key = match &passphrase {
+            Some(passphrase) => derive_key(passphrase.as_bytes(), &salt)?,
+            None => derive_key(machine_secret()?.as_bytes(), &salt)?,
+        };
+        vault.key_source = if passphrase.is_some() { KeySource::Passphrase } else { KeySource::Machine };
+        cache_key(&vault.key);
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credential(api_key: &str) -> Credential {
+        Credential {
+            id: "c1".to_string(),
+            name: "OpenAI".to_string(),
+            provider: "openai".to_string(),
+            api_key: api_key.to_string(),
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_seal_round_trip_and_wrong_key() {
+        let salt = new_salt();
+        let key = derive_key(b"correct horse", &salt).unwrap();
+        let file = seal(KeySource::Passphrase, &key, &[credential("sk-test-1234567890")]).unwrap();
+        assert!(!file.ciphertext.contains("sk-test"));
+
+        let credentials = unseal(&file, &key).unwrap();
+        assert_eq!(credentials[0].api_key, "sk-test-1234567890");
+
+        let wrong = derive_key(b"battery staple", &salt).unwrap();
+        assert!(unseal(&file, &wrong).is_err());
+    }
+
+    #[test]
+    fn test_mask_key() {
+        assert_eq!(mask_key("sk-proj-abcdefgh1234"), "sk-...1234");
+        assert_eq!(mask_key("short"), "*****");
+        assert_eq!(mask_key(""), "****");
+    }
+}
@@ -2,6 +2,12 @@ use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use crate::budget::{check_budget, BudgetExceeded};
+use crate::endpoint::api_base;
+use crate::image_preprocess::{decode_base64_image, parse_size, prepare_base64_image, prepare_image, UploadTarget};
+use crate::image_source::ImageSource;
+use crate::usage::{record_usage, Usage, UsageContext};
+use crate::vault::resolve_api_key;
 
 // ==================== 视频服务数据结构 ====================
 
@@ -10,12 +16,16 @@ use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 #[serde(rename_all = "camelCase")]
 pub struct VideoCreateParams {
     pub base_url: String,
-    pub api_key: String,
+    #[serde(default)]
+    pub api_key: String, // 使用 credential_id 时可省略
+    pub credential_id: Option<String>, // 凭据库中的凭据 ID（优先于 api_key）
     pub model: String,
     pub prompt: String,
     pub seconds: Option<String>,
     pub size: Option<String>,
     pub input_image: Option<String>,  // base64 编码的参考图片
+    #[serde(flatten)]
+    pub usage_context: UsageContext,
 }
 
 // 视频任务响应
@@ -31,6 +41,28 @@ pub struct VideoTaskResult {
     pub progress: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget_exceeded: Option<BudgetExceeded>, // 超出预算时未提交任务
+}
+
+impl VideoTaskResult {
+    fn failure(error: String) -> Self {
+        VideoTaskResult {
+            success: false,
+            task_id: None,
+            status: None,
+            progress: None,
+            error: Some(error),
+            budget_exceeded: None,
+        }
+    }
+
+    fn budget_exceeded(exceeded: BudgetExceeded) -> Self {
+        VideoTaskResult {
+            budget_exceeded: Some(exceeded.clone()),
+            ..VideoTaskResult::failure(exceeded.to_string())
+        }
+    }
 }
 
 // 视频内容结果
@@ -44,12 +76,24 @@ pub struct VideoContentResult {
     pub error: Option<String>,
 }
 
+impl VideoContentResult {
+    fn failure(error: String) -> Self {
+        VideoContentResult {
+            success: false,
+            video_data: None,
+            error: Some(error),
+        }
+    }
+}
+
 // 获取任务状态参数
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct VideoStatusParams {
     pub base_url: String,
-    pub api_key: String,
+    #[serde(default)]
+    pub api_key: String, // 使用 credential_id 时可省略
+    pub credential_id: Option<String>, // 凭据库中的凭据 ID（优先于 api_key）
     pub task_id: String,
 }
 
@@ -106,11 +150,15 @@ pub struct VeoMetadata {
 #[serde(rename_all = "camelCase")]
 pub struct VeoCreateParams {
     pub base_url: String,
-    pub api_key: String,
+    #[serde(default)]
+    pub api_key: String, // 使用 credential_id 时可省略
+    pub credential_id: Option<String>, // 凭据库中的凭据 ID（优先于 api_key）
     pub model: String,
     pub prompt: String,
-    pub images: Option<Vec<String>>,  // base64 编码的图片数组
+    pub images: Option<Vec<ImageSource>>,  // 输入图片数组（base64、存储路径或图片 ID）
     pub metadata: Option<VeoMetadata>,
+    #[serde(flatten)]
+    pub usage_context: UsageContext,
 }
 
 // Veo API 请求体
@@ -124,13 +172,44 @@ pub struct VeoApiRequest {
     pub metadata: Option<VeoMetadata>,
 }
 
+// ==================== 辅助函数 ====================
+
+// 读取并预处理 Veo 输入图片
+async fn prepare_veo_images(app: &tauri::AppHandle, images: &[ImageSource]) -> Result<Vec<String>, String> {
+    let mut prepared_images = Vec::with_capacity(images.len());
+    for (index, source) in images.iter().enumerate() {
+        let prepared = match source.load_bytes(app).await {
+            Ok(bytes) => prepare_image(bytes, UploadTarget::VeoReference, None).await,
+            Err(e) => Err(e),
+        }
+        .map_err(|e| format!("第 {} 张输入图片处理失败: {}", index + 1, e))?;
+        prepared_images.push(prepared.to_base64());
+    }
+    Ok(prepared_images)
+}
+
 // ==================== 创建视频任务 ====================
 
 #[tauri::command]
-pub async fn video_create_task(params: VideoCreateParams) -> VideoTaskResult {
-    println!("[Rust] video_create_task called");
-    println!("[Rust] base_url: {}", params.base_url);
-    println!("[Rust] model: {}", params.model);
+pub async fn video_create_task(app: tauri::AppHandle, mut params: VideoCreateParams) -> VideoTaskResult {
+    rust_log!("[Rust] video_create_task called");
+    rust_log!("[Rust] base_url: {}", params.base_url);
+    rust_log!("[Rust] model: {}", params.model);
+
+    if let Err(e) = resolve_api_key(&app, &params.credential_id, &mut params.api_key) {
+        return VideoTaskResult::failure(e);
+    }
+
+    // 计费时长（未指定时为 Sora 默认的 4 秒）
+    let video_seconds = params
+        .seconds
+        .as_deref()
+        .and_then(|s| s.trim().parse::<f64>().ok())
+        .unwrap_or(4.0);
+    let _reservation = match check_budget(&app, &params.usage_context, "openai", &params.model, &Usage::video_seconds(video_seconds)) {
+        Ok(reservation) => reservation,
+        Err(e) => return VideoTaskResult::budget_exceeded(e),
+    };
 
     // 创建 HTTP 客户端
     let client = match Client::builder()
@@ -145,6 +224,7 @@ pub async fn video_create_task(params: VideoCreateParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(format!("创建 HTTP 客户端失败: {}", e)),
+                budget_exceeded: None,
             }
         }
     };
@@ -158,22 +238,37 @@ pub async fn video_create_task(params: VideoCreateParams) -> VideoTaskResult {
         form = form.text("seconds", seconds);
     }
 
+    // 参考图分辨率需与输出视频一致
+    let reference_size = params.size.as_deref().and_then(parse_size);
+
     if let Some(size) = params.size {
         form = form.text("size", size);
     }
 
-    // 添加参考图片
+    // 添加参考图片（检测真实格式，按视频尺寸裁剪并修正 MIME）
     if let Some(image_base64) = params.input_image {
-        match BASE64.decode(&image_base64) {
-            Ok(image_bytes) => {
-                let part = reqwest::multipart::Part::bytes(image_bytes)
-                    .file_name("reference.png")
-                    .mime_str("image/png")
+        let prepared = match decode_base64_image(&image_base64) {
+            Ok(bytes) => prepare_image(bytes, UploadTarget::SoraReference, reference_size).await,
+            Err(e) => Err(e),
+        };
+        match prepared {
+            Ok(image) => {
+                let part = reqwest::multipart::Part::bytes(image.data)
+                    .file_name(format!("reference.{}", image.extension))
+                    .mime_str(&image.mime_type)
                     .unwrap_or_else(|_| reqwest::multipart::Part::bytes(vec![]));
                 form = form.part("input_reference", part);
             }
             Err(e) => {
-                println!("[Rust] Failed to decode input image: {}", e);
+                rust_log!("[Rust] Failed to prepare input image: {}", e);
+                return VideoTaskResult {
+                    success: false,
+                    task_id: None,
+                    status: None,
+                    progress: None,
+                    error: Some(format!("参考图片处理失败: {}", e)),
+                    budget_exceeded: None,
+                };
             }
         }
     }
@@ -181,12 +276,12 @@ pub async fn video_create_task(params: VideoCreateParams) -> VideoTaskResult {
     // 构建 URL
     let url = format!(
         "{}/v1/videos",
-        params.base_url.trim_end_matches('/')
+        api_base(&params.base_url)
     );
-    println!("[Rust] Request URL: {}", url);
+    rust_log!("[Rust] Request URL: {}", url);
 
     // 发送请求
-    println!("[Rust] Sending video create request...");
+    rust_log!("[Rust] Sending video create request...");
     let start_time = std::time::Instant::now();
 
     let response = match client
@@ -197,11 +292,11 @@ pub async fn video_create_task(params: VideoCreateParams) -> VideoTaskResult {
         .await
     {
         Ok(r) => {
-            println!("[Rust] Response received in {:?}", start_time.elapsed());
+            rust_log!("[Rust] Response received in {:?}", start_time.elapsed());
             r
         },
         Err(e) => {
-            println!("[Rust] Request failed: {}", e);
+            rust_log!("[Rust] Request failed: {}", e);
             let error_msg = if e.is_timeout() {
                 "请求超时，请稍后重试".to_string()
             } else if e.is_connect() {
@@ -215,6 +310,7 @@ pub async fn video_create_task(params: VideoCreateParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(error_msg),
+                budget_exceeded: None,
             };
         }
     };
@@ -230,18 +326,20 @@ pub async fn video_create_task(params: VideoCreateParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(format!("获取响应失败: {}", e)),
+                budget_exceeded: None,
             };
         }
     };
 
     if !status.is_success() {
-        println!("[Rust] Error response: {}", response_text);
+        rust_log!("[Rust] Error response: {}", response_text);
         return VideoTaskResult {
             success: false,
             task_id: None,
             status: None,
             progress: None,
             error: Some(format!("API 返回错误 ({}): {}", status, response_text)),
+            budget_exceeded: None,
         };
     }
 
@@ -250,13 +348,14 @@ pub async fn video_create_task(params: VideoCreateParams) -> VideoTaskResult {
     let api_response: VideoApiResponse = match serde_json::from_str(&response_text) {
         Ok(r) => r,
         Err(e) => {
-            println!("[Rust] Failed to parse JSON: {}", e);
+            rust_log!("[Rust] Failed to parse JSON: {}", e);
             return VideoTaskResult {
                 success: false,
                 task_id: None,
                 status: None,
                 progress: None,
                 error: Some(format!("解析响应失败: {}", e)),
+                budget_exceeded: None,
             };
         }
     };
@@ -269,6 +368,7 @@ pub async fn video_create_task(params: VideoCreateParams) -> VideoTaskResult {
             status: None,
             progress: None,
             error: err.message,
+            budget_exceeded: None,
         };
     }
 
@@ -280,10 +380,12 @@ pub async fn video_create_task(params: VideoCreateParams) -> VideoTaskResult {
             status: None,
             progress: None,
             error: Some("API 未返回任务 ID".to_string()),
+            budget_exceeded: None,
         };
     }
 
-    println!("[Rust] Video task created: {:?}", task_id);
+    rust_log!("[Rust] Video task created: {:?}", task_id);
+    record_usage(&app, &params.usage_context, "openai", &params.model, &Usage::video_seconds(video_seconds));
 
     VideoTaskResult {
         success: true,
@@ -291,14 +393,19 @@ pub async fn video_create_task(params: VideoCreateParams) -> VideoTaskResult {
         status: api_response.status,
         progress: api_response.progress,
         error: None,
+        budget_exceeded: None,
     }
 }
 
 // ==================== 获取视频任务状态 ====================
 
 #[tauri::command]
-pub async fn video_get_status(params: VideoStatusParams) -> VideoTaskResult {
-    println!("[Rust] video_get_status called, task_id: {}", params.task_id);
+pub async fn video_get_status(app: tauri::AppHandle, mut params: VideoStatusParams) -> VideoTaskResult {
+    rust_log!("[Rust] video_get_status called, task_id: {}", params.task_id);
+
+    if let Err(e) = resolve_api_key(&app, &params.credential_id, &mut params.api_key) {
+        return VideoTaskResult::failure(e);
+    }
 
     // 创建 HTTP 客户端
     let client = match Client::builder()
@@ -313,6 +420,7 @@ pub async fn video_get_status(params: VideoStatusParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(format!("创建 HTTP 客户端失败: {}", e)),
+                budget_exceeded: None,
             }
         }
     };
@@ -320,7 +428,7 @@ pub async fn video_get_status(params: VideoStatusParams) -> VideoTaskResult {
     // 构建 URL
     let url = format!(
         "{}/v1/videos/{}",
-        params.base_url.trim_end_matches('/'),
+        api_base(&params.base_url),
         params.task_id
     );
 
@@ -346,6 +454,7 @@ pub async fn video_get_status(params: VideoStatusParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(error_msg),
+                budget_exceeded: None,
             };
         }
     };
@@ -361,6 +470,7 @@ pub async fn video_get_status(params: VideoStatusParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(format!("获取响应失败: {}", e)),
+                budget_exceeded: None,
             };
         }
     };
@@ -372,6 +482,7 @@ pub async fn video_get_status(params: VideoStatusParams) -> VideoTaskResult {
             status: None,
             progress: None,
             error: Some(format!("API 返回错误 ({}): {}", status, response_text)),
+            budget_exceeded: None,
         };
     }
 
@@ -385,6 +496,7 @@ pub async fn video_get_status(params: VideoStatusParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(format!("解析响应失败: {}", e)),
+                budget_exceeded: None,
             };
         }
     };
@@ -397,6 +509,7 @@ pub async fn video_get_status(params: VideoStatusParams) -> VideoTaskResult {
             status: api_response.status,
             progress: api_response.progress,
             error: err.message,
+            budget_exceeded: None,
         };
     }
 
@@ -406,14 +519,19 @@ pub async fn video_get_status(params: VideoStatusParams) -> VideoTaskResult {
         status: api_response.status,
         progress: api_response.progress,
         error: None,
+        budget_exceeded: None,
     }
 }
 
 // ==================== 获取视频内容 ====================
 
 #[tauri::command]
-pub async fn video_get_content(params: VideoStatusParams) -> VideoContentResult {
-    println!("[Rust] video_get_content called, task_id: {}", params.task_id);
+pub async fn video_get_content(app: tauri::AppHandle, mut params: VideoStatusParams) -> VideoContentResult {
+    rust_log!("[Rust] video_get_content called, task_id: {}", params.task_id);
+
+    if let Err(e) = resolve_api_key(&app, &params.credential_id, &mut params.api_key) {
+        return VideoContentResult::failure(e);
+    }
 
     // 创建 HTTP 客户端（视频下载可能需要更长时间）
     let client = match Client::builder()
@@ -433,10 +551,10 @@ pub async fn video_get_content(params: VideoStatusParams) -> VideoContentResult
     // 构建 URL
     let url = format!(
         "{}/v1/videos/{}/content",
-        params.base_url.trim_end_matches('/'),
+        api_base(&params.base_url),
         params.task_id
     );
-    println!("[Rust] Fetching video content from: {}", url);
+    rust_log!("[Rust] Fetching video content from: {}", url);
 
     // 发送请求
     let start_time = std::time::Instant::now();
@@ -447,7 +565,7 @@ pub async fn video_get_content(params: VideoStatusParams) -> VideoContentResult
         .await
     {
         Ok(r) => {
-            println!("[Rust] Response headers received in {:?}", start_time.elapsed());
+            rust_log!("[Rust] Response headers received in {:?}", start_time.elapsed());
             r
         },
         Err(e) => {
@@ -489,7 +607,7 @@ pub async fn video_get_content(params: VideoStatusParams) -> VideoContentResult
         }
     };
 
-    println!("[Rust] Video downloaded: {} bytes in {:?}", video_bytes.len(), start_time.elapsed());
+    rust_log!("[Rust] Video downloaded: {} bytes in {:?}", video_bytes.len(), start_time.elapsed());
 
     // 转换为 base64
     let video_base64 = BASE64.encode(&video_bytes);
@@ -504,10 +622,25 @@ pub async fn video_get_content(params: VideoStatusParams) -> VideoContentResult
 // ==================== Veo 创建视频任务 ====================
 
 #[tauri::command]
-pub async fn veo_create_task(params: VeoCreateParams) -> VideoTaskResult {
-    println!("[Rust] veo_create_task called");
-    println!("[Rust] base_url: {}", params.base_url);
-    println!("[Rust] model: {}", params.model);
+pub async fn veo_create_task(app: tauri::AppHandle, mut params: VeoCreateParams) -> VideoTaskResult {
+    rust_log!("[Rust] veo_create_task called");
+    rust_log!("[Rust] base_url: {}", params.base_url);
+    rust_log!("[Rust] model: {}", params.model);
+
+    if let Err(e) = resolve_api_key(&app, &params.credential_id, &mut params.api_key) {
+        return VideoTaskResult::failure(e);
+    }
+
+    // 计费时长（未指定时为 Veo 默认的 8 秒）
+    let video_seconds = params
+        .metadata
+        .as_ref()
+        .and_then(|m| m.duration_seconds)
+        .unwrap_or(8) as f64;
+    let _reservation = match check_budget(&app, &params.usage_context, "veo", &params.model, &Usage::video_seconds(video_seconds)) {
+        Ok(reservation) => reservation,
+        Err(e) => return VideoTaskResult::budget_exceeded(e),
+    };
 
     // 创建 HTTP 客户端
     let client = match Client::builder()
@@ -522,27 +655,69 @@ pub async fn veo_create_task(params: VeoCreateParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(format!("创建 HTTP 客户端失败: {}", e)),
+                budget_exceeded: None,
             }
         }
     };
 
+    // 预处理输入图片（Veo 仅支持 PNG/JPEG）
+    let images = match &params.images {
+        Some(images) => prepare_veo_images(&app, images).await.map(Some),
+        None => Ok(None),
+    };
+    let images = match images {
+        Ok(images) => images,
+        Err(e) => {
+            return VideoTaskResult {
+                success: false,
+                task_id: None,
+                status: None,
+                progress: None,
+                error: Some(e),
+                budget_exceeded: None,
+            };
+        }
+    };
+
+    let mut metadata = params.metadata;
+    if let Some(reference_images) = metadata.as_mut().and_then(|m| m.reference_images.as_mut()) {
+        for (index, reference) in reference_images.iter_mut().enumerate() {
+            match prepare_base64_image(&reference.image.bytes_base64_encoded, UploadTarget::VeoReference).await {
+                Ok(prepared) => {
+                    reference.image.bytes_base64_encoded = prepared.to_base64();
+                    reference.image.mime_type = Some(prepared.mime_type);
+                }
+                Err(e) => {
+                    return VideoTaskResult {
+                        success: false,
+                        task_id: None,
+                        status: None,
+                        progress: None,
+                        error: Some(format!("第 {} 张参考图片处理失败: {}", index + 1, e)),
+                        budget_exceeded: None,
+                    };
+                }
+            }
+        }
+    }
+
     // 构建请求体
     let request_body = VeoApiRequest {
         model: params.model.clone(),
         prompt: params.prompt.clone(),
-        images: params.images.clone(),
-        metadata: params.metadata,
+        images,
+        metadata,
     };
 
     // 构建 URL
     let url = format!(
         "{}/v1/videos",
-        params.base_url.trim_end_matches('/')
+        api_base(&params.base_url)
     );
-    println!("[Rust] Request URL: {}", url);
+    rust_log!("[Rust] Request URL: {}", url);
 
     // 发送请求
-    println!("[Rust] Sending Veo create request...");
+    rust_log!("[Rust] Sending Veo create request...");
     let start_time = std::time::Instant::now();
 
     let response = match client
@@ -554,11 +729,11 @@ pub async fn veo_create_task(params: VeoCreateParams) -> VideoTaskResult {
         .await
     {
         Ok(r) => {
-            println!("[Rust] Response received in {:?}", start_time.elapsed());
+            rust_log!("[Rust] Response received in {:?}", start_time.elapsed());
             r
         },
         Err(e) => {
-            println!("[Rust] Request failed: {}", e);
+            rust_log!("[Rust] Request failed: {}", e);
             let error_msg = if e.is_timeout() {
                 "请求超时，请稍后重试".to_string()
             } else if e.is_connect() {
@@ -572,6 +747,7 @@ pub async fn veo_create_task(params: VeoCreateParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(error_msg),
+                budget_exceeded: None,
             };
         }
     };
@@ -587,18 +763,20 @@ pub async fn veo_create_task(params: VeoCreateParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(format!("获取响应失败: {}", e)),
+                budget_exceeded: None,
             };
         }
     };
 
     if !status.is_success() {
-        println!("[Rust] Error response: {}", response_text);
+        rust_log!("[Rust] Error response: {}", response_text);
         return VideoTaskResult {
             success: false,
             task_id: None,
             status: None,
             progress: None,
             error: Some(format!("API 返回错误 ({}): {}", status, response_text)),
+            budget_exceeded: None,
         };
     }
 
@@ -615,14 +793,15 @@ pub async fn veo_create_task(params: VeoCreateParams) -> VideoTaskResult {
     let api_response: VeoApiResponse = match serde_json::from_str(&response_text) {
         Ok(r) => r,
         Err(e) => {
-            println!("[Rust] Failed to parse JSON: {}", e);
-            println!("[Rust] Response text: {}", response_text);
+            rust_log!("[Rust] Failed to parse JSON: {}", e);
+            rust_log!("[Rust] Response text: {}", response_text);
             return VideoTaskResult {
                 success: false,
                 task_id: None,
                 status: None,
                 progress: None,
                 error: Some(format!("解析响应失败: {}", e)),
+                budget_exceeded: None,
             };
         }
     };
@@ -635,6 +814,7 @@ pub async fn veo_create_task(params: VeoCreateParams) -> VideoTaskResult {
             status: None,
             progress: None,
             error: err.message,
+            budget_exceeded: None,
         };
     }
 
@@ -647,10 +827,12 @@ pub async fn veo_create_task(params: VeoCreateParams) -> VideoTaskResult {
             status: None,
             progress: None,
             error: Some("API 未返回任务 ID".to_string()),
+            budget_exceeded: None,
         };
     }
 
-    println!("[Rust] Veo task created: {:?}", task_id);
+    rust_log!("[Rust] Veo task created: {:?}", task_id);
+    record_usage(&app, &params.usage_context, "veo", &params.model, &Usage::video_seconds(video_seconds));
 
     VideoTaskResult {
         success: true,
@@ -658,14 +840,19 @@ pub async fn veo_create_task(params: VeoCreateParams) -> VideoTaskResult {
         status: api_response.status,
         progress: api_response.progress,
         error: None,
+        budget_exceeded: None,
     }
 }
 
 // ==================== Veo 获取视频任务状态 ====================
 
 #[tauri::command]
-pub async fn veo_get_status(params: VideoStatusParams) -> VideoTaskResult {
-    println!("[Rust] veo_get_status called, task_id: {}", params.task_id);
+pub async fn veo_get_status(app: tauri::AppHandle, mut params: VideoStatusParams) -> VideoTaskResult {
+    rust_log!("[Rust] veo_get_status called, task_id: {}", params.task_id);
+
+    if let Err(e) = resolve_api_key(&app, &params.credential_id, &mut params.api_key) {
+        return VideoTaskResult::failure(e);
+    }
 
     // 创建 HTTP 客户端
     let client = match Client::builder()
@@ -680,6 +867,7 @@ pub async fn veo_get_status(params: VideoStatusParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(format!("创建 HTTP 客户端失败: {}", e)),
+                budget_exceeded: None,
             }
         }
     };
@@ -687,7 +875,7 @@ pub async fn veo_get_status(params: VideoStatusParams) -> VideoTaskResult {
     // 构建 URL
     let url = format!(
         "{}/v1/videos/{}",
-        params.base_url.trim_end_matches('/'),
+        api_base(&params.base_url),
         params.task_id
     );
 
@@ -713,6 +901,7 @@ pub async fn veo_get_status(params: VideoStatusParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(error_msg),
+                budget_exceeded: None,
             };
         }
     };
@@ -728,6 +917,7 @@ pub async fn veo_get_status(params: VideoStatusParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(format!("获取响应失败: {}", e)),
+                budget_exceeded: None,
             };
         }
     };
@@ -739,6 +929,7 @@ pub async fn veo_get_status(params: VideoStatusParams) -> VideoTaskResult {
             status: None,
             progress: None,
             error: Some(format!("API 返回错误 ({}): {}", status, response_text)),
+            budget_exceeded: None,
         };
     }
 
@@ -752,6 +943,7 @@ pub async fn veo_get_status(params: VideoStatusParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(format!("解析响应失败: {}", e)),
+                budget_exceeded: None,
             };
         }
     };
@@ -764,6 +956,7 @@ pub async fn veo_get_status(params: VideoStatusParams) -> VideoTaskResult {
             status: api_response.status,
             progress: api_response.progress,
             error: err.message,
+            budget_exceeded: None,
         };
     }
 
@@ -773,14 +966,19 @@ pub async fn veo_get_status(params: VideoStatusParams) -> VideoTaskResult {
         status: api_response.status,
         progress: api_response.progress,
         error: None,
+        budget_exceeded: None,
     }
 }
 
 // ==================== Veo 获取视频内容 ====================
 
 #[tauri::command]
-pub async fn veo_get_content(params: VideoStatusParams) -> VideoContentResult {
-    println!("[Rust] veo_get_content called, task_id: {}", params.task_id);
+pub async fn veo_get_content(app: tauri::AppHandle, mut params: VideoStatusParams) -> VideoContentResult {
+    rust_log!("[Rust] veo_get_content called, task_id: {}", params.task_id);
+
+    if let Err(e) = resolve_api_key(&app, &params.credential_id, &mut params.api_key) {
+        return VideoContentResult::failure(e);
+    }
 
     // 创建 HTTP 客户端（视频下载可能需要更长时间）
     let client = match Client::builder()
@@ -800,10 +998,10 @@ pub async fn veo_get_content(params: VideoStatusParams) -> VideoContentResult {
     // 构建 URL
     let url = format!(
         "{}/v1/videos/{}/content",
-        params.base_url.trim_end_matches('/'),
+        api_base(&params.base_url),
         params.task_id
     );
-    println!("[Rust] Fetching Veo video content from: {}", url);
+    rust_log!("[Rust] Fetching Veo video content from: {}", url);
 
     // 发送请求
     let start_time = std::time::Instant::now();
@@ -814,7 +1012,7 @@ pub async fn veo_get_content(params: VideoStatusParams) -> VideoContentResult {
         .await
     {
         Ok(r) => {
-            println!("[Rust] Response headers received in {:?}", start_time.elapsed());
+            rust_log!("[Rust] Response headers received in {:?}", start_time.elapsed());
             r
         },
         Err(e) => {
@@ -856,7 +1054,7 @@ pub async fn veo_get_content(params: VideoStatusParams) -> VideoContentResult {
         }
     };
 
-    println!("[Rust] Veo video downloaded: {} bytes in {:?}", video_bytes.len(), start_time.elapsed());
+    rust_log!("[Rust] Veo video downloaded: {} bytes in {:?}", video_bytes.len(), start_time.elapsed());
 
     // 转换为 base64
     let video_base64 = BASE64.encode(&video_bytes);
@@ -887,7 +1085,9 @@ pub struct KlingMetadata {
 #[serde(rename_all = "camelCase")]
 pub struct KlingCreateParams {
     pub base_url: String,
-    pub api_key: String,
+    #[serde(default)]
+    pub api_key: String, // 使用 credential_id 时可省略
+    pub credential_id: Option<String>, // 凭据库中的凭据 ID（优先于 api_key）
     pub model: String,
     pub prompt: String,
     pub mode: String,  // "text2video" 或 "image2video"
@@ -907,6 +1107,8 @@ pub struct KlingCreateParams {
     pub n: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<KlingMetadata>,
+    #[serde(flatten)]
+    pub usage_context: UsageContext,
 }
 
 // Kling 获取任务状态参数
@@ -914,7 +1116,9 @@ pub struct KlingCreateParams {
 #[serde(rename_all = "camelCase")]
 pub struct KlingStatusParams {
     pub base_url: String,
-    pub api_key: String,
+    #[serde(default)]
+    pub api_key: String, // 使用 credential_id 时可省略
+    pub credential_id: Option<String>, // 凭据库中的凭据 ID（优先于 api_key）
     pub task_id: String,
     pub mode: String,  // "text2video" 或 "image2video"
 }
@@ -957,6 +1161,17 @@ pub struct KlingContentResult {
     pub error: Option<String>,
 }
 
+impl KlingContentResult {
+    fn failure(error: String) -> Self {
+        KlingContentResult {
+            success: false,
+            video_url: None,
+            video_data: None,
+            error: Some(error),
+        }
+    }
+}
+
 // Kling 下载参数
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -967,11 +1182,22 @@ pub struct KlingDownloadParams {
 // ==================== Kling 创建视频任务 ====================
 
 #[tauri::command]
-pub async fn kling_create_task(params: KlingCreateParams) -> VideoTaskResult {
-    println!("[Rust] kling_create_task called");
-    println!("[Rust] base_url: {}", params.base_url);
-    println!("[Rust] model: {}", params.model);
-    println!("[Rust] mode: {}", params.mode);
+pub async fn kling_create_task(app: tauri::AppHandle, mut params: KlingCreateParams) -> VideoTaskResult {
+    rust_log!("[Rust] kling_create_task called");
+    rust_log!("[Rust] base_url: {}", params.base_url);
+    rust_log!("[Rust] model: {}", params.model);
+    rust_log!("[Rust] mode: {}", params.mode);
+
+    if let Err(e) = resolve_api_key(&app, &params.credential_id, &mut params.api_key) {
+        return VideoTaskResult::failure(e);
+    }
+
+    // 计费时长（未指定时为 Kling 默认的 5 秒，n 个视频分别计费）
+    let video_seconds = params.duration.unwrap_or(5.0) * params.n.unwrap_or(1).max(1) as f64;
+    let _reservation = match check_budget(&app, &params.usage_context, "kling", &params.model, &Usage::video_seconds(video_seconds)) {
+        Ok(reservation) => reservation,
+        Err(e) => return VideoTaskResult::budget_exceeded(e),
+    };
 
     // 创建 HTTP 客户端
     let client = match Client::builder()
@@ -986,15 +1212,36 @@ pub async fn kling_create_task(params: KlingCreateParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(format!("创建 HTTP 客户端失败: {}", e)),
+                budget_exceeded: None,
+            }
+        }
+    };
+
+    // 预处理 base64 图片（URL 原样传递）
+    let image = match &params.image {
+        Some(image) if !image.starts_with("http://") && !image.starts_with("https://") => {
+            match prepare_base64_image(image, UploadTarget::KlingImage).await {
+                Ok(prepared) => Some(prepared.to_base64()),
+                Err(e) => {
+                    return VideoTaskResult {
+                        success: false,
+                        task_id: None,
+                        status: None,
+                        progress: None,
+                        error: Some(format!("图片处理失败: {}", e)),
+                        budget_exceeded: None,
+                    };
+                }
             }
         }
+        other => other.clone(),
     };
 
     // 构建请求体
     let request_body = KlingApiRequest {
         model: Some(params.model.clone()),
         prompt: Some(params.prompt.clone()),
-        image: params.image.clone(),
+        image,
         duration: params.duration,
         width: params.width,
         height: params.height,
@@ -1013,13 +1260,13 @@ pub async fn kling_create_task(params: KlingCreateParams) -> VideoTaskResult {
 
     let url = format!(
         "{}/kling/v1/videos/{}",
-        params.base_url.trim_end_matches('/'),
+        api_base(&params.base_url),
         endpoint
     );
-    println!("[Rust] Request URL: {}", url);
+    rust_log!("[Rust] Request URL: {}", url);
 
     // 发送请求
-    println!("[Rust] Sending Kling create request...");
+    rust_log!("[Rust] Sending Kling create request...");
     let start_time = std::time::Instant::now();
 
     let response = match client
@@ -1031,11 +1278,11 @@ pub async fn kling_create_task(params: KlingCreateParams) -> VideoTaskResult {
         .await
     {
         Ok(r) => {
-            println!("[Rust] Response received in {:?}", start_time.elapsed());
+            rust_log!("[Rust] Response received in {:?}", start_time.elapsed());
             r
         },
         Err(e) => {
-            println!("[Rust] Request failed: {}", e);
+            rust_log!("[Rust] Request failed: {}", e);
             let error_msg = if e.is_timeout() {
                 "请求超时，请稍后重试".to_string()
             } else if e.is_connect() {
@@ -1049,6 +1296,7 @@ pub async fn kling_create_task(params: KlingCreateParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(error_msg),
+                budget_exceeded: None,
             };
         }
     };
@@ -1064,14 +1312,15 @@ pub async fn kling_create_task(params: KlingCreateParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(format!("获取响应失败: {}", e)),
+                budget_exceeded: None,
             };
         }
     };
 
-    println!("[Rust] Kling response: {}", response_text);
+    rust_log!("[Rust] Kling response: {}", response_text);
 
     if !status.is_success() {
-        println!("[Rust] Error response: {}", response_text);
+        rust_log!("[Rust] Error response: {}", response_text);
         // 尝试解析错误信息
         #[derive(Debug, Deserialize)]
         struct KlingErrorResponse {
@@ -1094,6 +1343,7 @@ pub async fn kling_create_task(params: KlingCreateParams) -> VideoTaskResult {
                     status: None,
                     progress: None,
                     error: err.message.or(Some(format!("API 错误: {}", status))),
+                    budget_exceeded: None,
                 };
             }
         }
@@ -1104,6 +1354,7 @@ pub async fn kling_create_task(params: KlingCreateParams) -> VideoTaskResult {
             status: None,
             progress: None,
             error: Some(format!("API 返回错误 ({}): {}", status, response_text)),
+            budget_exceeded: None,
         };
     }
 
@@ -1117,14 +1368,15 @@ pub async fn kling_create_task(params: KlingCreateParams) -> VideoTaskResult {
     let api_response: KlingCreateResponse = match serde_json::from_str(&response_text) {
         Ok(r) => r,
         Err(e) => {
-            println!("[Rust] Failed to parse JSON: {}", e);
-            println!("[Rust] Response text: {}", response_text);
+            rust_log!("[Rust] Failed to parse JSON: {}", e);
+            rust_log!("[Rust] Response text: {}", response_text);
             return VideoTaskResult {
                 success: false,
                 task_id: None,
                 status: None,
                 progress: None,
                 error: Some(format!("解析响应失败: {}", e)),
+                budget_exceeded: None,
             };
         }
     };
@@ -1137,10 +1389,12 @@ pub async fn kling_create_task(params: KlingCreateParams) -> VideoTaskResult {
             status: None,
             progress: None,
             error: Some("API 未返回任务 ID".to_string()),
+            budget_exceeded: None,
         };
     }
 
-    println!("[Rust] Kling task created: {:?}", task_id);
+    rust_log!("[Rust] Kling task created: {:?}", task_id);
+    record_usage(&app, &params.usage_context, "kling", &params.model, &Usage::video_seconds(video_seconds));
 
     VideoTaskResult {
         success: true,
@@ -1148,14 +1402,19 @@ pub async fn kling_create_task(params: KlingCreateParams) -> VideoTaskResult {
         status: api_response.status,
         progress: None,
         error: None,
+        budget_exceeded: None,
     }
 }
 
 // ==================== Kling 获取视频任务状态 ====================
 
 #[tauri::command]
-pub async fn kling_get_status(params: KlingStatusParams) -> VideoTaskResult {
-    println!("[Rust] kling_get_status called, task_id: {}, mode: {}", params.task_id, params.mode);
+pub async fn kling_get_status(app: tauri::AppHandle, mut params: KlingStatusParams) -> VideoTaskResult {
+    rust_log!("[Rust] kling_get_status called, task_id: {}, mode: {}", params.task_id, params.mode);
+
+    if let Err(e) = resolve_api_key(&app, &params.credential_id, &mut params.api_key) {
+        return VideoTaskResult::failure(e);
+    }
 
     // 创建 HTTP 客户端
     let client = match Client::builder()
@@ -1170,6 +1429,7 @@ pub async fn kling_get_status(params: KlingStatusParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(format!("创建 HTTP 客户端失败: {}", e)),
+                budget_exceeded: None,
             }
         }
     };
@@ -1183,11 +1443,11 @@ pub async fn kling_get_status(params: KlingStatusParams) -> VideoTaskResult {
 
     let url = format!(
         "{}/kling/v1/videos/{}/{}",
-        params.base_url.trim_end_matches('/'),
+        api_base(&params.base_url),
         endpoint,
         params.task_id
     );
-    println!("[Rust] Status URL: {}", url);
+    rust_log!("[Rust] Status URL: {}", url);
 
     // 发送请求
     let response = match client
@@ -1211,6 +1471,7 @@ pub async fn kling_get_status(params: KlingStatusParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(error_msg),
+                budget_exceeded: None,
             };
         }
     };
@@ -1226,11 +1487,12 @@ pub async fn kling_get_status(params: KlingStatusParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(format!("获取响应失败: {}", e)),
+                budget_exceeded: None,
             };
         }
     };
 
-    println!("[Rust] Kling status response: {}", response_text);
+    rust_log!("[Rust] Kling status response: {}", response_text);
 
     if !status.is_success() {
         return VideoTaskResult {
@@ -1239,6 +1501,7 @@ pub async fn kling_get_status(params: KlingStatusParams) -> VideoTaskResult {
             status: None,
             progress: None,
             error: Some(format!("API 返回错误 ({}): {}", status, response_text)),
+            budget_exceeded: None,
         };
     }
 
@@ -1269,6 +1532,7 @@ pub async fn kling_get_status(params: KlingStatusParams) -> VideoTaskResult {
                 status: None,
                 progress: None,
                 error: Some(format!("解析响应失败: {}", e)),
+                budget_exceeded: None,
             };
         }
     };
@@ -1282,6 +1546,7 @@ pub async fn kling_get_status(params: KlingStatusParams) -> VideoTaskResult {
                 status: api_response.status,
                 progress: None,
                 error: err.message,
+                budget_exceeded: None,
             };
         }
     }
@@ -1292,14 +1557,19 @@ pub async fn kling_get_status(params: KlingStatusParams) -> VideoTaskResult {
         status: api_response.status,
         progress: None,
         error: None,
+        budget_exceeded: None,
     }
 }
 
 // ==================== Kling 获取视频内容（URL 或下载） ====================
 
 #[tauri::command]
-pub async fn kling_get_content(params: KlingStatusParams) -> KlingContentResult {
-    println!("[Rust] kling_get_content called, task_id: {}, mode: {}", params.task_id, params.mode);
+pub async fn kling_get_content(app: tauri::AppHandle, mut params: KlingStatusParams) -> KlingContentResult {
+    rust_log!("[Rust] kling_get_content called, task_id: {}, mode: {}", params.task_id, params.mode);
+
+    if let Err(e) = resolve_api_key(&app, &params.credential_id, &mut params.api_key) {
+        return KlingContentResult::failure(e);
+    }
 
     // 创建 HTTP 客户端
     let client = match Client::builder()
@@ -1326,7 +1596,7 @@ pub async fn kling_get_content(params: KlingStatusParams) -> KlingContentResult
 
     let url = format!(
         "{}/kling/v1/videos/{}/{}",
-        params.base_url.trim_end_matches('/'),
+        api_base(&params.base_url),
         endpoint,
         params.task_id
     );
@@ -1421,7 +1691,7 @@ pub async fn kling_get_content(params: KlingStatusParams) -> KlingContentResult
         }
     };
 
-    println!("[Rust] Kling video URL: {}", video_url);
+    rust_log!("[Rust] Kling video URL: {}", video_url);
 
     KlingContentResult {
         success: true,
@@ -1435,7 +1705,7 @@ pub async fn kling_get_content(params: KlingStatusParams) -> KlingContentResult
 
 #[tauri::command]
 pub async fn kling_download_video(params: KlingDownloadParams) -> VideoContentResult {
-    println!("[Rust] kling_download_video called, url: {}", params.video_url);
+    rust_log!("[Rust] kling_download_video called, url: {}", params.video_url);
 
     // 创建 HTTP 客户端（视频下载可能需要更长时间）
     let client = match Client::builder()
@@ -1460,7 +1730,7 @@ pub async fn kling_download_video(params: KlingDownloadParams) -> VideoContentRe
         .await
     {
         Ok(r) => {
-            println!("[Rust] Response headers received in {:?}", start_time.elapsed());
+            rust_log!("[Rust] Response headers received in {:?}", start_time.elapsed());
             r
         },
         Err(e) => {
@@ -1502,7 +1772,7 @@ pub async fn kling_download_video(params: KlingDownloadParams) -> VideoContentRe
         }
     };
 
-    println!("[Rust] Kling video downloaded: {} bytes in {:?}", video_bytes.len(), start_time.elapsed());
+    rust_log!("[Rust] Kling video downloaded: {} bytes in {:?}", video_bytes.len(), start_time.elapsed());
 
     // 转换为 base64
     let video_base64 = BASE64.encode(&video_bytes);
@@ -52,7 +52,7 @@ function DalleGeneratorBase({
   data,
   selected,
 }: NodeProps<DalleGeneratorNode>) {
-  const { updateNodeData, getConnectedInputData, getConnectedInputDataAsync, getEmptyConnectedInputs, getConnectedImagesWithInfo } = useFlowStore();
+  const { updateNodeData, getConnectedInputData, getConnectedImageSources, getEmptyConnectedInputs, getConnectedImagesWithInfo } = useFlowStore();
   const [showPreview, setShowPreview] = useState(false);
   const [showErrorDetail, setShowErrorDetail] = useState(false);
 
@@ -110,7 +110,9 @@ function DalleGeneratorBase({
   }, [updateNodeData]);
 
   const handleGenerate = useCallback(async () => {
-    const { prompt, images } = await getConnectedInputDataAsync(id);
+    // 已保存的图片只传路径，由 Rust 侧读取，避免 base64 跨越 IPC
+    const { prompt } = getConnectedInputData(id);
+    const images = getConnectedImageSources(id);
     const { activeCanvasId } = useCanvasStore.getState();
     canvasIdRef.current = activeCanvasId;
 
@@ -128,6 +130,8 @@ function DalleGeneratorBase({
         inputImages: images.length > 0 ? images : undefined,
         aspectRatio: data.aspectRatio,
         imageSize: data.quality === "hd" ? "4K" : undefined,
+        canvasId: activeCanvasId ?? undefined,
+        nodeId: id,
       }, "dalleGenerator");
 
       if (response.imageData) {
@@ -184,7 +188,7 @@ function DalleGeneratorBase({
     } catch {
       updateNodeDataWithCanvas(id, { status: "error", error: "生成失败", errorDetails: undefined });
     }
-  }, [id, model, data.aspectRatio, data.quality, updateNodeDataWithCanvas, getConnectedInputDataAsync, getConnectedImagesWithInfo, updateNodeData]);
+  }, [id, model, data.aspectRatio, data.quality, updateNodeDataWithCanvas, getConnectedInputData, getConnectedImageSources, getConnectedImagesWithInfo, updateNodeData]);
 
   return (
     <>
@@ -19,9 +19,13 @@ interface DoubaoGeneratorNodeData {
     aspectRatio: "1:1" | "16:9" | "9:16" | "4:3" | "3:4";
     guidanceScale: number; // 提示词相关度 1-10
     watermark: boolean;
+    imageSize?: "1K" | "2K" | "4K"; // 尺寸档位，默认 2K
+    sequential?: boolean; // 组图生成
+    maxImages?: number; // 组图最大数量 1-15
     status: "idle" | "loading" | "success" | "error";
     outputImage?: string;
     outputImagePath?: string;
+    outputImagePaths?: string[]; // 组图生成的全部图片路径（第一张同 outputImagePath）
     error?: string;
     errorDetails?: ErrorDetails;
 }
@@ -30,6 +34,7 @@ type DoubaoGeneratorNode = Node<DoubaoGeneratorNodeData>;
 
 // 预设模型选项
 const presetModels = [
+    { value: "doubao-seedream-4-0-250828", label: "豆包 SeedDream 4.0" },
     { value: "doubao-seedream-3-0-t2i-250415", label: "豆包 SeedDream 3.0" },
     { value: "doubao-seedream-2-0-t2i", label: "豆包 SeedDream 2.0" },
 ];
@@ -43,13 +48,16 @@ const aspectRatioOptions = [
     { value: "3:4", label: "3:4" },
 ];
 
+// 尺寸档位选项
+const imageSizeOptions = ["1K", "2K", "4K"] as const;
+
 // 豆包节点组件
 function DoubaoGeneratorBase({
     id,
     data,
     selected,
 }: NodeProps<DoubaoGeneratorNode>) {
-    const { updateNodeData, getConnectedInputData, getConnectedInputDataAsync, getEmptyConnectedInputs, getConnectedImagesWithInfo } = useFlowStore();
+    const { updateNodeData, getConnectedInputData, getConnectedImageSources, getEmptyConnectedInputs, getConnectedImagesWithInfo } = useFlowStore();
     const [showPreview, setShowPreview] = useState(false);
     const [showErrorDetail, setShowErrorDetail] = useState(false);
 
@@ -74,6 +82,9 @@ function DoubaoGeneratorBase({
     // 默认提示词相关度
     const guidanceScale = data.guidanceScale ?? 5;
     const watermark = data.watermark ?? false;
+    const imageSize = data.imageSize ?? "2K";
+    const sequential = data.sequential ?? false;
+    const maxImages = data.maxImages ?? 4;
 
     // 处理模型变更
     const handleModelChange = (value: string) => {
@@ -111,7 +122,9 @@ function DoubaoGeneratorBase({
     }, [updateNodeData]);
 
     const handleGenerate = useCallback(async () => {
-        const { prompt, images } = await getConnectedInputDataAsync(id);
+        // 已保存的图片只传路径，由 Rust 侧读取，避免 base64 跨越 IPC
+        const { prompt } = getConnectedInputData(id);
+        const images = getConnectedImageSources(id);
         const { activeCanvasId } = useCanvasStore.getState();
         canvasIdRef.current = activeCanvasId;
 
@@ -128,7 +141,13 @@ function DoubaoGeneratorBase({
                 model,
                 inputImages: images.length > 0 ? images : undefined,
                 aspectRatio: data.aspectRatio,
+                imageSize,
                 guidanceScale,
+                watermark,
+                sequential,
+                maxImages: sequential ? maxImages : undefined,
+                canvasId: activeCanvasId ?? undefined,
+                nodeId: id,
             }, "doubaoGenerator");
 
             if (response.imageData) {
@@ -153,26 +172,33 @@ function DoubaoGeneratorBase({
                             }
                         }
 
-                        const imageInfo = await saveImage(
-                            response.imageData, activeCanvasId, id, prompt,
-                            inputImagesMetadata.length > 0 ? inputImagesMetadata : undefined, "generated"
-                        );
+                        // 组图生成时逐张保存
+                        const outputImagePaths: string[] = [];
+                        for (const imageData of response.images ?? [response.imageData]) {
+                            const imageInfo = await saveImage(
+                                imageData, activeCanvasId, id, prompt,
+                                inputImagesMetadata.length > 0 ? inputImagesMetadata : undefined, "generated"
+                            );
+                            outputImagePaths.push(imageInfo.path);
+                        }
 
                         updateNodeDataWithCanvas(id, {
                             status: "success", outputImage: undefined,
-                            outputImagePath: imageInfo.path, error: undefined,
+                            outputImagePath: outputImagePaths[0],
+                            outputImagePaths: outputImagePaths.length > 1 ? outputImagePaths : undefined,
+                            error: undefined,
                         });
                     } catch (saveError) {
                         console.warn("文件保存失败，回退到 base64 存储:", saveError);
                         updateNodeDataWithCanvas(id, {
                             status: "success", outputImage: response.imageData,
-                            outputImagePath: undefined, error: undefined,
+                            outputImagePath: undefined, outputImagePaths: undefined, error: undefined,
                         });
                     }
                 } else {
                     updateNodeDataWithCanvas(id, {
                         status: "success", outputImage: response.imageData,
-                        outputImagePath: undefined, error: undefined,
+                        outputImagePath: undefined, outputImagePaths: undefined, error: undefined,
                     });
                 }
             } else if (response.error) {
@@ -185,7 +211,7 @@ function DoubaoGeneratorBase({
         } catch {
             updateNodeDataWithCanvas(id, { status: "error", error: "生成失败", errorDetails: undefined });
         }
-    }, [id, model, data.aspectRatio, guidanceScale, updateNodeDataWithCanvas, getConnectedInputDataAsync, getConnectedImagesWithInfo, updateNodeData]);
+    }, [id, model, data.aspectRatio, imageSize, guidanceScale, watermark, sequential, maxImages, updateNodeDataWithCanvas, getConnectedInputData, getConnectedImageSources, getConnectedImagesWithInfo, updateNodeData]);
 
     return (
         <>
@@ -278,6 +304,27 @@ function DoubaoGeneratorBase({
                         </div>
                     </div>
 
+                    {/* 尺寸档位 */}
+                    <div>
+                        <label className="text-xs text-base-content/60 mb-0.5 block">尺寸</label>
+                        <div className="grid grid-cols-3 gap-1">
+                            {imageSizeOptions.map((size) => (
+                                <button
+                                    key={size}
+                                    type="button"
+                                    className={`btn btn-xs ${imageSize === size ? "btn-secondary" : "btn-ghost bg-base-200"}`}
+                                    onPointerDown={(e) => e.stopPropagation()}
+                                    onClick={(e) => {
+                                        e.stopPropagation();
+                                        updateNodeData<DoubaoGeneratorNodeData>(id, { imageSize: size });
+                                    }}
+                                >
+                                    {size}
+                                </button>
+                            ))}
+                        </div>
+                    </div>
+
                     {/* 提示词相关度 */}
                     <div>
                         <label className="text-xs text-base-content/60 mb-0.5 block">
@@ -310,6 +357,38 @@ function DoubaoGeneratorBase({
                         />
                     </div>
 
+                    {/* 组图生成 */}
+                    <div className="flex items-center justify-between">
+                        <label className="text-xs text-base-content/60">组图生成</label>
+                        <input
+                            type="checkbox"
+                            className="toggle toggle-xs toggle-primary"
+                            checked={sequential}
+                            onPointerDown={(e) => e.stopPropagation()}
+                            onChange={(e) => {
+                                updateNodeData<DoubaoGeneratorNodeData>(id, { sequential: e.target.checked });
+                            }}
+                        />
+                    </div>
+                    {sequential && (
+                        <div>
+                            <label className="text-xs text-base-content/60 mb-0.5 block">
+                                最多张数: {maxImages}
+                            </label>
+                            <input
+                                type="range"
+                                min="1"
+                                max="15"
+                                value={maxImages}
+                                className="range range-xs range-primary"
+                                onPointerDown={(e) => e.stopPropagation()}
+                                onChange={(e) => {
+                                    updateNodeData<DoubaoGeneratorNodeData>(id, { maxImages: parseInt(e.target.value) });
+                                }}
+                            />
+                        </div>
+                    )}
+
                     {/* 生成按钮 */}
                     <button
                         className={`btn btn-sm w-full gap-2 ${data.status === "loading" || !isPromptConnected ? "btn-disabled" : "btn-secondary"}`}
@@ -358,6 +437,11 @@ function DoubaoGeneratorBase({
                             <div className="absolute inset-0 bg-black/40 opacity-0 group-hover:opacity-100 transition-opacity rounded-lg flex items-center justify-center">
                                 <Maximize2 className="w-6 h-6 text-white" />
                             </div>
+                            {data.outputImagePaths && data.outputImagePaths.length > 1 && (
+                                <span className="absolute top-1 right-1 px-1.5 py-0.5 text-[10px] rounded bg-black/60 text-white">
+                                    共 {data.outputImagePaths.length} 张
+                                </span>
+                            )}
                         </div>
                     )}
                 </div>
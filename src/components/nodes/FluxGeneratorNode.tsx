@@ -17,6 +17,10 @@ interface FluxGeneratorNodeData {
   label: string;
   model: ModelType;
   aspectRatio: "1:1" | "16:9" | "9:16" | "4:3" | "3:4";
+  seed?: number; // 随机种子，留空则随机
+  safetyTolerance?: number; // 审核宽松度 0（最严格）- 6，默认 2
+  raw?: boolean; // raw 模式（仅 Ultra 模型）
+  imagePromptStrength?: number; // 参考图影响强度 0-1（仅 Ultra 模型）
   status: "idle" | "loading" | "success" | "error";
   outputImage?: string;
   outputImagePath?: string;
@@ -32,6 +36,7 @@ const presetModels = [
   { value: "flux-2-dev", label: "Flux 2 Dev" },
   { value: "flux-2-flex", label: "Flux 2 Flex" },
   { value: "flux-1-pro", label: "Flux 1 Pro" },
+  { value: "flux-pro-1.1-ultra", label: "Flux 1.1 Pro Ultra" },
   { value: "flux-1-dev", label: "Flux 1 Dev" },
   { value: "flux-1-schnell", label: "Flux 1 Schnell" },
   { value: "flux-kontext-pro", label: "Flux Kontext Pro" },
@@ -52,7 +57,7 @@ function FluxGeneratorBase({
   data,
   selected,
 }: NodeProps<FluxGeneratorNode>) {
-  const { updateNodeData, getConnectedInputData, getConnectedInputDataAsync, getEmptyConnectedInputs, getConnectedImagesWithInfo } = useFlowStore();
+  const { updateNodeData, getConnectedInputData, getConnectedImageSources, getEmptyConnectedInputs, getConnectedImagesWithInfo } = useFlowStore();
   const [showPreview, setShowPreview] = useState(false);
   const [showErrorDetail, setShowErrorDetail] = useState(false);
 
@@ -73,6 +78,8 @@ function FluxGeneratorBase({
   // 默认模型
   const defaultModel: ModelType = "flux-1-pro";
   const model: ModelType = data.model || defaultModel;
+  // raw 模式和参考图强度仅 Ultra 模型支持
+  const isUltra = model.includes("ultra");
 
   // 处理模型变更
   const handleModelChange = (value: string) => {
@@ -110,7 +117,9 @@ function FluxGeneratorBase({
   }, [updateNodeData]);
 
   const handleGenerate = useCallback(async () => {
-    const { prompt, images } = await getConnectedInputDataAsync(id);
+    // 已保存的图片只传路径，由 Rust 侧读取，避免 base64 跨越 IPC
+    const { prompt } = getConnectedInputData(id);
+    const images = getConnectedImageSources(id);
     const { activeCanvasId } = useCanvasStore.getState();
     canvasIdRef.current = activeCanvasId;
 
@@ -127,6 +136,12 @@ function FluxGeneratorBase({
         model,
         inputImages: images.length > 0 ? images : undefined,
         aspectRatio: data.aspectRatio,
+        seed: data.seed,
+        safetyTolerance: data.safetyTolerance,
+        raw: isUltra ? data.raw : undefined,
+        imagePromptStrength: isUltra && images.length > 0 ? data.imagePromptStrength : undefined,
+        canvasId: activeCanvasId ?? undefined,
+        nodeId: id,
       }, "fluxGenerator");
 
       if (response.imageData) {
@@ -183,7 +198,7 @@ function FluxGeneratorBase({
     } catch {
       updateNodeDataWithCanvas(id, { status: "error", error: "生成失败", errorDetails: undefined });
     }
-  }, [id, model, data.aspectRatio, updateNodeDataWithCanvas, getConnectedInputDataAsync, getConnectedImagesWithInfo, updateNodeData]);
+  }, [id, model, isUltra, data.aspectRatio, data.seed, data.safetyTolerance, data.raw, data.imagePromptStrength, updateNodeDataWithCanvas, getConnectedInputData, getConnectedImageSources, getConnectedImagesWithInfo, updateNodeData]);
 
   return (
     <>
@@ -292,6 +307,78 @@ function FluxGeneratorBase({
             </div>
           </div>
 
+          {/* 随机种子 */}
+          <div>
+            <label className="text-xs text-base-content/60 mb-0.5 block">随机种子</label>
+            <input
+              type="number"
+              className="input input-xs input-bordered w-full"
+              placeholder="留空则随机生成"
+              value={data.seed ?? ""}
+              onPointerDown={(e) => e.stopPropagation()}
+              onChange={(e) => {
+                const value = e.target.value;
+                updateNodeData<FluxGeneratorNodeData>(id, { seed: value ? parseInt(value, 10) : undefined });
+              }}
+            />
+          </div>
+
+          {/* 审核宽松度 */}
+          <div>
+            <label className="text-xs text-base-content/60 mb-0.5 block">
+              审核宽松度: {data.safetyTolerance ?? 2}
+            </label>
+            <input
+              type="range"
+              min="0"
+              max="6"
+              step="1"
+              value={data.safetyTolerance ?? 2}
+              className="range range-xs range-primary"
+              onPointerDown={(e) => e.stopPropagation()}
+              onChange={(e) => {
+                updateNodeData<FluxGeneratorNodeData>(id, { safetyTolerance: parseInt(e.target.value) });
+              }}
+            />
+          </div>
+
+          {isUltra && (
+            <>
+              {/* raw 模式 */}
+              <div className="flex items-center justify-between">
+                <label className="text-xs text-base-content/60">Raw 模式</label>
+                <input
+                  type="checkbox"
+                  className="toggle toggle-xs toggle-primary"
+                  checked={data.raw ?? false}
+                  onPointerDown={(e) => e.stopPropagation()}
+                  onChange={(e) => {
+                    updateNodeData<FluxGeneratorNodeData>(id, { raw: e.target.checked });
+                  }}
+                />
+              </div>
+
+              {/* 参考图强度 */}
+              <div>
+                <label className="text-xs text-base-content/60 mb-0.5 block">
+                  参考图强度: {(data.imagePromptStrength ?? 0.1).toFixed(2)}
+                </label>
+                <input
+                  type="range"
+                  min="0"
+                  max="1"
+                  step="0.05"
+                  value={data.imagePromptStrength ?? 0.1}
+                  className="range range-xs range-primary"
+                  onPointerDown={(e) => e.stopPropagation()}
+                  onChange={(e) => {
+                    updateNodeData<FluxGeneratorNodeData>(id, { imagePromptStrength: parseFloat(e.target.value) });
+                  }}
+                />
+              </div>
+            </>
+          )}
+
           {/* 生成按钮 */}
           <button
             className={`btn btn-sm w-full gap-2 ${data.status === "loading" || !isPromptConnected ? "btn-disabled" : "btn-secondary"}`}
@@ -62,7 +62,7 @@ function GptImageGeneratorBase({
     data,
     selected,
 }: NodeProps<GptImageGeneratorNode>) {
-    const { updateNodeData, getConnectedInputData, getConnectedInputDataAsync, getEmptyConnectedInputs, getConnectedImagesWithInfo } = useFlowStore();
+    const { updateNodeData, getConnectedInputData, getConnectedImageSources, getEmptyConnectedInputs, getConnectedImagesWithInfo } = useFlowStore();
     const [showPreview, setShowPreview] = useState(false);
     const [showErrorDetail, setShowErrorDetail] = useState(false);
 
@@ -120,7 +120,9 @@ function GptImageGeneratorBase({
     }, [updateNodeData]);
 
     const handleGenerate = useCallback(async () => {
-        const { prompt, images } = await getConnectedInputDataAsync(id);
+        // 已保存的图片只传路径，由 Rust 侧读取，避免 base64 跨越 IPC
+        const { prompt } = getConnectedInputData(id);
+        const images = getConnectedImageSources(id);
         const { activeCanvasId } = useCanvasStore.getState();
         canvasIdRef.current = activeCanvasId;
 
@@ -138,6 +140,8 @@ function GptImageGeneratorBase({
                 inputImages: images.length > 0 ? images : undefined,
                 aspectRatio: data.aspectRatio,
                 imageSize: data.quality === "high" ? "4K" : data.quality === "medium" ? "2K" : "1K",
+                canvasId: activeCanvasId ?? undefined,
+                nodeId: id,
             }, "gptImageGenerator");
 
             if (response.imageData) {
@@ -194,7 +198,7 @@ function GptImageGeneratorBase({
         } catch {
             updateNodeDataWithCanvas(id, { status: "error", error: "生成失败", errorDetails: undefined });
         }
-    }, [id, model, data.aspectRatio, data.quality, updateNodeDataWithCanvas, getConnectedInputDataAsync, getConnectedImagesWithInfo, updateNodeData]);
+    }, [id, model, data.aspectRatio, data.quality, updateNodeDataWithCanvas, getConnectedInputData, getConnectedImageSources, getConnectedImagesWithInfo, updateNodeData]);
 
     return (
         <>
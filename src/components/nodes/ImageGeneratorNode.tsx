@@ -60,7 +60,7 @@ function ImageGeneratorBase({
   selected,
   isPro,
 }: NodeProps<ImageGeneratorNode> & { isPro: boolean }) {
-  const { updateNodeData, getConnectedInputData, getConnectedInputDataAsync, getEmptyConnectedInputs, getConnectedImagesWithInfo } = useFlowStore();
+  const { updateNodeData, getConnectedInputData, getConnectedImageSources, getEmptyConnectedInputs, getConnectedImagesWithInfo } = useFlowStore();
   const [showPreview, setShowPreview] = useState(false);
   const [showErrorDetail, setShowErrorDetail] = useState(false);
 
@@ -130,8 +130,9 @@ function ImageGeneratorBase({
   }, [updateNodeData]);
 
   const handleGenerate = useCallback(async () => {
-    // 使用异步版本从文件按需加载图片数据
-    const { prompt, images } = await getConnectedInputDataAsync(id);
+    // 已保存的图片只传路径，由 Rust 侧读取，避免 base64 跨越 IPC
+    const { prompt } = getConnectedInputData(id);
+    const images = getConnectedImageSources(id);
     const { activeCanvasId } = useCanvasStore.getState();
 
     // 记录当前画布 ID
@@ -162,12 +163,16 @@ function ImageGeneratorBase({
             inputImages: images,
             aspectRatio: data.aspectRatio,
             imageSize: isPro ? data.imageSize : undefined,
+            canvasId: activeCanvasId ?? undefined,
+            nodeId: id,
           }, nodeType)
         : await generateImage({
             prompt,
             model,
             aspectRatio: data.aspectRatio,
             imageSize: isPro ? data.imageSize : undefined,
+            canvasId: activeCanvasId ?? undefined,
+            nodeId: id,
           }, nodeType);
 
       if (response.imageData) {
@@ -267,7 +272,7 @@ function ImageGeneratorBase({
         errorDetails: undefined,
       });
     }
-  }, [id, model, data.aspectRatio, data.imageSize, isPro, updateNodeDataWithCanvas, getConnectedInputDataAsync, getConnectedImagesWithInfo]);
+  }, [id, model, data.aspectRatio, data.imageSize, isPro, updateNodeDataWithCanvas, getConnectedInputData, getConnectedImageSources, getConnectedImagesWithInfo]);
 
   // 节点样式配置
   const headerGradient = isPro
@@ -169,6 +169,7 @@ export const KlingGeneratorNode = memo(({ id, data, selected }: NodeProps<KlingG
         params: {
           baseUrl: provider.baseUrl,
           apiKey: provider.apiKey,
+          credentialId: provider.credentialId,
           taskId: data.taskId,
           mode: data.mode || "text2video",
         },
@@ -264,6 +265,7 @@ export const KlingGeneratorNode = memo(({ id, data, selected }: NodeProps<KlingG
       interface KlingRequestParams {
         baseUrl: string;
         apiKey: string;
+        credentialId?: string; // 凭据库中的凭据 ID（优先于 apiKey）
         model: string;
         prompt: string;
         mode: string;
@@ -278,14 +280,19 @@ export const KlingGeneratorNode = memo(({ id, data, selected }: NodeProps<KlingG
           style?: string;
           quality_level?: string;
         };
+        canvasId?: string; // 用量归属
+        nodeId?: string;
       }
 
       const params: KlingRequestParams = {
         baseUrl: provider.baseUrl,
         apiKey: provider.apiKey,
+        credentialId: provider.credentialId,
         model: currentModel,
         prompt,
         mode,
+        canvasId: activeCanvasId,
+        nodeId: id,
       };
 
       // 添加图片（图生视频模式）
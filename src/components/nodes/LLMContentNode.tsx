@@ -162,6 +162,8 @@ export const LLMContentNode = memo(({ id, data, selected }: NodeProps<LLMContent
         temperature: data.temperature,
         maxTokens: data.maxTokens,
         files: allFiles.length > 0 ? allFiles : undefined,
+        canvasId: activeCanvasId ?? undefined,
+        nodeId: id,
       });
 
       if (response.content) {
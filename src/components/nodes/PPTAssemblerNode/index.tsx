@@ -26,6 +26,8 @@ import {
 } from "lucide-react";
 import { useFlowStore } from "@/stores/flowStore";
 import { useSettingsStore } from "@/stores/settingsStore";
+import { useCanvasStore } from "@/stores/canvasStore";
+import { getFallbackProviders } from "@/services/providerService";
 import type { PPTAssemblerNodeData, PPTPageData } from "./types";
 import { downloadPPT, downloadScripts, downloadEditablePPT } from "./pptBuilder";
 import { useLoadingDots } from "@/hooks/useLoadingDots";
@@ -367,7 +369,11 @@ export const PPTAssemblerNode = memo(({ id, data, selected }: NodeProps<PPTAssem
     const config = {
       geminiBaseUrl: provider.baseUrl,
       geminiApiKey: provider.apiKey,
+      geminiCredentialId: provider.credentialId,
       geminiModel: "gemini-3-flash-preview",
+      geminiFallbacks: getFallbackProviders("llm", provider),
+      canvasId: useCanvasStore.getState().activeCanvasId ?? undefined,
+      nodeId: id,
     };
 
     try {
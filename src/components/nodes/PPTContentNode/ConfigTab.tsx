@@ -4,6 +4,7 @@ import type { PPTContentNodeData, PageCountRange, DetailLevel, VisualStyleTempla
 import { IMAGE_PRESET_MODELS, VISUAL_STYLE_TEMPLATES } from "./types";
 import { useLLMPresetModels } from "@/config/presetModels";
 import { generateText } from "@/services/llmService";
+import { useCanvasStore } from "@/stores/canvasStore";
 
 // 独立的文本域组件，正确处理中文输入法
 function ComposableTextarea({
@@ -49,6 +50,7 @@ function ComposableTextarea({
 }
 
 interface ConfigTabProps {
+  nodeId: string; // 用量归属
   config: PPTContentNodeData["outlineConfig"];
   outlineModel: string;
   imageModel: string;
@@ -112,6 +114,7 @@ const imageSizeOptions = [
 ];
 
 export function ConfigTab({
+  nodeId,
   config,
   outlineModel,
   imageModel,
@@ -171,6 +174,8 @@ ${referencePrompt}
         model: outlineModel,
         systemPrompt,
         temperature: 0.7,
+        canvasId: useCanvasStore.getState().activeCanvasId ?? undefined,
+        nodeId,
       });
 
       if (response.content) {
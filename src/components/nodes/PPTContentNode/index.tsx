@@ -247,6 +247,7 @@ export const PPTContentNode = memo(({ id, data, selected }: NodeProps<PPTContent
         return (
           <div className="h-full flex flex-col">
             <ConfigTab
+              nodeId={id}
               config={data.outlineConfig}
               outlineModel={data.outlineModel || "gemini-3-pro-preview"}
               imageModel={data.imageModel || "gemini-3-pro-image-preview"}
@@ -4,7 +4,7 @@ import { useFlowStore } from "@/stores/flowStore";
 import { useCanvasStore } from "@/stores/canvasStore";
 import { generateText, validateJsonOutput } from "@/services/llmService";
 import { editImage } from "@/services/imageService";
-import { saveImage } from "@/services/fileStorageService";
+import { saveImage, type ImageSource } from "@/services/fileStorageService";
 import { generateThumbnail } from "@/utils/imageCompression";
 import type { PPTContentNodeData, PPTOutline, PPTPageItem, ConnectedImageInfo } from "./types";
 import { buildSystemPrompt, buildPageImagePrompt, getVisualStylePrompt, PPT_OUTLINE_JSON_SCHEMA, DEFAULT_OUTLINE_MODEL, DEFAULT_IMAGE_MODEL } from "./types";
@@ -28,7 +28,7 @@ export function usePPTContentExecution({
   void _getConnectedImages;
 
   const updateNodeData = useFlowStore((state) => state.updateNodeData);
-  const getConnectedImagesWithInfoAsync = useFlowStore((state) => state.getConnectedImagesWithInfoAsync);
+  const getConnectedImagesWithInfo = useFlowStore((state) => state.getConnectedImagesWithInfo);
 
   // 用于控制批量生成的暂停/停止
   const isPausedRef = useRef(false);
@@ -155,6 +155,8 @@ export function usePPTContentExecution({
           systemPrompt,
           files,
           responseJsonSchema: PPT_OUTLINE_JSON_SCHEMA,
+          canvasId: canvasIdRef.current ?? undefined,
+          nodeId,
         });
 
         if (response.error) {
@@ -296,18 +298,19 @@ export function usePPTContentExecution({
       abortControllersRef.current.set(pageId, abortController);
 
       try {
-        // 异步获取所有连接的图片信息（包括模板基底图和补充图片）
-        // 这样可以正确从文件系统加载图片数据
-        const allConnectedImages = await getConnectedImagesWithInfoAsync(nodeId);
+        // 获取所有连接的图片信息（包括模板基底图和补充图片）
+        // 已保存的图片只传路径，由 Rust 侧读取，避免 base64 跨越 IPC
+        const allConnectedImages = getConnectedImagesWithInfo(nodeId);
+        const toImageSource = (img?: ConnectedImageInfo): ImageSource | undefined =>
+          img?.imagePath ? { path: img.imagePath } : img?.imageData || undefined;
 
         // 获取模板基底图：优先使用选中的，否则使用第一张
-        let templateImage: string | undefined;
+        let templateImage: ImageSource | undefined;
         if (data.selectedTemplateId) {
-          const selected = allConnectedImages.find(img => img.id === data.selectedTemplateId);
-          templateImage = selected?.imageData;
+          templateImage = toImageSource(allConnectedImages.find(img => img.id === data.selectedTemplateId));
         }
         if (!templateImage && allConnectedImages.length > 0) {
-          templateImage = allConnectedImages[0]?.imageData;
+          templateImage = toImageSource(allConnectedImages[0]);
         }
 
         // 检查是否被停止
@@ -321,7 +324,7 @@ export function usePPTContentExecution({
         const supplementImageRefs = page.supplement?.imageRefs || [];
         const supplementImages = supplementImageRefs
           .map(refId => allConnectedImages.find(img => img.id === refId))
-          .filter((img): img is ConnectedImageInfo => !!img && !!img.imageData);
+          .filter((img): img is ConnectedImageInfo => !!img && !!(img.imagePath || img.imageData));
 
         // 判断是否为标题页（第一页且开启了标题页模式）
         const isTitlePage = page.pageNumber === 1 && data.firstPageIsTitlePage;
@@ -342,9 +345,10 @@ export function usePPTContentExecution({
         }
 
         // 准备输入图片：基底图 + 补充图片
-        const inputImages = [templateImage];
-        if (supplementImages.length > 0) {
-          inputImages.push(...supplementImages.map(img => img.imageData));
+        const inputImages: ImageSource[] = [templateImage];
+        for (const img of supplementImages) {
+          const source = toImageSource(img);
+          if (source) inputImages.push(source);
         }
 
         // 使用模板基底图 + 完整提示词生成 PPT 页面
@@ -357,6 +361,8 @@ export function usePPTContentExecution({
             inputImages,
             aspectRatio: data.imageConfig.aspectRatio,
             imageSize: data.imageConfig.imageSize,
+            canvasId: canvasIdRef.current ?? undefined,
+            nodeId,
           },
           "imageGeneratorPro",
           abortController.signal
@@ -472,7 +478,7 @@ export function usePPTContentExecution({
         abortControllersRef.current.delete(pageId);
       }
     },
-    [data.pages, data.selectedTemplateId, data.imageConfig.aspectRatio, data.imageConfig.imageSize, data.imageModel, data.visualStyleTemplate, data.customVisualStylePrompt, data.firstPageIsTitlePage, nodeId, updatePageState, getConnectedImagesWithInfoAsync]
+    [data.pages, data.selectedTemplateId, data.imageConfig.aspectRatio, data.imageConfig.imageSize, data.imageModel, data.visualStyleTemplate, data.customVisualStylePrompt, data.firstPageIsTitlePage, nodeId, updatePageState, getConnectedImagesWithInfo]
   );
 
   // 开始批量生成（并发执行所有待处理任务）
@@ -23,6 +23,7 @@ import {
 import { useFlowStore } from "@/stores/flowStore";
 import { useCanvasStore } from "@/stores/canvasStore";
 import type { VideoTaskStage } from "@/services/videoGeneration";
+import type { ImageSource } from "@/services/fileStorageService";
 import { taskManager } from "@/services/taskManager";
 import { useLoadingDots } from "@/hooks/useLoadingDots";
 import { ErrorDetailModal } from "@/components/ui/ErrorDetailModal";
@@ -99,7 +100,7 @@ const stageConfig: Record<VideoTaskStage, { label: string; color: string }> = {
 };
 
 export const VeoGeneratorNode = memo(({ id, data, selected }: NodeProps<VeoGeneratorNode>) => {
-  const { updateNodeData, getConnectedInputData, getConnectedInputDataAsync, getEmptyConnectedInputs } = useFlowStore();
+  const { updateNodeData, getConnectedInputData, getConnectedInputDataAsync, getConnectedImageSources, getEmptyConnectedInputs } = useFlowStore();
   const activeCanvasId = useCanvasStore((state) => state.activeCanvasId);
   const [previewState, setPreviewState] = useState<"idle" | "loading" | "ready">("idle");
   const [previewUrl, setPreviewUrl] = useState<string | null>(null);
@@ -163,6 +164,7 @@ export const VeoGeneratorNode = memo(({ id, data, selected }: NodeProps<VeoGener
         params: {
           baseUrl: provider.baseUrl,
           apiKey: provider.apiKey,
+          credentialId: provider.credentialId,
           taskId: data.taskId,
         },
       });
@@ -201,7 +203,9 @@ export const VeoGeneratorNode = memo(({ id, data, selected }: NodeProps<VeoGener
 
   // 生成视频
   const handleGenerate = useCallback(async () => {
-    const { prompt, images } = await getConnectedInputDataAsync(id);
+    // 已保存的图片只传路径，由 Rust 侧读取，避免 base64 跨越 IPC
+    const { prompt } = getConnectedInputData(id);
+    const images = getConnectedImageSources(id);
 
     if (!prompt) {
       updateNodeData<VeoGeneratorNodeData>(id, {
@@ -317,9 +321,10 @@ export const VeoGeneratorNode = memo(({ id, data, selected }: NodeProps<VeoGener
       interface VeoRequestParams {
         baseUrl: string;
         apiKey: string;
+        credentialId?: string; // 凭据库中的凭据 ID（优先于 apiKey）
         model: string;
         prompt: string;
-        images?: string[];
+        images?: ImageSource[];
         metadata?: {
           aspectRatio?: string;
           durationSeconds?: number;
@@ -327,13 +332,18 @@ export const VeoGeneratorNode = memo(({ id, data, selected }: NodeProps<VeoGener
           personGeneration?: string;
           referenceImages?: VeoReferenceImage[];
         };
+        canvasId?: string; // 用量归属
+        nodeId?: string;
       }
 
       const params: VeoRequestParams = {
         baseUrl: provider.baseUrl,
         apiKey: provider.apiKey,
+        credentialId: provider.credentialId,
         model: currentModel,
         prompt,
+        canvasId: activeCanvasId,
+        nodeId: id,
       };
 
       // 根据模式添加图片
@@ -362,7 +372,9 @@ export const VeoGeneratorNode = memo(({ id, data, selected }: NodeProps<VeoGener
       }
       // 参考图片模式
       if (mode === "reference" && images.length > 0) {
-        params.metadata.referenceImages = images.slice(0, 3).map((img) => ({
+        // 参考图片字段只接受 base64 数据
+        const { images: referenceImages } = await getConnectedInputDataAsync(id);
+        params.metadata.referenceImages = referenceImages.slice(0, 3).map((img) => ({
           image: { bytesBase64Encoded: img },
           referenceType: "asset" as const,
         }));
@@ -401,7 +413,7 @@ export const VeoGeneratorNode = memo(({ id, data, selected }: NodeProps<VeoGener
         taskStage: "failed",
       });
     }
-  }, [id, currentModel, data.aspectRatio, data.durationSeconds, data.negativePrompt, data.personGeneration, data.generationMode, activeCanvasId, updateNodeData, getConnectedInputDataAsync, handleClosePreview, isFastModel]);
+  }, [id, currentModel, data.aspectRatio, data.durationSeconds, data.negativePrompt, data.personGeneration, data.generationMode, activeCanvasId, updateNodeData, getConnectedInputData, getConnectedInputDataAsync, getConnectedImageSources, handleClosePreview, isFastModel]);
 
   const handleStop = useCallback(() => {
     if (activeCanvasId) {
@@ -436,6 +448,7 @@ export const VeoGeneratorNode = memo(({ id, data, selected }: NodeProps<VeoGener
         params: {
           baseUrl: provider.baseUrl,
           apiKey: provider.apiKey,
+          credentialId: provider.credentialId,
           taskId: data.taskId,
         },
       });
@@ -1174,6 +1187,7 @@ function VeoPreviewModal({ videoUrl, taskId, onClose }: VeoPreviewModalProps) {
         params: {
           baseUrl: provider.baseUrl,
           apiKey: provider.apiKey,
+          credentialId: provider.credentialId,
           taskId,
         },
       });
@@ -170,6 +170,8 @@ export const VideoGeneratorNode = memo(({ id, data, selected }: NodeProps<VideoG
         seconds: data.seconds || "10",
         size: data.size || "1280x720",
         inputImage: image,
+        canvasId: activeCanvasId,
+        nodeId: id,
       });
 
       if (createResult.error || !createResult.taskId) {
@@ -124,6 +124,8 @@ function ZImageGeneratorBase({
         model,
         aspectRatio: data.aspectRatio,
         negativePrompt: negativePrompt || undefined, // 传递负面提示词
+        canvasId: activeCanvasId ?? undefined,
+        nodeId: id,
       }, "zImageGenerator");
 
       if (response.imageData) {
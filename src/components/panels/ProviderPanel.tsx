@@ -5,6 +5,8 @@ import { useSettingsStore } from "@/stores/settingsStore";
 import { Select } from "@/components/ui/Select";
 import { Input } from "@/components/ui/Input";
 import { useModal, getModalAnimationClasses } from "@/hooks/useModal";
+import { saveProviderKey, deleteCredential } from "@/services/vaultService";
+import { toast } from "@/stores/toastStore";
 import { NODE_ALLOWED_PROTOCOLS } from "@/types";
 import type { Provider, NodeProviderMapping, ProviderProtocol } from "@/types";
 
@@ -99,7 +101,13 @@ export function ProviderPanel() {
     const { id } = deleteConfirm;
     setDeleteConfirm(null);
 
+    const credentialId = providers.find((p) => p.id === id)?.credentialId;
     removeProvider(id);
+    if (credentialId) {
+      deleteCredential(credentialId).catch((error) =>
+        console.warn("[ProviderPanel] 删除凭据失败:", error)
+      );
+    }
     // 同时清除本地状态中的映射
     const newLocalNodeProviders = { ...localNodeProviders };
     for (const key of Object.keys(newLocalNodeProviders) as (keyof NodeProviderMapping)[]) {
@@ -272,11 +280,22 @@ export function ProviderPanel() {
       {(isAddingProvider || editingProvider) && (
         <ProviderEditModal
           provider={editingProvider}
-          onSave={(data) => {
+          onSave={async (data) => {
+            // API Key 保存到凭据库，供应商只记录凭据 ID
+            let credentialId = editingProvider?.credentialId;
+            if (data.apiKey) {
+              try {
+                credentialId = await saveProviderKey({ ...data, credentialId }, data.apiKey);
+              } catch (error) {
+                toast.error(`API Key 保存到凭据库失败: ${error}`);
+                return;
+              }
+            }
+            const saved = { ...data, apiKey: "", credentialId };
             if (editingProvider) {
-              updateProvider(editingProvider.id, data);
+              updateProvider(editingProvider.id, saved);
             } else {
-              addProvider(data);
+              addProvider(saved);
             }
             setEditingProvider(null);
             setIsAddingProvider(false);
@@ -304,7 +323,7 @@ export function ProviderPanel() {
 // 供应商编辑弹窗组件
 interface ProviderEditModalProps {
   provider: Provider | null;
-  onSave: (data: Omit<Provider, "id">) => void;
+  onSave: (data: Omit<Provider, "id">) => Promise<void>;
   onClose: () => void;
 }
 
@@ -313,6 +332,7 @@ function ProviderEditModal({ provider, onSave, onClose }: ProviderEditModalProps
   const [apiKey, setApiKey] = useState(provider?.apiKey || "");
   const [baseUrl, setBaseUrl] = useState(provider?.baseUrl || "");
   const [protocol, setProtocol] = useState<ProviderProtocol>(provider?.protocol || "google");
+  const [isSaving, setIsSaving] = useState(false);
 
   // 使用统一的 modal hook
   const { isVisible, isClosing, handleClose, handleBackdropClick } = useModal({
@@ -324,16 +344,23 @@ function ProviderEditModal({ provider, onSave, onClose }: ProviderEditModalProps
   const { backdropClasses, contentClasses } = getModalAnimationClasses(isVisible, isClosing);
 
   const isEditing = !!provider;
-  const canSave = name.trim() && apiKey.trim() && baseUrl.trim();
+  // 已保存在凭据库时 API Key 可留空（保持不变）
+  const hasStoredKey = !!provider?.credentialId;
+  const canSave = name.trim() && (apiKey.trim() || hasStoredKey) && baseUrl.trim() && !isSaving;
 
-  const handleSave = () => {
+  const handleSave = async () => {
     if (!canSave) return;
-    onSave({
-      name: name.trim(),
-      apiKey: apiKey.trim(),
-      baseUrl: baseUrl.trim(),
-      protocol,
-    });
+    setIsSaving(true);
+    try {
+      await onSave({
+        name: name.trim(),
+        apiKey: apiKey.trim(),
+        baseUrl: baseUrl.trim(),
+        protocol,
+      });
+    } finally {
+      setIsSaving(false);
+    }
   };
 
   return (
@@ -410,10 +437,15 @@ function ProviderEditModal({ provider, onSave, onClose }: ProviderEditModalProps
             </label>
             <Input
               isPassword
-              placeholder="输入 API Key"
+              placeholder={hasStoredKey ? "已保存在凭据库，留空保持不变" : "输入 API Key"}
               value={apiKey}
               onChange={(e) => setApiKey(e.target.value)}
             />
+            <label className="label py-0.5">
+              <span className="label-text-alt text-base-content/50">
+                API Key 加密保存在本机凭据库中
+              </span>
+            </label>
           </div>
 
           {/* Base URL */}
@@ -1,10 +1,16 @@
 import React from "react";
 import ReactDOM from "react-dom/client";
 import App from "./App";
+import { loadStorageRootInfo } from "./services/fileStorageService";
 import "./index.css";
 
-ReactDOM.createRoot(document.getElementById("root") as HTMLElement).render(
-  <React.StrictMode>
-    <App />
-  </React.StrictMode>,
-);
+// 先加载存储位置，确保迁移前保存的图片路径在首次渲染时就能映射到当前目录
+loadStorageRootInfo()
+  .catch((error) => console.error("加载存储位置失败:", error))
+  .finally(() => {
+    ReactDOM.createRoot(document.getElementById("root") as HTMLElement).render(
+      <React.StrictMode>
+        <App />
+      </React.StrictMode>,
+    );
+  });
@@ -22,6 +22,23 @@ export interface ImageMetadata {
   node_id?: string;
   canvas_id?: string;
   created_at: number;
+  operation?: string;
+  // 生成命令直接保存时记录的生成信息
+  revised_prompt?: string;
+  provider?: string;
+  model?: string;
+  aspect_ratio?: string;
+  image_size?: string;
+  seed?: number;
+  width?: number;
+  height?: number;
+}
+
+// 生成命令的输出存储选项（指定后后端直接保存结果并返回 ImageInfo）
+export interface GenerationOutputOptions {
+  canvasId?: string;
+  nodeId?: string;
+  inputLabels?: string[];
 }
 
 // 图片信息类型
@@ -36,6 +53,14 @@ export interface ImageInfo {
   image_type?: ImageType;
 }
 
+// 图片输入来源（传给生成命令时由 Rust 侧读取文件，避免 base64 跨越 IPC）
+// 字符串视为 base64 数据；path 仅限应用存储目录内的图片
+export type ImageSource =
+  | string
+  | { data: string }
+  | { path: string }
+  | { assetId: string; canvasId?: string };
+
 // 带元数据的图片信息
 export interface ImageInfoWithMetadata extends ImageInfo {
   metadata?: ImageMetadata;
@@ -55,6 +80,34 @@ export interface CanvasImageStats {
   total_size: number;
 }
 
+// 存储位置信息
+export interface StorageRootInfo {
+  storage_root: string;
+  images_dir: string;
+  default_root: string;
+  is_custom: boolean;
+  previous_images_dirs: string[]; // 已迁移到当前目录的历史图片目录
+}
+
+// 存储迁移进度（事件 storage-migrate-progress）
+export interface StorageMigrateProgress {
+  phase: "scanning" | "copying" | "verifying" | "committing" | "cleanup" | "completed";
+  current: number;
+  total: number;
+  bytes_copied: number;
+  total_bytes: number;
+  file?: string;
+}
+
+// 存储迁移结果
+export interface StorageMigrateResult {
+  old_root: string;
+  new_root: string;
+  file_count: number;
+  total_bytes: number;
+  source_removed: boolean;
+}
+
 /**
  * 保存图片到文件系统
  * @param base64Data - 图片的 base64 数据（不含 data:image/xxx;base64, 前缀）
@@ -92,14 +145,52 @@ export async function readImage(path: string): Promise<string> {
   return await invoke<string>("read_image", { path });
 }
 
+// 当前存储位置（用于将迁移前保存的旧路径映射到当前图片目录）
+let storageRootInfo: StorageRootInfo | null = null;
+
+// 统一路径分隔符，用于前缀比较
+function normalizePath(path: string): string {
+  return path.replace(/\\/g, "/").replace(/\/+$/, "");
+}
+
+/**
+ * 加载存储位置（应用启动时调用，迁移或切换目录后会自动刷新）
+ */
+export async function loadStorageRootInfo(): Promise<void> {
+  storageRootInfo = await invoke<StorageRootInfo>("get_storage_root_info");
+}
+
+/**
+ * 将旧存储目录下的图片路径映射到当前图片目录
+ * @param path - 画布中保存的图片路径
+ * @returns 当前图片目录下的路径（不在旧目录下时原样返回）
+ */
+export function resolveImagePath(path: string): string {
+  if (!storageRootInfo) return path;
+  const normalized = normalizePath(path);
+  const imagesDir = normalizePath(storageRootInfo.images_dir);
+  if (normalized.startsWith(imagesDir + "/")) return path;
+
+  for (const dir of storageRootInfo.previous_images_dirs) {
+    const previous = normalizePath(dir);
+    if (normalized.startsWith(previous + "/")) {
+      const separator = storageRootInfo.images_dir.includes("\\") ? "\\" : "/";
+      const relative = normalized.slice(previous.length + 1).split("/").join(separator);
+      return storageRootInfo.images_dir.replace(/[\\/]+$/, "") + separator + relative;
+    }
+  }
+  return path;
+}
+
 /**
  * 获取图片的可访问 URL
  * 使用 Tauri 的 convertFileSrc 将本地路径转换为 webview 可访问的 URL
+ * 存储目录迁移后，旧路径会映射到当前图片目录
  * @param path - 图片文件路径
  * @returns 可在 webview 中使用的 URL
  */
 export function getImageUrl(path: string): string {
-  return convertFileSrc(path);
+  return convertFileSrc(resolveImagePath(path));
 }
 
 /**
@@ -151,6 +242,42 @@ export async function getStoragePath(): Promise<string> {
   return await invoke<string>("get_storage_path");
 }
 
+/**
+ * 获取存储位置详情
+ * @returns 当前存储根目录、图片目录及默认目录
+ */
+export async function getStorageRootInfo(): Promise<StorageRootInfo> {
+  storageRootInfo = await invoke<StorageRootInfo>("get_storage_root_info");
+  return storageRootInfo;
+}
+
+/**
+ * 切换存储根目录（不迁移已有文件）
+ * @param newRoot - 新的存储根目录，不传则恢复默认目录
+ * @returns 切换后的存储位置信息
+ */
+export async function setStorageRoot(newRoot?: string): Promise<StorageRootInfo> {
+  storageRootInfo = await invoke<StorageRootInfo>("set_storage_root", { newRoot });
+  return storageRootInfo;
+}
+
+/**
+ * 迁移存储目录（校验通过后才切换，失败时旧目录保持不变）
+ * 进度通过 storage-migrate-progress 事件推送
+ * @param newRoot - 新的存储根目录
+ * @param keepSource - 是否保留旧目录中的文件（复制而非移动）
+ * @returns 迁移结果
+ */
+export async function migrateStorage(
+  newRoot: string,
+  keepSource?: boolean
+): Promise<StorageMigrateResult> {
+  const result = await invoke<StorageMigrateResult>("migrate_storage", { newRoot, keepSource });
+  // 迁移后旧路径需要映射到新目录
+  await loadStorageRootInfo();
+  return result;
+}
+
 /**
  * 列出画布的所有图片（包含元数据）
  * @param canvasId - 画布 ID
@@ -169,6 +296,110 @@ export async function readImageMetadata(imagePath: string): Promise<ImageMetadat
   return await invoke<ImageMetadata | null>("read_image_metadata", { imagePath });
 }
 
+// 本地图片处理输出选项
+export interface ImageOutputOptions {
+  format?: "png" | "jpeg" | "webp";
+  quality?: number;
+  canvasId?: string;
+  nodeId?: string;
+}
+
+/**
+ * 缩放图片
+ * @param path - 源图片路径
+ * @param width - 目标宽度
+ * @param height - 目标高度
+ * @param mode - fit（默认）/ fill / exact
+ * @param filter - nearest / triangle / catmullrom / gaussian / lanczos3（默认）
+ * @returns 新图片信息
+ */
+export async function resizeImage(
+  path: string,
+  width: number,
+  height: number,
+  mode?: "fit" | "fill" | "exact",
+  filter?: "nearest" | "triangle" | "catmullrom" | "gaussian" | "lanczos3",
+  output?: ImageOutputOptions
+): Promise<ImageInfo> {
+  return await invoke<ImageInfo>("image_resize", {
+    params: { path, width, height, mode, filter, output },
+  });
+}
+
+/**
+ * 裁剪图片（像素坐标）
+ * @returns 新图片信息
+ */
+export async function cropImage(
+  path: string,
+  x: number,
+  y: number,
+  width: number,
+  height: number,
+  output?: ImageOutputOptions
+): Promise<ImageInfo> {
+  return await invoke<ImageInfo>("image_crop", {
+    params: { path, x, y, width, height, output },
+  });
+}
+
+/**
+ * 旋转/翻转图片
+ * @param degrees - 顺时针旋转角度（0/90/180/270）
+ * @returns 新图片信息
+ */
+export async function rotateImage(
+  path: string,
+  degrees?: number,
+  flipHorizontal?: boolean,
+  flipVertical?: boolean,
+  output?: ImageOutputOptions
+): Promise<ImageInfo> {
+  return await invoke<ImageInfo>("image_rotate", {
+    params: { path, degrees, flipHorizontal, flipVertical, output },
+  });
+}
+
+/**
+ * 补边至目标宽高比
+ * @param aspectRatio - 如 "16:9"
+ * @param color - 填充颜色（#RRGGBB / #RRGGBBAA），默认透明
+ * @returns 新图片信息
+ */
+export async function padImage(
+  path: string,
+  aspectRatio: string,
+  color?: string,
+  output?: ImageOutputOptions
+): Promise<ImageInfo> {
+  return await invoke<ImageInfo>("image_pad", {
+    params: { path, aspectRatio, color, output },
+  });
+}
+
+/**
+ * 格式转换
+ * @returns 新图片信息
+ */
+export async function convertImage(path: string, output: ImageOutputOptions): Promise<ImageInfo> {
+  return await invoke<ImageInfo>("image_convert", { params: { path, output } });
+}
+
+/**
+ * 压缩图片到指定体积以内（适配供应商上传限制）
+ * @param maxBytes - 目标最大字节数
+ * @returns 新图片信息
+ */
+export async function compressImageToSize(
+  path: string,
+  maxBytes: number,
+  output?: ImageOutputOptions
+): Promise<ImageInfo> {
+  return await invoke<ImageInfo>("image_compress", {
+    params: { path, maxBytes, output },
+  });
+}
+
 /**
  * 格式化文件大小
  * @param bytes - 字节数
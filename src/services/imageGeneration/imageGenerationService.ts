@@ -3,8 +3,9 @@
  */
 
 import { imageGenerationRegistry } from "./registry";
-import { geminiImageProvider, dalleImageProvider, fluxImageProvider } from "./providers";
+import { geminiImageProvider, dalleImageProvider, fluxImageProvider, doubaoImageProvider } from "./providers";
 import { useSettingsStore } from "@/stores/settingsStore";
+import { getFallbackProviders } from "@/services/providerService";
 import type {
   ImageGenerationRequest,
   ImageGenerationResponse,
@@ -22,15 +23,24 @@ export function initializeImageGenerationProviders(): void {
   // 注册 DALL-E 提供商（OpenAI Images API 格式）
   imageGenerationRegistry.register(dalleImageProvider);
 
-  // 注册 Flux 提供商（OpenAI Images API 格式）
+  // 注册 Flux 提供商（BFL 异步任务接口）
   imageGenerationRegistry.register(fluxImageProvider);
 
+  // 注册豆包提供商（火山方舟图片生成接口）
+  imageGenerationRegistry.register(doubaoImageProvider);
+
   console.log(
     "[ImageGenService] Providers initialized:",
     imageGenerationRegistry.getAll().map((p) => p.id)
   );
 }
 
+// 使用专用提供商的节点（其余节点按供应商协议选择）
+const NODE_PROVIDER_IDS: Partial<Record<ImageNodeType, string>> = {
+  fluxGenerator: "flux",
+  doubaoGenerator: "doubao",
+};
+
 /**
  * 获取节点对应的供应商配置
  */
@@ -47,15 +57,17 @@ function getProviderConfig(nodeType: ImageNodeType): ProviderConfig {
     throw new Error("供应商不存在，请重新配置");
   }
 
-  if (!provider.apiKey) {
+  if (!provider.apiKey && !provider.credentialId) {
     throw new Error("供应商 API Key 未配置");
   }
 
   return {
     apiKey: provider.apiKey,
+    credentialId: provider.credentialId,
     baseUrl: provider.baseUrl,
     protocol: provider.protocol,
     name: provider.name,
+    fallbacks: getFallbackProviders(nodeType, provider),
   };
 }
 
@@ -70,8 +82,11 @@ export async function generateImage(
   try {
     const config = getProviderConfig(nodeType);
 
-    // 根据协议获取对应的提供商实现
-    const provider = imageGenerationRegistry.getByProtocol(config.protocol);
+    // 专用节点使用固定的提供商，其余根据协议获取对应的提供商实现
+    const providerId = NODE_PROVIDER_IDS[nodeType];
+    const provider = providerId
+      ? imageGenerationRegistry.get(providerId)
+      : imageGenerationRegistry.getByProtocol(config.protocol);
 
     if (!provider) {
       return {
@@ -5,6 +5,7 @@
  */
 
 import { invoke } from "@tauri-apps/api/core";
+import type { ImageInfo } from "@/services/fileStorageService";
 import type {
   ImageGenerationProvider,
   ImageGenerationRequest,
@@ -21,6 +22,25 @@ interface TauriDalleResult {
   imageUrl?: string;
   revisedPrompt?: string;
   error?: string;
+  image?: ImageInfo; // 指定 output 时返回已保存的图片信息
+  images?: TauriDalleImage[]; // 全部图片，部分失败时各自带 error
+}
+
+// 流式生成的部分图片事件（dalle-partial-image）
+export interface DallePartialImageEvent {
+  streamId?: string;
+  partialImageIndex: number;
+  imageData: string;
+  outputFormat?: string;
+}
+
+interface TauriDalleImage {
+  index: number;
+  imageData?: string;
+  imageUrl?: string;
+  revisedPrompt?: string;
+  error?: string;
+  image?: ImageInfo;
 }
 
 /**
@@ -98,6 +118,7 @@ export class DalleImageProvider implements ImageGenerationProvider {
     return {
       baseUrl: config.baseUrl.replace(/\/+$/, ""),
       apiKey: config.apiKey,
+      credentialId: config.credentialId,
       model: request.model,
       prompt: request.prompt,
       inputImages: request.inputImages,
@@ -105,6 +126,9 @@ export class DalleImageProvider implements ImageGenerationProvider {
       quality: request.imageSize === "4K" ? "hd" : "standard",
       style: request.style,
       negativePrompt: request.negativePrompt,
+      canvasId: request.canvasId,
+      nodeId: request.nodeId,
+      fallbacks: config.fallbacks,
     };
   }
 
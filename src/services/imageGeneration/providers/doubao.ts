@@ -0,0 +1,222 @@
+/**
+ * 图片生成框架 - 豆包（火山方舟 Seedream）提供商实现
+ *
+ * 支持多图参考、组图生成、水印开关和 1K/2K/4K 尺寸预设
+ */
+
+import { invoke } from "@tauri-apps/api/core";
+import type {
+  ImageGenerationProvider,
+  ImageGenerationRequest,
+  ImageGenerationResponse,
+  ProviderConfig,
+  ImageGenerationCapability,
+} from "../types";
+import type { ErrorDetails } from "@/types";
+
+// Tauri 后端响应类型（复用 DALL-E 的响应格式）
+interface TauriDalleResult {
+  success: boolean;
+  imageData?: string;
+  imageUrl?: string;
+  error?: string;
+  images?: TauriDalleImage[]; // 组图生成时的全部图片，部分失败时各自带 error
+}
+
+interface TauriDalleImage {
+  index: number;
+  imageData?: string;
+  imageUrl?: string;
+  error?: string;
+}
+
+/**
+ * 豆包图片生成提供商
+ */
+export class DoubaoImageProvider implements ImageGenerationProvider {
+  readonly id = "doubao";
+  readonly name = "豆包";
+  readonly protocol = "openai" as const;
+
+  readonly capabilities: ImageGenerationCapability[] = [
+    "text-to-image",
+    "image-editing",
+  ];
+
+  // 与 Rust 端的 2K 尺寸预设一致
+  readonly supportedAspectRatios = [
+    "1:1",
+    "4:3",
+    "3:4",
+    "16:9",
+    "9:16",
+    "3:2",
+    "2:3",
+    "21:9",
+  ];
+
+  readonly supportedImageSizes = ["1K", "2K", "4K"];
+  readonly supportsMultipleInputImages = true;
+  readonly maxInputImages = 10;
+
+  /**
+   * 验证请求参数
+   */
+  validateRequest(request: ImageGenerationRequest): {
+    valid: boolean;
+    error?: string;
+  } {
+    if (!request.prompt?.trim()) {
+      return { valid: false, error: "提示词不能为空" };
+    }
+
+    if (
+      request.inputImages &&
+      request.inputImages.length > this.maxInputImages
+    ) {
+      return {
+        valid: false,
+        error: `豆包最多支持 ${this.maxInputImages} 张参考图`,
+      };
+    }
+
+    return { valid: true };
+  }
+
+  /**
+   * 构建 Tauri 后端请求参数
+   */
+  buildTauriParams(request: ImageGenerationRequest, config: ProviderConfig) {
+    return {
+      baseUrl: config.baseUrl.replace(/\/+$/, ""),
+      apiKey: config.apiKey,
+      credentialId: config.credentialId,
+      model: request.model,
+      prompt: request.prompt,
+      inputImages: request.inputImages,
+      size: request.imageSize || "2K",
+      aspectRatio: request.aspectRatio,
+      seed: request.seed,
+      sequential: request.sequential,
+      maxImages: request.maxImages,
+      watermark: request.watermark,
+      guidanceScale: request.guidanceScale,
+      canvasId: request.canvasId,
+      nodeId: request.nodeId,
+      fallbacks: config.fallbacks,
+    };
+  }
+
+  /**
+   * 生成图片
+   */
+  async generate(
+    request: ImageGenerationRequest,
+    config: ProviderConfig,
+    abortSignal?: AbortSignal
+  ): Promise<ImageGenerationResponse> {
+    const validation = this.validateRequest(request);
+    if (!validation.valid) {
+      return { error: validation.error };
+    }
+
+    if (abortSignal?.aborted) {
+      return { error: "已取消" };
+    }
+
+    try {
+      return await this.generateViaTauri(request, config);
+    } catch (error) {
+      if (error instanceof Error && error.name === "AbortError") {
+        return { error: "已取消" };
+      }
+      const message = error instanceof Error ? error.message : "生成失败";
+      return {
+        error: message,
+        errorDetails: this.buildErrorDetails(error, request, config),
+      };
+    }
+  }
+
+  /**
+   * 通过 Tauri 后端生成
+   */
+  private async generateViaTauri(
+    request: ImageGenerationRequest,
+    config: ProviderConfig
+  ): Promise<ImageGenerationResponse> {
+    const params = this.buildTauriParams(request, config);
+
+    console.log("[DoubaoProvider] Generating via Tauri backend...");
+
+    const result = await invoke<TauriDalleResult>("doubao_generate_image", {
+      params,
+    });
+
+    if (!result.success) {
+      return {
+        error: result.error || "请求失败",
+        errorDetails: this.buildErrorDetails(
+          new Error(result.error || "请求失败"),
+          request,
+          config
+        ),
+      };
+    }
+
+    // 组图生成时返回全部成功的图片，imageData 为第一张
+    const images = (result.images || [])
+      .map((image) => image.imageData)
+      .filter((data): data is string => !!data);
+
+    if (!result.imageData) {
+      return {
+        error: "API 返回成功但未包含图片数据",
+        errorDetails: {
+          name: "EmptyImageData",
+          message: "API 返回成功但未包含图片数据",
+          timestamp: new Date().toISOString(),
+          model: request.model,
+          provider: config.name,
+          requestUrl: `${config.baseUrl}/api/v3/images/generations`,
+          responseBody: {
+            success: result.success,
+            imageUrl: result.imageUrl,
+            hasImageData: !!result.imageData,
+          },
+        },
+      };
+    }
+
+    return {
+      imageData: result.imageData,
+      images: images.length > 1 ? images : undefined,
+      metadata: { model: request.model, seed: request.seed },
+    };
+  }
+
+  /**
+   * 构建错误详情
+   */
+  private buildErrorDetails(
+    error: unknown,
+    request: ImageGenerationRequest,
+    config: ProviderConfig
+  ): ErrorDetails {
+    const isError = error instanceof Error;
+    const message = isError ? error.message : String(error);
+
+    return {
+      name: isError ? error.name : "Error",
+      message,
+      stack: isError ? error.stack : undefined,
+      timestamp: new Date().toISOString(),
+      model: request.model,
+      provider: config.name,
+      requestUrl: `${config.baseUrl}/api/v3/images/generations`,
+    };
+  }
+}
+
+// 导出单例
+export const doubaoImageProvider = new DoubaoImageProvider();
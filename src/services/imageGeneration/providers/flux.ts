@@ -1,7 +1,7 @@
 /**
  * 图片生成框架 - Flux 提供商实现
  *
- * Flux 使用 OpenAI Images API 格式，支持宽高比参数
+ * Flux 使用 BFL 异步任务接口：提交任务后轮询状态，完成后下载结果图片
  */
 
 import { invoke } from "@tauri-apps/api/core";
@@ -14,7 +14,18 @@ import type {
 } from "../types";
 import type { ErrorDetails } from "@/types";
 
-// Tauri 后端响应类型（复用 DALL-E 的响应格式）
+// 任务响应（flux_create_task / flux_get_status）
+interface TauriFluxTaskResult {
+  success: boolean;
+  taskId?: string;
+  pollingUrl?: string;
+  status?: string; // Pending / Ready / Request Moderated / Content Moderated / Error
+  resultUrl?: string;
+  seed?: number;
+  error?: string;
+}
+
+// 下载结果响应（flux_get_content，复用 DALL-E 的响应格式）
 interface TauriDalleResult {
   success: boolean;
   imageData?: string;
@@ -23,6 +34,10 @@ interface TauriDalleResult {
   error?: string;
 }
 
+// 轮询间隔与最大次数（约 5 分钟）
+const POLL_INTERVAL_MS = 2000;
+const MAX_POLL_ATTEMPTS = 150;
+
 /**
  * Flux 图片生成提供商
  */
@@ -76,18 +91,24 @@ export class FluxImageProvider implements ImageGenerationProvider {
   }
 
   /**
-   * 构建 Tauri 后端请求参数
+   * 构建 Tauri 后端请求参数（flux_create_task，不支持备用供应商）
    */
   buildTauriParams(request: ImageGenerationRequest, config: ProviderConfig) {
     return {
       baseUrl: config.baseUrl.replace(/\/+$/, ""),
       apiKey: config.apiKey,
+      credentialId: config.credentialId,
       model: request.model,
       prompt: request.prompt,
       inputImages: request.inputImages,
       // Flux 使用 aspect_ratio 而不是 size
       aspectRatio: request.aspectRatio || "1:1",
-      negativePrompt: request.negativePrompt,
+      seed: request.seed,
+      safetyTolerance: request.safetyTolerance,
+      raw: request.raw,
+      imagePromptStrength: request.imagePromptStrength,
+      canvasId: request.canvasId,
+      nodeId: request.nodeId,
     };
   }
 
@@ -109,7 +130,7 @@ export class FluxImageProvider implements ImageGenerationProvider {
     }
 
     try {
-      return await this.generateViaTauri(request, config);
+      return await this.generateViaTauri(request, config, abortSignal);
     } catch (error) {
       if (error instanceof Error && error.name === "AbortError") {
         return { error: "已取消" };
@@ -123,29 +144,81 @@ export class FluxImageProvider implements ImageGenerationProvider {
   }
 
   /**
-   * 通过 Tauri 后端生成
+   * 通过 Tauri 后端生成：提交任务 → 轮询状态 → 下载结果
    */
   private async generateViaTauri(
     request: ImageGenerationRequest,
-    config: ProviderConfig
+    config: ProviderConfig,
+    abortSignal?: AbortSignal
   ): Promise<ImageGenerationResponse> {
     const params = this.buildTauriParams(request, config);
 
-    console.log("[FluxProvider] Generating via Tauri backend...");
+    console.log("[FluxProvider] Creating task via Tauri backend...");
+
+    const task = await invoke<TauriFluxTaskResult>("flux_create_task", { params });
+    if (!task.success || !task.taskId) {
+      return this.failure(task.error || "创建任务失败", request, config);
+    }
 
-    const result = await invoke<TauriDalleResult>("dalle_generate_image", {
-      params,
+    // 轮询任务状态
+    let status: TauriFluxTaskResult | undefined;
+    for (let attempt = 0; attempt < MAX_POLL_ATTEMPTS; attempt++) {
+      if (abortSignal?.aborted) {
+        return { error: "已取消" };
+      }
+
+      status = await invoke<TauriFluxTaskResult>("flux_get_status", {
+        params: {
+          baseUrl: params.baseUrl,
+          apiKey: config.apiKey,
+          credentialId: config.credentialId,
+          taskId: task.taskId,
+          pollingUrl: task.pollingUrl,
+        },
+      });
+      if (!status.success) {
+        return this.failure(status.error || "任务失败", request, config);
+      }
+      if (status.status === "Ready") break;
+
+      // 可中断的等待
+      await new Promise<void>((resolve) => {
+        const timeoutId = setTimeout(resolve, POLL_INTERVAL_MS);
+        abortSignal?.addEventListener(
+          "abort",
+          () => {
+            clearTimeout(timeoutId);
+            resolve();
+          },
+          { once: true }
+        );
+      });
+    }
+
+    if (abortSignal?.aborted) {
+      return { error: "已取消" };
+    }
+    if (status?.status !== "Ready") {
+      return this.failure("任务超时，请稍后重试", request, config);
+    }
+    if (!status.resultUrl) {
+      return this.failure("任务完成但未返回图片地址", request, config);
+    }
+
+    // 下载结果图片
+    const result = await invoke<TauriDalleResult>("flux_get_content", {
+      params: {
+        resultUrl: status.resultUrl,
+        prompt: request.prompt,
+        model: request.model,
+        seed: status.seed,
+        aspectRatio: params.aspectRatio,
+        inputImages: request.inputImages,
+      },
     });
 
     if (!result.success) {
-      return {
-        error: result.error || "请求失败",
-        errorDetails: this.buildErrorDetails(
-          new Error(result.error || "请求失败"),
-          request,
-          config
-        ),
-      };
+      return this.failure(result.error || "下载图片失败", request, config);
     }
 
     // 检查是否有图片数据
@@ -158,11 +231,10 @@ export class FluxImageProvider implements ImageGenerationProvider {
           timestamp: new Date().toISOString(),
           model: request.model,
           provider: config.name,
-          requestUrl: `${config.baseUrl}/v1/images/generations`,
+          requestUrl: `${config.baseUrl}/v1/${request.model}`,
           responseBody: {
             success: result.success,
             imageUrl: result.imageUrl,
-            revisedPrompt: result.revisedPrompt,
             hasImageData: !!result.imageData,
           },
         },
@@ -171,7 +243,21 @@ export class FluxImageProvider implements ImageGenerationProvider {
 
     return {
       imageData: result.imageData,
-      metadata: { model: request.model },
+      metadata: { model: request.model, seed: status.seed },
+    };
+  }
+
+  /**
+   * 构建失败响应
+   */
+  private failure(
+    message: string,
+    request: ImageGenerationRequest,
+    config: ProviderConfig
+  ): ImageGenerationResponse {
+    return {
+      error: message,
+      errorDetails: this.buildErrorDetails(new Error(message), request, config),
     };
   }
 
@@ -193,7 +279,7 @@ export class FluxImageProvider implements ImageGenerationProvider {
       timestamp: new Date().toISOString(),
       model: request.model,
       provider: config.name,
-      requestUrl: `${config.baseUrl}/v1/images/generations`,
+      requestUrl: `${config.baseUrl}/v1/${request.model}`,
     };
   }
 }
@@ -6,6 +6,7 @@
  */
 
 import { invoke } from "@tauri-apps/api/core";
+import type { ImageInfo } from "@/services/fileStorageService";
 import type {
   ImageGenerationProvider,
   ImageGenerationRequest,
@@ -21,6 +22,25 @@ interface TauriGeminiResult {
   imageData?: string;
   text?: string;
   error?: string;
+  image?: ImageInfo; // 指定 output 时返回已保存的图片信息
+  images?: TauriGeminiImage[]; // 全部生成图片（候选顺序）
+  finishReason?: string;
+  blockReason?: string;
+  safetyRatings?: { category: string; probability?: string; blocked: boolean }[];
+  usageMetadata?: {
+    promptTokenCount?: number;
+    candidatesTokenCount?: number;
+    thoughtsTokenCount?: number;
+    totalTokenCount?: number;
+  };
+}
+
+interface TauriGeminiImage {
+  candidateIndex: number;
+  mimeType: string;
+  imageData?: string;
+  text?: string;
+  image?: ImageInfo;
 }
 
 /**
@@ -101,11 +121,15 @@ export class GeminiImageProvider implements ImageGenerationProvider {
     return {
       baseUrl: apiBaseUrl,
       apiKey: config.apiKey,
+      credentialId: config.credentialId,
       model: request.model,
       prompt: request.prompt,
       inputImages: request.inputImages,
       aspectRatio: request.aspectRatio || "1:1",
       imageSize: request.imageSize,
+      canvasId: request.canvasId,
+      nodeId: request.nodeId,
+      fallbacks: config.fallbacks,
     };
   }
 
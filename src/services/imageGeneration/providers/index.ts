@@ -5,3 +5,4 @@
 export { geminiImageProvider, GeminiImageProvider } from "./gemini";
 export { dalleImageProvider, DalleImageProvider } from "./dalle";
 export { fluxImageProvider, FluxImageProvider } from "./flux";
+export { doubaoImageProvider, DoubaoImageProvider } from "./doubao";
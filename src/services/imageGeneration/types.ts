@@ -3,6 +3,9 @@
  */
 
 import type { ProviderProtocol, ErrorDetails } from "@/types";
+import type { ImageSource } from "@/services/fileStorageService";
+import type { UsageContext } from "@/services/usageService";
+import type { FallbackProvider } from "@/services/providerService";
 
 /**
  * 图片生成能力枚举
@@ -18,10 +21,10 @@ export type ImageGenerationCapability =
 /**
  * 图片生成请求参数（通用）
  */
-export interface ImageGenerationRequest {
+export interface ImageGenerationRequest extends UsageContext {
   prompt: string;
   model: string;
-  inputImages?: string[]; // base64 图片数组
+  inputImages?: ImageSource[]; // 输入图片（base64、存储路径或图片 ID）
   aspectRatio?: string; // 宽高比
   imageSize?: string; // 分辨率
   negativePrompt?: string; // 负面提示词（部分供应商支持）
@@ -29,6 +32,12 @@ export interface ImageGenerationRequest {
   steps?: number; // 生成步数
   guidanceScale?: number; // 引导强度
   style?: string; // 风格预设
+  safetyTolerance?: number; // 审核宽松度 0（最严格）- 6（Flux）
+  raw?: boolean; // raw 模式，画面更自然（Flux Ultra）
+  imagePromptStrength?: number; // 参考图影响强度 0-1（Flux Ultra）
+  watermark?: boolean; // 添加水印（豆包）
+  sequential?: boolean; // 组图生成（豆包）
+  maxImages?: number; // 组图最大数量 1-15（豆包）
 }
 
 /**
@@ -36,6 +45,7 @@ export interface ImageGenerationRequest {
  */
 export interface ImageGenerationResponse {
   imageData?: string; // base64 图片数据
+  images?: string[]; // 组图生成的全部图片（base64），imageData 为第一张
   text?: string; // 附带文本（如 Gemini 的描述）
   error?: string;
   errorDetails?: ErrorDetails;
@@ -52,9 +62,11 @@ export interface ImageGenerationResponse {
  */
 export interface ProviderConfig {
   apiKey: string;
+  credentialId?: string; // 凭据库中的凭据 ID（优先于 apiKey）
   baseUrl: string;
   protocol: ProviderProtocol;
   name: string;
+  fallbacks?: FallbackProvider[]; // 备用供应商（按顺序）
 }
 
 /**
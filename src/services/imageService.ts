@@ -1,6 +1,9 @@
 import { invoke } from "@tauri-apps/api/core";
+import type { ImageInfo, ImageSource } from "@/services/fileStorageService";
 import type { ImageGenerationParams, ImageEditParams, GenerationResponse, ErrorDetails } from "@/types";
 import { useSettingsStore } from "@/stores/settingsStore";
+import type { UsageContext } from "@/services/usageService";
+import { getFallbackProviders, type FallbackProvider } from "@/services/providerService";
 
 // 图片节点类型
 type ImageNodeType = "imageGeneratorPro" | "imageGeneratorFast";
@@ -19,7 +22,7 @@ function getProviderConfig(nodeType: ImageNodeType) {
     throw new Error("供应商不存在，请重新配置");
   }
 
-  if (!provider.apiKey) {
+  if (!provider.apiKey && !provider.credentialId) {
     throw new Error("供应商 API Key 未配置");
   }
 
@@ -27,14 +30,16 @@ function getProviderConfig(nodeType: ImageNodeType) {
 }
 
 // Tauri 后端代理请求参数
-interface TauriGeminiParams {
+interface TauriGeminiParams extends UsageContext {
   baseUrl: string;
   apiKey: string;
+  credentialId?: string; // 凭据库中的凭据 ID（优先于 apiKey）
   model: string;
   prompt: string;
-  inputImages?: string[];
+  inputImages?: ImageSource[];
   aspectRatio?: string;
   imageSize?: string;
+  fallbacks?: FallbackProvider[]; // 备用供应商（按顺序）
 }
 
 // Tauri 后端代理响应
@@ -43,6 +48,25 @@ interface TauriGeminiResult {
   imageData?: string;
   text?: string;
   error?: string;
+  image?: ImageInfo; // 指定 output 时返回已保存的图片信息
+  images?: TauriGeminiImage[]; // 全部生成图片（候选顺序）
+  finishReason?: string;
+  blockReason?: string;
+  safetyRatings?: { category: string; probability?: string; blocked: boolean }[];
+  usageMetadata?: {
+    promptTokenCount?: number;
+    candidatesTokenCount?: number;
+    thoughtsTokenCount?: number;
+    totalTokenCount?: number;
+  };
+}
+
+interface TauriGeminiImage {
+  candidateIndex: number;
+  mimeType: string;
+  imageData?: string;
+  text?: string;
+  image?: ImageInfo;
 }
 
 // 通过 Tauri 后端代理发送请求
@@ -154,10 +178,14 @@ export async function generateImage(
       {
         baseUrl: provider.baseUrl,
         apiKey: provider.apiKey,
+        credentialId: provider.credentialId,
         model: params.model,
         prompt: params.prompt,
         aspectRatio: params.aspectRatio || "1:1",
         imageSize: isPro ? params.imageSize : undefined,
+        canvasId: params.canvasId,
+        nodeId: params.nodeId,
+        fallbacks: getFallbackProviders(nodeType, provider),
       },
       { name: provider.name, protocol: provider.protocol }
     );
@@ -186,11 +214,15 @@ export async function editImage(
       {
         baseUrl: provider.baseUrl,
         apiKey: provider.apiKey,
+        credentialId: provider.credentialId,
         model: params.model,
         prompt: params.prompt,
         inputImages: params.inputImages,
         aspectRatio: params.aspectRatio || "1:1",
         imageSize: isPro ? params.imageSize : undefined,
+        canvasId: params.canvasId,
+        nodeId: params.nodeId,
+        fallbacks: getFallbackProviders(nodeType, provider),
       },
       { name: provider.name, protocol: provider.protocol }
     );
@@ -1,39 +1,91 @@
 import { invoke } from "@tauri-apps/api/core";
-import type { LLMModelType, Provider, ErrorDetails } from "@/types";
+import type { ApiFlavor, LLMModelType, Provider, ErrorDetails } from "@/types";
 import { useSettingsStore } from "@/stores/settingsStore";
+import type { BudgetExceeded, Usage, UsageContext } from "./usageService";
+import { getFallbackProviders, type FallbackProvider, type ServedBy } from "./providerService";
 
 // LLM 节点类型
 type LLMNodeType = "llm" | "llmContent";
 
+// 工具定义（parameters 为 JSON Schema）
+export interface ToolDefinition {
+  name: string;
+  description?: string;
+  parameters?: Record<string, unknown>;
+}
+
+// 模型发起的工具调用
+export interface ToolCall {
+  id: string;
+  name: string;
+  arguments: unknown;
+  signature?: string; // Gemini thoughtSignature，回传历史时原样带上
+}
+
+// 对话消息（多轮对话历史）
+export interface ChatMessage {
+  role: "system" | "user" | "assistant" | "tool";
+  content: string;
+  files?: Array<{ data: string; mimeType: string; fileName?: string }>; // 仅用户消息
+  toolCalls?: ToolCall[]; // 助手消息发起的工具调用
+  toolCallId?: string;    // 工具消息对应的调用 ID
+  name?: string;          // 工具消息对应的工具名
+}
+
+// Agent 工具调用事件（llm-tool-call），执行后通过 llm_submit_tool_result 回传
+export interface LLMToolCallEvent {
+  runId: string;
+  step: number;
+  call: ToolCall;
+}
+
 // LLM 生成参数
-export interface LLMGenerationParams {
+export interface LLMGenerationParams extends UsageContext {
   prompt: string;
   model: LLMModelType;
   systemPrompt?: string;
+  messages?: ChatMessage[]; // 多轮对话历史（排在本轮 prompt 之前）
+  tools?: ToolDefinition[];
+  toolChoice?: string; // auto / none / required / 指定工具名
   temperature?: number;
   maxTokens?: number;
   files?: Array<{ data: string; mimeType: string; fileName?: string }>; // 文件数据（base64）
   responseJsonSchema?: Record<string, unknown>; // 结构化输出的 JSON Schema
 }
 
+// 未能发送给模型的文件
+export interface SkippedFile {
+  fileName?: string;
+  mimeType: string;
+  reason: string;
+}
+
 // LLM 响应
 export interface LLMResponse {
   content?: string;
   error?: string;
   errorDetails?: ErrorDetails;  // 详细错误信息
+  skippedFiles?: SkippedFile[]; // 被跳过的文件及原因
+  toolCalls?: ToolCall[];       // 模型请求的工具调用
 }
 
 // Tauri 后端请求参数
-interface TauriLLMParams {
+interface TauriLLMParams extends UsageContext {
   baseUrl: string;
-  apiKey: string;
+  apiKey?: string;
+  credentialId?: string; // 凭据库中的凭据 ID（优先于 apiKey）
+  apiFlavor?: ApiFlavor;
   model: string;
   prompt: string;
   systemPrompt?: string;
+  messages?: ChatMessage[];
+  tools?: ToolDefinition[];
+  toolChoice?: string;
   temperature?: number;
   maxTokens?: number;
   files?: Array<{ data: string; mimeType: string; fileName?: string }>; // 文件数据（base64）
   responseJsonSchema?: Record<string, unknown>; // 结构化输出的 JSON Schema
+  fallbacks?: FallbackProvider[]; // 备用供应商（按顺序）
 }
 
 // Tauri 后端响应
@@ -41,6 +93,11 @@ interface TauriLLMResult {
   success: boolean;
   content?: string;
   error?: string;
+  skippedFiles?: SkippedFile[];
+  toolCalls?: ToolCall[];
+  usage?: Usage;
+  budgetExceeded?: BudgetExceeded; // 超出预算时未发出请求
+  servedBy?: ServedBy; // 配置了备用供应商时，实际完成请求的供应商
 }
 
 // 获取供应商配置
@@ -57,7 +114,7 @@ function getProviderConfig(nodeType: LLMNodeType) {
     throw new Error("供应商不存在，请重新配置");
   }
 
-  if (!provider.apiKey) {
+  if (!provider.apiKey && !provider.credentialId) {
     throw new Error("供应商 API Key 未配置");
   }
 
@@ -136,6 +193,7 @@ async function invokeLLMByProtocol(params: TauriLLMParams, provider: Provider):
     systemPrompt: params.systemPrompt ? params.systemPrompt.slice(0, 200) : undefined,
     temperature: params.temperature,
     maxTokens: params.maxTokens,
+    messagesCount: params.messages?.length || 0,
     filesCount: params.files?.length || 0,
     hasJsonSchema: !!params.responseJsonSchema,
   };
@@ -161,7 +219,11 @@ async function invokeLLMByProtocol(params: TauriLLMParams, provider: Provider):
       };
     }
 
-    return { content: result.content };
+    if (result.skippedFiles?.length) {
+      console.warn("[llmService] Skipped files:", result.skippedFiles);
+    }
+
+    return { content: result.content, skippedFiles: result.skippedFiles, toolCalls: result.toolCalls };
   } catch (error) {
     console.error("[llmService] Tauri invoke error:", error);
     const message = error instanceof Error ? error.message : String(error);
@@ -186,13 +248,21 @@ export async function generateText(params: LLMGenerationParams): Promise<LLMResp
     const requestParams: TauriLLMParams = {
       baseUrl,
       apiKey: provider.apiKey,
+      credentialId: provider.credentialId,
+      apiFlavor: provider.apiFlavor,
+      fallbacks: getFallbackProviders("llm", provider),
       model: params.model,
       prompt: params.prompt,
       systemPrompt: params.systemPrompt,
+      messages: params.messages,
+      tools: params.tools,
+      toolChoice: params.toolChoice,
       temperature: params.temperature,
       maxTokens: params.maxTokens,
       files: params.files,
       responseJsonSchema: params.responseJsonSchema,
+      canvasId: params.canvasId,
+      nodeId: params.nodeId,
     };
 
     return await invokeLLMByProtocol(requestParams, provider);
@@ -216,13 +286,21 @@ export async function generateLLMContent(params: LLMGenerationParams): Promise<L
     const requestParams: TauriLLMParams = {
       baseUrl,
       apiKey: provider.apiKey,
+      credentialId: provider.credentialId,
+      apiFlavor: provider.apiFlavor,
+      fallbacks: getFallbackProviders("llmContent", provider),
       model: params.model,
       prompt: params.prompt,
       systemPrompt: params.systemPrompt,
+      messages: params.messages,
+      tools: params.tools,
+      toolChoice: params.toolChoice,
       temperature: params.temperature,
       maxTokens: params.maxTokens,
       files: params.files,
       responseJsonSchema: params.responseJsonSchema,
+      canvasId: params.canvasId,
+      nodeId: params.nodeId,
     };
 
     return await invokeLLMByProtocol(requestParams, provider);
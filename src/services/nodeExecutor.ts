@@ -18,7 +18,8 @@ import { useCanvasStore } from "@/stores/canvasStore";
 import { generateImage, editImage } from "@/services/imageService";
 import { generateLLMContent } from "@/services/llmService";
 import { createVideoTask, pollVideoTask } from "@/services/videoGeneration";
-import { saveImage, readImage } from "@/services/fileStorageService";
+import { saveImage, readImage, type ImageSource } from "@/services/fileStorageService";
+import { collectImageSources } from "@/utils/imageSources";
 
 // 自定义节点类型
 type CustomNode = Node<CustomNodeData>;
@@ -26,10 +27,12 @@ type CustomNode = Node<CustomNodeData>;
 /**
  * 从指定画布获取连接的输入数据（异步版本，支持从文件加载图片）
  * 解决画布切换时数据读取错误的问题
+ * loadImages 为 false 时不读取图片文件（图片改用 getConnectedImageSourcesFromCanvas 传路径）
  */
 async function getConnectedInputDataFromCanvas(
   nodeId: string,
-  canvasId: string
+  canvasId: string,
+  loadImages = true
 ): Promise<{
   prompt?: string;
   images: string[];
@@ -39,7 +42,8 @@ async function getConnectedInputDataFromCanvas(
 
   // 如果是当前活跃画布，使用 flowStore 的异步版本
   if (canvasId === activeCanvasId) {
-    return useFlowStore.getState().getConnectedInputDataAsync(nodeId);
+    const flowStore = useFlowStore.getState();
+    return loadImages ? flowStore.getConnectedInputDataAsync(nodeId) : flowStore.getConnectedInputData(nodeId);
   }
 
   // 否则从 canvasStore 读取目标画布的数据
@@ -77,7 +81,7 @@ async function getConnectedInputDataFromCanvas(
       if (sourceNode.type === "imageInputNode") {
         const data = sourceNode.data as { imageData?: string; imagePath?: string };
         // 优先从文件加载
-        if (data.imagePath) {
+        if (data.imagePath && loadImages) {
           try {
             imageData = await readImage(data.imagePath);
           } catch (err) {
@@ -90,7 +94,7 @@ async function getConnectedInputDataFromCanvas(
       } else if (sourceNode.type === "imageGeneratorProNode" || sourceNode.type === "imageGeneratorFastNode") {
         const data = sourceNode.data as { outputImage?: string; outputImagePath?: string };
         // 优先从文件加载
-        if (data.outputImagePath) {
+        if (data.outputImagePath && loadImages) {
           try {
             imageData = await readImage(data.outputImagePath);
           } catch (err) {
@@ -126,7 +130,7 @@ async function getConnectedInputDataFromCanvas(
       } else if (sourceNode.type === "imageInputNode") {
         const data = sourceNode.data as { imageData?: string; imagePath?: string };
         let imageData: string | undefined;
-        if (data.imagePath) {
+        if (data.imagePath && loadImages) {
           try {
             imageData = await readImage(data.imagePath);
           } catch (err) {
@@ -140,7 +144,7 @@ async function getConnectedInputDataFromCanvas(
       } else if (sourceNode.type === "imageGeneratorProNode" || sourceNode.type === "imageGeneratorFastNode") {
         const data = sourceNode.data as { outputImage?: string; outputImagePath?: string };
         let imageData: string | undefined;
-        if (data.outputImagePath) {
+        if (data.outputImagePath && loadImages) {
           try {
             imageData = await readImage(data.outputImagePath);
           } catch (err) {
@@ -165,6 +169,21 @@ async function getConnectedInputDataFromCanvas(
   return { prompt, images, files };
 }
 
+/**
+ * 从指定画布获取连接的图片来源（已保存的图片只传路径，由 Rust 侧读取）
+ */
+function getConnectedImageSourcesFromCanvas(nodeId: string, canvasId: string): ImageSource[] {
+  const { activeCanvasId } = useCanvasStore.getState();
+  if (canvasId === activeCanvasId) {
+    return useFlowStore.getState().getConnectedImageSources(nodeId);
+  }
+  const canvas = useCanvasStore.getState().canvases.find((c) => c.id === canvasId);
+  if (!canvas) {
+    return [];
+  }
+  return collectImageSources(canvas.nodes as CustomNode[], canvas.edges as Edge[], nodeId);
+}
+
 /**
  * 画布感知的节点数据更新
  * 确保即使用户切换画布，状态也能正确更新到目标画布
@@ -214,8 +233,9 @@ async function executeImageGeneratorNode(
   const data = node.data as ImageGeneratorNodeData;
   const isPro = node.type === "imageGeneratorProNode";
   const nodeType = isPro ? "imageGeneratorPro" : "imageGeneratorFast";
-  // 使用画布感知的数据读取，解决画布切换问题（异步从文件加载图片）
-  const { prompt, images } = await getConnectedInputDataFromCanvas(node.id, canvasId);
+  // 使用画布感知的数据读取，解决画布切换问题（已保存的图片只传路径）
+  const { prompt } = await getConnectedInputDataFromCanvas(node.id, canvasId, false);
+  const images = getConnectedImageSourcesFromCanvas(node.id, canvasId);
 
   // 验证输入
   if (!prompt) {
@@ -243,6 +263,8 @@ async function executeImageGeneratorNode(
               inputImages: images,
               aspectRatio: data.aspectRatio,
               imageSize: isPro ? data.imageSize : undefined,
+              canvasId,
+              nodeId: node.id,
             },
             nodeType,
             signal
@@ -253,6 +275,8 @@ async function executeImageGeneratorNode(
               model: data.model,
               aspectRatio: data.aspectRatio,
               imageSize: isPro ? data.imageSize : undefined,
+              canvasId,
+              nodeId: node.id,
             },
             nodeType,
             signal
@@ -362,6 +386,8 @@ async function executeLLMContentNode(
       temperature: data.temperature,
       maxTokens: data.maxTokens,
       files: files.length > 0 ? files : undefined,
+      canvasId,
+      nodeId: node.id,
     });
 
     // 检查中断
@@ -449,6 +475,8 @@ async function executeVideoGeneratorNode(
       seconds: data.seconds,
       size: data.size,
       inputImage: images.length > 0 ? images[0] : undefined,
+      canvasId,
+      nodeId: node.id,
     }, "videoGenerator", signal);
 
     if (createResult.error || !createResult.taskId) {
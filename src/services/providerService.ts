@@ -0,0 +1,118 @@
+/**
+ * 供应商服务
+ * 从供应商的 /models 端点获取实际可用的模型及其能力，测试供应商配置是否可用，
+ * 并构建节点的备用供应商链
+ */
+
+import { invoke } from "@tauri-apps/api/core";
+import type { ApiFlavor, NodeProviderMapping, Provider } from "@/types";
+import { useSettingsStore } from "@/stores/settingsStore";
+
+// 归一化后的模型信息
+export interface ModelInfo {
+  id: string;
+  displayName: string;
+  textOutput: boolean;
+  imageOutput: boolean;
+  visionInput: boolean;
+  contextLength?: number; // 供应商未提供时为空
+  outputTokenLimit?: number;
+}
+
+/**
+ * 获取供应商提供的模型列表
+ */
+export async function listModels(provider: Provider): Promise<ModelInfo[]> {
+  return await invoke<ModelInfo[]>("list_models", {
+    params: {
+      provider: provider.protocol,
+      baseUrl: provider.baseUrl,
+      apiFlavor: provider.apiFlavor,
+      apiKey: provider.apiKey,
+      credentialId: provider.credentialId,
+    },
+  });
+}
+
+// 连接测试的诊断类型
+export type ProviderDiagnosis =
+  | "ok"
+  | "invalidUrl"
+  | "dnsFailed"
+  | "connectFailed"
+  | "tlsFailed"
+  | "proxyFailed"
+  | "timeout"
+  | "duplicatedVersion" // Base URL 末尾多了 /v1 等版本路径
+  | "wrongBasePath"
+  | "unauthorized"
+  | "modelNotFound"
+  | "rateLimited"
+  | "serverError"
+  | "unknown";
+
+export interface ProviderTestResult {
+  success: boolean;
+  diagnosis: ProviderDiagnosis;
+  message: string;
+  url: string; // 实际请求的端点
+  latencyMs: number;
+  dnsMs?: number;
+  status?: number;
+  modelCount?: number;
+}
+
+/**
+ * 测试供应商连接（DNS、TLS、鉴权，指定 model 时同时确认模型可用）
+ */
+export async function testProvider(provider: Provider, model?: string): Promise<ProviderTestResult> {
+  return await invoke<ProviderTestResult>("test_provider", {
+    params: {
+      provider: provider.protocol,
+      baseUrl: provider.baseUrl,
+      apiFlavor: provider.apiFlavor,
+      apiKey: provider.apiKey,
+      credentialId: provider.credentialId,
+      model,
+    },
+  });
+}
+
+// 备用供应商（与 Rust 端 FallbackProvider 对应）
+export interface FallbackProvider {
+  name?: string;
+  baseUrl: string;
+  apiFlavor?: ApiFlavor;
+  apiKey?: string;
+  credentialId?: string;
+  model?: string; // 未设置时沿用主供应商的模型
+}
+
+// 配置了备用供应商时，结果中记录实际完成请求的供应商
+export interface ServedBy {
+  index: number; // 0 为主供应商
+  name?: string;
+  baseUrl: string;
+  model: string;
+  fallbackErrors?: string[]; // 之前各供应商的失败原因
+}
+
+/**
+ * 获取节点的备用供应商（按顺序，跳过已删除或与主供应商协议不同的供应商）
+ */
+export function getFallbackProviders(nodeType: keyof NodeProviderMapping, primary: Provider): FallbackProvider[] {
+  const { settings } = useSettingsStore.getState();
+  const providerIds = settings.nodeFallbacks?.[nodeType] ?? [];
+
+  return providerIds
+    .filter((id) => id !== primary.id)
+    .map((id) => settings.providers.find((p) => p.id === id))
+    .filter((p): p is Provider => !!p && p.protocol === primary.protocol)
+    .map((p) => ({
+      name: p.name,
+      baseUrl: p.baseUrl,
+      apiFlavor: p.apiFlavor,
+      apiKey: p.apiKey,
+      credentialId: p.credentialId,
+    }));
+}
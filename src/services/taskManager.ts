@@ -196,6 +196,7 @@ class TaskManager {
           params: {
             baseUrl: provider.baseUrl,
             apiKey: provider.apiKey,
+            credentialId: provider.credentialId,
             taskId,
           },
         });
@@ -549,6 +550,7 @@ class TaskManager {
           params: {
             baseUrl: provider.baseUrl,
             apiKey: provider.apiKey,
+            credentialId: provider.credentialId,
             taskId,
             mode,
           },
@@ -583,6 +585,7 @@ class TaskManager {
             params: {
               baseUrl: provider.baseUrl,
               apiKey: provider.apiKey,
+              credentialId: provider.credentialId,
               taskId,
               mode,
             },
@@ -5,14 +5,18 @@
 
 import { invoke } from "@tauri-apps/api/core";
 import { listen, type UnlistenFn } from "@tauri-apps/api/event";
+import type { FallbackProvider, ServedBy } from "./providerService";
+import type { BudgetExceeded, UsageContext } from "./usageService";
 
 // ==================== 类型定义 ====================
 
 /** 文字去除配置 */
-export interface TextRemovalConfig {
+export interface TextRemovalConfig extends UsageContext {
   geminiBaseUrl: string;
   geminiApiKey: string;
+  geminiCredentialId?: string; // 凭据库中的凭据 ID（优先于 geminiApiKey）
   geminiModel: string;
+  geminiFallbacks?: FallbackProvider[]; // 备用供应商（文字检测和样式提取使用）
 }
 
 /** 文本框数据 */
@@ -40,6 +44,8 @@ interface TextRemovalResult {
   backgroundImage: string | null;
   textBoxes: TextBox[];
   error: string | null;
+  budgetExceeded?: BudgetExceeded; // 超出预算时未发出检测请求
+  servedBy?: ServedBy; // 配置了备用供应商时，实际完成检测的供应商
 }
 
 /** 文字检测结果 */
@@ -47,6 +53,8 @@ interface TextDetectionResult {
   success: boolean;
   regions: TextRegionData[];
   error: string | null;
+  budgetExceeded?: BudgetExceeded; // 超出预算时未发出检测请求
+  servedBy?: ServedBy; // 配置了备用供应商时，实际完成检测的供应商
 }
 
 /** 背景修复结果 */
@@ -73,7 +81,11 @@ export async function removeTextFromImage(
       imageData,
       geminiBaseUrl: config.geminiBaseUrl,
       geminiApiKey: config.geminiApiKey,
+      geminiCredentialId: config.geminiCredentialId,
       geminiModel: config.geminiModel,
+      geminiFallbacks: config.geminiFallbacks,
+      canvasId: config.canvasId,
+      nodeId: config.nodeId,
     },
   });
 
@@ -102,7 +114,11 @@ export async function detectTextRegions(
       imageData,
       geminiBaseUrl: config.geminiBaseUrl,
       geminiApiKey: config.geminiApiKey,
+      geminiCredentialId: config.geminiCredentialId,
       geminiModel: config.geminiModel,
+      geminiFallbacks: config.geminiFallbacks,
+      canvasId: config.canvasId,
+      nodeId: config.nodeId,
     },
   });
 
@@ -147,10 +163,12 @@ export interface BatchPageInput {
 }
 
 /** 批量处理配置 */
-export interface BatchProcessConfig {
+export interface BatchProcessConfig extends UsageContext {
   geminiBaseUrl: string;
   geminiApiKey: string;
+  geminiCredentialId?: string; // 凭据库中的凭据 ID（优先于 geminiApiKey）
   geminiModel: string;
+  geminiFallbacks?: FallbackProvider[]; // 备用供应商（每个页面的检测各自切换）
 }
 
 /** 页面处理进度事件 */
@@ -163,6 +181,7 @@ export interface PageProgressEvent {
   textBoxes?: TextBox[];
   imageWidth?: number;
   imageHeight?: number;
+  budgetExceeded?: BudgetExceeded; // 超出预算时未发出检测请求
 }
 
 /** 批量处理完成事件 */
@@ -228,7 +247,11 @@ export async function processPagesBatch(
       pages,
       geminiBaseUrl: config.geminiBaseUrl,
       geminiApiKey: config.geminiApiKey,
+      geminiCredentialId: config.geminiCredentialId,
       geminiModel: config.geminiModel,
+      geminiFallbacks: config.geminiFallbacks,
+      canvasId: config.canvasId,
+      nodeId: config.nodeId,
     },
   }).finally(cleanup);
 
@@ -0,0 +1,108 @@
+/**
+ * 用量与费用服务
+ * 查询 Rust 端记录的调用台账（tokens、图片数、视频秒数和估算费用）
+ */
+
+import { invoke } from "@tauri-apps/api/core";
+
+// 单次调用或汇总的用量
+export interface Usage {
+  inputTokens: number;
+  outputTokens: number;
+  images: number;
+  videoSeconds: number;
+}
+
+// 用量归属（随请求参数平铺传给 Rust）
+export interface UsageContext {
+  canvasId?: string;
+  nodeId?: string;
+}
+
+// 按画布或日期汇总的结果
+export interface UsageSummary extends Usage {
+  key: string; // 画布 ID 或日期（YYYY-MM-DD）
+  requests: number;
+  cost: number;
+  byModel: Record<string, number>; // 各模型费用
+}
+
+// 模型价格（model 以 * 结尾时为前缀匹配）
+export interface ModelPrice {
+  model: string;
+  provider?: string;
+  inputPerMillion?: number;
+  outputPerMillion?: number;
+  perImage?: number;
+  perVideoSecond?: number;
+}
+
+export interface PriceTable {
+  currency: string;
+  prices: ModelPrice[];
+}
+
+/**
+ * 按画布汇总用量
+ * @param canvasId - 可选，只返回指定画布
+ */
+export async function getCanvasUsage(canvasId?: string): Promise<UsageSummary[]> {
+  return await invoke<UsageSummary[]>("usage_canvas_totals", { canvasId });
+}
+
+/**
+ * 按日汇总用量（包含起止日期）
+ */
+export async function getDailyUsage(startDate?: string, endDate?: string): Promise<UsageSummary[]> {
+  return await invoke<UsageSummary[]>("usage_daily_totals", { startDate, endDate });
+}
+
+export async function getPriceTable(): Promise<PriceTable> {
+  return await invoke<PriceTable>("usage_get_price_table");
+}
+
+export async function setPriceTable(table: PriceTable): Promise<void> {
+  await invoke("usage_set_price_table", { table });
+}
+
+// 预算配置（未设置的项不限制，费用单位与价格表一致）
+export interface BudgetConfig {
+  dailyLimit?: number;
+  monthlyLimit?: number;
+  canvasLimit?: number; // 每个画布的累计上限
+  canvasLimits?: Record<string, number>; // 单独设置的画布上限
+  providerDailyRequests?: Record<string, number>; // 各供应商每日请求数上限
+}
+
+// 超出预算时命令结果中的 budgetExceeded（此时未发出请求）
+export interface BudgetExceeded {
+  scope: "daily" | "monthly" | "canvas" | "providerRequests" | "invalidConfig";
+  limit: number;
+  spent: number; // 当前花费（providerRequests 时为今日请求数）
+  planned: number; // 本次请求的预估费用
+  currency: string;
+  provider?: string;
+  canvasId?: string;
+  reason?: string; // invalidConfig 时的解析错误
+}
+
+export interface BudgetStatus {
+  config: BudgetConfig;
+  currency: string;
+  dailySpent: number;
+  monthlySpent: number;
+  canvasSpent?: number;
+  providerRequests: Record<string, number>;
+}
+
+export async function getBudgetConfig(): Promise<BudgetConfig> {
+  return await invoke<BudgetConfig>("budget_get_config");
+}
+
+export async function setBudgetConfig(config: BudgetConfig): Promise<void> {
+  await invoke("budget_set_config", { config });
+}
+
+export async function getBudgetStatus(canvasId?: string): Promise<BudgetStatus> {
+  return await invoke<BudgetStatus>("budget_get_status", { canvasId });
+}
@@ -0,0 +1,94 @@
+/**
+ * 凭据库服务
+ * API Key 加密保存在 Rust 端，前端只保存凭据 ID，调用生成命令时通过 credentialId 引用
+ */
+
+import { invoke } from "@tauri-apps/api/core";
+import type { Provider } from "@/types";
+
+// 凭据信息（密钥已遮盖）
+export interface CredentialSummary {
+  id: string;
+  name: string;
+  provider: string;
+  maskedKey: string;
+  createdAt: number;
+  updatedAt: number;
+}
+
+export interface VaultStatus {
+  exists: boolean;
+  keySource: "machine" | "passphrase";
+  locked: boolean; // 口令模式下需要先解锁
+}
+
+export async function addCredential(name: string, provider: string, apiKey: string): Promise<CredentialSummary> {
+  return await invoke<CredentialSummary>("credential_add", { params: { name, provider, apiKey } });
+}
+
+export async function listCredentials(): Promise<CredentialSummary[]> {
+  return await invoke<CredentialSummary[]>("credential_list");
+}
+
+/**
+ * 更换凭据的 API Key（凭据 ID 不变）
+ */
+export async function rotateCredential(credentialId: string, apiKey: string): Promise<CredentialSummary> {
+  return await invoke<CredentialSummary>("credential_rotate", { credentialId, apiKey });
+}
+
+export async function deleteCredential(credentialId: string): Promise<void> {
+  await invoke("credential_delete", { credentialId });
+}
+
+export async function getVaultStatus(): Promise<VaultStatus> {
+  return await invoke<VaultStatus>("vault_status");
+}
+
+export async function unlockVault(passphrase: string): Promise<void> {
+  await invoke("vault_unlock", { passphrase });
+}
+
+export async function lockVault(): Promise<void> {
+  await invoke("vault_lock");
+}
+
+/**
+ * 设置口令；传空值时改回设备密钥
+ */
+export async function setVaultPassphrase(passphrase?: string): Promise<void> {
+  await invoke("vault_set_passphrase", { passphrase });
+}
+
+/**
+ * 将供应商的 API Key 保存到凭据库：已有凭据时更换密钥，否则新建凭据
+ * @returns 凭据 ID
+ */
+export async function saveProviderKey(
+  provider: Pick<Provider, "name" | "protocol" | "credentialId">,
+  apiKey: string
+): Promise<string> {
+  if (provider.credentialId) {
+    return (await rotateCredential(provider.credentialId, apiKey)).id;
+  }
+  return (await addCredential(provider.name, provider.protocol, apiKey)).id;
+}
+
+/**
+ * 将仍保存明文 API Key 的供应商迁移到凭据库
+ * 凭据库锁定等原因失败时保留明文，下次启动时重试
+ */
+export async function migrateProviderKeys(
+  providers: Provider[],
+  updateProvider: (id: string, updates: Partial<Omit<Provider, "id">>) => void
+): Promise<void> {
+  for (const provider of providers) {
+    if (!provider.apiKey || provider.credentialId) continue;
+    try {
+      const credentialId = await saveProviderKey(provider, provider.apiKey);
+      updateProvider(provider.id, { credentialId, apiKey: "" });
+    } catch (error) {
+      console.warn(`[vaultService] 供应商「${provider.name}」的 API Key 迁移失败:`, error);
+    }
+  }
+}
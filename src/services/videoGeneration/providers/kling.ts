@@ -14,6 +14,7 @@ import type {
   VideoGenerationCapability,
 } from "../types";
 import type { ErrorDetails } from "@/types";
+import type { UsageContext } from "@/services/usageService";
 
 // Kling 支持的模型
 export type KlingModel = "kling-v1" | "kling-v1-5" | string;
@@ -45,7 +46,7 @@ export interface KlingMetadata {
 }
 
 // Kling 生成请求参数
-export interface KlingGenerationRequest {
+export interface KlingGenerationRequest extends UsageContext {
   prompt: string;
   model: KlingModel;
   mode: KlingMode;
@@ -60,9 +61,10 @@ export interface KlingGenerationRequest {
 }
 
 // Tauri 后端请求参数
-interface TauriKlingCreateParams {
+interface TauriKlingCreateParams extends UsageContext {
   baseUrl: string;
   apiKey: string;
+  credentialId?: string; // 凭据库中的凭据 ID（优先于 apiKey）
   model: string;
   prompt: string;
   mode: string;
@@ -83,6 +85,7 @@ interface TauriKlingCreateParams {
 interface TauriKlingStatusParams {
   baseUrl: string;
   apiKey: string;
+  credentialId?: string; // 凭据库中的凭据 ID（优先于 apiKey）
   taskId: string;
   mode: string;
 }
@@ -165,9 +168,12 @@ export class KlingVideoProvider implements VideoGenerationProvider {
     const params: TauriKlingCreateParams = {
       baseUrl: config.baseUrl.replace(/\/+$/, ""),
       apiKey: config.apiKey,
+      credentialId: config.credentialId,
       model: request.model,
       prompt: request.prompt,
       mode: request.mode,
+      canvasId: request.canvasId,
+      nodeId: request.nodeId,
     };
 
     // 添加图片（图生视频模式）
@@ -310,6 +316,7 @@ export class KlingVideoProvider implements VideoGenerationProvider {
       const tauriParams: TauriKlingStatusParams = {
         baseUrl: config.baseUrl.replace(/\/+$/, ""),
         apiKey: config.apiKey,
+        credentialId: config.credentialId,
         taskId,
         mode,
       };
@@ -347,6 +354,7 @@ export class KlingVideoProvider implements VideoGenerationProvider {
       const tauriParams: TauriKlingStatusParams = {
         baseUrl: config.baseUrl.replace(/\/+$/, ""),
         apiKey: config.apiKey,
+        credentialId: config.credentialId,
         taskId,
         mode,
       };
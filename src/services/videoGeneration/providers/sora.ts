@@ -15,11 +15,13 @@ import type {
   VideoDurationType,
 } from "../types";
 import type { ErrorDetails } from "@/types";
+import type { UsageContext } from "@/services/usageService";
 
 // Tauri 后端请求参数
-interface TauriVideoCreateParams {
+interface TauriVideoCreateParams extends UsageContext {
   baseUrl: string;
   apiKey: string;
+  credentialId?: string; // 凭据库中的凭据 ID（优先于 apiKey）
   model: string;
   prompt: string;
   seconds?: string;
@@ -30,6 +32,7 @@ interface TauriVideoCreateParams {
 interface TauriVideoStatusParams {
   baseUrl: string;
   apiKey: string;
+  credentialId?: string; // 凭据库中的凭据 ID（优先于 apiKey）
   taskId: string;
 }
 
@@ -104,11 +107,14 @@ export class SoraVideoProvider implements VideoGenerationProvider {
     return {
       baseUrl: config.baseUrl.replace(/\/+$/, ""),
       apiKey: config.apiKey,
+      credentialId: config.credentialId,
       model: request.model,
       prompt: request.prompt,
       seconds: request.seconds,
       size: request.size,
       inputImage: request.inputImage,
+      canvasId: request.canvasId,
+      nodeId: request.nodeId,
     };
   }
 
@@ -210,6 +216,7 @@ export class SoraVideoProvider implements VideoGenerationProvider {
       const tauriParams: TauriVideoStatusParams = {
         baseUrl: config.baseUrl.replace(/\/+$/, ""),
         apiKey: config.apiKey,
+        credentialId: config.credentialId,
         taskId,
       };
 
@@ -245,6 +252,7 @@ export class SoraVideoProvider implements VideoGenerationProvider {
       const tauriParams: TauriVideoStatusParams = {
         baseUrl: config.baseUrl.replace(/\/+$/, ""),
         apiKey: config.apiKey,
+        credentialId: config.credentialId,
         taskId,
       };
 
@@ -16,6 +16,7 @@ import type {
   VideoGenerationCapability,
 } from "../types";
 import type { ErrorDetails } from "@/types";
+import type { UsageContext } from "@/services/usageService";
 
 // Veo 特有的视频尺寸类型（宽高比格式）
 export type VeoAspectRatio = "16:9" | "9:16";
@@ -36,7 +37,7 @@ export interface VeoReferenceImage {
 }
 
 // Veo 生成请求参数
-export interface VeoGenerationRequest {
+export interface VeoGenerationRequest extends UsageContext {
   prompt: string;
   model: string;
   images?: string[];  // base64 编码的图片数组（1张=图生视频，2张=帧插值）
@@ -50,9 +51,10 @@ export interface VeoGenerationRequest {
 }
 
 // Tauri 后端请求参数
-interface TauriVeoCreateParams {
+interface TauriVeoCreateParams extends UsageContext {
   baseUrl: string;
   apiKey: string;
+  credentialId?: string; // 凭据库中的凭据 ID（优先于 apiKey）
   model: string;
   prompt: string;
   images?: string[];  // base64 数组
@@ -68,6 +70,7 @@ interface TauriVeoCreateParams {
 interface TauriVeoStatusParams {
   baseUrl: string;
   apiKey: string;
+  credentialId?: string; // 凭据库中的凭据 ID（优先于 apiKey）
   taskId: string;
 }
 
@@ -165,8 +168,11 @@ export class VeoVideoProvider implements VideoGenerationProvider {
     const params: TauriVeoCreateParams = {
       baseUrl: config.baseUrl.replace(/\/+$/, ""),
       apiKey: config.apiKey,
+      credentialId: config.credentialId,
       model: request.model,
       prompt: request.prompt,
+      canvasId: request.canvasId,
+      nodeId: request.nodeId,
     };
 
     // 添加图片（图生视频或帧插值）
@@ -294,6 +300,7 @@ export class VeoVideoProvider implements VideoGenerationProvider {
       const tauriParams: TauriVeoStatusParams = {
         baseUrl: config.baseUrl.replace(/\/+$/, ""),
         apiKey: config.apiKey,
+        credentialId: config.credentialId,
         taskId,
       };
 
@@ -329,6 +336,7 @@ export class VeoVideoProvider implements VideoGenerationProvider {
       const tauriParams: TauriVeoStatusParams = {
         baseUrl: config.baseUrl.replace(/\/+$/, ""),
         apiKey: config.apiKey,
+        credentialId: config.credentialId,
         taskId,
       };
 
@@ -3,6 +3,7 @@
  */
 
 import type { ProviderProtocol, ErrorDetails } from "@/types";
+import type { UsageContext } from "@/services/usageService";
 
 /**
  * 视频生成能力枚举
@@ -29,7 +30,7 @@ export type VideoDurationType = "10" | "15" | "25";
 /**
  * 视频生成请求参数（通用）
  */
-export interface VideoGenerationRequest {
+export interface VideoGenerationRequest extends UsageContext {
   prompt: string;
   model: string;
   inputImage?: string; // base64 首帧参考图
@@ -75,6 +76,7 @@ export interface VideoProgressInfo {
  */
 export interface VideoProviderConfig {
   apiKey: string;
+  credentialId?: string; // 凭据库中的凭据 ID（优先于 apiKey）
   baseUrl: string;
   protocol: ProviderProtocol;
   name: string;
@@ -47,12 +47,13 @@ function getProviderConfig(nodeType: VideoNodeType): VideoProviderConfig {
     throw new Error("供应商不存在，请重新配置");
   }
 
-  if (!provider.apiKey) {
+  if (!provider.apiKey && !provider.credentialId) {
     throw new Error("供应商 API Key 未配置");
   }
 
   return {
     apiKey: provider.apiKey,
+    credentialId: provider.credentialId,
     baseUrl: provider.baseUrl,
     protocol: provider.protocol,
     name: provider.name,
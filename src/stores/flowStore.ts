@@ -18,6 +18,8 @@ import type { PromptNodeTemplate } from "@/config/promptConfig";
 import { validateConnection } from "@/utils/connectionValidator";
 import { WorkflowEngine } from "@/services/workflowEngine";
 import { useCanvasStore } from "@/stores/canvasStore";
+import { collectImageSources } from "@/utils/imageSources";
+import type { ImageSource } from "@/services/fileStorageService";
 import { toast } from "@/stores/toastStore";
 import { readImage } from "@/services/fileStorageService";
 
@@ -113,6 +115,9 @@ interface FlowStore {
     files: Array<{ data: string; mimeType: string; fileName?: string }>;
   }>;
 
+  // 获取连接的图片来源（已保存的图片只传路径，由 Rust 侧读取）
+  getConnectedImageSources: (nodeId: string) => ImageSource[];
+
   // 获取连接的图片详细信息（包含 ID、文件名、路径）- 同步版本
   getConnectedImagesWithInfo: (nodeId: string) => Array<{
     id: string;
@@ -1088,6 +1093,12 @@ export const useFlowStore = create<FlowStore>((set, get) => ({
     return { prompt, images, files };
   },
 
+  // 获取连接的图片来源（已保存的图片只传路径，由 Rust 侧读取）
+  getConnectedImageSources: (nodeId) => {
+    const { nodes, edges } = get();
+    return collectImageSources(nodes, edges, nodeId);
+  },
+
   // 获取连接的图片详细信息（包含 ID、文件名、路径）- 同步版本，用于检测连接状态
   getConnectedImagesWithInfo: (nodeId) => {
     const { nodes, edges } = get();
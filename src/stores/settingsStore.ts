@@ -2,6 +2,7 @@ import { create } from "zustand";
 import { persist, createJSONStorage } from "zustand/middleware";
 import type { AppSettings, SettingsState, Provider, NodeProviderMapping, ProviderProtocol } from "@/types";
 import { tauriStorage } from "@/utils/tauriStorage";
+import { migrateProviderKeys } from "@/services/vaultService";
 
 // 默认设置
 const defaultSettings: AppSettings = {
@@ -54,6 +55,7 @@ interface SettingsStore extends SettingsState {
   // 节点供应商映射
   setNodeProvider: (nodeType: keyof NodeProviderMapping, providerId: string | undefined) => void;
   getNodeProvider: (nodeType: keyof NodeProviderMapping) => Provider | undefined;
+  setNodeFallbacks: (nodeType: keyof NodeProviderMapping, providerIds: string[]) => void;
 
   // 供应商面板状态
   isProviderPanelOpen: boolean;
@@ -119,11 +121,17 @@ export const useSettingsStore = create<SettingsStore>()(
             }
           }
 
+          const newNodeFallbacks = { ...state.settings.nodeFallbacks };
+          for (const key of Object.keys(newNodeFallbacks) as (keyof NodeProviderMapping)[]) {
+            newNodeFallbacks[key] = newNodeFallbacks[key]?.filter((providerId) => providerId !== id);
+          }
+
           return {
             settings: {
               ...state.settings,
               providers: state.settings.providers.filter((p) => p.id !== id),
               nodeProviders: newNodeProviders,
+              nodeFallbacks: newNodeFallbacks,
             },
           };
         }),
@@ -151,6 +159,18 @@ export const useSettingsStore = create<SettingsStore>()(
         return state.settings.providers.find((p) => p.id === providerId);
       },
 
+      // 备用供应商（主供应商连接失败、5xx 或额度不足时按顺序使用）
+      setNodeFallbacks: (nodeType, providerIds) =>
+        set((state) => ({
+          settings: {
+            ...state.settings,
+            nodeFallbacks: {
+              ...state.settings.nodeFallbacks,
+              [nodeType]: providerIds,
+            },
+          },
+        })),
+
       // 供应商面板状态
       openProviderPanel: () =>
         set({ isProviderPanelOpen: true }),
@@ -181,6 +201,8 @@ export const useSettingsStore = create<SettingsStore>()(
               console.log("[settingsStore] 执行供应商数据迁移");
               state.updateSettings({ providers: migratedProviders });
             }
+            // 明文 API Key 迁移到凭据库
+            void migrateProviderKeys(migratedProviders, state.updateProvider);
           }
         };
       },
@@ -1,4 +1,6 @@
 import type { Node, Edge } from "@xyflow/react";
+import type { ImageSource } from "@/services/fileStorageService";
+import type { UsageContext } from "@/services/usageService";
 
 // 详细错误信息结构
 export interface ErrorDetails {
@@ -69,7 +71,7 @@ export interface VideoGenerationResponse {
 }
 
 // 图片生成参数
-export interface ImageGenerationParams {
+export interface ImageGenerationParams extends UsageContext {
   prompt: string;
   model: ModelType;
   aspectRatio?: "1:1" | "16:9" | "9:16" | "4:3" | "3:4" | "3:2" | "2:3" | "5:4" | "4:5" | "21:9";
@@ -79,7 +81,7 @@ export interface ImageGenerationParams {
 
 // 图片编辑参数
 export interface ImageEditParams extends ImageGenerationParams {
-  inputImages?: string[]; // base64 编码的图片数组（支持多图输入）
+  inputImages?: ImageSource[]; // 输入图片（base64、存储路径或图片 ID，支持多图输入）
 }
 
 // API 响应
@@ -204,12 +206,17 @@ export interface NodeDefinition {
 // 供应商协议类型
 export type ProviderProtocol = 'openai' | 'google' | 'claude';
 
+// OpenAI 兼容接口类型（Azure 使用部署路径、api-version 和 api-key 请求头）
+export type ApiFlavor = 'openAI' | 'azure' | 'openRouter' | 'relay';
+
 // 供应商配置
 export interface Provider {
   id: string;           // 唯一标识 (uuid)
   name: string;         // 供应商名称
-  apiKey: string;       // API Key
-  baseUrl: string;      // Base URL（不包含版本路径如 /v1beta）
+  apiKey: string;       // API Key（使用凭据库时为空）
+  credentialId?: string; // 凭据库中的凭据 ID（设置后不再保存明文 API Key）
+  apiFlavor?: ApiFlavor; // OpenAI 兼容接口类型，未设置时按域名识别
+  baseUrl: string;      // Base URL（末尾的 /v1、/v1beta 会自动去掉）
   protocol: ProviderProtocol;  // API 协议类型
 }
 
@@ -249,6 +256,7 @@ export const NODE_ALLOWED_PROTOCOLS: Record<keyof NodeProviderMapping, ProviderP
 export interface AppSettings {
   providers: Provider[];              // 供应商列表
   nodeProviders: NodeProviderMapping; // 节点类型 -> 供应商映射
+  nodeFallbacks?: Partial<Record<keyof NodeProviderMapping, string[]>>; // 节点类型 -> 备用供应商 ID（按顺序）
   theme: "light" | "dark" | "system";
 }
 
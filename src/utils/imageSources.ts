@@ -0,0 +1,44 @@
+/**
+ * 图片输入来源收集工具
+ * 已保存到文件的图片只传路径，由 Rust 侧读取，避免 base64 两次跨越 IPC 阻塞界面
+ */
+
+import type { Edge, Node } from "@xyflow/react";
+import type { ImageSource } from "@/services/fileStorageService";
+
+/**
+ * 收集连接到节点图片输入端口的图片来源（按连线顺序）
+ * 有文件路径时使用 { path }，否则使用内存中的 base64 数据
+ */
+export function collectImageSources(nodes: Node[], edges: Edge[], nodeId: string): ImageSource[] {
+  const sources: ImageSource[] = [];
+
+  for (const edge of edges) {
+    if (edge.target !== nodeId) continue;
+    // 兼容旧的没有 handle ID 的连接
+    if (edge.targetHandle && edge.targetHandle !== "input-image") continue;
+
+    const sourceNode = nodes.find((n) => n.id === edge.source);
+    if (!sourceNode) continue;
+
+    let imagePath: string | undefined;
+    let imageData: string | undefined;
+    if (sourceNode.type === "imageInputNode") {
+      const data = sourceNode.data as { imageData?: string; imagePath?: string };
+      imagePath = data.imagePath;
+      imageData = data.imageData;
+    } else if (sourceNode.type === "imageGeneratorProNode" || sourceNode.type === "imageGeneratorFastNode") {
+      const data = sourceNode.data as { outputImage?: string; outputImagePath?: string };
+      imagePath = data.outputImagePath;
+      imageData = data.outputImage;
+    }
+
+    if (imagePath) {
+      sources.push({ path: imagePath });
+    } else if (imageData) {
+      sources.push(imageData);
+    }
+  }
+
+  return sources;
+}